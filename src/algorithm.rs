@@ -0,0 +1,520 @@
+//! Signing and digest algorithms.
+
+use data_encoding::HEXLOWER;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::md::{Md, MdRef};
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::{RsaPssSaltlen, Signer, Verifier};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::progress::Progress;
+
+/// Digest an artifact is identified by in bundles and log entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake3,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha384" => Ok(HashAlgorithm::Sha384),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => anyhow::bail!("unknown digest algorithm: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl HashAlgorithm {
+    /// Name used in Rekor entries and search queries.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Name used in the protobuf-specs `HashAlgorithm` enum, which has no
+    /// entry for BLAKE3.
+    pub fn bundle_name(&self) -> Result<&'static str, anyhow::Error> {
+        match self {
+            HashAlgorithm::Sha256 => Ok("SHA2_256"),
+            HashAlgorithm::Sha384 => Ok("SHA2_384"),
+            HashAlgorithm::Sha512 => Ok("SHA2_512"),
+            HashAlgorithm::Blake3 => anyhow::bail!("bundles cannot carry blake3 digests"),
+        }
+    }
+
+    pub fn from_bundle_name(name: &str) -> Result<Self, anyhow::Error> {
+        match name {
+            "SHA2_256" => Ok(HashAlgorithm::Sha256),
+            "SHA2_384" => Ok(HashAlgorithm::Sha384),
+            "SHA2_512" => Ok(HashAlgorithm::Sha512),
+            _ => anyhow::bail!("unsupported bundle digest algorithm {}", name),
+        }
+    }
+
+    // openssl has no blake3
+    fn md(&self) -> Result<&'static MdRef, anyhow::Error> {
+        match self {
+            HashAlgorithm::Sha256 => Ok(Md::sha256()),
+            HashAlgorithm::Sha384 => Ok(Md::sha384()),
+            HashAlgorithm::Sha512 => Ok(Md::sha512()),
+            HashAlgorithm::Blake3 => anyhow::bail!("blake3 digests can only be signed with ECDSA"),
+        }
+    }
+
+    /// Length of the digest in bytes.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let digest = match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+            HashAlgorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+        };
+        Ok(digest)
+    }
+
+    /// Hashes everything read from `reader` in fixed size chunks, so memory
+    /// use does not depend on the amount of data.
+    pub fn digest_reader<R: Read>(&self, mut reader: R) -> Result<Vec<u8>, anyhow::Error> {
+        let mut hasher = Hasher::new(*self);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let count = reader.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+        Ok(hasher.finalize())
+    }
+
+    /// Hashes the file at `path` without reading it into memory.
+    pub fn digest_file(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        let file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("could not open {}: {}", path.display(), e))?;
+        self.digest_reader(file)
+    }
+
+    /// Hashes the files at `paths` on up to `concurrency` blocking threads,
+    /// returning the digests in the order of `paths`.
+    pub async fn digest_files(
+        &self,
+        paths: &[PathBuf],
+        concurrency: usize,
+        progress: Progress,
+    ) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let mut total = 0;
+        for path in paths {
+            total += std::fs::metadata(path)
+                .map_err(|e| anyhow::anyhow!("could not open {}: {}", path.display(), e))?
+                .len();
+        }
+        let bar = progress.bytes(total, "Hashing");
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = Vec::with_capacity(paths.len());
+        for path in paths {
+            let permit = permits.clone().acquire_owned().await?;
+            let algorithm = *self;
+            let path = path.clone();
+            let bar = bar.clone();
+            tasks.push(tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let file = File::open(&path)
+                    .map_err(|e| anyhow::anyhow!("could not open {}: {}", path.display(), e))?;
+                algorithm.digest_reader(bar.wrap_read(file))
+            }));
+        }
+        let mut digests = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            match task.await? {
+                Ok(digest) => digests.push(digest),
+                Err(e) => {
+                    bar.abandon();
+                    return Err(e);
+                }
+            }
+        }
+        bar.finish_and_clear();
+        Ok(digests)
+    }
+
+    pub fn hex_digest(&self, data: &[u8]) -> Result<String, anyhow::Error> {
+        Ok(HEXLOWER.encode(&self.digest(data)?))
+    }
+}
+
+/// Key type and signature scheme used for signing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SigningAlgorithm {
+    #[default]
+    EcdsaP256Sha256,
+    EcdsaP384Sha384,
+    Ed25519,
+    RsaPssSha256,
+}
+
+// incremental state for each digest algorithm
+enum Hasher {
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha384 => Hasher::Sha384(Sha384::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha384(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha384(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+impl FromStr for SigningAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ecdsa-p256" => Ok(SigningAlgorithm::EcdsaP256Sha256),
+            "ecdsa-p384" => Ok(SigningAlgorithm::EcdsaP384Sha384),
+            "ed25519" => Ok(SigningAlgorithm::Ed25519),
+            "rsa-pss" => Ok(SigningAlgorithm::RsaPssSha256),
+            _ => anyhow::bail!("unknown signing algorithm: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for SigningAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SigningAlgorithm::EcdsaP256Sha256 => "ecdsa-p256",
+            SigningAlgorithm::EcdsaP384Sha384 => "ecdsa-p384",
+            SigningAlgorithm::Ed25519 => "ed25519",
+            SigningAlgorithm::RsaPssSha256 => "rsa-pss",
+        };
+        f.write_str(name)
+    }
+}
+
+impl SigningAlgorithm {
+    /// The scheme signatures made with `key` use. RSA keys are assumed to
+    /// sign with PSS.
+    pub fn for_key<T: HasPublic>(key: &PKeyRef<T>) -> Result<Self, anyhow::Error> {
+        match key.id() {
+            Id::EC => match key.ec_key()?.group().curve_name() {
+                Some(Nid::X9_62_PRIME256V1) => Ok(SigningAlgorithm::EcdsaP256Sha256),
+                Some(Nid::SECP384R1) => Ok(SigningAlgorithm::EcdsaP384Sha384),
+                _ => anyhow::bail!("unsupported elliptic curve"),
+            },
+            Id::ED25519 => Ok(SigningAlgorithm::Ed25519),
+            Id::RSA => Ok(SigningAlgorithm::RsaPssSha256),
+            _ => anyhow::bail!("unsupported key type"),
+        }
+    }
+
+    /// Value of the `algorithm` field in Fulcio signing certificate requests.
+    pub fn fulcio_algorithm(&self) -> &'static str {
+        match self {
+            SigningAlgorithm::EcdsaP256Sha256 | SigningAlgorithm::EcdsaP384Sha384 => "ecdsa",
+            SigningAlgorithm::Ed25519 => "ed25519",
+            SigningAlgorithm::RsaPssSha256 => "rsa",
+        }
+    }
+
+    /// Digest signatures are made over, and that artifacts are logged with.
+    /// Ed25519 signs artifacts directly, they are logged by their sha256.
+    pub fn hash(&self) -> HashAlgorithm {
+        match self {
+            SigningAlgorithm::EcdsaP384Sha384 => HashAlgorithm::Sha384,
+            _ => HashAlgorithm::Sha256,
+        }
+    }
+
+    /// Whether signatures are made over a digest of the artifact, which is
+    /// what Rekor hashedrekord entries need.
+    pub fn is_prehashed(&self) -> bool {
+        *self != SigningAlgorithm::Ed25519
+    }
+
+    /// Generates a key pair, returning the private key and the PEM encoded
+    /// public key.
+    pub fn generate(&self) -> Result<(PKey<Private>, String), anyhow::Error> {
+        let private_key = match self {
+            SigningAlgorithm::EcdsaP256Sha256 => {
+                let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+                PKey::from_ec_key(EcKey::generate(&group)?)?
+            }
+            SigningAlgorithm::EcdsaP384Sha384 => {
+                let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+                PKey::from_ec_key(EcKey::generate(&group)?)?
+            }
+            SigningAlgorithm::Ed25519 => PKey::generate_ed25519()?,
+            SigningAlgorithm::RsaPssSha256 => PKey::from_rsa(Rsa::generate(3072)?)?,
+        };
+        let public_key_pem = String::from_utf8(private_key.public_key_to_pem()?)?;
+        Ok((private_key, public_key_pem))
+    }
+
+    /// Signs `data` with `key`, prehashed schemes use their own digest.
+    pub fn sign(&self, key: &PKeyRef<Private>, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        if !self.is_prehashed() {
+            return Ok(Signer::new_without_digest(key)?.sign_oneshot_to_vec(data)?);
+        }
+        let digest_algorithm = self.hash();
+        self.sign_digest(key, digest_algorithm, &digest_algorithm.digest(data)?)
+    }
+
+    /// Checks `signature` over `data` with `key`.
+    pub fn verify(
+        &self,
+        key: &PKeyRef<Public>,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, anyhow::Error> {
+        if !self.is_prehashed() {
+            return Ok(Verifier::new_without_digest(key)?.verify_oneshot(signature, data)?);
+        }
+        let digest_algorithm = self.hash();
+        self.verify_digest(
+            key,
+            digest_algorithm,
+            &digest_algorithm.digest(data)?,
+            signature,
+        )
+    }
+
+    /// Signs an artifact by its `digest`, computed with `digest_algorithm`.
+    /// ECDSA signs any digest as is, RSA-PSS needs one openssl knows and
+    /// Ed25519 cannot sign digests at all.
+    pub fn sign_digest(
+        &self,
+        key: &PKeyRef<Private>,
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            SigningAlgorithm::EcdsaP256Sha256 | SigningAlgorithm::EcdsaP384Sha384 => {
+                let ec_key = key.ec_key()?;
+                Ok(EcdsaSig::sign(digest, &ec_key)?.to_der()?)
+            }
+            SigningAlgorithm::RsaPssSha256 => {
+                let mut ctx = PkeyCtx::new(key)?;
+                ctx.sign_init()?;
+                ctx.set_rsa_padding(Padding::PKCS1_PSS)?;
+                ctx.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+                ctx.set_signature_md(digest_algorithm.md()?)?;
+                let mut signature = Vec::new();
+                ctx.sign_to_vec(digest, &mut signature)?;
+                Ok(signature)
+            }
+            SigningAlgorithm::Ed25519 => anyhow::bail!("ed25519 cannot sign a prehashed digest"),
+        }
+    }
+
+    /// Checks `signature` over an artifact with the given `digest`.
+    pub fn verify_digest(
+        &self,
+        key: &PKeyRef<Public>,
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, anyhow::Error> {
+        match self {
+            SigningAlgorithm::EcdsaP256Sha256 | SigningAlgorithm::EcdsaP384Sha384 => {
+                let ec_key = key.ec_key()?;
+                match EcdsaSig::from_der(signature) {
+                    Ok(signature) => Ok(signature.verify(digest, &ec_key)?),
+                    Err(_) => Ok(false),
+                }
+            }
+            SigningAlgorithm::RsaPssSha256 => {
+                let mut ctx = PkeyCtx::new(key)?;
+                ctx.verify_init()?;
+                ctx.set_rsa_padding(Padding::PKCS1_PSS)?;
+                ctx.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+                ctx.set_signature_md(digest_algorithm.md()?)?;
+                Ok(ctx.verify(digest, signature).unwrap_or(false))
+            }
+            SigningAlgorithm::Ed25519 => anyhow::bail!("ed25519 cannot verify a prehashed digest"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        for name in ["ecdsa-p256", "ecdsa-p384", "ed25519", "rsa-pss"] {
+            let algorithm: SigningAlgorithm = name.parse().unwrap();
+            assert_eq!(algorithm.to_string(), name);
+
+            let (private_key, public_key_pem) = algorithm.generate().unwrap();
+            let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+            assert_eq!(SigningAlgorithm::for_key(&public_key).unwrap(), algorithm);
+
+            let signature = algorithm.sign(&private_key, b"lolwut").unwrap();
+            assert!(algorithm
+                .verify(&public_key, b"lolwut", &signature)
+                .unwrap());
+            assert!(!algorithm.verify(&public_key, b"ohhai", &signature).unwrap());
+        }
+        assert!("dsa".parse::<SigningAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_hash_algorithm() {
+        let digest = HashAlgorithm::Sha256
+            .hex_digest(&std::fs::read("test_data/test_digest.txt").unwrap())
+            .unwrap();
+        assert_eq!(
+            digest,
+            "6c3b04483dacd643f7cd12086d817e0a9233a2192ba2030c64049d2952f198b5"
+        );
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha384,
+            HashAlgorithm::Sha512,
+        ] {
+            assert_eq!(
+                HashAlgorithm::from_bundle_name(algorithm.bundle_name().unwrap()).unwrap(),
+                algorithm
+            );
+            assert_eq!(
+                algorithm.name().parse::<HashAlgorithm>().unwrap(),
+                algorithm
+            );
+        }
+        assert!(HashAlgorithm::Blake3.bundle_name().is_err());
+        assert_eq!(HashAlgorithm::Blake3.digest(b"lolwut").unwrap().len(), 32);
+    }
+
+    #[tokio::test]
+    async fn test_digest_files() {
+        let paths = vec![PathBuf::from("test_data/test_digest.txt"); 5];
+        let digests = HashAlgorithm::Sha256
+            .digest_files(&paths, 2, Progress::default())
+            .await
+            .unwrap();
+        assert_eq!(digests.len(), 5);
+        for digest in digests {
+            assert_eq!(
+                HEXLOWER.encode(&digest),
+                "6c3b04483dacd643f7cd12086d817e0a9233a2192ba2030c64049d2952f198b5"
+            );
+        }
+        let paths = vec![PathBuf::from("test_data/does_not_exist")];
+        assert!(HashAlgorithm::Sha256
+            .digest_files(&paths, 2, Progress::default())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_digest_reader() {
+        // spans several reads
+        let data = vec![0x2a; 200 * 1024];
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha384,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            let digest = algorithm.digest_reader(&data[..]).unwrap();
+            assert_eq!(digest, algorithm.digest(&data).unwrap());
+            assert_eq!(digest.len(), algorithm.digest_len());
+        }
+        assert_eq!(
+            HashAlgorithm::Sha256
+                .digest_file(Path::new("test_data/test_digest.txt"))
+                .unwrap(),
+            HashAlgorithm::Sha256
+                .digest(&std::fs::read("test_data/test_digest.txt").unwrap())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sign_digest() {
+        let data = b"lolwut";
+        for name in ["ecdsa-p256", "ecdsa-p384", "rsa-pss"] {
+            let algorithm: SigningAlgorithm = name.parse().unwrap();
+            let (private_key, public_key_pem) = algorithm.generate().unwrap();
+            let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+
+            // signing a digest is the same as signing the data with that digest
+            let signature = algorithm.sign(&private_key, data).unwrap();
+            let digest = algorithm.hash().digest(data).unwrap();
+            assert!(algorithm
+                .verify_digest(&public_key, algorithm.hash(), &digest, &signature)
+                .unwrap());
+
+            let digest = HashAlgorithm::Sha512.digest(data).unwrap();
+            let signature = algorithm
+                .sign_digest(&private_key, HashAlgorithm::Sha512, &digest)
+                .unwrap();
+            assert!(algorithm
+                .verify_digest(&public_key, HashAlgorithm::Sha512, &digest, &signature)
+                .unwrap());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Ambient OIDC credentials provided by CI systems.
+
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+use crate::http::{self, Service};
+use crate::oauth::{IdentityToken, SIGSTORE_AUDIENCE};
+use crate::spiffe;
+
+/// A CI system that can hand out identity tokens without user interaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHubActions,
+    GitLabCi,
+    CircleCi,
+    Buildkite,
+    Kubernetes,
+    Spiffe,
+}
+
+#[derive(Deserialize)]
+struct GitHubTokenResponse {
+    value: String,
+}
+
+impl Provider {
+    /// Providers in the order they are tried.
+    pub const ALL: &'static [Provider] = &[
+        Provider::GitHubActions,
+        Provider::GitLabCi,
+        Provider::CircleCi,
+        Provider::Buildkite,
+        Provider::Kubernetes,
+        Provider::Spiffe,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::GitHubActions => "GitHub Actions",
+            Provider::GitLabCi => "GitLab CI",
+            Provider::CircleCi => "CircleCI",
+            Provider::Buildkite => "Buildkite",
+            Provider::Kubernetes => "Kubernetes",
+            Provider::Spiffe => "the SPIFFE Workload API",
+        }
+    }
+
+    /// Whether the environment looks like this provider.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Provider::GitHubActions => {
+                env::var_os("ACTIONS_ID_TOKEN_REQUEST_URL").is_some()
+                    && env::var_os("ACTIONS_ID_TOKEN_REQUEST_TOKEN").is_some()
+            }
+            Provider::GitLabCi => {
+                env::var_os("GITLAB_CI").is_some()
+                    && GITLAB_TOKEN_VARS.iter().any(|v| env::var_os(v).is_some())
+            }
+            Provider::CircleCi => env::var_os("CIRCLECI").is_some(),
+            Provider::Buildkite => env::var_os("BUILDKITE").is_some(),
+            Provider::Kubernetes => {
+                (env::var_os(KUBERNETES_TOKEN_PATH_VAR).is_some()
+                    || env::var_os("KUBERNETES_SERVICE_HOST").is_some())
+                    && kubernetes_token_path().is_file()
+            }
+            Provider::Spiffe => env::var_os(spiffe::ENDPOINT_SOCKET_VAR).is_some(),
+        }
+    }
+
+    /// The issuer of this provider's tokens, if it is known up front.
+    /// Tokens are only checked against the keys of a known issuer, never
+    /// against those of the issuer they name.
+    pub fn issuer(&self) -> Option<String> {
+        match self {
+            Provider::GitHubActions => {
+                Some("https://token.actions.githubusercontent.com".to_string())
+            }
+            // self-managed instances issue their own
+            Provider::GitLabCi => {
+                Some(env::var("CI_SERVER_URL").unwrap_or_else(|_| "https://gitlab.com".to_string()))
+            }
+            Provider::CircleCi => env::var("CIRCLE_ORGANIZATION_ID")
+                .ok()
+                .map(|id| format!("https://oidc.circleci.com/org/{}", id)),
+            Provider::Buildkite => Some("https://agent.buildkite.com".to_string()),
+            // every cluster and trust domain has its own
+            Provider::Kubernetes | Provider::Spiffe => None,
+        }
+    }
+
+    /// Obtains a raw identity token for the sigstore audience.
+    pub async fn token(&self) -> Result<String, anyhow::Error> {
+        match self {
+            Provider::GitHubActions => {
+                let url = env::var("ACTIONS_ID_TOKEN_REQUEST_URL")?;
+                let request_token = env::var("ACTIONS_ID_TOKEN_REQUEST_TOKEN")?;
+                let mut url = url::Url::parse(&url)?;
+                url.query_pairs_mut()
+                    .append_pair("audience", SIGSTORE_AUDIENCE);
+
+                let client = http::client(Service::Oidc);
+                let response: GitHubTokenResponse = http::send(
+                    client
+                        .get(url)
+                        .header("Authorization", format!("bearer {}", request_token)),
+                )
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+                Ok(response.value)
+            }
+            Provider::GitLabCi => GITLAB_TOKEN_VARS
+                .iter()
+                .find_map(|v| env::var(v).ok())
+                .ok_or_else(|| anyhow::anyhow!("no GitLab CI identity token variable set")),
+            Provider::CircleCi => {
+                let claims = format!(r#"{{"aud":"{}"}}"#, SIGSTORE_AUDIENCE);
+                run_token_command("circleci", &["run", "oidc", "get", "--claims", &claims]).await
+            }
+            Provider::Buildkite => {
+                run_token_command(
+                    "buildkite-agent",
+                    &["oidc", "request-token", "--audience", SIGSTORE_AUDIENCE],
+                )
+                .await
+            }
+            // the kubelet rotates the token, so it is read afresh every time
+            Provider::Kubernetes => Ok(tokio::fs::read_to_string(kubernetes_token_path())
+                .await?
+                .trim()
+                .to_string()),
+            Provider::Spiffe => {
+                let address = env::var(spiffe::ENDPOINT_SOCKET_VAR)?;
+                // spire holds the call until the workload is attested
+                let fetch = spiffe::fetch_jwt_svid(&address, SIGSTORE_AUDIENCE);
+                let svid = match http::timeout(Service::Oidc) {
+                    Some(limit) => tokio::time::timeout(limit, fetch).await.map_err(|_| {
+                        anyhow::anyhow!("the Workload API did not respond within {:?}", limit)
+                    })??,
+                    None => fetch.await?,
+                };
+                tracing::debug!("Workload API issued a JWT-SVID for {}", svid.spiffe_id);
+                Ok(svid.token)
+            }
+        }
+    }
+}
+
+/// Where a projected service account token for the sigstore audience is
+/// mounted, unless `FERRIS_SIGN_KUBERNETES_TOKEN_PATH` says otherwise:
+///
+/// ```yaml
+/// volumes:
+///   - name: oidc-info
+///     projected:
+///       sources:
+///         - serviceAccountToken:
+///             path: oidc-token
+///             expirationSeconds: 600
+///             audience: sigstore
+/// ```
+///
+/// mounted at `/var/run/sigstore/cosign`, the path cosign reads too. The
+/// default service account token is for the API server and is not used.
+pub const KUBERNETES_TOKEN_PATH: &str = "/var/run/sigstore/cosign/oidc-token";
+
+/// Environment variable overriding [`KUBERNETES_TOKEN_PATH`].
+pub const KUBERNETES_TOKEN_PATH_VAR: &str = "FERRIS_SIGN_KUBERNETES_TOKEN_PATH";
+
+fn kubernetes_token_path() -> PathBuf {
+    env::var_os(KUBERNETES_TOKEN_PATH_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(KUBERNETES_TOKEN_PATH))
+}
+
+// gitlab hands out tokens through variables named in the job's id_tokens
+// section, SIGSTORE_ID_TOKEN is the name sigstore clients agree on
+const GITLAB_TOKEN_VARS: &[&str] = &["SIGSTORE_ID_TOKEN", "CI_JOB_JWT_V2"];
+
+// circleci and buildkite mint tokens through their agent CLIs
+async fn run_token_command(program: &str, args: &[&str]) -> Result<String, anyhow::Error> {
+    let output = Command::new(program).args(args).output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed to issue an identity token: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Tries every provider whose environment is present, returning the first
+/// identity token obtained.
+pub async fn detect() -> Result<Option<(Provider, IdentityToken)>, anyhow::Error> {
+    for provider in Provider::ALL {
+        if provider.is_available() {
+            let token = provider.token().await?;
+            return Ok(Some((*provider, IdentityToken::from_jwt(&token)?)));
+        }
+    }
+    Ok(None)
+}
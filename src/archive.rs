@@ -0,0 +1,287 @@
+//! Bundles kept inside the tar or zip archive they sign, so that a release
+//! can be shipped as one self-contained file.
+//!
+//! Tar archives get a last member [`TAR_MEMBER`] and zip archives carry the
+//! bundle as their comment. Either way the signature is over the archive
+//! without the bundle: the entries up to the bundle member for tar, and
+//! the archive with an empty comment for zip, which for a zip that had no
+//! comment is the file as it was before signing.
+
+const BLOCK: usize = 512;
+const ZIP_LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+const ZIP_END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+const ZIP_END_LEN: usize = 22;
+
+/// Name of the tar member holding the bundle.
+pub const TAR_MEMBER: &str = ".sigstore/bundle.sigstore.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// An uncompressed ustar or GNU tar archive.
+    Tar,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Tells the archive type by its magic bytes.
+    pub fn detect(archive: &[u8]) -> Result<Self, anyhow::Error> {
+        if archive.starts_with(ZIP_LOCAL_HEADER)
+            || archive.starts_with(ZIP_END_OF_CENTRAL_DIRECTORY)
+        {
+            return Ok(ArchiveKind::Zip);
+        }
+        if archive.len() >= BLOCK && archive[257..].starts_with(b"ustar") {
+            return Ok(ArchiveKind::Tar);
+        }
+        anyhow::bail!("not an uncompressed tar or a zip archive")
+    }
+}
+
+/// An archive taken apart into what is signed and the embedded bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embedded<'a> {
+    pub kind: ArchiveKind,
+    /// The bytes the signature is made over.
+    pub content: Vec<u8>,
+    pub bundle: Option<&'a [u8]>,
+}
+
+/// Separates the embedded bundle, if any, from the signed content.
+pub fn split(archive: &[u8]) -> Result<Embedded<'_>, anyhow::Error> {
+    match ArchiveKind::detect(archive)? {
+        ArchiveKind::Tar => split_tar(archive),
+        ArchiveKind::Zip => split_zip(archive),
+    }
+}
+
+/// Returns `archive` with `bundle` embedded, in place of any bundle it
+/// already had.
+pub fn embed(archive: &[u8], bundle: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let Embedded { kind, content, .. } = split(archive)?;
+    match kind {
+        ArchiveKind::Tar => {
+            let mut out = content;
+            out.extend_from_slice(&tar_header(TAR_MEMBER, bundle.len())?);
+            out.extend_from_slice(bundle);
+            out.resize(padded(out.len()), 0);
+            // the end of archive marker
+            out.resize(out.len() + 2 * BLOCK, 0);
+            Ok(out)
+        }
+        ArchiveKind::Zip => {
+            let len = u16::try_from(bundle.len())
+                .map_err(|_| anyhow::anyhow!("bundle is too large for a zip comment"))?;
+            let mut out = content;
+            let at = out.len() - 2;
+            out[at..].copy_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(bundle);
+            Ok(out)
+        }
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.next_multiple_of(BLOCK)
+}
+
+fn split_tar(archive: &[u8]) -> Result<Embedded<'_>, anyhow::Error> {
+    let mut offset = 0;
+    let mut bundle = None;
+    loop {
+        let header = archive
+            .get(offset..offset + BLOCK)
+            .ok_or_else(|| anyhow::anyhow!("tar archive is truncated"))?;
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        if bundle.is_some() {
+            anyhow::bail!("{} is not the last member of the archive", TAR_MEMBER);
+        }
+        let size = usize::try_from(tar_number(&header[124..136])?)?;
+        let data = offset + BLOCK;
+        let data_end = data
+            .checked_add(size)
+            .filter(|end| *end <= archive.len())
+            .ok_or_else(|| anyhow::anyhow!("tar archive is truncated"))?;
+        if tar_name(header) == TAR_MEMBER {
+            bundle = Some((offset, &archive[data..data_end]));
+        }
+        offset = data + padded(size);
+    }
+    let (end, bundle) = match bundle {
+        Some((start, bundle)) => (start, Some(bundle)),
+        None => (offset, None),
+    };
+    Ok(Embedded {
+        kind: ArchiveKind::Tar,
+        content: archive[..end].to_vec(),
+        bundle,
+    })
+}
+
+// ustar splits long names into a prefix and a name
+fn tar_name(header: &[u8]) -> String {
+    let field = |bytes: &[u8]| {
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+    let name = field(&header[..100]);
+    let prefix = field(&header[345..500]);
+    if header[257..].starts_with(b"ustar\0") && !prefix.is_empty() {
+        format!("{}/{}", prefix, name)
+    } else {
+        name
+    }
+}
+
+// octal, or big endian base-256 with the high bit set for GNU large files
+fn tar_number(field: &[u8]) -> Result<u64, anyhow::Error> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |value, byte| {
+                (value << 8) | u64::from(*byte)
+            }));
+    }
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| anyhow::anyhow!("invalid tar number {}", digits))
+}
+
+fn tar_header(name: &str, size: usize) -> Result<[u8; BLOCK], anyhow::Error> {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    let size = format!("{:011o}\0", size);
+    if size.len() != 12 {
+        anyhow::bail!("bundle is too large for a tar member");
+    }
+    header[124..136].copy_from_slice(size.as_bytes());
+    // no modification time, for the same bundle to give the same archive
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // the checksum is summed with its own field as spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+fn split_zip(archive: &[u8]) -> Result<Embedded<'_>, anyhow::Error> {
+    // the end of central directory record is followed only by its comment,
+    // which is at most 64 KiB
+    let last = archive
+        .len()
+        .checked_sub(ZIP_END_LEN)
+        .ok_or_else(|| anyhow::anyhow!("zip archive is truncated"))?;
+    let end = (last.saturating_sub(usize::from(u16::MAX))..=last)
+        .rev()
+        .find(|offset| {
+            let record = &archive[*offset..];
+            record.starts_with(ZIP_END_OF_CENTRAL_DIRECTORY)
+                && ZIP_END_LEN + usize::from(u16::from_le_bytes([record[20], record[21]]))
+                    == record.len()
+        })
+        .ok_or_else(|| anyhow::anyhow!("zip archive has no end of central directory"))?;
+    let comment = &archive[end + ZIP_END_LEN..];
+    if !comment.is_empty() && !comment.starts_with(b"{") {
+        anyhow::bail!("zip archive already has a comment that is not a bundle");
+    }
+    let mut content = archive[..end + ZIP_END_LEN].to_vec();
+    content[end + 20..].copy_from_slice(&[0, 0]);
+    Ok(Embedded {
+        kind: ArchiveKind::Zip,
+        content,
+        bundle: Some(comment).filter(|comment| !comment.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, data) in members {
+            out.extend_from_slice(&tar_header(name, data.len()).unwrap());
+            out.extend_from_slice(data);
+            out.resize(padded(out.len()), 0);
+        }
+        out.resize(out.len() + 20 * BLOCK, 0);
+        out
+    }
+
+    #[test]
+    fn test_tar() {
+        let archive = tar(&[("README.md", &b"hello"[..]), ("src/lib.rs", &b""[..])]);
+        let split = split(&archive).unwrap();
+        assert_eq!(split.kind, ArchiveKind::Tar);
+        assert_eq!(split.content, archive[..3 * BLOCK]);
+        assert_eq!(split.bundle, None);
+
+        let embedded = embed(&archive, b"{}").unwrap();
+        let split = super::split(&embedded).unwrap();
+        assert_eq!(split.content, archive[..3 * BLOCK]);
+        assert_eq!(split.bundle, Some(&b"{}"[..]));
+        // signing again replaces the bundle
+        let again = embed(&embedded, b"{\"a\":1}").unwrap();
+        assert_eq!(
+            super::split(&again).unwrap().bundle,
+            Some(&b"{\"a\":1}"[..])
+        );
+        assert_eq!(again.len(), embedded.len());
+
+        let appended = tar(&[
+            ("README.md", &b"hello"[..]),
+            (TAR_MEMBER, &b"{}"[..]),
+            ("evil", &b""[..]),
+        ]);
+        assert!(super::split(&appended).is_err());
+        assert!(super::split(&archive[..BLOCK + 2]).is_err());
+    }
+
+    #[test]
+    fn test_tar_number() {
+        assert_eq!(tar_number(b"00000000012\0").unwrap(), 10);
+        assert_eq!(tar_number(b"     12 \0\0\0\0").unwrap(), 10);
+        assert_eq!(
+            tar_number(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]).unwrap(),
+            256
+        );
+        assert!(tar_number(b"00000000089\0").is_err());
+    }
+
+    #[test]
+    fn test_zip() {
+        // an empty zip is only its end of central directory record
+        let mut archive = ZIP_END_OF_CENTRAL_DIRECTORY.to_vec();
+        archive.resize(ZIP_END_LEN, 0);
+        let split = split(&archive).unwrap();
+        assert_eq!(split.kind, ArchiveKind::Zip);
+        assert_eq!(split.content, archive);
+        assert_eq!(split.bundle, None);
+
+        let embedded = embed(&archive, b"{}").unwrap();
+        assert_eq!(&embedded[20..], b"\x02\x00{}");
+        let split = super::split(&embedded).unwrap();
+        assert_eq!(split.content, archive);
+        assert_eq!(split.bundle, Some(&b"{}"[..]));
+
+        let mut commented = archive.clone();
+        commented[20] = 2;
+        commented.extend_from_slice(b"hi");
+        assert!(super::split(&commented).is_err());
+        assert!(embed(&archive, &[b' '; 70000]).is_err());
+        assert!(ArchiveKind::detect(b"\x1f\x8b").is_err());
+    }
+}
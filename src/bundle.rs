@@ -0,0 +1,911 @@
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+
+use crate::algorithm::HashAlgorithm;
+use crate::dsse::Envelope;
+use crate::merkle;
+use crate::protobuf::{self, Writer};
+use crate::rekor_api::LogEntry;
+
+/// Bundle carrying an inclusion promise only.
+pub const BUNDLE_V01_MEDIA_TYPE: &str = "application/vnd.dev.sigstore.bundle+json;version=0.1";
+/// Bundle carrying an inclusion proof.
+pub const BUNDLE_V02_MEDIA_TYPE: &str = "application/vnd.dev.sigstore.bundle+json;version=0.2";
+/// Bundle carrying an inclusion proof and only the leaf certificate.
+pub const BUNDLE_V03_MEDIA_TYPE: &str = "application/vnd.dev.sigstore.bundle.v0.3+json";
+
+/// A Sigstore bundle in the protobuf-specs JSON encoding. Byte fields are
+/// base64 encoded and 64 bit integers are encoded as strings. The binary
+/// protobuf encoding is read and written with [`Bundle::from_protobuf`] and
+/// [`Bundle::to_protobuf`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub media_type: String,
+    pub verification_material: VerificationMaterial,
+    pub message_signature: MessageSignature,
+    /// Signatures over the message signature by other signers, each a bundle
+    /// of its own. Not part of the Sigstore bundle format, they are only
+    /// kept in the JSON encoding.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub countersignatures: Vec<Bundle>,
+    /// Signed claim of when the signature expires, see [`crate::expiry`].
+    /// Like countersignatures only kept in the JSON encoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<Envelope>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMaterial {
+    /// The signing certificate followed by its chain, up to version 0.2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x509_certificate_chain: Option<X509CertificateChain>,
+    /// The signing certificate alone, from version 0.3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<X509Certificate>,
+    #[serde(default)]
+    pub tlog_entries: Vec<TransparencyLogEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_verification_data: Option<TimestampVerificationData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimestampVerificationData {
+    #[serde(default)]
+    pub rfc3161_timestamps: Vec<Rfc3161SignedTimestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rfc3161SignedTimestamp {
+    /// Base64 encoded DER timestamp token.
+    pub signed_timestamp: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct X509CertificateChain {
+    pub certificates: Vec<X509Certificate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct X509Certificate {
+    pub raw_bytes: String,
+}
+
+// proto3 json leaves out zero values, v2 logs never set the integrated time
+fn zero() -> String {
+    String::from("0")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparencyLogEntry {
+    #[serde(default = "zero")]
+    pub log_index: String,
+    pub log_id: LogId,
+    pub kind_version: KindVersion,
+    #[serde(default = "zero")]
+    pub integrated_time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_promise: Option<InclusionPromise>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<InclusionProof>,
+    pub canonicalized_body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogId {
+    pub key_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KindVersion {
+    pub kind: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionPromise {
+    pub signed_entry_timestamp: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProof {
+    #[serde(default = "zero")]
+    pub log_index: String,
+    pub root_hash: String,
+    #[serde(default = "zero")]
+    pub tree_size: String,
+    pub hashes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checkpoint {
+    pub envelope: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSignature {
+    pub message_digest: HashOutput,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashOutput {
+    pub algorithm: String,
+    pub digest: String,
+}
+
+// rekor hex encodes hashes and log ids, the bundle wants base64
+fn hex_to_base64(hex: &str) -> Result<String, anyhow::Error> {
+    Ok(base64::encode(HEXLOWER_PERMISSIVE.decode(hex.as_bytes())?))
+}
+
+impl TransparencyLogEntry {
+    /// Converts a Rekor API log entry into its bundle representation.
+    pub fn from_log_entry(log_entry: &LogEntry) -> Result<Self, anyhow::Error> {
+        let (kind, version, _) = log_entry.decode_body()?;
+        let verification = log_entry.verification.as_ref();
+
+        let inclusion_promise = verification
+            .and_then(|v| v.signed_entry_timestamp.as_ref())
+            .map(|set| InclusionPromise {
+                signed_entry_timestamp: set.clone(),
+            });
+        let inclusion_proof = match verification.and_then(|v| v.inclusion_proof.as_ref()) {
+            Some(proof) => Some(InclusionProof {
+                log_index: proof.log_index.to_string(),
+                root_hash: hex_to_base64(&proof.root_hash)?,
+                tree_size: proof.tree_size.to_string(),
+                hashes: proof
+                    .hashes
+                    .iter()
+                    .map(|h| hex_to_base64(h))
+                    .collect::<Result<_, _>>()?,
+                checkpoint: proof.checkpoint.as_ref().map(|c| Checkpoint {
+                    envelope: c.clone(),
+                }),
+            }),
+            None => None,
+        };
+
+        Ok(TransparencyLogEntry {
+            log_index: log_entry.log_index.to_string(),
+            log_id: LogId {
+                key_id: hex_to_base64(&log_entry.log_id)?,
+            },
+            kind_version: KindVersion { kind, version },
+            integrated_time: log_entry.integrated_time.to_string(),
+            inclusion_promise,
+            inclusion_proof,
+            canonicalized_body: log_entry.body.clone(),
+        })
+    }
+
+    /// UUID Rekor looks the entry up by, the hex encoded leaf hash of its
+    /// body.
+    pub fn uuid(&self) -> Result<String, anyhow::Error> {
+        let body = base64::decode(&self.canonicalized_body)?;
+        Ok(HEXLOWER.encode(&merkle::leaf_hash(&body)))
+    }
+
+    /// The digest of the artifact a hashedrekord entry records, and its
+    /// algorithm.
+    pub fn artifact_digest(&self) -> Result<(HashAlgorithm, Vec<u8>), anyhow::Error> {
+        let body: serde_json::Value =
+            serde_json::from_slice(&base64::decode(&self.canonicalized_body)?)?;
+        // 0.0.2 encodes the digest as bundles do
+        if self.kind_version.version == "0.0.2" {
+            let data = &body["spec"]["hashedRekordV002"]["data"];
+            return Ok((
+                HashAlgorithm::from_bundle_name(data["algorithm"].as_str().unwrap_or_default())?,
+                base64::decode(data["digest"].as_str().unwrap_or_default())?,
+            ));
+        }
+        let hash = &body["spec"]["data"]["hash"];
+        Ok((
+            hash["algorithm"].as_str().unwrap_or_default().parse()?,
+            HEXLOWER_PERMISSIVE.decode(hash["value"].as_str().unwrap_or_default().as_bytes())?,
+        ))
+    }
+}
+
+impl Bundle {
+    /// Builds a bundle for a signature over an artifact with the given
+    /// `digest`, made with the key in the DER encoded `cert`. Without a
+    /// `log_entry` the bundle carries no transparency log entry at all.
+    pub fn new(
+        signature: &[u8],
+        cert: &[u8],
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+        log_entry: Option<&LogEntry>,
+    ) -> Result<Self, anyhow::Error> {
+        let tlog_entries = log_entry
+            .map(TransparencyLogEntry::from_log_entry)
+            .transpose()?
+            .into_iter()
+            .collect::<Vec<_>>();
+        // only 0.1 bundles can make do with an inclusion promise
+        let media_type = match tlog_entries.first() {
+            Some(entry) if entry.inclusion_proof.is_none() => BUNDLE_V01_MEDIA_TYPE,
+            _ => BUNDLE_V02_MEDIA_TYPE,
+        };
+
+        Ok(Bundle {
+            media_type: media_type.to_string(),
+            verification_material: VerificationMaterial {
+                x509_certificate_chain: Some(X509CertificateChain {
+                    certificates: vec![X509Certificate {
+                        raw_bytes: base64::encode(cert),
+                    }],
+                }),
+                certificate: None,
+                tlog_entries,
+                timestamp_verification_data: None,
+            },
+            message_signature: MessageSignature {
+                message_digest: HashOutput {
+                    algorithm: digest_algorithm.bundle_name()?.to_string(),
+                    digest: base64::encode(digest),
+                },
+                signature: base64::encode(signature),
+            },
+            countersignatures: Vec::new(),
+            expiry: None,
+        })
+    }
+
+    /// Adds a DER encoded RFC 3161 timestamp token over the signature.
+    pub fn add_timestamp(&mut self, token: &[u8]) {
+        self.verification_material
+            .timestamp_verification_data
+            .get_or_insert(TimestampVerificationData {
+                rfc3161_timestamps: Vec::new(),
+            })
+            .rfc3161_timestamps
+            .push(Rfc3161SignedTimestamp {
+                signed_timestamp: base64::encode(token),
+            });
+    }
+
+    /// The signature the bundle carries, which countersignatures are made
+    /// over.
+    pub fn signature(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(base64::decode(&self.message_signature.signature)?)
+    }
+
+    /// Appends `countersignature`, a bundle for a signature over
+    /// [`Bundle::signature`].
+    pub fn add_countersignature(&mut self, countersignature: Bundle) {
+        self.countersignatures.push(countersignature);
+    }
+
+    /// The DER encoded RFC 3161 timestamp tokens in the bundle.
+    pub fn timestamps(&self) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let timestamps = match &self.verification_material.timestamp_verification_data {
+            Some(data) => &data.rfc3161_timestamps,
+            None => return Ok(Vec::new()),
+        };
+        timestamps
+            .iter()
+            .map(|timestamp| Ok(base64::decode(&timestamp.signed_timestamp)?))
+            .collect()
+    }
+
+    /// The leaf certificate the bundle was signed with.
+    pub fn signing_cert(&self) -> Result<X509, anyhow::Error> {
+        let material = &self.verification_material;
+        let leaf = material
+            .certificate
+            .as_ref()
+            .or_else(|| {
+                material
+                    .x509_certificate_chain
+                    .as_ref()
+                    .and_then(|chain| chain.certificates.first())
+            })
+            .ok_or_else(|| anyhow::anyhow!("bundle has no signing certificate"))?;
+        Ok(X509::from_der(&base64::decode(&leaf.raw_bytes)?)?)
+    }
+
+    /// The same bundle as version 0.3, which requires an inclusion proof for
+    /// every log entry and carries the signing certificate without a chain.
+    pub fn to_v03(&self) -> Result<Self, anyhow::Error> {
+        let material = &self.verification_material;
+        if material
+            .tlog_entries
+            .iter()
+            .any(|entry| entry.inclusion_proof.is_none())
+        {
+            anyhow::bail!("version 0.3 bundles need an inclusion proof for every log entry");
+        }
+        let leaf = X509Certificate {
+            raw_bytes: base64::encode(self.signing_cert()?.to_der()?),
+        };
+        let mut bundle = self.clone();
+        bundle.media_type = BUNDLE_V03_MEDIA_TYPE.to_string();
+        bundle.verification_material.x509_certificate_chain = None;
+        bundle.verification_material.certificate = Some(leaf);
+        Ok(bundle)
+    }
+
+    fn check_media_type(&self) -> Result<(), anyhow::Error> {
+        if !self
+            .media_type
+            .starts_with("application/vnd.dev.sigstore.bundle")
+        {
+            anyhow::bail!("{} is not a bundle", self.media_type);
+        }
+        Ok(())
+    }
+
+    /// Parses a bundle in either the JSON or the binary protobuf encoding.
+    pub fn parse(data: &[u8]) -> Result<Self, anyhow::Error> {
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Bundle::from_json(data),
+            _ => Bundle::from_protobuf(data),
+        }
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        let bundle: Bundle = serde_json::from_slice(json)?;
+        bundle.check_media_type()?;
+        Ok(bundle)
+    }
+
+    pub fn from_protobuf(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let bundle = Bundle::decode(data)?;
+        bundle.check_media_type()?;
+        Ok(bundle)
+    }
+
+    pub fn to_protobuf(&self) -> Result<Vec<u8>, anyhow::Error> {
+        if !self.countersignatures.is_empty() {
+            anyhow::bail!("countersignatures can only be kept in JSON bundles");
+        }
+        if self.expiry.is_some() {
+            anyhow::bail!("expiration claims can only be kept in JSON bundles");
+        }
+        self.encode()
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// the binary encoding of the protobuf-specs messages, with the field
+// numbers of sigstore_bundle.proto, sigstore_rekor.proto and
+// sigstore_common.proto. unknown fields are skipped, as when parsing json
+trait Message: Sized {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error>;
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error>;
+}
+
+// protobuf-specs HashAlgorithm enum values
+const HASH_ALGORITHMS: [(&str, u64); 3] = [("SHA2_256", 1), ("SHA2_384", 2), ("SHA2_512", 3)];
+
+fn int64(value: &str) -> Result<u64, anyhow::Error> {
+    // negative int64 values are sent as their two's complement
+    Ok(value.parse::<i64>()? as u64)
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(base64::decode(value)?)
+}
+
+impl Message for Bundle {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(Writer::new()
+            .string(1, &self.media_type)
+            .message(2, &self.verification_material.encode()?)
+            .message(3, &self.message_signature.encode()?)
+            .finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut media_type = String::new();
+        let mut verification_material = None;
+        let mut message_signature = None;
+        for field in protobuf::fields(data)? {
+            match field.number {
+                1 => media_type = field.string()?.to_string(),
+                2 => verification_material = Some(VerificationMaterial::decode(field.bytes()?)?),
+                3 => message_signature = Some(MessageSignature::decode(field.bytes()?)?),
+                4 => anyhow::bail!("bundles with a DSSE envelope are not supported"),
+                _ => {}
+            }
+        }
+        Ok(Bundle {
+            media_type,
+            verification_material: verification_material
+                .ok_or_else(|| anyhow::anyhow!("bundle has no verification material"))?,
+            message_signature: message_signature
+                .ok_or_else(|| anyhow::anyhow!("bundle has no message signature"))?,
+            countersignatures: Vec::new(),
+            expiry: None,
+        })
+    }
+}
+
+impl Message for VerificationMaterial {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut writer = Writer::new();
+        if let Some(chain) = &self.x509_certificate_chain {
+            writer = writer.message(2, &chain.encode()?);
+        }
+        for entry in &self.tlog_entries {
+            writer = writer.message(3, &entry.encode()?);
+        }
+        if let Some(data) = &self.timestamp_verification_data {
+            writer = writer.message(4, &data.encode()?);
+        }
+        if let Some(certificate) = &self.certificate {
+            writer = writer.message(5, &certificate.encode()?);
+        }
+        Ok(writer.finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut material = VerificationMaterial {
+            x509_certificate_chain: None,
+            certificate: None,
+            tlog_entries: Vec::new(),
+            timestamp_verification_data: None,
+        };
+        for field in protobuf::fields(data)? {
+            match field.number {
+                2 => {
+                    material.x509_certificate_chain =
+                        Some(X509CertificateChain::decode(field.bytes()?)?)
+                }
+                3 => material
+                    .tlog_entries
+                    .push(TransparencyLogEntry::decode(field.bytes()?)?),
+                4 => {
+                    material.timestamp_verification_data =
+                        Some(TimestampVerificationData::decode(field.bytes()?)?)
+                }
+                5 => material.certificate = Some(X509Certificate::decode(field.bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(material)
+    }
+}
+
+impl Message for TimestampVerificationData {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut writer = Writer::new();
+        for timestamp in &self.rfc3161_timestamps {
+            let timestamp = Writer::new()
+                .bytes(1, &decode_base64(&timestamp.signed_timestamp)?)
+                .finish();
+            writer = writer.message(1, &timestamp);
+        }
+        Ok(writer.finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut rfc3161_timestamps = Vec::new();
+        for field in protobuf::fields(data)? {
+            if field.number != 1 {
+                continue;
+            }
+            let mut signed_timestamp = String::new();
+            for field in protobuf::fields(field.bytes()?)? {
+                if field.number == 1 {
+                    signed_timestamp = base64::encode(field.bytes()?);
+                }
+            }
+            rfc3161_timestamps.push(Rfc3161SignedTimestamp { signed_timestamp });
+        }
+        Ok(TimestampVerificationData { rfc3161_timestamps })
+    }
+}
+
+impl Message for X509CertificateChain {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut writer = Writer::new();
+        for certificate in &self.certificates {
+            writer = writer.message(1, &certificate.encode()?);
+        }
+        Ok(writer.finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let certificates = protobuf::fields(data)?
+            .iter()
+            .filter(|field| field.number == 1)
+            .map(|field| X509Certificate::decode(field.bytes()?))
+            .collect::<Result<_, _>>()?;
+        Ok(X509CertificateChain { certificates })
+    }
+}
+
+impl Message for X509Certificate {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(Writer::new()
+            .bytes(1, &decode_base64(&self.raw_bytes)?)
+            .finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut raw_bytes = String::new();
+        for field in protobuf::fields(data)? {
+            if field.number == 1 {
+                raw_bytes = base64::encode(field.bytes()?);
+            }
+        }
+        Ok(X509Certificate { raw_bytes })
+    }
+}
+
+impl Message for TransparencyLogEntry {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let log_id = Writer::new()
+            .bytes(1, &decode_base64(&self.log_id.key_id)?)
+            .finish();
+        let kind_version = Writer::new()
+            .string(1, &self.kind_version.kind)
+            .string(2, &self.kind_version.version)
+            .finish();
+        let mut writer = Writer::new()
+            .varint(1, int64(&self.log_index)?)
+            .message(2, &log_id)
+            .message(3, &kind_version)
+            .varint(4, int64(&self.integrated_time)?);
+        if let Some(promise) = &self.inclusion_promise {
+            let promise = Writer::new()
+                .bytes(1, &decode_base64(&promise.signed_entry_timestamp)?)
+                .finish();
+            writer = writer.message(5, &promise);
+        }
+        if let Some(proof) = &self.inclusion_proof {
+            writer = writer.message(6, &proof.encode()?);
+        }
+        Ok(writer
+            .bytes(7, &decode_base64(&self.canonicalized_body)?)
+            .finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut entry = TransparencyLogEntry {
+            log_index: String::from("0"),
+            log_id: LogId {
+                key_id: String::new(),
+            },
+            kind_version: KindVersion {
+                kind: String::new(),
+                version: String::new(),
+            },
+            integrated_time: String::from("0"),
+            inclusion_promise: None,
+            inclusion_proof: None,
+            canonicalized_body: String::new(),
+        };
+        for field in protobuf::fields(data)? {
+            match field.number {
+                1 => entry.log_index = (field.varint()? as i64).to_string(),
+                2 => {
+                    for field in protobuf::fields(field.bytes()?)? {
+                        if field.number == 1 {
+                            entry.log_id.key_id = base64::encode(field.bytes()?);
+                        }
+                    }
+                }
+                3 => {
+                    for field in protobuf::fields(field.bytes()?)? {
+                        match field.number {
+                            1 => entry.kind_version.kind = field.string()?.to_string(),
+                            2 => entry.kind_version.version = field.string()?.to_string(),
+                            _ => {}
+                        }
+                    }
+                }
+                4 => entry.integrated_time = (field.varint()? as i64).to_string(),
+                5 => {
+                    let mut signed_entry_timestamp = String::new();
+                    for field in protobuf::fields(field.bytes()?)? {
+                        if field.number == 1 {
+                            signed_entry_timestamp = base64::encode(field.bytes()?);
+                        }
+                    }
+                    entry.inclusion_promise = Some(InclusionPromise {
+                        signed_entry_timestamp,
+                    });
+                }
+                6 => entry.inclusion_proof = Some(InclusionProof::decode(field.bytes()?)?),
+                7 => entry.canonicalized_body = base64::encode(field.bytes()?),
+                _ => {}
+            }
+        }
+        Ok(entry)
+    }
+}
+
+impl Message for InclusionProof {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut writer = Writer::new()
+            .varint(1, int64(&self.log_index)?)
+            .bytes(2, &decode_base64(&self.root_hash)?)
+            .varint(3, int64(&self.tree_size)?);
+        for hash in &self.hashes {
+            // repeated fields keep their empty elements
+            writer = writer.message(4, &decode_base64(hash)?);
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            writer = writer.message(5, &Writer::new().string(1, &checkpoint.envelope).finish());
+        }
+        Ok(writer.finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut proof = InclusionProof {
+            log_index: String::from("0"),
+            root_hash: String::new(),
+            tree_size: String::from("0"),
+            hashes: Vec::new(),
+            checkpoint: None,
+        };
+        for field in protobuf::fields(data)? {
+            match field.number {
+                1 => proof.log_index = (field.varint()? as i64).to_string(),
+                2 => proof.root_hash = base64::encode(field.bytes()?),
+                3 => proof.tree_size = (field.varint()? as i64).to_string(),
+                4 => proof.hashes.push(base64::encode(field.bytes()?)),
+                5 => {
+                    let mut envelope = String::new();
+                    for field in protobuf::fields(field.bytes()?)? {
+                        if field.number == 1 {
+                            envelope = field.string()?.to_string();
+                        }
+                    }
+                    proof.checkpoint = Some(Checkpoint { envelope });
+                }
+                _ => {}
+            }
+        }
+        Ok(proof)
+    }
+}
+
+impl Message for MessageSignature {
+    fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let algorithm = HASH_ALGORITHMS
+            .iter()
+            .find(|(name, _)| *name == self.message_digest.algorithm)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unsupported bundle digest algorithm {}",
+                    self.message_digest.algorithm
+                )
+            })?;
+        let digest = Writer::new()
+            .varint(1, algorithm)
+            .bytes(2, &decode_base64(&self.message_digest.digest)?)
+            .finish();
+        Ok(Writer::new()
+            .message(1, &digest)
+            .bytes(2, &decode_base64(&self.signature)?)
+            .finish())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut signature = MessageSignature {
+            message_digest: HashOutput {
+                algorithm: String::new(),
+                digest: String::new(),
+            },
+            signature: String::new(),
+        };
+        for field in protobuf::fields(data)? {
+            match field.number {
+                1 => {
+                    for field in protobuf::fields(field.bytes()?)? {
+                        match field.number {
+                            1 => {
+                                let value = field.varint()?;
+                                signature.message_digest.algorithm = HASH_ALGORITHMS
+                                    .iter()
+                                    .find(|(_, known)| *known == value)
+                                    .map(|(name, _)| name.to_string())
+                                    .ok_or_else(|| {
+                                        anyhow::anyhow!(
+                                            "unsupported bundle digest algorithm {}",
+                                            value
+                                        )
+                                    })?;
+                            }
+                            2 => signature.message_digest.digest = base64::encode(field.bytes()?),
+                            _ => {}
+                        }
+                    }
+                }
+                2 => signature.signature = base64::encode(field.bytes()?),
+                _ => {}
+            }
+        }
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rekor_api::Verification;
+
+    fn log_entry() -> LogEntry {
+        let body = r#"{"apiVersion":"0.0.1","kind":"hashedrekord","spec":{}}"#;
+        LogEntry {
+            uuid: String::from("24296fb24b8ad77a"),
+            body: base64::encode(body),
+            integrated_time: 1660000000,
+            log_id: String::from("c0d23d6ad406973f"),
+            log_index: 42,
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some(String::from("MEUCIQA=")),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_bundle_from_log_entry() {
+        let bundle = Bundle::new(
+            b"sig",
+            b"cert",
+            HashAlgorithm::Sha256,
+            b"digest",
+            Some(&log_entry()),
+        )
+        .unwrap();
+        assert_eq!(bundle.media_type, BUNDLE_V01_MEDIA_TYPE);
+        let tlog_entry = &bundle.verification_material.tlog_entries[0];
+        assert_eq!(tlog_entry.kind_version.kind, "hashedrekord");
+        assert_eq!(tlog_entry.kind_version.version, "0.0.1");
+        assert_eq!(tlog_entry.log_index, "42");
+        assert_eq!(tlog_entry.log_id.key_id, "wNI9atQGlz8=");
+        assert_eq!(
+            tlog_entry
+                .inclusion_promise
+                .as_ref()
+                .unwrap()
+                .signed_entry_timestamp,
+            "MEUCIQA="
+        );
+    }
+
+    #[test]
+    fn test_artifact_digest() {
+        let body = r#"{"apiVersion":"0.0.1","kind":"hashedrekord","spec":{"data":{"hash":{"algorithm":"sha256","value":"0a0b"}}}}"#;
+        let entry = TransparencyLogEntry::from_log_entry(&LogEntry {
+            body: base64::encode(body),
+            ..log_entry()
+        })
+        .unwrap();
+        assert_eq!(
+            entry.artifact_digest().unwrap(),
+            (HashAlgorithm::Sha256, vec![0x0a, 0x0b])
+        );
+        assert_eq!(
+            entry.uuid().unwrap(),
+            "42e540974c031e6f270f501043ee6ff741a311bd5959f8afb58fb970e3af8acd"
+        );
+    }
+
+    #[test]
+    fn test_bundle_json_round_trip() {
+        let mut bundle = Bundle::new(
+            b"sig",
+            b"cert",
+            HashAlgorithm::Sha256,
+            b"digest",
+            Some(&log_entry()),
+        )
+        .unwrap();
+        bundle.add_timestamp(b"token");
+        let json = bundle.to_json().unwrap();
+        assert!(json.contains("\"mediaType\""));
+        assert!(json.contains("\"x509CertificateChain\""));
+        assert!(json.contains("\"rfc3161Timestamps\""));
+        assert_eq!(Bundle::from_json(json.as_bytes()).unwrap(), bundle);
+        assert_eq!(bundle.timestamps().unwrap(), vec![b"token".to_vec()]);
+
+        let unlogged =
+            Bundle::new(b"sig", b"cert", HashAlgorithm::Sha256, b"digest", None).unwrap();
+        assert!(unlogged.verification_material.tlog_entries.is_empty());
+        let json = unlogged.to_json().unwrap();
+        assert_eq!(Bundle::from_json(json.as_bytes()).unwrap(), unlogged);
+        assert!(Bundle::from_json(br#"{"mediaType": "application/json"}"#).is_err());
+    }
+
+    #[test]
+    fn test_countersignatures() {
+        let mut bundle = Bundle::new(
+            b"sig",
+            b"cert",
+            HashAlgorithm::Sha256,
+            b"digest",
+            Some(&log_entry()),
+        )
+        .unwrap();
+        assert!(!bundle.to_json().unwrap().contains("countersignatures"));
+        let countersignature =
+            Bundle::new(b"countersig", b"cert", HashAlgorithm::Sha256, b"sig", None).unwrap();
+        bundle.add_countersignature(countersignature.clone());
+        assert_eq!(bundle.signature().unwrap(), b"sig");
+
+        let parsed = Bundle::from_json(bundle.to_json().unwrap().as_bytes()).unwrap();
+        assert_eq!(parsed.countersignatures, vec![countersignature]);
+        assert!(bundle.to_protobuf().is_err());
+    }
+
+    #[test]
+    fn test_bundle_protobuf_round_trip() {
+        let cert = X509::from_pem(&std::fs::read("test_data/signing_cert.pem").unwrap()).unwrap();
+        let mut proven = log_entry();
+        proven.log_index = 0;
+        proven.verification.as_mut().unwrap().inclusion_proof =
+            Some(crate::rekor_api::InclusionProof {
+                hashes: vec![String::from("00ff"), String::new()],
+                log_index: 0,
+                root_hash: String::from("abcdef"),
+                tree_size: 2,
+                checkpoint: Some(String::from("rekor.example.com - 42\n2\n")),
+            });
+        let mut bundle = Bundle::new(
+            b"sig",
+            &cert.to_der().unwrap(),
+            HashAlgorithm::Sha384,
+            b"digest",
+            Some(&proven),
+        )
+        .unwrap();
+        bundle.add_timestamp(b"token");
+        assert_eq!(bundle.media_type, BUNDLE_V02_MEDIA_TYPE);
+
+        let encoded = bundle.to_protobuf().unwrap();
+        assert_eq!(Bundle::parse(&encoded).unwrap(), bundle);
+        assert_eq!(
+            Bundle::parse(bundle.to_json().unwrap().as_bytes()).unwrap(),
+            bundle
+        );
+
+        let v03 = bundle.to_v03().unwrap();
+        assert_eq!(v03.media_type, BUNDLE_V03_MEDIA_TYPE);
+        assert!(v03.verification_material.x509_certificate_chain.is_none());
+        assert_eq!(
+            v03.signing_cert().unwrap().to_der().unwrap(),
+            cert.to_der().unwrap()
+        );
+        assert_eq!(Bundle::parse(&v03.to_protobuf().unwrap()).unwrap(), v03);
+        assert!(Bundle::new(
+            b"sig",
+            &cert.to_der().unwrap(),
+            HashAlgorithm::Sha256,
+            b"digest",
+            Some(&log_entry()),
+        )
+        .unwrap()
+        .to_v03()
+        .is_err());
+        assert!(Bundle::parse(b"MEUCIQ==").is_err());
+    }
+}
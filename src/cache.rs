@@ -0,0 +1,198 @@
+//! Encrypted on-disk cache of identity tokens, so that signing several files
+//! in a row only needs one interactive login.
+//!
+//! Entries are sealed with AES-256-GCM under a random key kept next to the
+//! cache and readable by the owner only. This keeps tokens out of backups and
+//! casual greps of the cache directory, it is no protection against other
+//! processes of the same user.
+
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::oauth::{self, IdentityToken, RefreshToken};
+
+const KEY_FILE: &str = "cache.key";
+const TOKENS_FILE: &str = "tokens";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// `$XDG_CACHE_HOME/ferris-sign`, or `~/.cache/ferris-sign`.
+pub fn cache_dir() -> Result<PathBuf, anyhow::Error> {
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME is not set"))?)
+            .join(".cache"),
+    };
+    Ok(base.join("ferris-sign"))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedToken {
+    id_token: String,
+    #[serde(default)]
+    refresh: Option<RefreshToken>,
+}
+
+/// Identity tokens cached per OIDC issuer.
+#[derive(Debug, Clone)]
+pub struct TokenCache {
+    dir: PathBuf,
+}
+
+impl TokenCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        TokenCache { dir: dir.into() }
+    }
+
+    /// The cache in [`cache_dir`].
+    pub fn open_default() -> Result<Self, anyhow::Error> {
+        Ok(TokenCache::new(cache_dir()?))
+    }
+
+    fn write_private(&self, name: &str, contents: &[u8]) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(self.dir.join(name))?, contents)?;
+        Ok(())
+    }
+
+    fn key(&self) -> Result<Vec<u8>, anyhow::Error> {
+        match fs::read(self.dir.join(KEY_FILE)) {
+            Ok(key) if key.len() == KEY_LEN => Ok(key),
+            Ok(_) => anyhow::bail!("token cache key is corrupt"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut key = vec![0; KEY_LEN];
+                rand_bytes(&mut key)?;
+                self.write_private(KEY_FILE, &key)?;
+                Ok(key)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn load(&self) -> Result<HashMap<String, CachedToken>, anyhow::Error> {
+        let sealed = match fs::read(self.dir.join(TOKENS_FILE)) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            anyhow::bail!("token cache is truncated");
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (tag, ciphertext) = rest.split_at(TAG_LEN);
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key()?,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| anyhow::anyhow!("token cache could not be decrypted"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    fn store(&self, tokens: &HashMap<String, CachedToken>) -> Result<(), anyhow::Error> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand_bytes(&mut nonce)?;
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key()?,
+            Some(&nonce),
+            &[],
+            &serde_json::to_vec(tokens)?,
+            &mut tag,
+        )?;
+        self.write_private(
+            TOKENS_FILE,
+            &[&nonce[..], &tag[..], &ciphertext[..]].concat(),
+        )
+    }
+
+    /// Returns a usable identity token for `issuer` and `audience`,
+    /// refreshing an expired one when a refresh token was cached with it.
+    pub async fn get(
+        &self,
+        issuer: &str,
+        audience: &str,
+    ) -> Result<Option<IdentityToken>, anyhow::Error> {
+        let mut tokens = self.load()?;
+        let cached = match tokens.get(issuer) {
+            Some(cached) => cached.clone(),
+            None => return Ok(None),
+        };
+        if let Ok(mut identity) = IdentityToken::from_jwt_for(&cached.id_token, audience) {
+            identity.refresh = cached.refresh;
+            return Ok(Some(identity));
+        }
+
+        let refreshed = match &cached.refresh {
+            Some(refresh) => oauth::refresh(refresh).await.ok(),
+            None => None,
+        };
+        match refreshed {
+            Some(identity) => {
+                self.put(issuer, &identity)?;
+                Ok(Some(identity))
+            }
+            // expired and not refreshable, drop it so the next login replaces it
+            None => {
+                tokens.remove(issuer);
+                self.store(&tokens)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Caches `identity` as the token for `issuer`.
+    pub fn put(&self, issuer: &str, identity: &IdentityToken) -> Result<(), anyhow::Error> {
+        let mut tokens = self.load().unwrap_or_default();
+        tokens.insert(
+            issuer.to_string(),
+            CachedToken {
+                id_token: identity.token.clone(),
+                refresh: identity.refresh.clone(),
+            },
+        );
+        self.store(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = env::temp_dir().join(format!("ferris-sign-cache-{}", std::process::id()));
+        let cache = TokenCache::new(&dir);
+        let mut tokens = HashMap::new();
+        tokens.insert(
+            String::from("https://oauth2.sigstore.dev/auth"),
+            CachedToken {
+                id_token: String::from("eyJhbGciOiJSUzI1NiJ9.e30.c2ln"),
+                refresh: None,
+            },
+        );
+        cache.store(&tokens).unwrap();
+        assert_eq!(cache.load().unwrap(), tokens);
+
+        let sealed = fs::read(dir.join(TOKENS_FILE)).unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("eyJhbGci"));
+
+        fs::write(dir.join(KEY_FILE), [0u8; KEY_LEN]).unwrap();
+        assert!(cache.load().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! RFC 8785 JSON canonicalization, which Rekor applies to proposed entries
+//! before storing and hashing them. Without it the same entry serialized
+//! by two implementations need not be the same bytes.
+//!
+//! TUF metadata uses a different, older canonical form, see
+//! [`crate::tuf::canonical_json`].
+
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+/// Serializes `value` as canonical JSON.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(to_string(value)?.into_bytes())
+}
+
+/// Serializes `value` as a canonical JSON string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, anyhow::Error> {
+    let mut out = String::new();
+    write_value(&serde_json::to_value(value)?, &mut out)?;
+    Ok(out)
+}
+
+fn write_value(value: &Value, out: &mut String) -> Result<(), anyhow::Error> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(value) => write_number(value, out)?,
+        Value::String(value) => write_string(value, out),
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(value, out)?;
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            // properties are ordered by their utf-16 code units, which only
+            // differs from the utf-8 byte order beyond the BMP
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(value, out)?;
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// numbers are formatted as ecmascript formats doubles
+fn write_number(value: &Number, out: &mut String) -> Result<(), anyhow::Error> {
+    // larger integers do not survive the round trip through a double
+    const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+    if let Some(value) = value.as_u64() {
+        if value > MAX_SAFE_INTEGER {
+            anyhow::bail!("{} cannot be represented in canonical JSON", value);
+        }
+        out.push_str(&value.to_string());
+        return Ok(());
+    }
+    if let Some(value) = value.as_i64() {
+        if value.unsigned_abs() > MAX_SAFE_INTEGER {
+            anyhow::bail!("{} cannot be represented in canonical JSON", value);
+        }
+        out.push_str(&value.to_string());
+        return Ok(());
+    }
+    let value = value
+        .as_f64()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| anyhow::anyhow!("{} cannot be represented in canonical JSON", value))?;
+    if value == 0.0 {
+        out.push('0');
+        return Ok(());
+    }
+    if value < 0.0 {
+        out.push('-');
+    }
+
+    // the shortest digits that round trip, and where the decimal point goes
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .ok_or_else(|| anyhow::anyhow!("unexpected float format {}", scientific))?;
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>()? + 1;
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-n as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push_str(&format!(
+            "e{}{}",
+            if n > 0 { "+" } else { "-" },
+            (n - 1).abs()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({
+            "numbers": [333333333.3333333, 1E30, 4.50, 2e-3, 0.000000000000000000000000001, -0.0, 42],
+            "string": "\u{20ac}$\u{f}\nA'B\"\\\\\"/",
+            "literals": [null, true, false],
+            "\u{1f600}": 1,
+            "\u{e000}": 2
+        });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            concat!(
+                r#"{"literals":[null,true,false],"#,
+                r#""numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27,0,42],"#,
+                "\"string\":\"\u{20ac}$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\",",
+                "\"\u{1f600}\":1,\"\u{e000}\":2}"
+            )
+        );
+        assert!(to_string(&json!(9007199254740993u64)).is_err());
+        assert_eq!(to_vec(&json!(1e21)).unwrap(), b"1e+21");
+        assert_eq!(to_vec(&json!(123456.0)).unwrap(), b"123456");
+    }
+}
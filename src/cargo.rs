@@ -0,0 +1,119 @@
+//! Attestations about `.crate` packages as `cargo package` writes them. The
+//! statement has the crate file as its only subject, with the crate name and
+//! version recorded as subject annotations.
+
+use regex::Regex;
+use std::path::Path;
+
+use crate::intoto::{Statement, Subject};
+
+pub const PREDICATE_TYPE: &str = "https://github.com/lkatalin/ferris-sign/cargo-package/v1";
+pub const NAME_ANNOTATION: &str = "cargo.crate.name";
+pub const VERSION_ANNOTATION: &str = "cargo.crate.version";
+
+/// Where `cargo package` leaves its output, relative to the workspace root.
+pub const PACKAGE_DIR: &str = "target/package";
+
+/// A packaged crate, identified by the name of its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateFile {
+    pub name: String,
+    pub version: String,
+}
+
+impl CrateFile {
+    /// Parses `<name>-<version>.crate`. Names may contain dashes, so the
+    /// version is the first dash separated tail that is a semver version.
+    pub fn from_path(path: &Path) -> Result<Self, anyhow::Error> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("{} is not a crate file", path.display()))?;
+        let stem = file_name
+            .strip_suffix(".crate")
+            .ok_or_else(|| anyhow::anyhow!("{} does not end in .crate", file_name))?;
+        let semver = Regex::new(r"^\d+\.\d+\.\d+(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?$").unwrap();
+        stem.match_indices('-')
+            .map(|(dash, _)| (&stem[..dash], &stem[dash + 1..]))
+            .find(|(name, version)| !name.is_empty() && semver.is_match(version))
+            .map(|(name, version)| CrateFile {
+                name: name.to_string(),
+                version: version.to_string(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("{} is not named <name>-<version>.crate", file_name))
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}-{}.crate", self.name, self.version)
+    }
+
+    /// Statement about this crate with the hex encoded sha256 `digest`.
+    pub fn statement(&self, digest: &str) -> Statement {
+        let subject = Subject::sha256(&self.file_name(), digest)
+            .with_annotation(NAME_ANNOTATION, &self.name)
+            .with_annotation(VERSION_ANNOTATION, &self.version);
+        Statement::new(vec![subject], PREDICATE_TYPE, serde_json::json!({}))
+    }
+
+    /// Checks that `statement` is about this crate and its contents have the
+    /// hex encoded sha256 `digest`.
+    pub fn check_statement(
+        &self,
+        statement: &Statement,
+        digest: &str,
+    ) -> Result<(), anyhow::Error> {
+        if statement.predicate_type != PREDICATE_TYPE {
+            anyhow::bail!(
+                "attestation predicate type is {}, expected {}",
+                statement.predicate_type,
+                PREDICATE_TYPE
+            );
+        }
+        let subject = statement
+            .subject
+            .iter()
+            .find(|subject| subject.digest.get("sha256").map(String::as_str) == Some(digest))
+            .ok_or_else(|| anyhow::anyhow!("attestation is not about {}", self.file_name()))?;
+        let recorded = (
+            subject.annotation(NAME_ANNOTATION),
+            subject.annotation(VERSION_ANNOTATION),
+        );
+        if recorded != (Some(self.name.as_str()), Some(self.version.as_str())) {
+            anyhow::bail!(
+                "attestation is for {} {}, not {} {}",
+                recorded.0.unwrap_or("<no name>"),
+                recorded.1.unwrap_or("<no version>"),
+                self.name,
+                self.version
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crate_file() {
+        let krate =
+            CrateFile::from_path(Path::new("target/package/ferris-sign-0.1.0.crate")).unwrap();
+        assert_eq!(krate.name, "ferris-sign");
+        assert_eq!(krate.version, "0.1.0");
+        let krate = CrateFile::from_path(Path::new("tokio-2d-1.0.0-rc.1.crate")).unwrap();
+        assert_eq!(krate.name, "tokio-2d");
+        assert_eq!(krate.version, "1.0.0-rc.1");
+        assert!(CrateFile::from_path(Path::new("ferris-sign.crate")).is_err());
+        assert!(CrateFile::from_path(Path::new("ferris-sign-0.1.0.tar.gz")).is_err());
+
+        let statement = krate.statement("abcd");
+        krate.check_statement(&statement, "abcd").unwrap();
+        assert!(krate.check_statement(&statement, "ef01").is_err());
+        let other = CrateFile {
+            name: "tokio-2d".to_string(),
+            version: "1.0.1".to_string(),
+        };
+        assert!(other.check_statement(&statement, "abcd").is_err());
+    }
+}
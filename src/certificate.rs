@@ -0,0 +1,279 @@
+//! Access to the parts of Fulcio certificates that openssl does not expose.
+
+use openssl::x509::{X509NameRef, X509};
+use serde::Serialize;
+
+use crate::der;
+use crate::sct;
+
+/// Fulcio OIDC issuer extension (v1, raw string value).
+pub const OID_FULCIO_ISSUER: &str = "1.3.6.1.4.1.57264.1.1";
+/// Fulcio OIDC issuer extension (v2, DER encoded UTF8String).
+pub const OID_FULCIO_ISSUER_V2: &str = "1.3.6.1.4.1.57264.1.8";
+/// GitHub workflow extensions Fulcio adds for Actions tokens (raw string
+/// values).
+pub const OID_WORKFLOW_TRIGGER: &str = "1.3.6.1.4.1.57264.1.2";
+pub const OID_WORKFLOW_SHA: &str = "1.3.6.1.4.1.57264.1.3";
+pub const OID_WORKFLOW_NAME: &str = "1.3.6.1.4.1.57264.1.4";
+pub const OID_WORKFLOW_REPOSITORY: &str = "1.3.6.1.4.1.57264.1.5";
+pub const OID_WORKFLOW_REF: &str = "1.3.6.1.4.1.57264.1.6";
+/// Embedded signed certificate timestamp list.
+pub const OID_SCT_LIST: &str = "1.3.6.1.4.1.11129.2.4.2";
+
+/// A certificate extension, `value` is the content of the extnValue octet
+/// string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    pub oid: String,
+    pub critical: bool,
+    pub value: Vec<u8>,
+}
+
+fn tbs_certificate(cert_der: &[u8]) -> Result<der::Tlv<'_>, anyhow::Error> {
+    let cert = der::expect(cert_der, der::TAG_SEQUENCE)?;
+    der::expect(cert.value, der::TAG_SEQUENCE)
+}
+
+fn parse_extension(tlv: &der::Tlv) -> Result<Extension, anyhow::Error> {
+    let fields = der::children(tlv.value)?;
+    let oid = fields
+        .first()
+        .filter(|f| f.tag == der::TAG_OID)
+        .ok_or_else(|| anyhow::anyhow!("extension without an OID"))?;
+    let critical = fields
+        .iter()
+        .find(|f| f.tag == der::TAG_BOOLEAN)
+        .map(|f| f.value != [0])
+        .unwrap_or(false);
+    let value = fields
+        .iter()
+        .find(|f| f.tag == der::TAG_OCTET_STRING)
+        .ok_or_else(|| anyhow::anyhow!("extension without a value"))?;
+    Ok(Extension {
+        oid: der::oid_to_string(oid.value),
+        critical,
+        value: value.value.to_vec(),
+    })
+}
+
+/// Lists the extensions of a DER encoded certificate.
+pub fn extensions(cert_der: &[u8]) -> Result<Vec<Extension>, anyhow::Error> {
+    let tbs = tbs_certificate(cert_der)?;
+    let extensions = match der::children(tbs.value)?
+        .into_iter()
+        .find(|f| f.tag == der::TAG_EXTENSIONS)
+    {
+        Some(extensions) => extensions,
+        None => return Ok(Vec::new()),
+    };
+    let extensions = der::expect(extensions.value, der::TAG_SEQUENCE)?;
+    der::children(extensions.value)?
+        .iter()
+        .map(parse_extension)
+        .collect()
+}
+
+/// Finds the extension with the given dotted `oid`.
+pub fn extension(cert_der: &[u8], oid: &str) -> Result<Option<Extension>, anyhow::Error> {
+    Ok(extensions(cert_der)?.into_iter().find(|e| e.oid == oid))
+}
+
+/// Re-encodes the TBS certificate with the extension `oid` removed, which is
+/// how the precertificate an SCT was issued over is reconstructed.
+pub fn tbs_without_extension(cert_der: &[u8], oid: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let tbs = tbs_certificate(cert_der)?;
+    let mut tbs_value = Vec::new();
+    for field in der::children(tbs.value)? {
+        if field.tag != der::TAG_EXTENSIONS {
+            tbs_value.extend_from_slice(field.raw);
+            continue;
+        }
+        let extensions = der::expect(field.value, der::TAG_SEQUENCE)?;
+        let mut kept = Vec::new();
+        for extension in der::children(extensions.value)? {
+            if parse_extension(&extension)?.oid != oid {
+                kept.extend_from_slice(extension.raw);
+            }
+        }
+        let extensions = der::encode(der::TAG_SEQUENCE, &kept);
+        tbs_value.extend(der::encode(der::TAG_EXTENSIONS, &extensions));
+    }
+    Ok(der::encode(der::TAG_SEQUENCE, &tbs_value))
+}
+
+/// Email addresses and URIs in the subject alternative name of `cert`.
+pub fn san_identities(cert: &X509) -> Vec<String> {
+    let names = match cert.subject_alt_names() {
+        Some(names) => names,
+        None => return Vec::new(),
+    };
+    names
+        .iter()
+        .filter_map(|name| name.email().or_else(|| name.uri()))
+        .map(String::from)
+        .collect()
+}
+
+/// The OIDC issuer Fulcio recorded in `cert`.
+pub fn oidc_issuer(cert: &X509) -> Result<Option<String>, anyhow::Error> {
+    let cert_der = cert.to_der()?;
+    if let Some(extension) = extension(&cert_der, OID_FULCIO_ISSUER_V2)? {
+        let value = der::parse(&extension.value)?.0.value;
+        return Ok(Some(String::from_utf8(value.to_vec())?));
+    }
+    match extension(&cert_der, OID_FULCIO_ISSUER)? {
+        Some(extension) => Ok(Some(String::from_utf8(extension.value)?)),
+        None => Ok(None),
+    }
+}
+
+/// What `inspect-cert` shows about a signing certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Inspection {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub san: Vec<String>,
+    pub oidc_issuer: Option<String>,
+    #[serde(skip_serializing_if = "Workflow::is_empty")]
+    pub workflow: Workflow,
+    pub sct: Vec<SctInfo>,
+    pub public_key: String,
+}
+
+/// The GitHub workflow that requested the certificate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Workflow {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SctInfo {
+    /// Hex encoded SHA-256 of the CT log key.
+    pub log_id: String,
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl Workflow {
+    pub fn is_empty(&self) -> bool {
+        self == &Workflow::default()
+    }
+}
+
+// names as openssl prints them on one line, CN=..., O=...
+fn name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn string_extension(cert_der: &[u8], oid: &str) -> Result<Option<String>, anyhow::Error> {
+    match extension(cert_der, oid)? {
+        Some(extension) => Ok(Some(String::from_utf8(extension.value)?)),
+        None => Ok(None),
+    }
+}
+
+/// Collects the fields of `cert` that matter for keyless signatures.
+pub fn inspect(cert: &X509) -> Result<Inspection, anyhow::Error> {
+    let cert_der = cert.to_der()?;
+    let workflow = Workflow {
+        trigger: string_extension(&cert_der, OID_WORKFLOW_TRIGGER)?,
+        sha: string_extension(&cert_der, OID_WORKFLOW_SHA)?,
+        name: string_extension(&cert_der, OID_WORKFLOW_NAME)?,
+        repository: string_extension(&cert_der, OID_WORKFLOW_REPOSITORY)?,
+        git_ref: string_extension(&cert_der, OID_WORKFLOW_REF)?,
+    };
+    let sct = sct::embedded_scts(cert)?
+        .into_iter()
+        .map(|sct| SctInfo {
+            log_id: data_encoding::HEXLOWER.encode(&sct.log_id),
+            timestamp: sct.timestamp,
+        })
+        .collect();
+    Ok(Inspection {
+        subject: name_to_string(cert.subject_name()),
+        issuer: name_to_string(cert.issuer_name()),
+        not_before: cert.not_before().to_string(),
+        not_after: cert.not_after().to_string(),
+        san: san_identities(cert),
+        oidc_issuer: oidc_issuer(cert)?,
+        workflow,
+        sct,
+        public_key: String::from_utf8(cert.public_key()?.public_key_to_pem()?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_cert() -> Vec<u8> {
+        let pem = std::fs::read("test_data/signing_cert.pem").unwrap();
+        X509::from_pem(&pem).unwrap().to_der().unwrap()
+    }
+
+    #[test]
+    fn test_fulcio_issuer_extension() {
+        let issuer = extension(&signing_cert(), OID_FULCIO_ISSUER)
+            .unwrap()
+            .unwrap();
+        assert!(!issuer.critical);
+        assert_eq!(issuer.value, b"https://accounts.example.com");
+    }
+
+    #[test]
+    fn test_identity() {
+        let cert = X509::from_der(&signing_cert()).unwrap();
+        assert_eq!(san_identities(&cert), vec!["ferris@example.com"]);
+        assert_eq!(
+            oidc_issuer(&cert).unwrap().unwrap(),
+            "https://accounts.example.com"
+        );
+    }
+
+    #[test]
+    fn test_tbs_without_extension() {
+        let cert = signing_cert();
+        let tbs = tbs_without_extension(&cert, OID_FULCIO_ISSUER).unwrap();
+        let unchanged = tbs_without_extension(&cert, "1.2.3.4").unwrap();
+        assert_eq!(unchanged, tbs_certificate(&cert).unwrap().raw);
+        assert!(tbs.len() < unchanged.len());
+    }
+
+    #[test]
+    fn test_inspect() {
+        let cert = X509::from_der(&signing_cert()).unwrap();
+        let inspection = inspect(&cert).unwrap();
+        assert_eq!(inspection.subject, "");
+        assert_eq!(
+            inspection.issuer,
+            "O=sigstore.dev, CN=sigstore-intermediate"
+        );
+        assert_eq!(inspection.not_before, "Aug  1 12:00:00 2022 GMT");
+        assert_eq!(inspection.san, vec!["ferris@example.com"]);
+        assert!(inspection.workflow.is_empty());
+        assert!(inspection.sct.is_empty());
+        assert!(inspection
+            .public_key
+            .starts_with("-----BEGIN PUBLIC KEY-----"));
+        let json = serde_json::to_value(&inspection).unwrap();
+        assert!(json.get("workflow").is_none());
+    }
+}
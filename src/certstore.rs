@@ -0,0 +1,227 @@
+//! Signing with a certificate and its CNG key from the Windows certificate
+//! store, addressed as `certstore://<sha1 thumbprint>`. The certificate is
+//! looked up in the current user's personal store, so enterprise issued
+//! credentials work without exporting the key. Only EC keys on P-256 and
+//! P-384 are supported.
+
+#[cfg(windows)]
+use openssl::pkey::{PKey, Public};
+#[cfg(windows)]
+use openssl::x509::X509;
+#[cfg(windows)]
+use std::fmt;
+#[cfg(windows)]
+use windows_sys::Win32::Security::Cryptography::{
+    CertCloseStore, CertFindCertificateInStore, CertFreeCertificateContext, CertOpenSystemStoreW,
+    CryptAcquireCertificatePrivateKey, NCryptFreeObject, NCryptSignHash, CERT_FIND_HASH,
+    CRYPT_ACQUIRE_ONLY_NCRYPT_KEY_FLAG, CRYPT_ACQUIRE_SILENT_FLAG, CRYPT_INTEGER_BLOB,
+    PKCS_7_ASN_ENCODING, X509_ASN_ENCODING,
+};
+
+#[cfg(windows)]
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+#[cfg(windows)]
+use crate::key::{self, SigningKey};
+
+pub const URI_SCHEME: &str = "certstore://";
+
+/// Parses the SHA-1 thumbprint out of a `certstore://<thumbprint>` URI.
+/// Spaces and colons, as copied from the certificate manager, are allowed.
+pub fn parse_uri(uri: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let thumbprint = uri
+        .strip_prefix(URI_SCHEME)
+        .ok_or_else(|| anyhow::anyhow!("{} is not a certstore:// URI", uri))?;
+    let hex: String = thumbprint
+        .chars()
+        .filter(|c| *c != ' ' && *c != ':')
+        .collect::<String>()
+        .to_lowercase();
+    let thumbprint = data_encoding::HEXLOWER
+        .decode(hex.as_bytes())
+        .map_err(|_| anyhow::anyhow!("invalid certificate thumbprint {}", thumbprint))?;
+    if thumbprint.len() != 20 {
+        anyhow::bail!("certificate thumbprints are 20 byte SHA-1 digests");
+    }
+    Ok(thumbprint)
+}
+
+/// A certificate from the store and the CNG key behind it. The private key
+/// stays with CNG, digests are handed to it for signing.
+#[cfg(windows)]
+pub struct CertStoreKey {
+    key: usize,
+    free_key: bool,
+    cert: X509,
+    algorithm: SigningAlgorithm,
+}
+
+// NCrypt key handles can be used from any thread
+#[cfg(windows)]
+unsafe impl Send for CertStoreKey {}
+#[cfg(windows)]
+unsafe impl Sync for CertStoreKey {}
+
+#[cfg(windows)]
+impl fmt::Debug for CertStoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertStoreKey")
+            .field("subject", self.cert.subject_name())
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(windows)]
+impl Drop for CertStoreKey {
+    fn drop(&mut self) {
+        if self.free_key {
+            unsafe { NCryptFreeObject(self.key as _) };
+        }
+    }
+}
+
+#[cfg(windows)]
+impl CertStoreKey {
+    /// Finds the certificate with `thumbprint` in the personal store and
+    /// acquires its CNG private key.
+    pub fn open(thumbprint: &[u8]) -> Result<Self, anyhow::Error> {
+        let store_name: Vec<u16> = "MY\0".encode_utf16().collect();
+        let store = unsafe { CertOpenSystemStoreW(0, store_name.as_ptr()) };
+        if store.is_null() {
+            anyhow::bail!("could not open the personal certificate store");
+        }
+        let result = unsafe { open_in_store(store, thumbprint) };
+        unsafe { CertCloseStore(store, 0) };
+        result
+    }
+
+    /// The certificate the key belongs to.
+    pub fn certificate(&self) -> &X509 {
+        &self.cert
+    }
+}
+
+#[cfg(windows)]
+unsafe fn open_in_store(
+    store: *mut std::ffi::c_void,
+    thumbprint: &[u8],
+) -> Result<CertStoreKey, anyhow::Error> {
+    let blob = CRYPT_INTEGER_BLOB {
+        cbData: thumbprint.len() as u32,
+        pbData: thumbprint.as_ptr() as *mut u8,
+    };
+    let context = CertFindCertificateInStore(
+        store,
+        X509_ASN_ENCODING | PKCS_7_ASN_ENCODING,
+        0,
+        CERT_FIND_HASH,
+        &blob as *const _ as *const _,
+        std::ptr::null(),
+    );
+    if context.is_null() {
+        anyhow::bail!("no certificate with that thumbprint in the personal store");
+    }
+    let encoded =
+        std::slice::from_raw_parts((*context).pbCertEncoded, (*context).cbCertEncoded as usize);
+    let cert = X509::from_der(encoded);
+
+    let mut key = 0;
+    let mut key_spec = 0;
+    let mut free_key = 0;
+    let acquired = CryptAcquireCertificatePrivateKey(
+        context,
+        CRYPT_ACQUIRE_ONLY_NCRYPT_KEY_FLAG | CRYPT_ACQUIRE_SILENT_FLAG,
+        std::ptr::null(),
+        &mut key,
+        &mut key_spec,
+        &mut free_key,
+    );
+    CertFreeCertificateContext(context);
+    if acquired == 0 {
+        anyhow::bail!("the certificate has no CNG private key that can be used silently");
+    }
+    let cert = match cert {
+        Ok(cert) => cert,
+        Err(e) => {
+            if free_key != 0 {
+                NCryptFreeObject(key as _);
+            }
+            return Err(e.into());
+        }
+    };
+    // from here on dropping the key frees the handle
+    let public_key = cert.public_key()?;
+    let key = CertStoreKey {
+        key: key as usize,
+        free_key: free_key != 0,
+        algorithm: SigningAlgorithm::for_key(&public_key)?,
+        cert,
+    };
+    let algorithm = key.algorithm;
+    if !matches!(
+        algorithm,
+        SigningAlgorithm::EcdsaP256Sha256 | SigningAlgorithm::EcdsaP384Sha384
+    ) {
+        anyhow::bail!("only EC certificate keys are supported");
+    }
+    Ok(key)
+}
+
+#[cfg(windows)]
+impl SigningKey for CertStoreKey {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        Ok(self.algorithm)
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        Ok(self.cert.public_key()?)
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let digest_algorithm = self.algorithm.hash();
+        self.sign_digest(digest_algorithm, &digest_algorithm.digest(data)?)
+    }
+
+    // ECDSA keys take no padding info and return r || s
+    fn sign_digest(
+        &self,
+        _digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let mut raw = vec![0u8; 2 * 66];
+        let mut length = 0u32;
+        let status = unsafe {
+            NCryptSignHash(
+                self.key as _,
+                std::ptr::null(),
+                digest.as_ptr(),
+                digest.len() as u32,
+                raw.as_mut_ptr(),
+                raw.len() as u32,
+                &mut length,
+                0,
+            )
+        };
+        if status != 0 {
+            anyhow::bail!("CNG did not sign, status {:#x}", status);
+        }
+        raw.truncate(length as usize);
+        key::ecdsa_der_from_raw(&raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri() {
+        let thumbprint =
+            parse_uri("certstore://3B:7E 0A4F5D1C2B3A49586776859403A2B1C0D9E8").unwrap();
+        assert_eq!(thumbprint.len(), 20);
+        assert_eq!(thumbprint[0], 0x3b);
+        assert!(parse_uri("certstore://3b7e").is_err());
+        assert!(parse_uri("certstore://lolwut").is_err());
+        assert!(parse_uri("keychain://release").is_err());
+    }
+}
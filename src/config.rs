@@ -0,0 +1,190 @@
+//! Defaults for command line flags, read from
+//! `~/.config/ferris-sign/config.toml`.
+//!
+//! Keys are the long flag names, values what would be passed on the command
+//! line. Named profiles under `[profiles.<name>]` override the top level
+//! values when selected with `--profile`, or by a top level `profile` key:
+//!
+//! ```toml
+//! format = "cosign"
+//! timeout = 30
+//!
+//! [profiles.corp]
+//! fulcio-url = "https://fulcio.corp.example.com"
+//! rekor-url = "https://rekor.corp.example.com"
+//! oidc-issuer = "https://sso.corp.example.com"
+//! root = "/etc/ferris-sign/corp-fulcio.pem"
+//! certificate-oidc-issuer = "https://sso.corp.example.com"
+//! ```
+//!
+//! The `public` and `staging` profiles select the sigstore instances, and
+//! `local` a development deployment on localhost, unless the file defines
+//! profiles of those names.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// `$FERRIS_SIGN_CONFIG`, else `$XDG_CONFIG_HOME/ferris-sign/config.toml`,
+/// or `~/.config/ferris-sign/config.toml`.
+pub fn config_path() -> Result<PathBuf, anyhow::Error> {
+    if let Some(path) = env::var_os("FERRIS_SIGN_CONFIG").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME is not set"))?)
+            .join(".config"),
+    };
+    Ok(base.join("ferris-sign").join("config.toml"))
+}
+
+const PROFILES: &str = "profiles";
+
+/// Flag values by long flag name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    values: BTreeMap<String, String>,
+    profiles: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Config {
+    /// Reads the configuration from [`config_path`], an empty one if there
+    /// is no file.
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let path = config_path()?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::from_toml(&contents)
+                .map_err(|e| anyhow::anyhow!("could not parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => anyhow::bail!("could not read {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, anyhow::Error> {
+        let mut table: toml::value::Table = toml::from_str(contents)?;
+        let mut profiles = BTreeMap::new();
+        match table.remove(PROFILES) {
+            Some(toml::Value::Table(tables)) => {
+                for (name, profile) in tables {
+                    match profile {
+                        toml::Value::Table(profile) => {
+                            profiles.insert(name, values(profile)?);
+                        }
+                        _ => anyhow::bail!("profile {} must be a table", name),
+                    }
+                }
+            }
+            Some(_) => anyhow::bail!("{} must be a table of profiles", PROFILES),
+            None => {}
+        }
+        Ok(Config {
+            values: values(table)?,
+            profiles,
+        })
+    }
+
+    /// The top level values overridden by those of the profile `name`.
+    pub fn profile(&self, name: &str) -> Result<Config, anyhow::Error> {
+        let mut values = self.values.clone();
+        match (self.profiles.get(name), name) {
+            (Some(profile), _) => values.extend(profile.clone()),
+            (None, "public") => {}
+            (None, "staging") => {
+                values.insert("staging".to_string(), "true".to_string());
+            }
+            (None, "local") => {
+                values.insert("local".to_string(), "true".to_string());
+            }
+            (None, _) => anyhow::bail!(
+                "there is no profile {} in {}",
+                name,
+                config_path()?.display()
+            ),
+        }
+        Ok(Config {
+            values,
+            profiles: BTreeMap::new(),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+fn values(table: toml::value::Table) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let mut values = BTreeMap::new();
+    for (key, value) in table {
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            _ => anyhow::bail!("{} must be a string, integer or boolean", key),
+        };
+        values.insert(key, value);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            fulcio-url = "https://fulcio.example.com"
+            timeout = 30
+            no-browser = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.get("fulcio-url"), Some("https://fulcio.example.com"));
+        assert_eq!(config.get("timeout"), Some("30"));
+        assert_eq!(config.get("no-browser"), Some("true"));
+        assert_eq!(config.get("rekor-url"), None);
+        assert_eq!(config.iter().count(), 3);
+        assert!(Config::from_toml("format = [\"raw\"]").is_err());
+        assert!(Config::from_toml("format = ").is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let config = Config::from_toml(
+            r#"
+            format = "cosign"
+            rekor-url = "https://rekor.sigstore.dev"
+
+            [profiles.corp]
+            rekor-url = "https://rekor.corp.example.com"
+            root = "corp.pem"
+            "#,
+        )
+        .unwrap();
+        let corp = config.profile("corp").unwrap();
+        assert_eq!(corp.get("format"), Some("cosign"));
+        assert_eq!(
+            corp.get("rekor-url"),
+            Some("https://rekor.corp.example.com")
+        );
+        assert_eq!(corp.get("root"), Some("corp.pem"));
+        assert_eq!(
+            config.profile("staging").unwrap().get("staging"),
+            Some("true")
+        );
+        assert_eq!(config.profile("local").unwrap().get("local"), Some("true"));
+        assert_eq!(config.profile("public").unwrap().get("root"), None);
+        assert!(config.profile("private").is_err());
+        assert!(Config::from_toml("[profiles]\ncorp = 1").is_err());
+    }
+}
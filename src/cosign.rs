@@ -0,0 +1,522 @@
+//! Image signatures stored in the registry the way cosign lays them out: an
+//! image tagged `sha256-<digest>.sig` next to the signed image, with one
+//! layer per signature. Each layer is a simple signing payload naming the
+//! image digest, the signature and certificate ride along as annotations.
+//!
+//! Attestations are laid out the same way under `sha256-<digest>.att`, each
+//! layer being a DSSE envelope.
+//!
+//! Registries implementing the OCI 1.1 referrers API get each signature or
+//! attestation as an artifact manifest with the image as its subject
+//! instead.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::registry::{self, Client, Descriptor, ImageManifest, Reference};
+use crate::rekor_api::{LogEntry, Verification};
+
+pub const SIMPLE_SIGNING_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+pub const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+pub const CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+pub const CHAIN_ANNOTATION: &str = "dev.sigstore.cosign/chain";
+pub const BUNDLE_ANNOTATION: &str = "dev.sigstore.cosign/bundle";
+pub const SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.dev.cosign.artifact.sig.v1+json";
+pub const DSSE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+pub const PREDICATE_TYPE_ANNOTATION: &str = "predicateType";
+
+const SIGNATURE_TYPE: &str = "cosign container image signature";
+
+/// Tag the signatures of the image with manifest `digest` are stored under.
+pub fn signature_tag(digest: &str) -> String {
+    format!("{}.sig", digest.replace(':', "-"))
+}
+
+/// Tag the attestations of the image with manifest `digest` are stored
+/// under.
+pub fn attestation_tag(digest: &str) -> String {
+    format!("{}.att", digest.replace(':', "-"))
+}
+
+/// The simple signing payload that gets signed for an image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimpleSigning {
+    pub critical: Critical,
+    #[serde(default)]
+    pub optional: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Critical {
+    pub identity: Identity,
+    pub image: Image,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    #[serde(rename = "docker-reference")]
+    pub docker_reference: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Image {
+    #[serde(rename = "docker-manifest-digest")]
+    pub docker_manifest_digest: String,
+}
+
+impl SimpleSigning {
+    /// Payload for the image `name` (without tag) with manifest `digest`.
+    pub fn new(name: &str, digest: &str) -> Self {
+        SimpleSigning {
+            critical: Critical {
+                identity: Identity {
+                    docker_reference: name.to_string(),
+                },
+                image: Image {
+                    docker_manifest_digest: digest.to_string(),
+                },
+                kind: SIGNATURE_TYPE.to_string(),
+            },
+            optional: None,
+        }
+    }
+
+    /// Adds an annotation to the optional section, where cosign keeps its
+    /// `-a key=value` annotations.
+    pub fn with_annotation(mut self, key: &str, value: &str) -> Self {
+        self.optional
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), serde_json::Value::from(value));
+        self
+    }
+
+    /// A string annotation of the optional section.
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        self.optional.as_ref()?.get(key)?.as_str()
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        let payload: SimpleSigning = serde_json::from_slice(json)?;
+        if payload.critical.kind != SIGNATURE_TYPE {
+            anyhow::bail!("not a cosign signature payload: {}", payload.critical.kind);
+        }
+        Ok(payload)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+/// The log entry as cosign embeds it, so signatures verify offline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RekorBundle {
+    #[serde(rename = "SignedEntryTimestamp")]
+    pub signed_entry_timestamp: String,
+    #[serde(rename = "Payload")]
+    pub payload: RekorPayload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RekorPayload {
+    pub body: String,
+    pub integrated_time: i64,
+    pub log_index: i64,
+    #[serde(rename = "logID")]
+    pub log_id: String,
+}
+
+impl RekorBundle {
+    pub fn from_log_entry(log_entry: &LogEntry) -> Result<Self, anyhow::Error> {
+        let signed_entry_timestamp = log_entry
+            .verification
+            .as_ref()
+            .and_then(|verification| verification.signed_entry_timestamp.clone())
+            .ok_or_else(|| anyhow::anyhow!("log entry has no signed entry timestamp"))?;
+        Ok(RekorBundle {
+            signed_entry_timestamp,
+            payload: RekorPayload {
+                body: log_entry.body.clone(),
+                integrated_time: log_entry.integrated_time,
+                log_index: log_entry.log_index,
+                log_id: log_entry.log_id.clone(),
+            },
+        })
+    }
+
+    /// The log entry back as Rekor would return it, minus its UUID.
+    pub fn to_log_entry(&self) -> LogEntry {
+        LogEntry {
+            uuid: String::new(),
+            body: self.payload.body.clone(),
+            integrated_time: self.payload.integrated_time,
+            log_id: self.payload.log_id.clone(),
+            log_index: self.payload.log_index,
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some(self.signed_entry_timestamp.clone()),
+            }),
+        }
+    }
+}
+
+/// One signature read back from the registry, nothing about it verified yet.
+#[derive(Debug, Clone)]
+pub struct ImageSignature {
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub cert_pem: Option<String>,
+    pub bundle: Option<RekorBundle>,
+}
+
+impl ImageSignature {
+    fn from_layer(layer: &Descriptor, payload: Vec<u8>) -> Result<Self, anyhow::Error> {
+        let annotations = layer.annotations.clone().unwrap_or_default();
+        let signature = annotations
+            .get(SIGNATURE_ANNOTATION)
+            .ok_or_else(|| anyhow::anyhow!("layer {} has no signature", layer.digest))?;
+        let bundle = annotations
+            .get(BUNDLE_ANNOTATION)
+            .map(|bundle| serde_json::from_str(bundle))
+            .transpose()?;
+        Ok(ImageSignature {
+            payload,
+            signature: base64::decode(signature)?,
+            cert_pem: annotations.get(CERTIFICATE_ANNOTATION).cloned(),
+            bundle,
+        })
+    }
+}
+
+/// One attestation read back from the registry, nothing about it verified
+/// yet.
+#[derive(Debug, Clone)]
+pub struct ImageAttestation {
+    /// DSSE envelope JSON.
+    pub envelope: Vec<u8>,
+    pub cert_pem: Option<String>,
+    pub bundle: Option<RekorBundle>,
+}
+
+impl ImageAttestation {
+    fn from_layer(layer: &Descriptor, envelope: Vec<u8>) -> Result<Self, anyhow::Error> {
+        let annotations = layer.annotations.clone().unwrap_or_default();
+        let bundle = annotations
+            .get(BUNDLE_ANNOTATION)
+            .map(|bundle| serde_json::from_str(bundle))
+            .transpose()?;
+        Ok(ImageAttestation {
+            envelope,
+            cert_pem: annotations.get(CERTIFICATE_ANNOTATION).cloned(),
+            bundle,
+        })
+    }
+}
+
+// the manifests of the referrers of `artifact_type` and the one under the
+// tag, each with the reference its blobs are fetched through
+async fn manifests(
+    client: &Client,
+    image: &Reference,
+    artifact_type: &str,
+    tag: fn(&str) -> String,
+) -> Result<Vec<(Reference, ImageManifest)>, anyhow::Error> {
+    let digest = image
+        .digest
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("{} is not pinned to a digest", image))?;
+
+    let mut manifests = Vec::new();
+    let referrers = client
+        .get_referrers(image, artifact_type)
+        .await?
+        .unwrap_or_default();
+    for referrer in referrers {
+        let referrer = image.with_digest(&referrer.digest);
+        match client.get_manifest(&referrer).await? {
+            Some(manifest) => manifests.push((referrer, manifest.image_manifest()?)),
+            None => anyhow::bail!("referrer {} does not exist", referrer),
+        }
+    }
+    // artifacts pushed by tools that only know the tag scheme live here
+    let tagged = image.with_tag(&tag(digest));
+    if let Some(manifest) = client.get_manifest(&tagged).await? {
+        manifests.push((tagged, manifest.image_manifest()?));
+    }
+    Ok(manifests)
+}
+
+/// Fetches the signatures stored for `image`, which must be pinned to a
+/// digest, both as referrers and under the signature tag. Layers that are
+/// not simple signing payloads are skipped.
+pub async fn signatures(
+    client: &Client,
+    image: &Reference,
+) -> Result<Vec<ImageSignature>, anyhow::Error> {
+    let manifests = manifests(client, image, SIGNATURE_ARTIFACT_TYPE, signature_tag).await?;
+    let mut found = Vec::new();
+    for (reference, manifest) in &manifests {
+        for layer in &manifest.layers {
+            if layer.media_type != SIMPLE_SIGNING_MEDIA_TYPE {
+                continue;
+            }
+            let payload = client.get_blob(reference, &layer.digest).await?;
+            found.push(ImageSignature::from_layer(layer, payload)?);
+        }
+    }
+    if found.is_empty() {
+        anyhow::bail!("no signatures found for {}", image);
+    }
+    Ok(found)
+}
+
+/// Like [`signatures`], for the attestations of `image`. An image without
+/// any has an empty list.
+pub async fn attestations(
+    client: &Client,
+    image: &Reference,
+) -> Result<Vec<ImageAttestation>, anyhow::Error> {
+    let manifests = manifests(client, image, DSSE_MEDIA_TYPE, attestation_tag).await?;
+    let mut found = Vec::new();
+    for (reference, manifest) in &manifests {
+        for layer in &manifest.layers {
+            if layer.media_type != DSSE_MEDIA_TYPE {
+                continue;
+            }
+            let envelope = client.get_blob(reference, &layer.digest).await?;
+            found.push(ImageAttestation::from_layer(layer, envelope)?);
+        }
+    }
+    Ok(found)
+}
+
+/// Describes the layer for one signature over `payload`.
+pub fn signature_layer(
+    payload: &[u8],
+    signature: &[u8],
+    cert_pem: &str,
+    log_entry: &LogEntry,
+) -> Result<Descriptor, anyhow::Error> {
+    let mut annotations = BTreeMap::new();
+    annotations.insert(SIGNATURE_ANNOTATION.to_string(), base64::encode(signature));
+    annotations.insert(CERTIFICATE_ANNOTATION.to_string(), cert_pem.to_string());
+    annotations.insert(
+        BUNDLE_ANNOTATION.to_string(),
+        serde_json::to_string(&RekorBundle::from_log_entry(log_entry)?)?,
+    );
+    let mut layer = Descriptor::for_data(SIMPLE_SIGNING_MEDIA_TYPE, payload);
+    layer.annotations = Some(annotations);
+    Ok(layer)
+}
+
+/// Describes the layer for one attestation, `envelope` being the DSSE
+/// envelope JSON.
+pub fn attestation_layer(
+    envelope: &[u8],
+    predicate_type: &str,
+    cert_pem: &str,
+    log_entry: &LogEntry,
+) -> Result<Descriptor, anyhow::Error> {
+    let mut annotations = BTreeMap::new();
+    // the signature is inside the envelope, cosign still sets the annotation
+    annotations.insert(SIGNATURE_ANNOTATION.to_string(), String::new());
+    annotations.insert(
+        PREDICATE_TYPE_ANNOTATION.to_string(),
+        predicate_type.to_string(),
+    );
+    annotations.insert(CERTIFICATE_ANNOTATION.to_string(), cert_pem.to_string());
+    annotations.insert(
+        BUNDLE_ANNOTATION.to_string(),
+        serde_json::to_string(&RekorBundle::from_log_entry(log_entry)?)?,
+    );
+    let mut layer = Descriptor::for_data(DSSE_MEDIA_TYPE, envelope);
+    layer.annotations = Some(annotations);
+    Ok(layer)
+}
+
+// cosign lists the layers again as the diff ids of an otherwise empty config
+fn config(layers: &[Descriptor]) -> Result<Vec<u8>, anyhow::Error> {
+    let diff_ids: Vec<&str> = layers.iter().map(|layer| layer.digest.as_str()).collect();
+    Ok(serde_json::to_vec(&serde_json::json!({
+        "architecture": "",
+        "config": {},
+        "created": "0001-01-01T00:00:00Z",
+        "history": [{"created": "0001-01-01T00:00:00Z"}],
+        "os": "",
+        "rootfs": {"type": "layers", "diff_ids": diff_ids},
+    }))?)
+}
+
+/// Adds a signature layer for `payload` to the signatures of `image`, which
+/// must be pinned to a digest. Returns the reference the signature was
+/// pushed to.
+///
+/// Registries with the referrers API get the signature as a referrer of the
+/// image, the others under the signature tag, keeping the signatures
+/// already stored there.
+pub async fn attach_signature(
+    client: &Client,
+    image: &Reference,
+    payload: &[u8],
+    layer: Descriptor,
+) -> Result<Reference, anyhow::Error> {
+    attach(
+        client,
+        image,
+        signature_tag,
+        SIGNATURE_ARTIFACT_TYPE,
+        payload,
+        layer,
+    )
+    .await
+}
+
+/// Like [`attach_signature`], for an attestation layer with the DSSE
+/// `envelope`.
+pub async fn attach_attestation(
+    client: &Client,
+    image: &Reference,
+    envelope: &[u8],
+    layer: Descriptor,
+) -> Result<Reference, anyhow::Error> {
+    attach(
+        client,
+        image,
+        attestation_tag,
+        DSSE_MEDIA_TYPE,
+        envelope,
+        layer,
+    )
+    .await
+}
+
+async fn attach(
+    client: &Client,
+    image: &Reference,
+    tag: fn(&str) -> String,
+    artifact_type: &str,
+    payload: &[u8],
+    layer: Descriptor,
+) -> Result<Reference, anyhow::Error> {
+    let digest = image
+        .digest
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("{} is not pinned to a digest", image))?;
+    if client.get_referrers(image, artifact_type).await?.is_some() {
+        return attach_referrer(client, image, artifact_type, payload, layer).await;
+    }
+    let tagged = image.with_tag(&tag(digest));
+
+    let mut layers = match client.get_manifest(&tagged).await? {
+        Some(manifest) => manifest.image_manifest()?.layers,
+        None => Vec::new(),
+    };
+    if layers.contains(&layer) {
+        return Ok(tagged);
+    }
+    client.put_blob(&tagged, &layer.media_type, payload).await?;
+    layers.push(layer);
+
+    let config = client
+        .put_blob(&tagged, registry::OCI_CONFIG, &config(&layers)?)
+        .await?;
+    let manifest = serde_json::to_vec(&ImageManifest::new(config, layers))?;
+    client
+        .put_manifest(&tagged, registry::OCI_MANIFEST, &manifest)
+        .await?;
+    Ok(tagged)
+}
+
+/// Pushes `payload` as the single `layer` of an artifact manifest of
+/// `artifact_type` that names `image` as its subject. Returns the reference
+/// of the artifact manifest.
+pub async fn attach_referrer(
+    client: &Client,
+    image: &Reference,
+    artifact_type: &str,
+    payload: &[u8],
+    layer: Descriptor,
+) -> Result<Reference, anyhow::Error> {
+    let subject = match client.get_manifest(image).await? {
+        Some(manifest) => manifest.descriptor(),
+        None => anyhow::bail!("{} does not exist", image),
+    };
+    client.put_blob(image, &layer.media_type, payload).await?;
+    let config = client.put_blob(image, registry::OCI_EMPTY, b"{}").await?;
+    let manifest = ImageManifest::new(config, vec![layer]).with_subject(artifact_type, subject);
+    let manifest = serde_json::to_vec(&manifest)?;
+
+    // pushed by digest, the registry lists it under the subject by itself
+    let referrer =
+        image.with_digest(&Descriptor::for_data(registry::OCI_MANIFEST, &manifest).digest);
+    client
+        .put_manifest(&referrer, registry::OCI_MANIFEST, &manifest)
+        .await?;
+    Ok(referrer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let payload = SimpleSigning::new("ghcr.io/org/app", &digest);
+        let json = String::from_utf8(payload.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"critical":{{"identity":{{"docker-reference":"ghcr.io/org/app"}},"image":{{"docker-manifest-digest":"{}"}},"type":"cosign container image signature"}},"optional":null}}"#,
+                digest
+            )
+        );
+        assert_eq!(SimpleSigning::from_json(json.as_bytes()).unwrap(), payload);
+        let annotated = payload.with_annotation("build", "42");
+        assert!(String::from_utf8(annotated.to_json().unwrap())
+            .unwrap()
+            .ends_with(r#""optional":{"build":"42"}}"#));
+        assert_eq!(annotated.annotation("build"), Some("42"));
+        assert_eq!(annotated.annotation("commit"), None);
+        assert_eq!(
+            signature_tag(&digest),
+            format!("sha256-{}.sig", "a".repeat(64))
+        );
+        assert_eq!(
+            attestation_tag(&digest),
+            format!("sha256-{}.att", "a".repeat(64))
+        );
+    }
+
+    #[test]
+    fn test_signature_from_layer() {
+        let log_entry = LogEntry {
+            uuid: "abc".to_string(),
+            body: "Ym9keQ==".to_string(),
+            integrated_time: 1650000000,
+            log_id: "c0d23d6ad406973f".to_string(),
+            log_index: 42,
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some("c2V0".to_string()),
+            }),
+        };
+        let payload = b"payload".to_vec();
+        let layer = signature_layer(&payload, b"sig", "PEM", &log_entry).unwrap();
+
+        let signature = ImageSignature::from_layer(&layer, payload.clone()).unwrap();
+        assert_eq!(signature.payload, payload);
+        assert_eq!(signature.signature, b"sig");
+        assert_eq!(signature.cert_pem.as_deref(), Some("PEM"));
+        let bundled = signature.bundle.unwrap().to_log_entry();
+        assert_eq!(bundled.body, log_entry.body);
+        assert_eq!(bundled.log_index, 42);
+        assert_eq!(bundled.verification, log_entry.verification);
+
+        let unsigned = Descriptor::for_data(SIMPLE_SIGNING_MEDIA_TYPE, &payload);
+        assert!(ImageSignature::from_layer(&unsigned, payload).is_err());
+    }
+}
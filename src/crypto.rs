@@ -9,6 +9,8 @@ use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::PathBuf;
 
+/// Generates an ephemeral P-256 key pair, returning the private key and the
+/// PEM encoded public key.
 pub fn create_keys() -> Result<(PKey<Private>, String), anyhow::Error> {
     let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
     let key_pair = EcKey::generate(&group).unwrap();
@@ -20,11 +22,18 @@ pub fn create_keys() -> Result<(PKey<Private>, String), anyhow::Error> {
     Ok((private_key, String::from_utf8(public_key_pem.to_vec())?))
 }
 
+/// Creates an ECDSA-SHA256 signer for `key`.
 pub fn create_signer(key: &PKey<Private>) -> Result<Signer<'_>, openssl::error::ErrorStack> {
     let signer = Signer::new(MessageDigest::sha256(), key).unwrap();
     Ok(signer)
 }
 
+/// Hex encoded sha256 digest of `data`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    HEXLOWER.encode(Sha256::digest(data).as_ref())
+}
+
+/// Hex encoded sha256 digest of the file at `path`.
 pub fn sha256_digest(path: PathBuf) -> Result<String, anyhow::Error> {
     let input = File::open(path)?;
     let mut reader = BufReader::new(input);
@@ -69,4 +78,13 @@ mod tests {
             "6c3b04483dacd643f7cd12086d817e0a9233a2192ba2030c64049d2952f198b5"
         );
     }
+    // test sha256_hex
+    #[test]
+    fn test_sha256_hex() {
+        let data = std::fs::read("test_data/test_digest.txt").unwrap();
+        assert_eq!(
+            sha256_hex(&data),
+            "6c3b04483dacd643f7cd12086d817e0a9233a2192ba2030c64049d2952f198b5"
+        );
+    }
 }
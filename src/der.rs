@@ -0,0 +1,149 @@
+//! Just enough DER to pick certificates and timestamps apart, openssl does
+//! not expose arbitrary extensions, the raw TBS certificate or RFC 3161
+//! structures.
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+/// `[0] EXPLICIT`, used for the content of CMS structures.
+pub const TAG_CONTEXT_0: u8 = 0xa0;
+pub const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// A single DER element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub value: &'a [u8],
+    /// The whole element, header included.
+    pub raw: &'a [u8],
+}
+
+/// Parses the element at the start of `input`, returning it and the
+/// remaining bytes.
+pub fn parse(input: &[u8]) -> Result<(Tlv<'_>, &[u8]), anyhow::Error> {
+    if input.len() < 2 {
+        anyhow::bail!("truncated DER element");
+    }
+    let tag = input[0];
+    if tag & 0x1f == 0x1f {
+        anyhow::bail!("multi byte DER tags are not supported");
+    }
+    let (length, header_len) = match input[1] {
+        l if l < 0x80 => (l as usize, 2),
+        0x80 => anyhow::bail!("indefinite length is not valid DER"),
+        l => {
+            let count = (l & 0x7f) as usize;
+            if count > 4 || input.len() < 2 + count {
+                anyhow::bail!("invalid DER length");
+            }
+            let length = input[2..2 + count]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (length, 2 + count)
+        }
+    };
+    let end = header_len
+        .checked_add(length)
+        .filter(|end| *end <= input.len())
+        .ok_or_else(|| anyhow::anyhow!("DER element overruns its input"))?;
+    let tlv = Tlv {
+        tag,
+        value: &input[header_len..end],
+        raw: &input[..end],
+    };
+    Ok((tlv, &input[end..]))
+}
+
+/// Parses `input` as a single element of the given `tag`.
+pub fn expect(input: &[u8], tag: u8) -> Result<Tlv<'_>, anyhow::Error> {
+    let (tlv, _) = parse(input)?;
+    if tlv.tag != tag {
+        anyhow::bail!("expected DER tag {:#04x}, found {:#04x}", tag, tlv.tag);
+    }
+    Ok(tlv)
+}
+
+/// Parses all elements contained in a constructed value.
+pub fn children(mut value: &[u8]) -> Result<Vec<Tlv<'_>>, anyhow::Error> {
+    let mut elements = Vec::new();
+    while !value.is_empty() {
+        let (tlv, rest) = parse(value)?;
+        elements.push(tlv);
+        value = rest;
+    }
+    Ok(elements)
+}
+
+/// Encodes an element with a definite length.
+pub fn encode(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let length = value.len();
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        let bytes: Vec<u8> = length
+            .to_be_bytes()
+            .iter()
+            .copied()
+            .skip_while(|b| *b == 0)
+            .collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+/// Renders an encoded OID in dotted form.
+pub fn oid_to_string(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut value: u64 = 0;
+    for b in oid {
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = std::cmp::min(value / 40, 2);
+                arcs.push(first);
+                arcs.push(value - first * 40);
+            } else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+    arcs.iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_encode() {
+        let long_value = vec![0u8; 300];
+        for value in [&b"ohhai"[..], &long_value[..]] {
+            let encoded = encode(TAG_OCTET_STRING, value);
+            let (tlv, rest) = parse(&encoded).unwrap();
+            assert_eq!(tlv.tag, TAG_OCTET_STRING);
+            assert_eq!(tlv.value, value);
+            assert_eq!(tlv.raw, &encoded[..]);
+            assert!(rest.is_empty());
+        }
+        assert!(parse(&[TAG_SEQUENCE, 0x05, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_oid_to_string() {
+        // 1.3.6.1.4.1.57264.1.1
+        let oid = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x01];
+        assert_eq!(oid_to_string(&oid), "1.3.6.1.4.1.57264.1.1");
+    }
+}
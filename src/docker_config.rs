@@ -0,0 +1,206 @@
+//! Registry credentials the way docker keeps them: `~/.docker/config.json`
+//! with inline `auths`, a default `credsStore` and per registry
+//! `credHelpers`, the latter two being `docker-credential-<name>` programs.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+// docker hub credentials are stored under its v1 API url
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+/// The parts of the docker configuration that say how to log in.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    #[serde(default)]
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct AuthEntry {
+    /// Base64 of `username:password`.
+    #[serde(default)]
+    auth: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+/// `$DOCKER_CONFIG/config.json`, or `~/.docker/config.json`.
+pub fn config_path() -> Result<PathBuf, anyhow::Error> {
+    let dir = match env::var_os("DOCKER_CONFIG") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME is not set"))?)
+            .join(".docker"),
+    };
+    Ok(dir.join("config.json"))
+}
+
+impl DockerConfig {
+    /// Reads the configuration from [`config_path`], an empty one if there
+    /// is no file.
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let path = config_path()?;
+        match fs::read(&path) {
+            Ok(json) => Self::from_json(&json)
+                .map_err(|e| anyhow::anyhow!("could not parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DockerConfig::default()),
+            Err(e) => anyhow::bail!("could not read {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    /// Username and password for `registry`, as docker would pick them: the
+    /// registry's helper, else the default store, else the inline entry.
+    pub async fn credentials(
+        &self,
+        registry: &str,
+    ) -> Result<Option<(String, String)>, anyhow::Error> {
+        if let Some(helper) = self.cred_helpers.get(registry) {
+            return helper_credentials(helper, &server_url(registry)).await;
+        }
+        if let Some(store) = &self.creds_store {
+            if let Some(credentials) = helper_credentials(store, &server_url(registry)).await? {
+                return Ok(Some(credentials));
+            }
+        }
+        self.inline_credentials(registry)
+    }
+
+    fn inline_credentials(
+        &self,
+        registry: &str,
+    ) -> Result<Option<(String, String)>, anyhow::Error> {
+        let entry = self
+            .auths
+            .iter()
+            .find(|(server, _)| registry_of(server) == registry)
+            .map(|(_, entry)| entry);
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let (Some(username), Some(password)) = (&entry.username, &entry.password) {
+            return Ok(Some((username.clone(), password.clone())));
+        }
+        match &entry.auth {
+            Some(auth) => {
+                let auth = String::from_utf8(base64::decode(auth)?)?;
+                let (username, password) = auth
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("malformed auth entry for {}", registry))?;
+                Ok(Some((username.to_string(), password.to_string())))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+// what docker login stores the credentials for `registry` under
+fn server_url(registry: &str) -> String {
+    if registry == DOCKER_HUB {
+        DOCKER_HUB_SERVER.to_string()
+    } else {
+        registry.to_string()
+    }
+}
+
+// auths keys may be bare hosts or urls, docker hub has several spellings
+fn registry_of(server: &str) -> &str {
+    let host = server
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => DOCKER_HUB,
+        _ => host,
+    }
+}
+
+async fn helper_credentials(
+    helper: &str,
+    server_url: &str,
+) -> Result<Option<(String, String)>, anyhow::Error> {
+    let program = format!("docker-credential-{}", helper);
+    let mut child = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("could not run {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server_url.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        // helpers report a missing entry on stdout and fail
+        let message = String::from_utf8_lossy(&output.stdout);
+        if message.contains("credentials not found") {
+            return Ok(None);
+        }
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let credentials: HelperCredentials = serde_json::from_slice(&output.stdout)?;
+    Ok(Some((credentials.username, credentials.secret)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_credentials() {
+        let config = DockerConfig::from_json(
+            br#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "dXNlcjpzZWNyZXQ="},
+                    "ghcr.io": {"username": "octocat", "password": "ghp_token"},
+                    "quay.io": {}
+                },
+                "credHelpers": {"gcr.io": "gcloud"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.inline_credentials("docker.io").unwrap(),
+            Some(("user".to_string(), "secret".to_string()))
+        );
+        assert_eq!(
+            config.inline_credentials("ghcr.io").unwrap(),
+            Some(("octocat".to_string(), "ghp_token".to_string()))
+        );
+        assert_eq!(config.inline_credentials("quay.io").unwrap(), None);
+        assert_eq!(config.inline_credentials("localhost:5000").unwrap(), None);
+        assert_eq!(config.cred_helpers["gcr.io"], "gcloud");
+        assert_eq!(server_url("docker.io"), DOCKER_HUB_SERVER);
+        assert_eq!(registry_of("https://localhost:5000/v2/"), "localhost:5000");
+    }
+}
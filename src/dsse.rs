@@ -0,0 +1,114 @@
+//! Dead Simple Signing Envelopes.
+
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use serde::{Deserialize, Serialize};
+
+use crate::algorithm::SigningAlgorithm;
+use crate::canonical;
+
+/// A DSSE envelope in its JSON encoding, `payload` is base64 encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub payload_type: String,
+    pub payload: String,
+    pub signatures: Vec<Signature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    #[serde(default)]
+    pub keyid: String,
+    /// Base64 encoded signature over the PAE of the payload.
+    pub sig: String,
+}
+
+/// The pre-authentication encoding that DSSE signatures are made over, with
+/// the lengths in bytes as ASCII decimals.
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+impl Envelope {
+    /// Signs `payload` with `key`, producing an envelope with one signature.
+    pub fn sign(
+        payload_type: &str,
+        payload: &[u8],
+        key: &PKey<Private>,
+    ) -> Result<Self, anyhow::Error> {
+        let signature = SigningAlgorithm::for_key(key)?.sign(key, &pae(payload_type, payload))?;
+        Ok(Envelope {
+            payload_type: payload_type.to_string(),
+            payload: base64::encode(payload),
+            signatures: vec![Signature {
+                keyid: String::new(),
+                sig: base64::encode(signature),
+            }],
+        })
+    }
+
+    /// The decoded payload.
+    pub fn payload(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(base64::decode(&self.payload)?)
+    }
+
+    /// Checks that at least one signature verifies under `key`.
+    pub fn verify(&self, key: &PKeyRef<Public>) -> Result<(), anyhow::Error> {
+        let algorithm = SigningAlgorithm::for_key(key)?;
+        let pae = pae(&self.payload_type, &self.payload()?);
+        for signature in &self.signatures {
+            if algorithm.verify(key, &pae, &base64::decode(&signature.sig)?)? {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("no envelope signature verifies with the signing key")
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    /// Canonical JSON, as the envelope is embedded in Rekor dsse entries.
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        canonical::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pae() {
+        // test vector from the DSSE protocol description
+        assert_eq!(
+            pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+        // lengths count bytes, not characters
+        assert_eq!(
+            pae("t\u{fc}p", "h\u{e9}".as_bytes()),
+            "DSSEv1 4 t\u{fc}p 3 h\u{e9}".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (private_key, public_key_pem) = SigningAlgorithm::Ed25519.generate().unwrap();
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+        let envelope = Envelope::sign("text/plain", b"ohhai", &private_key).unwrap();
+        envelope.verify(&public_key).unwrap();
+
+        let mut tampered = envelope.clone();
+        tampered.payload = base64::encode("lolwut");
+        assert!(tampered.verify(&public_key).is_err());
+    }
+}
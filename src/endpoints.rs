@@ -0,0 +1,79 @@
+use crate::{fulcio, oauth, rekor_api, tuf};
+
+pub const FULCIO_STAGING_URL: &str = "https://fulcio.sigstage.dev";
+pub const REKOR_STAGING_URL: &str = "https://rekor.sigstage.dev";
+pub const SIGSTORE_STAGING_OAUTH_URL: &str = "https://oauth2.sigstage.dev/auth";
+
+// where the docker-compose files of fulcio and rekor publish their services
+pub const LOCAL_FULCIO_PORT: u16 = 5555;
+pub const LOCAL_REKOR_PORT: u16 = 3000;
+pub const LOCAL_DEX_PORT: u16 = 8888;
+
+/// The set of services making up a sigstore deployment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    pub fulcio_url: String,
+    pub rekor_url: String,
+    pub oidc_issuer: String,
+    /// TUF repository distributing the deployment's trust root, if it has
+    /// one.
+    pub tuf_url: Option<String>,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints::production()
+    }
+}
+
+impl Endpoints {
+    /// The public sigstore instance.
+    pub fn production() -> Self {
+        Endpoints {
+            fulcio_url: fulcio::FULCIO_URL.to_string(),
+            rekor_url: rekor_api::REKOR_URL.to_string(),
+            oidc_issuer: oauth::SIGSTORE_OAUTH_URL.to_string(),
+            tuf_url: Some(tuf::SIGSTORE_TUF_URL.to_string()),
+        }
+    }
+
+    /// The public sigstore staging instance, for testing integrations
+    /// without writing to the production log.
+    pub fn staging() -> Self {
+        Endpoints {
+            fulcio_url: FULCIO_STAGING_URL.to_string(),
+            rekor_url: REKOR_STAGING_URL.to_string(),
+            oidc_issuer: SIGSTORE_STAGING_OAUTH_URL.to_string(),
+            tuf_url: Some(tuf::SIGSTORE_STAGING_TUF_URL.to_string()),
+        }
+    }
+
+    /// A development deployment on localhost, as the docker-compose files
+    /// of fulcio and rekor and sigstore-scaffolding run it, with Dex as the
+    /// OIDC issuer. Over https if its certificates are trusted, its trust
+    /// root is whatever Fulcio and Rekor serve.
+    pub fn local(https: bool) -> Self {
+        let scheme = if https { "https" } else { "http" };
+        let url = |port| format!("{}://localhost:{}", scheme, port);
+        Endpoints {
+            fulcio_url: url(LOCAL_FULCIO_PORT),
+            rekor_url: url(LOCAL_REKOR_PORT),
+            oidc_issuer: format!("{}/auth", url(LOCAL_DEX_PORT)),
+            tuf_url: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local() {
+        let local = Endpoints::local(false);
+        assert_eq!(local.fulcio_url, "http://localhost:5555");
+        assert_eq!(local.oidc_issuer, "http://localhost:8888/auth");
+        assert_eq!(Endpoints::local(true).rekor_url, "https://localhost:3000");
+        assert_eq!(local.tuf_url, None);
+    }
+}
@@ -0,0 +1,90 @@
+//! Exit codes of the command line tool, so that pipelines can tell a bad
+//! signature from a service that could not be reached.
+//!
+//! | code | meaning                                                   |
+//! |------|-----------------------------------------------------------|
+//! | 0    | success                                                   |
+//! | 1    | a signature, certificate, policy or log proof was invalid |
+//! | 2    | the command line or configuration was invalid             |
+//! | 3    | Fulcio, Rekor, an OIDC issuer or registry failed          |
+//! | 4    | a local file could not be read or written                 |
+//! | 5    | anything else                                             |
+
+use std::fmt;
+use std::io;
+
+pub const VERIFICATION_FAILED: i32 = 1;
+pub const USAGE: i32 = 2;
+pub const SERVICE: i32 = 3;
+pub const IO: i32 = 4;
+pub const FAILURE: i32 = 5;
+
+/// Context marking an error as a failed verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerificationFailed;
+
+impl fmt::Display for VerificationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("verification failed")
+    }
+}
+
+/// An invalid combination of flags, or a value clap could not check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError(String);
+
+impl UsageError {
+    pub fn new(message: impl Into<String>) -> Self {
+        UsageError(message.into())
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// The exit code for `error`. What went wrong underneath takes precedence
+/// over the verification it happened in, a Rekor outage is not a bad
+/// signature.
+pub fn code(error: &anyhow::Error) -> i32 {
+    for cause in error.chain() {
+        if cause.is::<UsageError>() || cause.is::<clap::Error>() {
+            return USAGE;
+        }
+        if cause.is::<reqwest::Error>() {
+            return SERVICE;
+        }
+        if cause.is::<io::Error>() {
+            return IO;
+        }
+    }
+    if error.downcast_ref::<VerificationFailed>().is_some() {
+        VERIFICATION_FAILED
+    } else {
+        FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        let invalid = anyhow::anyhow!("Signature verification failed");
+        assert_eq!(code(&invalid), FAILURE);
+        assert_eq!(
+            code(&invalid.context(VerificationFailed)),
+            VERIFICATION_FAILED
+        );
+
+        let missing = anyhow::Error::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(code(&missing.context(VerificationFailed)), IO);
+        let usage = anyhow::Error::from(UsageError::new("--offline requires --root"));
+        assert_eq!(code(&usage.context(VerificationFailed)), USAGE);
+    }
+}
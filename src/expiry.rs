@@ -0,0 +1,70 @@
+//! Expiration claims, so that short lived artifacts such as preview builds
+//! stop verifying after a while instead of being accepted forever.
+//!
+//! The claim is a DSSE envelope signed with the same key as the artifact,
+//! carried next to the signature in the bundle. Nothing stops the bundle
+//! from being passed on without the claim, verifiers that need an upper
+//! bound regardless set a maximum age in their policy.
+
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use serde::{Deserialize, Serialize};
+
+use crate::bundle::HashOutput;
+use crate::dsse::Envelope;
+
+pub const PAYLOAD_TYPE: &str = "application/vnd.dev.ferris-sign.expiry+json";
+
+/// When the signature over an artifact stops being valid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claim {
+    /// Digest of the artifact, as in the message signature of the bundle.
+    pub digest: HashOutput,
+    /// Seconds since the epoch.
+    pub expires: i64,
+}
+
+impl Claim {
+    pub fn sign(&self, key: &PKey<Private>) -> Result<Envelope, anyhow::Error> {
+        Envelope::sign(PAYLOAD_TYPE, &serde_json::to_vec(self)?, key)
+    }
+
+    /// Checks that `envelope` is a claim signed with `key`.
+    pub fn verify(envelope: &Envelope, key: &PKeyRef<Public>) -> Result<Self, anyhow::Error> {
+        if envelope.payload_type != PAYLOAD_TYPE {
+            anyhow::bail!(
+                "envelope payload type {} is not an expiration claim",
+                envelope.payload_type
+            );
+        }
+        envelope.verify(key)?;
+        Ok(serde_json::from_slice(&envelope.payload()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::SigningAlgorithm;
+
+    #[test]
+    fn test_claim() {
+        let (key, public_key_pem) = SigningAlgorithm::default().generate().unwrap();
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+        let claim = Claim {
+            digest: HashOutput {
+                algorithm: String::from("SHA2_256"),
+                digest: base64::encode([0xab; 32]),
+            },
+            expires: 1700000000,
+        };
+        let envelope = claim.sign(&key).unwrap();
+        assert_eq!(Claim::verify(&envelope, &public_key).unwrap(), claim);
+
+        let (other, _) = SigningAlgorithm::default().generate().unwrap();
+        let other = PKey::public_key_from_der(&other.public_key_to_der().unwrap()).unwrap();
+        assert!(Claim::verify(&envelope, &other).is_err());
+        let mut statement = envelope;
+        statement.payload_type = String::from("application/vnd.in-toto+json");
+        assert!(Claim::verify(&statement, &public_key).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::x509::X509;
+use std::str::FromStr;
+
+use crate::algorithm::SigningAlgorithm;
+use crate::bundle::Bundle;
+
+/// On disk layout of signatures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignatureFormat {
+    /// DER encoded signature bytes, an ASN.1 structure for ECDSA.
+    #[default]
+    Der,
+    /// Fixed size `r || s` for ECDSA, as JWS and PKCS#11 use them. Other
+    /// signatures have no other encoding, they are written as they are.
+    Raw,
+    /// Base64 of the DER signature, as written and read by `cosign sign-blob`.
+    Base64,
+    /// Hex of the DER signature.
+    Hex,
+}
+
+impl FromStr for SignatureFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "der" => Ok(SignatureFormat::Der),
+            "raw" => Ok(SignatureFormat::Raw),
+            "base64" | "cosign" => Ok(SignatureFormat::Base64),
+            "hex" => Ok(SignatureFormat::Hex),
+            _ => anyhow::bail!("unknown signature format: {}", s),
+        }
+    }
+}
+
+// size of r and s in fixed size ecdsa signatures
+fn component_len(algorithm: SigningAlgorithm) -> Option<usize> {
+    match algorithm {
+        SigningAlgorithm::EcdsaP256Sha256 => Some(32),
+        SigningAlgorithm::EcdsaP384Sha384 => Some(48),
+        _ => None,
+    }
+}
+
+impl SignatureFormat {
+    /// Encodes the DER `signature`, made with `algorithm`.
+    pub fn encode_signature(
+        &self,
+        signature: &[u8],
+        algorithm: SigningAlgorithm,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            SignatureFormat::Der => Ok(signature.to_vec()),
+            SignatureFormat::Raw => match component_len(algorithm) {
+                Some(len) => {
+                    let signature = EcdsaSig::from_der(signature)?;
+                    let mut raw = signature.r().to_vec_padded(len as i32)?;
+                    raw.extend(signature.s().to_vec_padded(len as i32)?);
+                    Ok(raw)
+                }
+                None => Ok(signature.to_vec()),
+            },
+            SignatureFormat::Base64 => Ok(base64::encode(signature).into_bytes()),
+            SignatureFormat::Hex => Ok(data_encoding::HEXLOWER.encode(signature).into_bytes()),
+        }
+    }
+
+    /// Decodes `data` to the DER signature of `algorithm`.
+    pub fn decode_signature(
+        &self,
+        data: &[u8],
+        algorithm: SigningAlgorithm,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            SignatureFormat::Der => Ok(data.to_vec()),
+            SignatureFormat::Raw => match component_len(algorithm) {
+                Some(len) => {
+                    if data.len() != 2 * len {
+                        anyhow::bail!(
+                            "raw {} signatures are {} bytes, not {}",
+                            algorithm,
+                            2 * len,
+                            data.len()
+                        );
+                    }
+                    let (r, s) = data.split_at(len);
+                    let signature = EcdsaSig::from_private_components(
+                        BigNum::from_slice(r)?,
+                        BigNum::from_slice(s)?,
+                    )?;
+                    Ok(signature.to_der()?)
+                }
+                None => Ok(data.to_vec()),
+            },
+            SignatureFormat::Base64 => {
+                let data = String::from_utf8(data.to_vec())?;
+                Ok(base64::decode(data.trim())?)
+            }
+            SignatureFormat::Hex => {
+                let data = String::from_utf8(data.to_vec())?;
+                Ok(data_encoding::HEXLOWER_PERMISSIVE.decode(data.trim().as_bytes())?)
+            }
+        }
+    }
+}
+
+/// On disk layout of certificates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CertificateFormat {
+    #[default]
+    Pem,
+    Der,
+}
+
+impl FromStr for CertificateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pem" => Ok(CertificateFormat::Pem),
+            "der" => Ok(CertificateFormat::Der),
+            _ => anyhow::bail!("unknown certificate format: {}", s),
+        }
+    }
+}
+
+impl CertificateFormat {
+    pub fn encode_certificate(&self, cert_pem: &str) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            CertificateFormat::Pem => Ok(cert_pem.as_bytes().to_vec()),
+            CertificateFormat::Der => Ok(X509::from_pem(cert_pem.as_bytes())?.to_der()?),
+        }
+    }
+}
+
+/// On disk layout of Sigstore bundles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BundleFormat {
+    /// The protobuf-specs JSON encoding, as version 0.2.
+    #[default]
+    Json,
+    /// The binary protobuf encoding, as version 0.3.
+    Protobuf,
+}
+
+impl FromStr for BundleFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(BundleFormat::Json),
+            "protobuf" => Ok(BundleFormat::Protobuf),
+            _ => anyhow::bail!("unknown bundle format: {}", s),
+        }
+    }
+}
+
+impl BundleFormat {
+    pub fn encode_bundle(&self, bundle: &Bundle) -> Result<Vec<u8>, anyhow::Error> {
+        match self {
+            BundleFormat::Json => Ok(bundle.to_json()?.into_bytes()),
+            BundleFormat::Protobuf => bundle.to_v03()?.to_protobuf(),
+        }
+    }
+
+    /// File name extension of bundles, `.sigstore.json` as cosign writes
+    /// them for JSON.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BundleFormat::Json => "sigstore.json",
+            BundleFormat::Protobuf => "sigstore.pb",
+        }
+    }
+}
+
+/// Reads a certificate in either format.
+pub fn decode_certificate(data: &[u8]) -> Result<X509, anyhow::Error> {
+    match X509::from_pem(data) {
+        Ok(cert) => Ok(cert),
+        Err(_) => Ok(X509::from_der(data)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::HashAlgorithm;
+    use crate::verify::verify_blob;
+
+    #[test]
+    fn test_signature_format_round_trip() {
+        let algorithm = SigningAlgorithm::EcdsaP256Sha256;
+        let (key, _) = algorithm.generate().unwrap();
+        let signature = algorithm.sign(&key, b"lolwut").unwrap();
+        for format in [
+            SignatureFormat::Der,
+            SignatureFormat::Raw,
+            SignatureFormat::Base64,
+            SignatureFormat::Hex,
+        ] {
+            let encoded = format.encode_signature(&signature, algorithm).unwrap();
+            assert_eq!(
+                format.decode_signature(&encoded, algorithm).unwrap(),
+                signature
+            );
+        }
+        let raw = SignatureFormat::Raw
+            .encode_signature(&signature, algorithm)
+            .unwrap();
+        assert_eq!(raw.len(), 64);
+        assert_eq!(
+            SignatureFormat::Base64
+                .encode_signature(b"lolwut", SigningAlgorithm::Ed25519)
+                .unwrap(),
+            b"bG9sd3V0"
+        );
+    }
+
+    #[test]
+    fn test_certificate_format() {
+        let cert_pem = std::fs::read_to_string("test_data/signing_cert.pem").unwrap();
+        let der = CertificateFormat::Der
+            .encode_certificate(&cert_pem)
+            .unwrap();
+        assert_eq!(
+            decode_certificate(&der).unwrap().to_der().unwrap(),
+            decode_certificate(cert_pem.as_bytes())
+                .unwrap()
+                .to_der()
+                .unwrap()
+        );
+    }
+
+    // artifacts laid out the way cosign sign-blob writes them
+    #[test]
+    fn test_verify_cosign_layout() {
+        let blob = std::fs::read("test_data/test_digest.txt").unwrap();
+        let signature = SignatureFormat::Base64
+            .decode_signature(
+                &std::fs::read("test_data/cosign/test_digest.txt.sig").unwrap(),
+                SigningAlgorithm::EcdsaP256Sha256,
+            )
+            .unwrap();
+        let cert = X509::from_pem(&std::fs::read("test_data/cosign/test_digest.txt.pem").unwrap())
+            .unwrap();
+        let chain =
+            X509::stack_from_pem(&std::fs::read("test_data/fulcio_chain.pem").unwrap()).unwrap();
+        verify_blob(&cert, &chain, HashAlgorithm::Sha256, &blob, &signature).unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+use base64::encode;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private, Public};
+use openssl::x509::{X509NameBuilder, X509ReqBuilder, X509VerifyResult, X509};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::algorithm::SigningAlgorithm;
+use crate::http::{self, Service};
+
+/// Public Fulcio instance.
+pub const FULCIO_URL: &str = "https://fulcio.sigstore.dev";
+
+const SIGNING_CERT_PATH: &str = "/api/v1/signingCert";
+const ROOT_CERT_PATH: &str = "/api/v1/rootCert";
+pub(crate) const SIGNING_CERT_V2_PATH: &str = "/api/v2/signingCert";
+pub(crate) const TRUST_BUNDLE_PATH: &str = "/api/v2/trustBundle";
+
+/// The Fulcio API signing certificates are requested through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FulcioApi {
+    /// `/api/v2`, which takes a certificate signing request.
+    #[default]
+    V2,
+    /// The deprecated `/api/v1`, which takes the public key and the signed
+    /// email address, for deployments that predate v2.
+    V1,
+}
+
+impl FromStr for FulcioApi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(FulcioApi::V1),
+            "v2" => Ok(FulcioApi::V2),
+            _ => anyhow::bail!("unknown Fulcio API version: {}", s),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FulcioPayload {
+    pub public_key: PubKey,
+    pub signed_email_address: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PubKey {
+    pub algorithm: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SigningCertRequest {
+    credentials: Credentials,
+    /// Base64 of the PEM encoded CSR.
+    certificate_signing_request: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Credentials {
+    oidc_identity_token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SigningCertResponse {
+    #[serde(default)]
+    signed_certificate_embedded_sct: Option<SignedCertificate>,
+    #[serde(default)]
+    signed_certificate_detached_sct: Option<SignedCertificate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignedCertificate {
+    chain: CertificateChain,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateChain {
+    /// PEM encoded certificates.
+    certificates: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrustBundle {
+    chains: Vec<CertificateChain>,
+}
+
+/// A certificate issued by Fulcio along with the rest of the chain Fulcio
+/// returned.
+#[derive(Debug, Clone)]
+pub struct SigningCertificate {
+    /// The leaf certificate, PEM encoded.
+    pub cert_pem: String,
+    pub chain: Vec<X509>,
+}
+
+/// Requests a signing certificate for `public_key_pem`, a key for
+/// `algorithm`.
+///
+/// `proof` is the email address from the identity token signed with the
+/// matching private key.
+pub async fn request_signing_cert(
+    fulcio_url: &str,
+    id_token: &str,
+    public_key_pem: &str,
+    algorithm: SigningAlgorithm,
+    proof: &[u8],
+) -> Result<SigningCertificate, anyhow::Error> {
+    let params = FulcioPayload {
+        public_key: PubKey {
+            content: encode(public_key_pem),
+            algorithm: algorithm.fulcio_algorithm().to_string(),
+        },
+        signed_email_address: encode(proof),
+    };
+    let body = serde_json::to_string(&params)?;
+
+    let client = http::client(Service::Fulcio);
+    let response = http::send(
+        client
+            .post(format!("{}{}", fulcio_url, SIGNING_CERT_PATH))
+            .header("Authorization", format!("Bearer {}", id_token))
+            .header("Content-Type", "application/json")
+            .body(body),
+    )
+    .await?;
+    let certs = response.text().await?;
+
+    let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+    parse_chain(&certs, &public_key)
+}
+
+/// A PEM encoded certificate signing request for `email`, signed with
+/// `private_key` to prove possession of it.
+pub fn csr(private_key: &PKeyRef<Private>, email: &str) -> Result<String, anyhow::Error> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", email)?;
+    let mut builder = X509ReqBuilder::new()?;
+    builder.set_subject_name(&name.build())?;
+    builder.set_pubkey(private_key)?;
+    // ed25519 signs the message itself
+    let digest = match SigningAlgorithm::for_key(private_key)? {
+        SigningAlgorithm::Ed25519 => MessageDigest::null(),
+        SigningAlgorithm::EcdsaP384Sha384 => MessageDigest::sha384(),
+        SigningAlgorithm::EcdsaP256Sha256 | SigningAlgorithm::RsaPssSha256 => {
+            MessageDigest::sha256()
+        }
+    };
+    builder.sign(private_key, digest)?;
+    Ok(String::from_utf8(builder.build().to_pem()?)?)
+}
+
+/// Requests a signing certificate for the key that signed `csr_pem`
+/// through the v2 API.
+pub async fn request_signing_cert_v2(
+    fulcio_url: &str,
+    id_token: &str,
+    csr_pem: &str,
+    public_key: &PKeyRef<Public>,
+) -> Result<SigningCertificate, anyhow::Error> {
+    let body = serde_json::to_string(&SigningCertRequest {
+        credentials: Credentials {
+            oidc_identity_token: id_token.to_string(),
+        },
+        certificate_signing_request: encode(csr_pem),
+    })?;
+
+    let client = http::client(Service::Fulcio);
+    let response = http::send(
+        client
+            .post(format!("{}{}", fulcio_url, SIGNING_CERT_V2_PATH))
+            .header("Content-Type", "application/json")
+            .body(body),
+    )
+    .await?;
+    let response = response.text().await?;
+    parse_chain(&response_chain(&response)?, public_key)
+}
+
+// the pem chain of a v2 response, whether the sct is embedded or not
+fn response_chain(response: &str) -> Result<String, anyhow::Error> {
+    let parsed: SigningCertResponse = serde_json::from_str(response)
+        .map_err(|e| anyhow::anyhow!("unexpected Fulcio response ({}): {}", e, response))?;
+    let signed = parsed
+        .signed_certificate_embedded_sct
+        .or(parsed.signed_certificate_detached_sct)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Fulcio response did not contain a signing certificate: {}",
+                response
+            )
+        })?;
+    Ok(signed.chain.certificates.concat())
+}
+
+// the leaf is whichever certificate certifies the key that was sent, the
+// rest is kept in the order fulcio returned it
+fn parse_chain(
+    response: &str,
+    public_key: &PKeyRef<Public>,
+) -> Result<SigningCertificate, anyhow::Error> {
+    let mut chain = X509::stack_from_pem(response.as_bytes()).map_err(|e| {
+        anyhow::anyhow!(
+            "Fulcio response is not a PEM certificate chain ({}): {}",
+            e,
+            response
+        )
+    })?;
+    let leaf = chain
+        .iter()
+        .position(|cert| cert.public_key().is_ok_and(|key| key.public_eq(public_key)))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Fulcio response did not contain a signing certificate: {}",
+                response
+            )
+        })?;
+    let leaf = chain.remove(leaf);
+    Ok(SigningCertificate {
+        cert_pem: String::from_utf8(leaf.to_pem()?)?,
+        chain,
+    })
+}
+
+/// The leaf `cert_pem` followed by the intermediates in `chain`, which is
+/// what verifiers need besides their trust root.
+pub fn chain_pem(cert_pem: &str, chain: &[X509]) -> Result<String, anyhow::Error> {
+    let mut pem = cert_pem.to_string();
+    for cert in chain {
+        // roots are self-issued, verifiers bring their own
+        if cert.issued(cert) == X509VerifyResult::OK {
+            continue;
+        }
+        pem.push_str(std::str::from_utf8(&cert.to_pem()?)?);
+    }
+    Ok(pem)
+}
+
+/// Fetches the Fulcio root certificate chain as PEM.
+pub async fn fetch_root(fulcio_url: &str, api: FulcioApi) -> Result<Vec<u8>, anyhow::Error> {
+    let path = match api {
+        FulcioApi::V1 => ROOT_CERT_PATH,
+        FulcioApi::V2 => TRUST_BUNDLE_PATH,
+    };
+    let root = http::send(http::client(Service::Fulcio).get(format!("{}{}", fulcio_url, path)))
+        .await?
+        .bytes()
+        .await?;
+    match api {
+        FulcioApi::V1 => Ok(root.to_vec()),
+        FulcioApi::V2 => {
+            let bundle: TrustBundle = serde_json::from_slice(&root)?;
+            Ok(bundle
+                .chains
+                .iter()
+                .flat_map(|chain| &chain.certificates)
+                .map(String::as_str)
+                .collect::<String>()
+                .into_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chain() {
+        let leaf = std::fs::read_to_string("test_data/signing_cert.pem").unwrap();
+        let chain = std::fs::read_to_string("test_data/fulcio_chain.pem").unwrap();
+        let public_key = X509::from_pem(leaf.as_bytes())
+            .unwrap()
+            .public_key()
+            .unwrap();
+
+        // the leaf need not come first
+        let signing_cert = parse_chain(&format!("{}{}", chain, leaf), &public_key).unwrap();
+        assert_eq!(
+            X509::from_pem(signing_cert.cert_pem.as_bytes())
+                .unwrap()
+                .to_der()
+                .unwrap(),
+            X509::from_pem(leaf.as_bytes()).unwrap().to_der().unwrap()
+        );
+        assert_eq!(
+            signing_cert.chain.len(),
+            X509::stack_from_pem(chain.as_bytes()).unwrap().len()
+        );
+        let pem = chain_pem(&signing_cert.cert_pem, &signing_cert.chain).unwrap();
+        assert!(pem.starts_with(&signing_cert.cert_pem));
+
+        let other_key = signing_cert.chain[0].public_key().unwrap();
+        assert!(parse_chain(&leaf, &other_key).is_err());
+        assert!(parse_chain("{\"code\":401}", &public_key).is_err());
+    }
+
+    #[test]
+    fn test_response_chain() {
+        let leaf = std::fs::read_to_string("test_data/signing_cert.pem").unwrap();
+        let response = serde_json::json!({
+            "signedCertificateEmbeddedSct": {"chain": {"certificates": [leaf]}}
+        });
+        assert_eq!(response_chain(&response.to_string()).unwrap(), leaf);
+        assert!(response_chain("{}").is_err());
+    }
+
+    #[test]
+    fn test_csr() {
+        for algorithm in [SigningAlgorithm::EcdsaP256Sha256, SigningAlgorithm::Ed25519] {
+            let (private_key, _) = algorithm.generate().unwrap();
+            let csr = openssl::x509::X509Req::from_pem(
+                csr(&private_key, "ferris@example.com").unwrap().as_bytes(),
+            )
+            .unwrap();
+            assert!(csr.verify(&csr.public_key().unwrap()).unwrap());
+        }
+    }
+}
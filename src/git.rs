@@ -0,0 +1,182 @@
+//! Signatures for git commits and tags in the form git expects from a
+//! `gpg.x509.program`: detached PKCS#7 signed data, armored as a
+//! `SIGNED MESSAGE`.
+//!
+//! To use it, point git at the ferris-sign binary:
+//!
+//! ```text
+//! git config gpg.format x509
+//! git config gpg.x509.program ferris-sign
+//! git config commit.gpgsign true
+//! ```
+//!
+//! The signed data has no signed attributes, so its signature is a plain
+//! signature over the commit or tag object and can be logged in Rekor like
+//! that of any other artifact.
+
+use openssl::hash::MessageDigest;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::pkey::{PKeyRef, Private};
+use openssl::stack::Stack;
+use openssl::x509::X509;
+
+use crate::der;
+
+const ARMOR_BEGIN: &str = "-----BEGIN SIGNED MESSAGE-----";
+const ARMOR_END: &str = "-----END SIGNED MESSAGE-----";
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+// signed attributes are [0] IMPLICIT in a SignerInfo
+const TAG_SIGNED_ATTRS: u8 = 0xa0;
+
+/// A parsed detached signature.
+#[derive(Debug, Clone)]
+pub struct SignedMessage {
+    /// The signing certificate embedded in the signed data.
+    pub certificate: X509,
+    /// Signature over the signed object, DER encoded for ECDSA.
+    pub signature: Vec<u8>,
+}
+
+/// Signs `data` with `key`, embedding `cert`, returning the DER encoded
+/// signed data.
+pub fn sign(cert: &X509, key: &PKeyRef<Private>, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let flags = Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY | Pkcs7Flags::NOATTR;
+    let certs = Stack::<X509>::new()?;
+    let pkcs7 = Pkcs7::sign(cert, key, &certs, data, flags)?;
+    Ok(pkcs7.to_der()?)
+}
+
+/// Armors DER encoded signed data the way gpgsm prints it.
+pub fn armor(signed_data: &[u8]) -> String {
+    let encoded = base64::encode(signed_data);
+    let mut armored = format!("{}\n", ARMOR_BEGIN);
+    for line in encoded.as_bytes().chunks(64) {
+        armored.push_str(std::str::from_utf8(line).unwrap());
+        armored.push('\n');
+    }
+    armored.push_str(ARMOR_END);
+    armored.push('\n');
+    armored
+}
+
+/// Strips the armor from a signature as git stores it.
+pub fn dearmor(armored: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let start = armored
+        .find(ARMOR_BEGIN)
+        .ok_or_else(|| anyhow::anyhow!("not an armored signed message"))?
+        + ARMOR_BEGIN.len();
+    let end = armored[start..]
+        .find(ARMOR_END)
+        .ok_or_else(|| anyhow::anyhow!("armored signed message is not terminated"))?
+        + start;
+    let encoded: String = armored[start..end]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    Ok(base64::decode(encoded)?)
+}
+
+impl SignedMessage {
+    pub fn from_der(der_bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let content_info = der::children(der::expect(der_bytes, der::TAG_SEQUENCE)?.value)?;
+        match content_info.first() {
+            Some(oid)
+                if oid.tag == der::TAG_OID && der::oid_to_string(oid.value) == OID_SIGNED_DATA => {}
+            _ => anyhow::bail!("signature is not PKCS#7 signed data"),
+        }
+        let signed_data = content_info
+            .get(1)
+            .filter(|content| content.tag == der::TAG_CONTEXT_0)
+            .ok_or_else(|| anyhow::anyhow!("signature has no signed data"))?;
+        let fields = der::children(der::expect(signed_data.value, der::TAG_SEQUENCE)?.value)?;
+
+        let certificate = fields
+            .iter()
+            .find(|field| field.tag == der::TAG_CONTEXT_0)
+            .map(|embedded| der::children(embedded.value))
+            .transpose()?
+            .and_then(|certs| certs.first().map(|cert| X509::from_der(cert.raw)))
+            .transpose()?
+            .ok_or_else(|| anyhow::anyhow!("signature has no certificate"))?;
+
+        // signerInfos is always last, only a single signer is expected
+        let signer_infos = fields
+            .last()
+            .filter(|field| field.tag == der::TAG_SET)
+            .ok_or_else(|| anyhow::anyhow!("signature has no signer"))?;
+        let signer_info = match der::children(signer_infos.value)?.as_slice() {
+            [signer_info] => der::children(signer_info.value)?,
+            _ => anyhow::bail!("signature must have exactly one signer"),
+        };
+        if signer_info
+            .iter()
+            .any(|field| field.tag == TAG_SIGNED_ATTRS)
+        {
+            anyhow::bail!("signatures with signed attributes are not supported");
+        }
+        let signature = signer_info
+            .iter()
+            .find(|field| field.tag == der::TAG_OCTET_STRING)
+            .ok_or_else(|| anyhow::anyhow!("signer has no signature"))?
+            .value
+            .to_vec();
+        Ok(SignedMessage {
+            certificate,
+            signature,
+        })
+    }
+}
+
+/// SHA-1 fingerprint of `cert` in hex, which git shows as the key id.
+pub fn fingerprint(cert: &X509) -> Result<String, anyhow::Error> {
+    let digest = cert.digest(MessageDigest::sha1())?;
+    Ok(data_encoding::HEXUPPER.encode(&digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::SigningAlgorithm;
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    #[test]
+    fn test_sign_and_parse() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "ferris").unwrap();
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = builder.build();
+
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+        let armored = armor(&sign(&cert, &key, data).unwrap());
+        assert!(armored.starts_with(ARMOR_BEGIN));
+
+        let message = SignedMessage::from_der(&dearmor(&armored).unwrap()).unwrap();
+        assert_eq!(
+            message.certificate.to_der().unwrap(),
+            cert.to_der().unwrap()
+        );
+        let public_key = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        assert!(SigningAlgorithm::EcdsaP256Sha256
+            .verify(&public_key, data, &message.signature)
+            .unwrap());
+        assert_eq!(fingerprint(&cert).unwrap().len(), 40);
+    }
+}
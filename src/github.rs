@@ -0,0 +1,161 @@
+//! The parts of the GitHub REST API needed to sign release assets: looking up
+//! a release by tag, downloading its assets and uploading new ones.
+
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Deserialize;
+
+use crate::http::{self, Service};
+
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// A release and its assets.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub id: u64,
+    pub tag_name: String,
+    pub html_url: String,
+    /// URI template ending in `{?name,label}`.
+    pub upload_url: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub id: u64,
+    pub name: String,
+    /// API url of the asset, which serves its content when asked for
+    /// `application/octet-stream`.
+    pub url: String,
+    pub size: u64,
+}
+
+/// Talks to the GitHub API, authenticated if a token is given.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new(GITHUB_API_URL)
+    }
+}
+
+impl Client {
+    pub fn new(api_url: &str) -> Self {
+        Client {
+            http: http::client(Service::Other),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Looks up the release of `repo`, given as `owner/name`, for `tag`.
+    pub async fn get_release(&self, repo: &str, tag: &str) -> Result<Release, anyhow::Error> {
+        check_repo(repo)?;
+        let url = format!("{}/repos/{}/releases/tags/{}", self.api_url, repo, tag);
+        let response = http::send(self.request(self.http.get(&url))).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            anyhow::bail!("{} has no release for tag {}", repo, tag);
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    pub async fn download_asset(&self, asset: &Asset) -> Result<Vec<u8>, anyhow::Error> {
+        let response = http::send(
+            self.request(self.http.get(&asset.url))
+                .header("Accept", "application/octet-stream"),
+        )
+        .await?
+        .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Uploads `data` as the asset `name` of `release`, replacing an asset
+    /// of that name if there is one.
+    pub async fn upload_asset(
+        &self,
+        repo: &str,
+        release: &Release,
+        name: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<Asset, anyhow::Error> {
+        check_repo(repo)?;
+        if let Some(existing) = release.assets.iter().find(|asset| asset.name == name) {
+            let url = format!(
+                "{}/repos/{}/releases/assets/{}",
+                self.api_url, repo, existing.id
+            );
+            http::send(self.request(self.http.delete(&url)))
+                .await?
+                .error_for_status()?;
+        }
+        let upload_url = release
+            .upload_url
+            .split_once('{')
+            .map_or(release.upload_url.as_str(), |(url, _)| url);
+        let asset = http::send(
+            self.request(self.http.post(upload_url))
+                .query(&[("name", name)])
+                .header("Content-Type", content_type)
+                .body(data.to_vec()),
+        )
+        .await?
+        .error_for_status()
+        .map_err(|e| anyhow::anyhow!("could not upload {}: {}", name, e))?
+        .json()
+        .await?;
+        Ok(asset)
+    }
+
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        // github rejects requests without a user agent, the client sends one
+        let request = request.header("X-GitHub-Api-Version", "2022-11-28");
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+// owner and name, anything else would end up somewhere else in the API
+fn check_repo(repo: &str) -> Result<(), anyhow::Error> {
+    match repo.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(())
+        }
+        _ => anyhow::bail!("repository {} is not of the form owner/name", repo),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_json() {
+        let release: Release = serde_json::from_str(
+            r#"{
+                "id": 1,
+                "tag_name": "v1.2.3",
+                "html_url": "https://github.com/org/proj/releases/tag/v1.2.3",
+                "upload_url": "https://uploads.github.com/repos/org/proj/releases/1/assets{?name,label}",
+                "assets": [{"id": 7, "name": "app.tar.gz", "url": "https://api.github.com/repos/org/proj/releases/assets/7", "size": 42}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(release.assets[0].name, "app.tar.gz");
+        assert!(check_repo("org/proj").is_ok());
+        assert!(check_repo("org").is_err());
+        assert!(check_repo("org/proj/../other").is_err());
+    }
+}
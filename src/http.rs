@@ -0,0 +1,406 @@
+//! The HTTP client requests to Fulcio, Rekor, OIDC issuers and registries
+//! are made with, so that they share timeouts and a proxy and are traced the
+//! same way.
+//!
+//! There is one client per [`Service`], shared by everything talking to it,
+//! so that a batch reuses its connections to the same hosts rather than
+//! setting up TLS for every request.
+//!
+//! Without [`set_proxy`], reqwest picks the proxy up from `HTTPS_PROXY`,
+//! `HTTP_PROXY` and `NO_PROXY`. Private instances behind a corporate CA or
+//! requiring client certificates are reached with [`set_tls`].
+
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{
+    Certificate, Client, Identity, NoProxy, Proxy, RequestBuilder, Response, ResponseBuilderExt,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::Level;
+
+// form parameters, json fields and headers whose values are never traced
+const SECRET_PARAMS: [&str; 8] = [
+    "code",
+    "code_verifier",
+    "device_code",
+    "refresh_token",
+    "client_secret",
+    "id_token",
+    "access_token",
+    "subject_token",
+];
+const SECRET_FIELDS: [&str; 5] = [
+    "oidcIdentityToken",
+    "access_token",
+    "id_token",
+    "refresh_token",
+    "client_secret",
+];
+const SECRET_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+const REDACTED: &str = "<redacted>";
+// larger bodies are blobs and certificates nobody wants in a terminal
+const MAX_TRACED_BODY: usize = 4096;
+// how long idle connections are kept open for the next request
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// The user agent sent unless [`set_user_agent`] is called.
+pub const DEFAULT_USER_AGENT: &str = concat!("ferris-sign/", env!("CARGO_PKG_VERSION"));
+
+/// What a client talks to, each may have its own timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Fulcio,
+    Rekor,
+    /// OIDC issuers, for logins and token refreshes.
+    Oidc,
+    /// Everything else: registries, timestamp authorities, GitHub.
+    Other,
+}
+
+// in milliseconds by service, zero for none. other doubles as the default
+// of the rest
+static TIMEOUTS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Sets the timeout of clients for `service` created from now on, `None`
+/// for no timeout. The timeout of [`Service::Other`] also applies to
+/// services without a timeout of their own.
+pub fn set_timeout(service: Service, timeout: Option<Duration>) {
+    let millis = timeout.map_or(0, |timeout| timeout.as_millis().max(1) as u64);
+    TIMEOUTS[service as usize].store(millis, Ordering::Relaxed);
+    reset_clients();
+}
+
+pub fn timeout(service: Service) -> Option<Duration> {
+    let millis = match TIMEOUTS[service as usize].load(Ordering::Relaxed) {
+        0 => TIMEOUTS[Service::Other as usize].load(Ordering::Relaxed),
+        millis => millis,
+    };
+    match millis {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+static PROXY: RwLock<Option<Proxy>> = RwLock::new(None);
+
+/// Sends the requests of clients created from now on through the proxy at
+/// `url`, except those to hosts in `NO_PROXY`.
+pub fn set_proxy(url: &str) -> Result<(), anyhow::Error> {
+    let proxy = Proxy::all(url)?.no_proxy(NoProxy::from_env());
+    if let Ok(mut current) = PROXY.write() {
+        *current = Some(proxy);
+    }
+    reset_clients();
+    Ok(())
+}
+
+static USER_AGENT: RwLock<Option<String>> = RwLock::new(None);
+
+/// Sends `user_agent` rather than [`DEFAULT_USER_AGENT`] from clients
+/// created from now on.
+pub fn set_user_agent(user_agent: &str) -> Result<(), anyhow::Error> {
+    HeaderValue::from_str(user_agent)?;
+    if let Ok(mut current) = USER_AGENT.write() {
+        *current = Some(user_agent.to_string());
+    }
+    reset_clients();
+    Ok(())
+}
+
+fn user_agent() -> String {
+    USER_AGENT
+        .read()
+        .ok()
+        .and_then(|user_agent| user_agent.clone())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string())
+}
+
+/// TLS settings beyond the system's trust store.
+#[derive(Clone, Default)]
+pub struct Tls {
+    roots: Vec<Certificate>,
+    identity: Option<Identity>,
+    insecure: bool,
+}
+
+impl Tls {
+    /// Also trusts the CA certificates in the PEM `bundle`.
+    pub fn with_ca_bundle(mut self, bundle: &[u8]) -> Result<Self, anyhow::Error> {
+        let certs = X509::stack_from_pem(bundle)?;
+        if certs.is_empty() {
+            anyhow::bail!("no certificates in the CA bundle");
+        }
+        for cert in certs {
+            self.roots.push(Certificate::from_der(&cert.to_der()?)?);
+        }
+        Ok(self)
+    }
+
+    /// Authenticates with the PEM certificate `cert` and its PEM private key.
+    pub fn with_client_identity(mut self, cert: &[u8], key: &[u8]) -> Result<Self, anyhow::Error> {
+        // reqwest only takes PKCS#8 keys, PEM files are often PKCS#1 or SEC1
+        let key = PKey::private_key_from_pem(key)?.private_key_to_pem_pkcs8()?;
+        self.identity = Some(Identity::from_pkcs8_pem(cert, &key)?);
+        Ok(self)
+    }
+
+    /// Accepts any server certificate. Anyone on the network path can then
+    /// impersonate Fulcio and Rekor.
+    pub fn with_insecure_skip_verify(mut self) -> Self {
+        self.insecure = true;
+        self
+    }
+}
+
+static TLS: RwLock<Option<Tls>> = RwLock::new(None);
+
+/// Applies `tls` to clients created from now on.
+pub fn set_tls(tls: Tls) {
+    if let Ok(mut current) = TLS.write() {
+        *current = Some(tls);
+    }
+    reset_clients();
+}
+
+// by service, built on first use. clones of a client share its connections
+const NO_CLIENT: Option<Client> = None;
+static CLIENTS: RwLock<[Option<Client>; 4]> = RwLock::new([NO_CLIENT; 4]);
+
+// settings only apply to clients built after they changed
+fn reset_clients() {
+    if let Ok(mut clients) = CLIENTS.write() {
+        *clients = [NO_CLIENT; 4];
+    }
+}
+
+/// The client for `service`, with the timeout configured for it, the proxy,
+/// the TLS settings and the user agent.
+pub fn client(service: Service) -> Client {
+    if let Some(client) = CLIENTS
+        .read()
+        .ok()
+        .and_then(|clients| clients[service as usize].clone())
+    {
+        return client;
+    }
+    let client = build_client(service);
+    if let Ok(mut clients) = CLIENTS.write() {
+        clients[service as usize] = Some(client.clone());
+    }
+    client
+}
+
+fn build_client(service: Service) -> Client {
+    let mut builder = Client::builder()
+        .user_agent(user_agent())
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if let Some(timeout) = timeout(service) {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = PROXY.read().ok().and_then(|proxy| proxy.clone()) {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(tls) = TLS.read().ok().and_then(|tls| tls.clone()) {
+        for root in tls.roots {
+            builder = builder.add_root_certificate(root);
+        }
+        if let Some(identity) = tls.identity {
+            builder = builder.identity(identity);
+        }
+        builder = builder.danger_accept_invalid_certs(tls.insecure);
+    }
+    // like reqwest::Client::new, this only fails if TLS cannot be set up,
+    // the certificates and key were already parsed
+    builder
+        .build()
+        .expect("could not initialize the HTTP client")
+}
+
+/// Sends `request`, tracing the request and response status at debug level
+/// and headers and bodies at trace level, with credentials redacted.
+pub async fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let url = request.url().clone();
+    tracing::debug!("{} {}", method, url);
+    if tracing::enabled!(Level::TRACE) {
+        for (name, value) in request.headers() {
+            tracing::trace!("> {}: {}", name, redact_header(name, value));
+        }
+        if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+            tracing::trace!("> {}", redact_body(body, is_form(request.headers())));
+        }
+    }
+    let started = Instant::now();
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::debug!("{} {} failed: {}", method, url, e);
+            return Err(e);
+        }
+    };
+    tracing::debug!(
+        "{} {} returned {} in {:?}",
+        method,
+        url,
+        response.status(),
+        started.elapsed()
+    );
+    if !tracing::enabled!(Level::TRACE) {
+        return Ok(response);
+    }
+    for (name, value) in response.headers() {
+        tracing::trace!("< {}: {}", name, redact_header(name, value));
+    }
+    // the body is read to trace it, and handed back in a rebuilt response
+    let mut builder = hyper::Response::builder()
+        .status(response.status())
+        .version(response.version())
+        .url(response.url().clone());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+    let form = is_form(response.headers());
+    let body = response.bytes().await?;
+    tracing::trace!("< {}", redact_body(&body, form));
+    Ok(Response::from(
+        builder
+            .body(body)
+            .expect("a response rebuilt from its own parts"),
+    ))
+}
+
+fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value == "application/x-www-form-urlencoded")
+}
+
+fn redact_header(name: &HeaderName, value: &HeaderValue) -> String {
+    if SECRET_HEADERS.contains(&name.as_str()) {
+        return REDACTED.to_string();
+    }
+    String::from_utf8_lossy(value.as_bytes()).into_owned()
+}
+
+fn redact_body(body: &[u8], form: bool) -> String {
+    let text = match std::str::from_utf8(body) {
+        Ok(text) if body.len() <= MAX_TRACED_BODY => text,
+        _ => return format!("<{} bytes>", body.len()),
+    };
+    if !form {
+        return match serde_json::from_str(text) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                json.to_string()
+            }
+            Err(_) => text.to_string(),
+        };
+    }
+    text.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if SECRET_PARAMS.contains(&key) => format!("{}={}", key, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *value = serde_json::Value::from(REDACTED);
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout() {
+        set_timeout(Service::Other, Some(Duration::from_secs(30)));
+        set_timeout(Service::Fulcio, Some(Duration::from_secs(5)));
+        assert_eq!(timeout(Service::Fulcio), Some(Duration::from_secs(5)));
+        assert_eq!(timeout(Service::Rekor), Some(Duration::from_secs(30)));
+        set_timeout(Service::Other, None);
+        set_timeout(Service::Fulcio, None);
+        assert_eq!(timeout(Service::Oidc), None);
+    }
+
+    #[test]
+    fn test_user_agent() {
+        assert!(user_agent().starts_with("ferris-sign/"));
+        assert!(set_user_agent("bad\nagent").is_err());
+        assert!(user_agent().starts_with("ferris-sign/"));
+    }
+
+    #[test]
+    fn test_ca_bundle() {
+        let bundle = std::fs::read("test_data/fulcio_chain.pem").unwrap();
+        assert!(!Tls::default()
+            .with_ca_bundle(&bundle)
+            .unwrap()
+            .roots
+            .is_empty());
+        assert!(Tls::default().with_ca_bundle(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let body = b"grant_type=refresh_token&refresh_token=s3cret&client_id=sigstore";
+        assert_eq!(
+            redact_body(body, true),
+            "grant_type=refresh_token&refresh_token=<redacted>&client_id=sigstore"
+        );
+        assert_eq!(
+            redact_body(br#"{"kind":"hashedrekord"}"#, false),
+            r#"{"kind":"hashedrekord"}"#
+        );
+        assert_eq!(
+            redact_body(br#"{"access_token":"s3cret"}"#, false),
+            r#"{"access_token":"<redacted>"}"#
+        );
+        assert_eq!(redact_body(&[0xff, 0x00], false), "<2 bytes>");
+        let header = HeaderValue::from_static("Bearer eyJhbGciOi");
+        assert_eq!(
+            redact_header(&reqwest::header::AUTHORIZATION, &header),
+            REDACTED
+        );
+        assert_eq!(
+            redact_header(&CONTENT_TYPE, &HeaderValue::from_static("text/plain")),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn test_redact_fulcio_v2() {
+        // the body request_signing_cert_v2 posts
+        let body = br#"{"credentials":{"oidcIdentityToken":"eyJhbGciOi.eyJzdWIi.c2ln"},"certificateSigningRequest":"LS0tLS1CRUdJTg=="}"#;
+        let traced = redact_body(body, false);
+        assert!(!traced.contains("eyJ"));
+        let traced: serde_json::Value = serde_json::from_str(&traced).unwrap();
+        assert_eq!(traced["credentials"]["oidcIdentityToken"], REDACTED);
+        assert_eq!(traced["certificateSigningRequest"], "LS0tLS1CRUdJTg==");
+    }
+}
@@ -0,0 +1,179 @@
+//! Offline summaries of signatures and bundles, to triage an artifact
+//! without contacting Fulcio or Rekor. Nothing here is verified.
+
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use openssl::x509::X509;
+use serde::Serialize;
+
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+use crate::bundle::Bundle;
+use crate::certificate;
+use crate::der;
+
+/// What `inspect` shows about a `.sig`, `.sigstore.json` or `.sigstore.pb`
+/// file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Inspection {
+    /// `bundle` or `signature`.
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// How the signature bytes are laid out, `der`, `base64`, `hex` or `raw`.
+    pub encoding: &'static str,
+    /// Signing algorithm, known only when there is a certificate.
+    pub algorithm: Option<String>,
+    pub digest: Option<Digest>,
+    pub identity: Option<Identity>,
+    pub tlog_entries: Vec<LogCoordinates>,
+    pub timestamps: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Digest {
+    pub algorithm: String,
+    /// Hex encoded.
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Identity {
+    pub san: Vec<String>,
+    pub oidc_issuer: Option<String>,
+}
+
+/// Where a signature was logged in Rekor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogCoordinates {
+    pub log_index: String,
+    /// Hex encoded SHA-256 of the log key.
+    pub log_id: String,
+    pub integrated_time: String,
+    pub kind: String,
+    pub version: String,
+    pub inclusion_proof: bool,
+    pub inclusion_promise: bool,
+}
+
+impl Digest {
+    pub fn new(algorithm: HashAlgorithm, digest: &[u8]) -> Self {
+        Digest {
+            algorithm: algorithm.name().to_string(),
+            value: HEXLOWER.encode(digest),
+        }
+    }
+}
+
+fn identity(cert: &X509) -> Result<Identity, anyhow::Error> {
+    Ok(Identity {
+        san: certificate::san_identities(cert),
+        oidc_issuer: certificate::oidc_issuer(cert)?,
+    })
+}
+
+fn algorithm(cert: &X509) -> Result<String, anyhow::Error> {
+    let public_key = cert.public_key()?;
+    Ok(SigningAlgorithm::for_key(&public_key)?.to_string())
+}
+
+// hex first, every hex string is also valid base64
+fn signature_encoding(data: &[u8]) -> &'static str {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let text = text.trim();
+        if !text.is_empty() && HEXLOWER_PERMISSIVE.decode(text.as_bytes()).is_ok() {
+            return "hex";
+        }
+        if !text.is_empty() && base64::decode(text).is_ok() {
+            return "base64";
+        }
+    }
+    match der::parse(data) {
+        Ok((tlv, rest)) if tlv.tag == der::TAG_SEQUENCE && rest.is_empty() => "der",
+        _ => "raw",
+    }
+}
+
+/// Summarizes a bundle from the certificate and log entries it carries.
+pub fn inspect_bundle(bundle: &Bundle) -> Result<Inspection, anyhow::Error> {
+    let cert = bundle.signing_cert()?;
+    let message_digest = &bundle.message_signature.message_digest;
+    let digest = Digest::new(
+        HashAlgorithm::from_bundle_name(&message_digest.algorithm)?,
+        &base64::decode(&message_digest.digest)?,
+    );
+    let tlog_entries = bundle
+        .verification_material
+        .tlog_entries
+        .iter()
+        .map(|entry| {
+            Ok(LogCoordinates {
+                log_index: entry.log_index.clone(),
+                log_id: HEXLOWER.encode(&base64::decode(&entry.log_id.key_id)?),
+                integrated_time: entry.integrated_time.clone(),
+                kind: entry.kind_version.kind.clone(),
+                version: entry.kind_version.version.clone(),
+                inclusion_proof: entry.inclusion_proof.is_some(),
+                inclusion_promise: entry.inclusion_promise.is_some(),
+            })
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+    Ok(Inspection {
+        kind: "bundle",
+        media_type: Some(bundle.media_type.clone()),
+        encoding: "der",
+        algorithm: Some(algorithm(&cert)?),
+        digest: Some(digest),
+        identity: Some(identity(&cert)?),
+        tlog_entries,
+        timestamps: bundle.timestamps()?.len(),
+    })
+}
+
+/// Summarizes a detached signature, with the certificate it was made with
+/// if there is one. A detached signature carries no digest or log entry.
+pub fn inspect_signature(data: &[u8], cert: Option<&X509>) -> Result<Inspection, anyhow::Error> {
+    Ok(Inspection {
+        kind: "signature",
+        media_type: None,
+        encoding: signature_encoding(data),
+        algorithm: cert.map(algorithm).transpose()?,
+        digest: None,
+        identity: cert.map(identity).transpose()?,
+        tlog_entries: Vec::new(),
+        timestamps: 0,
+    })
+}
+
+/// Inspects `data` as a bundle if it parses as one, as a detached signature
+/// otherwise.
+pub fn inspect(data: &[u8], cert: Option<&X509>) -> Result<Inspection, anyhow::Error> {
+    match Bundle::parse(data) {
+        Ok(bundle) => inspect_bundle(&bundle),
+        Err(_) => inspect_signature(data, cert),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_encoding() {
+        let der = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02];
+        assert_eq!(signature_encoding(&der), "der");
+        assert_eq!(signature_encoding(base64::encode(der).as_bytes()), "base64");
+        assert_eq!(signature_encoding(HEXLOWER.encode(&der).as_bytes()), "hex");
+        assert_eq!(signature_encoding(&[0xff; 64]), "raw");
+    }
+
+    #[test]
+    fn test_inspect_signature() {
+        let cert = X509::from_pem(&std::fs::read("test_data/signing_cert.pem").unwrap()).unwrap();
+        let inspection = inspect(b"MEUCIQ==\n", Some(&cert)).unwrap();
+        assert_eq!(inspection.kind, "signature");
+        assert_eq!(inspection.encoding, "base64");
+        assert_eq!(inspection.algorithm.as_deref(), Some("ecdsa-p256"));
+        let identity = inspection.identity.unwrap();
+        assert_eq!(identity.san, vec!["ferris@example.com"]);
+        assert!(inspection.tlog_entries.is_empty());
+    }
+}
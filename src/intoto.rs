@@ -0,0 +1,109 @@
+//! In-toto attestation statements.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::canonical;
+
+/// DSSE payload type of in-toto statements.
+pub const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+/// Statement layer version 1.
+pub const STATEMENT_TYPE_V1: &str = "https://in-toto.io/Statement/v1";
+
+/// An in-toto statement binding a predicate to one or more subjects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<Subject>,
+    pub predicate_type: String,
+    pub predicate: serde_json::Value,
+}
+
+/// An artifact the statement is about, identified by its digests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subject {
+    pub name: String,
+    /// Algorithm name to hex encoded digest, e.g. `sha256`.
+    pub digest: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+impl Subject {
+    pub fn sha256(name: &str, digest: &str) -> Self {
+        let mut digests = BTreeMap::new();
+        digests.insert(String::from("sha256"), digest.to_string());
+        Subject {
+            name: name.to_string(),
+            digest: digests,
+            annotations: None,
+        }
+    }
+
+    pub fn with_annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations.as_ref()?.get(key).map(String::as_str)
+    }
+}
+
+impl Statement {
+    pub fn new(subject: Vec<Subject>, predicate_type: &str, predicate: serde_json::Value) -> Self {
+        Statement {
+            statement_type: STATEMENT_TYPE_V1.to_string(),
+            subject,
+            predicate_type: predicate_type.to_string(),
+            predicate,
+        }
+    }
+
+    /// Whether one of the subjects has the given hex encoded sha256 digest.
+    pub fn has_sha256_subject(&self, digest: &str) -> bool {
+        self.subject
+            .iter()
+            .any(|subject| subject.digest.get("sha256").map(String::as_str) == Some(digest))
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    /// Canonical JSON, so the signed payload does not depend on how the
+    /// predicate was put together.
+    pub fn to_json(&self) -> Result<Vec<u8>, anyhow::Error> {
+        canonical::to_vec(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_json() {
+        let statement = Statement::new(
+            vec![Subject::sha256("test_digest.txt", "6c3b0448")],
+            "https://example.com/predicate/v1",
+            serde_json::json!({ "ok": true }),
+        );
+        let json: serde_json::Value =
+            serde_json::from_slice(&statement.to_json().unwrap()).unwrap();
+        assert_eq!(json["_type"], STATEMENT_TYPE_V1);
+        assert_eq!(json["predicateType"], "https://example.com/predicate/v1");
+        assert_eq!(json["subject"][0]["digest"]["sha256"], "6c3b0448");
+        assert!(json["subject"][0].get("annotations").is_none());
+        assert!(statement.has_sha256_subject("6c3b0448"));
+        assert!(!statement.has_sha256_subject("deadbeef"));
+        assert_eq!(
+            Statement::from_json(&statement.to_json().unwrap()).unwrap(),
+            statement
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Checks identity tokens before they are sent to Fulcio: their signature
+//! against the keys the issuer publishes, and their issuer, audience, expiry
+//! and nonce claims, with an error saying which of them is wrong.
+
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::{RsaPssSaltlen, Verifier};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::http::{self, Service};
+use crate::key;
+
+// the clocks of issuers and signers drift apart a little
+const LEEWAY_SECS: u64 = 60;
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The claims of an identity token that are checked.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    aud: Audience,
+    pub exp: u64,
+    #[serde(default)]
+    pub nbf: Option<u64>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+impl Claims {
+    pub fn audiences(&self) -> Vec<&str> {
+        match &self.aud {
+            Audience::One(aud) => vec![aud.as_str()],
+            Audience::Many(auds) => auds.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// What the claims of a token have to match.
+#[derive(Debug, Clone, Default)]
+pub struct Expected<'a> {
+    /// Issuer the token has to come from, whose keys it is checked with.
+    pub issuer: &'a str,
+    pub audience: &'a str,
+    /// Nonce sent with the authorization request the token answers.
+    pub nonce: Option<&'a str>,
+}
+
+// header, payload and signature
+fn split(token: &str) -> Result<(&str, &str, &str), anyhow::Error> {
+    let mut parts = token.trim().split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature), None) => Ok((header, payload, signature)),
+        _ => anyhow::bail!("identity token is not a JWT"),
+    }
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(base64::decode_config(part, base64::URL_SAFE_NO_PAD)?)
+}
+
+fn decode_json<T: DeserializeOwned>(part: &str, what: &str) -> Result<T, anyhow::Error> {
+    serde_json::from_slice(&decode(part)?)
+        .map_err(|e| anyhow::anyhow!("could not parse the identity token {}: {}", what, e))
+}
+
+/// Checks the claims of `token` against `expected`, not its signature.
+pub fn check_claims(token: &str, expected: &Expected) -> Result<Claims, anyhow::Error> {
+    let (_, payload, _) = split(token)?;
+    let claims: Claims = decode_json(payload, "claims")?;
+    if claims.iss.trim_end_matches('/') != expected.issuer.trim_end_matches('/') {
+        anyhow::bail!(
+            "identity token was issued by {}, not {}",
+            claims.iss,
+            expected.issuer
+        );
+    }
+    if !claims.audiences().contains(&expected.audience) {
+        anyhow::bail!(
+            "identity token audience {:?} does not include {}",
+            claims.audiences(),
+            expected.audience
+        );
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if claims.exp <= now {
+        anyhow::bail!("identity token expired {} seconds ago", now - claims.exp);
+    }
+    if let Some(nbf) = claims.nbf {
+        if nbf > now + LEEWAY_SECS {
+            anyhow::bail!(
+                "identity token is only valid in {} seconds, check the clock",
+                nbf - now
+            );
+        }
+    }
+    match (expected.nonce, &claims.nonce) {
+        (Some(nonce), Some(claimed)) if nonce != claimed => {
+            anyhow::bail!("identity token nonce does not match the login request")
+        }
+        (Some(_), None) => anyhow::bail!("identity token has no nonce"),
+        _ => {}
+    }
+    Ok(claims)
+}
+
+/// A key from an issuer's JSON Web Key Set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+fn member(value: &Option<String>, name: &str) -> Result<BigNum, anyhow::Error> {
+    let value = value
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("JWK has no {}", name))?;
+    Ok(BigNum::from_slice(&decode(value)?)?)
+}
+
+impl Jwk {
+    pub fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", _) => Ok(PKey::from_rsa(Rsa::from_public_components(
+                member(&self.n, "n")?,
+                member(&self.e, "e")?,
+            )?)?),
+            ("EC", Some(crv)) => {
+                let nid = match crv {
+                    "P-256" => Nid::X9_62_PRIME256V1,
+                    "P-384" => Nid::SECP384R1,
+                    _ => anyhow::bail!("unsupported JWK curve {}", crv),
+                };
+                let group = EcGroup::from_curve_name(nid)?;
+                let x = member(&self.x, "x")?;
+                let y = member(&self.y, "y")?;
+                let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+                Ok(PKey::from_ec_key(key)?)
+            }
+            ("OKP", Some("Ed25519")) => {
+                let x = self
+                    .x
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("JWK has no x"))?;
+                Ok(PKey::public_key_from_raw_bytes(
+                    &decode(x)?,
+                    openssl::pkey::Id::ED25519,
+                )?)
+            }
+            (kty, crv) => anyhow::bail!("unsupported JWK type {} {}", kty, crv.unwrap_or("")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    jwks_uri: String,
+}
+
+// whether `signature` by `key` over `data` verifies with the jws algorithm
+// `alg`. ecdsa signatures are r || s in a jws
+fn verify_with(
+    alg: &str,
+    key: &PKey<Public>,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let md = match alg {
+        "EdDSA" => {
+            return Ok(Verifier::new_without_digest(key)?.verify_oneshot(signature, data)?);
+        }
+        "RS256" | "PS256" | "ES256" => MessageDigest::sha256(),
+        "RS384" | "PS384" | "ES384" => MessageDigest::sha384(),
+        "RS512" | "PS512" => MessageDigest::sha512(),
+        _ => anyhow::bail!("identity token algorithm {} is not supported", alg),
+    };
+    let mut verifier = Verifier::new(md, key)?;
+    if alg.starts_with("ES") {
+        verifier.update(data)?;
+        return Ok(verifier.verify(&key::ecdsa_der_from_raw(signature)?)?);
+    }
+    if alg.starts_with("PS") {
+        verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+        verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    } else {
+        verifier.set_rsa_padding(Padding::PKCS1)?;
+    }
+    verifier.update(data)?;
+    Ok(verifier.verify(signature)?)
+}
+
+/// Checks that `token` is signed by one of `keys`, the one named by its key
+/// id if it has one.
+pub fn verify_signature(token: &str, keys: &[Jwk]) -> Result<(), anyhow::Error> {
+    let (header_part, payload, signature) = split(token)?;
+    let header: Header = decode_json(header_part, "header")?;
+    let candidates: Vec<&Jwk> = keys
+        .iter()
+        .filter(|key| header.kid.is_none() || key.kid == header.kid)
+        .collect();
+    if candidates.is_empty() {
+        anyhow::bail!(
+            "the issuer has no key {} for the identity token",
+            header.kid.as_deref().unwrap_or_default()
+        );
+    }
+    let data = format!("{}.{}", header_part, payload);
+    let signature = decode(signature)?;
+    for key in candidates {
+        // keys of another type than the algorithm cannot have signed it, and
+        // the set may hold keys of types that are not supported
+        let public_key = match key.public_key() {
+            Ok(public_key) => public_key,
+            Err(_) => continue,
+        };
+        if let Ok(true) = verify_with(&header.alg, &public_key, data.as_bytes(), &signature) {
+            return Ok(());
+        }
+    }
+    anyhow::bail!(
+        "identity token signature does not verify with the keys of its issuer, algorithm {}",
+        header.alg
+    )
+}
+
+/// Fetches the keys `issuer` signs identity tokens with, from the JWKS its
+/// discovery document points at.
+pub async fn issuer_keys(issuer: &str) -> Result<Vec<Jwk>, anyhow::Error> {
+    let client = http::client(Service::Oidc);
+    let metadata: ProviderMetadata = http::send(client.get(format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    )))
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    let jwks: Jwks = http::send(client.get(&metadata.jwks_uri))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(jwks.keys)
+}
+
+/// Checks the claims of `token` and its signature against the keys of the
+/// expected issuer.
+pub async fn verify(token: &str, expected: &Expected<'_>) -> Result<Claims, anyhow::Error> {
+    let claims = check_claims(token, expected)?;
+    let keys = issuer_keys(expected.issuer)
+        .await
+        .map_err(|e| anyhow::anyhow!("could not fetch the keys of {}: {}", expected.issuer, e))?;
+    verify_signature(token, &keys)?;
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::SigningAlgorithm;
+    use crate::key::SigningKey;
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::pkey::Private;
+    use serde_json::json;
+
+    fn encode(data: &[u8]) -> String {
+        base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+    }
+
+    // a P-256 key as a jwk and a token it signed with `claims`
+    fn signed(key: &PKey<Private>, kid: &str, claims: serde_json::Value) -> String {
+        let header = encode(json!({"alg": "ES256", "kid": kid}).to_string().as_bytes());
+        let payload = encode(claims.to_string().as_bytes());
+        let der = key
+            .sign(format!("{}.{}", header, payload).as_bytes())
+            .unwrap();
+        let signature = EcdsaSig::from_der(&der).unwrap();
+        let raw = [
+            signature.r().to_vec_padded(32).unwrap(),
+            signature.s().to_vec_padded(32).unwrap(),
+        ]
+        .concat();
+        format!("{}.{}.{}", header, payload, encode(&raw))
+    }
+
+    fn jwk(key: &PKey<Private>, kid: &str) -> Jwk {
+        let ec_key = key.ec_key().unwrap();
+        let mut ctx = openssl::bn::BigNumContext::new().unwrap();
+        let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+        ec_key
+            .public_key()
+            .affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut ctx)
+            .unwrap();
+        serde_json::from_value(json!({
+            "kty": "EC",
+            "kid": kid,
+            "crv": "P-256",
+            "x": encode(&x.to_vec_padded(32).unwrap()),
+            "y": encode(&y.to_vec_padded(32).unwrap()),
+        }))
+        .unwrap()
+    }
+
+    fn claims() -> serde_json::Value {
+        json!({
+            "iss": "https://oauth2.sigstore.dev/auth",
+            "aud": "sigstore",
+            "exp": u64::MAX / 2,
+            "nonce": "n0nce",
+        })
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let (other, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let token = signed(&key, "1", claims());
+        let keys = vec![jwk(&other, "2"), jwk(&key, "1")];
+        verify_signature(&token, &keys).unwrap();
+        assert!(verify_signature(&token, &[jwk(&other, "1")]).is_err());
+        assert!(verify_signature(&token, &[jwk(&key, "2")]).is_err());
+
+        let (header, _, signature) = split(&token).unwrap();
+        let mut tampered = claims();
+        tampered["aud"] = json!("other");
+        let tampered = format!(
+            "{}.{}.{}",
+            header,
+            encode(tampered.to_string().as_bytes()),
+            signature
+        );
+        assert!(verify_signature(&tampered, &keys).is_err());
+    }
+
+    #[test]
+    fn test_check_claims() {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let token = signed(&key, "1", claims());
+        let expected = Expected {
+            issuer: "https://oauth2.sigstore.dev/auth/",
+            audience: "sigstore",
+            nonce: Some("n0nce"),
+        };
+        let checked = check_claims(&token, &expected).unwrap();
+        assert_eq!(checked.audiences(), vec!["sigstore"]);
+
+        let error = |expected: &Expected| check_claims(&token, expected).unwrap_err().to_string();
+        assert!(error(&Expected {
+            issuer: "https://accounts.google.com",
+            ..expected.clone()
+        })
+        .contains("issued by"));
+        assert!(error(&Expected {
+            audience: "other",
+            ..expected.clone()
+        })
+        .contains("audience"));
+        assert!(error(&Expected {
+            nonce: Some("other"),
+            ..expected.clone()
+        })
+        .contains("nonce"));
+
+        let mut expired = claims();
+        expired["exp"] = json!(1);
+        let expired = signed(&key, "1", expired);
+        assert!(check_claims(&expired, &expected)
+            .unwrap_err()
+            .to_string()
+            .contains("expired"));
+        let mut early = claims();
+        early["nbf"] = json!(u64::MAX / 4);
+        assert!(check_claims(&signed(&key, "1", early), &expected).is_err());
+    }
+
+    #[test]
+    fn test_rsa_jwk() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kty": "RSA",
+            "n": encode(&rsa.n().to_vec()),
+            "e": encode(&rsa.e().to_vec()),
+        }))
+        .unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        let header = encode(br#"{"alg":"RS256"}"#);
+        let payload = encode(claims().to_string().as_bytes());
+        let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer
+            .update(format!("{}.{}", header, payload).as_bytes())
+            .unwrap();
+        let token = format!(
+            "{}.{}.{}",
+            header,
+            payload,
+            encode(&signer.sign_to_vec().unwrap())
+        );
+        verify_signature(&token, &[jwk]).unwrap();
+    }
+}
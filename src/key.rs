@@ -0,0 +1,277 @@
+//! Private keys that signers can use, wherever they are kept.
+
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcKeyRef, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private, Public};
+use openssl::sign::Signer;
+use std::fmt;
+
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+
+/// A private key held in memory or by a hardware backend.
+pub trait SigningKey: fmt::Debug + Send + Sync {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error>;
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error>;
+
+    /// Signs `data` itself, hashing it first unless the algorithm signs
+    /// whole messages.
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error>;
+
+    /// Signs a `digest` computed with `digest_algorithm`.
+    fn sign_digest(
+        &self,
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error>;
+}
+
+impl SigningKey for PKey<Private> {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        SigningAlgorithm::for_key(self)
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        Ok(PKey::public_key_from_der(&self.public_key_to_der()?)?)
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        self.algorithm()?.sign(self, data)
+    }
+
+    fn sign_digest(
+        &self,
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        self.algorithm()?
+            .sign_digest(self, digest_algorithm, digest)
+    }
+}
+
+/// Reads a PEM private key, decrypting it with `passphrase` if given.
+pub fn private_key_from_pem(
+    pem: &[u8],
+    passphrase: Option<&[u8]>,
+) -> Result<PKey<Private>, anyhow::Error> {
+    match passphrase {
+        Some(passphrase) => PKey::private_key_from_pem_passphrase(pem, passphrase)
+            .map_err(|_| anyhow::anyhow!("could not decrypt the private key")),
+        None => Ok(PKey::private_key_from_pem(pem)?),
+    }
+}
+
+/// A key in memory that makes the same signature over the same data every
+/// time. ECDSA nonces are derived from the key and digest as in RFC 6979
+/// rather than drawn at random, Ed25519 is deterministic by itself.
+#[derive(Debug)]
+pub struct DeterministicKey(PKey<Private>);
+
+impl DeterministicKey {
+    pub fn new(key: PKey<Private>) -> Result<Self, anyhow::Error> {
+        // pss salts are random
+        if SigningAlgorithm::for_key(&key)? == SigningAlgorithm::RsaPssSha256 {
+            anyhow::bail!(
+                "RSA-PSS signatures cannot be made deterministic, use an ECDSA or Ed25519 key"
+            );
+        }
+        Ok(DeterministicKey(key))
+    }
+}
+
+impl SigningKey for DeterministicKey {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        self.0.algorithm()
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        self.0.public_key()
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let algorithm = self.algorithm()?;
+        if !algorithm.is_prehashed() {
+            return self.0.sign(data);
+        }
+        self.sign_digest(algorithm.hash(), &algorithm.hash().digest(data)?)
+    }
+
+    fn sign_digest(
+        &self,
+        digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        match self.algorithm()? {
+            SigningAlgorithm::EcdsaP256Sha256 => {
+                let ec_key = self.0.ec_key()?;
+                rfc6979_sign(&ec_key, MessageDigest::sha256(), digest)
+            }
+            SigningAlgorithm::EcdsaP384Sha384 => {
+                let ec_key = self.0.ec_key()?;
+                rfc6979_sign(&ec_key, MessageDigest::sha384(), digest)
+            }
+            _ => self.0.sign_digest(digest_algorithm, digest),
+        }
+    }
+}
+
+fn hmac(md: MessageDigest, key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>, anyhow::Error> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(md, &key)?;
+    for part in parts {
+        signer.update(part)?;
+    }
+    Ok(signer.sign_to_vec()?)
+}
+
+// the leftmost bits of `bytes` as a number below 2^qlen. the orders of the
+// nist curves are whole bytes, so that is the leftmost bytes
+fn bits2int(bytes: &[u8], order_len: usize) -> Result<BigNum, anyhow::Error> {
+    Ok(BigNum::from_slice(&bytes[..bytes.len().min(order_len)])?)
+}
+
+// ecdsa with the nonce of RFC 6979, section 3.2, using the hash of the curve
+// for the hmac whatever the digest was computed with
+fn rfc6979_sign(
+    key: &EcKeyRef<Private>,
+    md: MessageDigest,
+    digest: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let group = key.group();
+    let mut ctx = BigNumContext::new()?;
+    let mut order = BigNum::new()?;
+    group.order(&mut order, &mut ctx)?;
+    let order_len = order.num_bytes() as usize;
+
+    let z = bits2int(digest, order_len)?;
+    let mut h1 = BigNum::new()?;
+    h1.nnmod(&z, &order, &mut ctx)?;
+    let x = key.private_key().to_vec_padded(order_len as i32)?;
+    let h1 = h1.to_vec_padded(order_len as i32)?;
+
+    let mut v = vec![0x01; md.size()];
+    let mut k = hmac(md, &vec![0x00; md.size()], &[&v, &[0x00], &x, &h1])?;
+    v = hmac(md, &k, &[&v])?;
+    k = hmac(md, &k, &[&v, &[0x01], &x, &h1])?;
+    v = hmac(md, &k, &[&v])?;
+    loop {
+        let mut t = Vec::with_capacity(order_len);
+        while t.len() < order_len {
+            v = hmac(md, &k, &[&v])?;
+            t.extend_from_slice(&v);
+        }
+        let nonce = bits2int(&t, order_len)?;
+        if nonce.num_bits() > 0 && nonce < order {
+            if let Some(signature) = ecdsa_sign_with(key, &order, &nonce, &z, &mut ctx)? {
+                return Ok(signature.to_der()?);
+            }
+        }
+        k = hmac(md, &k, &[&v, &[0x00]])?;
+        v = hmac(md, &k, &[&v])?;
+    }
+}
+
+// s = nonce^-1 (z + r d) with r the x coordinate of nonce * G, none if r or
+// s is zero
+fn ecdsa_sign_with(
+    key: &EcKeyRef<Private>,
+    order: &BigNumRef,
+    nonce: &BigNumRef,
+    z: &BigNumRef,
+    ctx: &mut BigNumContext,
+) -> Result<Option<EcdsaSig>, anyhow::Error> {
+    let group = key.group();
+    let mut point = EcPoint::new(group)?;
+    point.mul_generator2(group, nonce, ctx)?;
+    let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+    point.affine_coordinates_gfp(group, &mut x, &mut y, ctx)?;
+    let mut r = BigNum::new()?;
+    r.nnmod(&x, order, ctx)?;
+    let mut rd = BigNum::new()?;
+    rd.mod_mul(&r, key.private_key(), order, ctx)?;
+    let mut sum = BigNum::new()?;
+    sum.mod_add(z, &rd, order, ctx)?;
+    let mut inverse = BigNum::new()?;
+    inverse.mod_inverse(nonce, order, ctx)?;
+    let mut s = BigNum::new()?;
+    s.mod_mul(&inverse, &sum, order, ctx)?;
+    if r.num_bits() == 0 || s.num_bits() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(EcdsaSig::from_private_components(r, s)?))
+}
+
+/// Converts the `r || s` ECDSA signatures made by hardware tokens to the DER
+/// encoding used everywhere else.
+pub fn ecdsa_der_from_raw(raw: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    if raw.is_empty() || !raw.len().is_multiple_of(2) {
+        anyhow::bail!("invalid raw ECDSA signature of {} bytes", raw.len());
+    }
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let signature =
+        EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+    Ok(signature.to_der()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecdsa_der_from_raw() {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let digest = HashAlgorithm::Sha256.digest(b"lolwut").unwrap();
+        let der = key.sign_digest(HashAlgorithm::Sha256, &digest).unwrap();
+
+        let signature = EcdsaSig::from_der(&der).unwrap();
+        let mut raw = signature.r().to_vec_padded(32).unwrap();
+        raw.extend(signature.s().to_vec_padded(32).unwrap());
+        assert_eq!(ecdsa_der_from_raw(&raw).unwrap(), der);
+        assert!(ecdsa_der_from_raw(&raw[1..]).is_err());
+    }
+
+    #[test]
+    fn test_rfc6979() {
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::nid::Nid;
+
+        // the P-256 and SHA-256 example of RFC 6979, appendix A.2.5
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let d = BigNum::from_hex_str(
+            "C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721",
+        )
+        .unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut public = EcPoint::new(&group).unwrap();
+        public.mul_generator2(&group, &d, &mut ctx).unwrap();
+        let ec_key = EcKey::from_private_components(&group, &d, &public).unwrap();
+        let key = DeterministicKey::new(PKey::from_ec_key(ec_key).unwrap()).unwrap();
+
+        let digest = HashAlgorithm::Sha256.digest(b"sample").unwrap();
+        let der = key.sign_digest(HashAlgorithm::Sha256, &digest).unwrap();
+        let signature = EcdsaSig::from_der(&der).unwrap();
+        assert_eq!(
+            signature.r().to_hex_str().unwrap().to_string(),
+            "EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716"
+        );
+        assert_eq!(
+            signature.s().to_hex_str().unwrap().to_string(),
+            "F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8"
+        );
+        assert_eq!(key.sign(b"sample").unwrap(), der);
+
+        let (p384, _) = SigningAlgorithm::EcdsaP384Sha384.generate().unwrap();
+        let verifying_key = p384.public_key().unwrap();
+        let key = DeterministicKey::new(p384).unwrap();
+        let signature = key.sign(b"lolwut").unwrap();
+        assert_eq!(key.sign(b"lolwut").unwrap(), signature);
+        assert!(SigningAlgorithm::EcdsaP384Sha384
+            .verify(&verifying_key, b"lolwut", &signature)
+            .unwrap());
+
+        let (rsa, _) = SigningAlgorithm::RsaPssSha256.generate().unwrap();
+        assert!(DeterministicKey::new(rsa).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Signing with keys in the macOS Keychain, addressed as
+//! `keychain://<label>`. Missing keys are generated in the Secure Enclave
+//! and every signature then needs Touch ID or the login password, so no key
+//! material is ever on disk.
+
+#[cfg(target_os = "macos")]
+use openssl::bn::BigNumContext;
+#[cfg(target_os = "macos")]
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+#[cfg(target_os = "macos")]
+use openssl::nid::Nid;
+#[cfg(target_os = "macos")]
+use openssl::pkey::{PKey, Public};
+#[cfg(target_os = "macos")]
+use security_framework::access_control::SecAccessControl;
+#[cfg(target_os = "macos")]
+use security_framework::item::{ItemClass, ItemSearchOptions, Location, Reference, SearchResult};
+#[cfg(target_os = "macos")]
+use security_framework::key::{Algorithm, GenerateKeyOptions, KeyType, SecKey, Token};
+#[cfg(target_os = "macos")]
+use std::fmt;
+
+#[cfg(target_os = "macos")]
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+#[cfg(target_os = "macos")]
+use crate::key::SigningKey;
+
+pub const URI_SCHEME: &str = "keychain://";
+
+/// Parses the key label out of a `keychain://<label>` URI.
+pub fn parse_uri(uri: &str) -> Result<&str, anyhow::Error> {
+    match uri.strip_prefix(URI_SCHEME) {
+        Some(label) if !label.is_empty() => Ok(label),
+        _ => anyhow::bail!("{} is not a keychain://<label> URI", uri),
+    }
+}
+
+// kSecAccessControlPrivateKeyUsage | kSecAccessControlUserPresence
+#[cfg(target_os = "macos")]
+const ACCESS_CONTROL_FLAGS: usize = (1 << 30) | 1;
+
+/// An EC key in the Keychain or the Secure Enclave.
+#[cfg(target_os = "macos")]
+pub struct KeychainKey {
+    key: SecKey,
+    label: String,
+    algorithm: SigningAlgorithm,
+    public_key: PKey<Public>,
+}
+
+#[cfg(target_os = "macos")]
+impl fmt::Debug for KeychainKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeychainKey")
+            .field("label", &self.label)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl KeychainKey {
+    /// Finds the private key labelled `label`, generating a P-256 key in the
+    /// Secure Enclave if there is none.
+    pub fn open_or_create(label: &str) -> Result<Self, anyhow::Error> {
+        let key = match find(label)? {
+            Some(key) => key,
+            None => create(label)?,
+        };
+        let public_key = key
+            .public_key()
+            .and_then(|public_key| public_key.external_representation())
+            .ok_or_else(|| anyhow::anyhow!("could not read the public key of {}", label))?;
+        let (algorithm, public_key) = ec_public_key(public_key.bytes())?;
+        Ok(KeychainKey {
+            key,
+            label: label.to_string(),
+            algorithm,
+            public_key,
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn find(label: &str) -> Result<Option<SecKey>, anyhow::Error> {
+    let results = match ItemSearchOptions::new()
+        .class(ItemClass::key())
+        .label(label)
+        .load_refs(true)
+        .search()
+    {
+        Ok(results) => results,
+        // errSecItemNotFound
+        Err(e) if e.code() == -25300 => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    for result in results {
+        if let SearchResult::Ref(Reference::Key(key)) = result {
+            return Ok(Some(key));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(target_os = "macos")]
+fn create(label: &str) -> Result<SecKey, anyhow::Error> {
+    let access_control = SecAccessControl::create_with_flags(ACCESS_CONTROL_FLAGS as _)?;
+    let mut options = GenerateKeyOptions::default();
+    options
+        .set_key_type(KeyType::ec())
+        .set_size_in_bits(256)
+        .set_label(label)
+        .set_token(Token::SecureEnclave)
+        .set_location(Location::DataProtectionKeychain)
+        .set_access_control(access_control);
+    SecKey::generate(options.to_dictionary())
+        .map_err(|e| anyhow::anyhow!("could not create a Secure Enclave key: {}", e))
+}
+
+// the external representation of EC public keys is the uncompressed point
+#[cfg(target_os = "macos")]
+fn ec_public_key(point: &[u8]) -> Result<(SigningAlgorithm, PKey<Public>), anyhow::Error> {
+    let (nid, algorithm) = match point.len() {
+        65 => (Nid::X9_62_PRIME256V1, SigningAlgorithm::EcdsaP256Sha256),
+        97 => (Nid::SECP384R1, SigningAlgorithm::EcdsaP384Sha384),
+        _ => anyhow::bail!("only P-256 and P-384 Keychain keys are supported"),
+    };
+    let group = EcGroup::from_curve_name(nid)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, point, &mut ctx)?;
+    Ok((
+        algorithm,
+        PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?,
+    ))
+}
+
+#[cfg(target_os = "macos")]
+impl SigningKey for KeychainKey {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        Ok(self.algorithm)
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        Ok(self.public_key.clone())
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let digest_algorithm = self.algorithm.hash();
+        self.sign_digest(digest_algorithm, &digest_algorithm.digest(data)?)
+    }
+
+    // blocks on the Touch ID prompt for Secure Enclave keys
+    fn sign_digest(
+        &self,
+        _digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        self.key
+            .create_signature(Algorithm::ECDSASignatureDigestX962, digest)
+            .map_err(|e| anyhow::anyhow!("the Keychain did not sign: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(parse_uri("keychain://release").unwrap(), "release");
+        assert!(parse_uri("keychain://").is_err());
+        assert!(parse_uri("tpm://0x81000001").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Basic sigstore keyless signing.
+//!
+//! The workflow is split into small modules that can be used on their own:
+//! [`oauth`] obtains an OIDC identity token, [`fulcio`] exchanges it for a
+//! short lived signing certificate, [`rekor_api`] records the signature in the
+//! transparency log and [`verify`] checks the result. [`KeylessSigner`] ties
+//! the signing half together, and [`bundle`] packages its output for other
+//! sigstore clients. Bundles can be verified offline against a
+//! [`verify::TrustRoot`].
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), anyhow::Error> {
+//! use ferris_sign::oauth::{self, InteractiveOptions};
+//!
+//! let identity =
+//!     oauth::interactive_flow(oauth::SIGSTORE_OAUTH_URL, &InteractiveOptions::default()).await?;
+//! let signer = ferris_sign::KeylessSigner::default();
+//! let signed = signer.sign_blob(&identity, b"ohhai").await?;
+//! println!("{}", signed.cert_pem);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod algorithm;
+pub mod ambient;
+pub mod archive;
+pub mod bundle;
+pub mod cache;
+pub mod canonical;
+pub mod cargo;
+pub mod certificate;
+pub mod certstore;
+pub mod config;
+pub mod cosign;
+pub mod crypto;
+pub mod der;
+pub mod docker_config;
+pub mod dsse;
+pub mod endpoints;
+pub mod exit;
+pub mod expiry;
+pub mod format;
+pub mod fulcio;
+pub mod git;
+pub mod github;
+pub mod http;
+pub mod inspect;
+pub mod intoto;
+pub mod jwt;
+pub mod key;
+pub mod keychain;
+pub mod merkle;
+pub mod minisign;
+pub mod monitor;
+pub mod oauth;
+pub mod pkcs11;
+pub mod policy;
+pub mod predicate;
+pub mod progress;
+pub mod protobuf;
+pub mod registry;
+pub mod rekor_api;
+pub mod sct;
+pub mod signer;
+pub mod slsa;
+pub mod spiffe;
+pub mod ssh;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timestamp;
+pub mod tlog;
+pub mod tpm;
+pub mod trusted_root;
+pub mod tuf;
+pub mod verify;
+pub mod watch;
+pub mod witness;
+
+pub use signer::{
+    DryRun, KeySignature, KeySigner, KeylessAttestation, KeylessSession, KeylessSignature,
+    KeylessSigner,
+};
@@ -1,200 +1,4391 @@
-use anyhow::Result;
-use base64::encode;
-use clap::{Arg, Command};
-use open;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use sigstore::oauth;
-use std::io::Read;
-use std::path::PathBuf;
-use std::{fs::File, io::Write};
-use tokio::task;
-
-mod crypto;
-mod rekor_api;
-extern crate question;
-
-const FULCIO_URL: &str = "https://fulcio.sigstore.dev/api/v1/signingCert";
-const SIGSTORE_OAUTH_URL: &str = "https://oauth2.sigstore.dev/auth";
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FulcioPayload {
-    pub public_key: PubKey,
-    pub signed_email_address: String,
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PubKey {
-    pub algorithm: String,
-    pub content: String,
-}
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    let matches = Command::new("ferris-sign")
+use anyhow::{Context, Result};
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use data_encoding::HEXLOWER;
+use ferris_sign::algorithm::{HashAlgorithm, SigningAlgorithm};
+use ferris_sign::bundle::{Bundle, TransparencyLogEntry};
+use ferris_sign::cache::{self, TokenCache};
+use ferris_sign::cargo::{self, CrateFile};
+use ferris_sign::config::{self, Config};
+use ferris_sign::cosign::{self, ImageAttestation, ImageSignature, SimpleSigning};
+use ferris_sign::docker_config::DockerConfig;
+use ferris_sign::dsse::Envelope;
+use ferris_sign::endpoints::Endpoints;
+use ferris_sign::exit::{self, UsageError, VerificationFailed};
+use ferris_sign::format::{self, BundleFormat, CertificateFormat, SignatureFormat};
+use ferris_sign::http::{self, Service};
+use ferris_sign::intoto::{Statement, Subject};
+use ferris_sign::key::{self, DeterministicKey};
+use ferris_sign::monitor::{self, Monitor};
+use ferris_sign::oauth::{IdentityToken, InteractiveOptions, OidcClient};
+use ferris_sign::pkcs11::{self, Pkcs11Key, Pkcs11Uri};
+use ferris_sign::policy::{self, IdentityPolicy, Matcher, Policy};
+use ferris_sign::predicate::{self, PredicateType};
+use ferris_sign::progress::Progress;
+use ferris_sign::registry::{self, Reference};
+use ferris_sign::rekor_api::{RekorVersion, SearchIndex, SearchPublicKey};
+use ferris_sign::slsa::Provenance;
+use ferris_sign::tpm;
+use ferris_sign::trusted_root::{self, TrustedRoot};
+use ferris_sign::verify::TrustRoot;
+use ferris_sign::watch::Watcher;
+use ferris_sign::witness::Witness;
+use ferris_sign::{
+    ambient, archive, certificate, certstore, crypto, fulcio, git, github, inspect, jwt, keychain,
+    minisign, oauth, rekor_api, ssh, tlog, tuf, verify, DryRun, KeySigner, KeylessSession,
+    KeylessSignature, KeylessSigner,
+};
+use openssl::pkey::{PKey, PKeyRef, Public};
+use openssl::x509::X509;
+use question::{Answer, Question};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::OnceCell;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+
+const DIGEST_ALGORITHMS: [&str; 4] = ["sha256", "sha384", "sha512", "blake3"];
+const SIGNATURE_FORMATS: [&str; 4] = ["der", "raw", "base64", "hex"];
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// informational messages go to stderr, stdout only carries what was asked
+// for, and --quiet silences them
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+// instance selection shared by every subcommand, the environment variables
+// are also how git mode is configured
+fn global_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("profile")
+            .long("profile")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_PROFILE")
+            .help("Named set of settings from the configuration file to use"),
+        Arg::new("fulcio-url")
+            .long("fulcio-url")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_FULCIO_URL")
+            .help(
+                "Fulcio instance to request signing certificates from [default: public instance]",
+            ),
+        Arg::new("fulcio-api")
+            .long("fulcio-api")
+            .global(true)
+            .takes_value(true)
+            .possible_values(["v1", "v2"])
+            .default_value("v2")
+            .env("FERRIS_SIGN_FULCIO_API")
+            .help("Fulcio API version, v1 for private deployments that predate the CSR based v2 API"),
+        Arg::new("rekor-url")
+            .long("rekor-url")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_REKOR_URL")
+            .help("Rekor instance to record and look up signatures in [default: public instance]"),
+        Arg::new("rekor-api")
+            .long("rekor-api")
+            .global(true)
+            .takes_value(true)
+            .possible_values(["v1", "v2"])
+            .env("FERRIS_SIGN_REKOR_API")
+            .help("Rekor API version, v2 for tile backed logs [default: asked of the log]"),
+        Arg::new("oidc-issuer")
+            .long("oidc-issuer")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_OIDC_ISSUER")
+            .help("OIDC issuer to obtain identity tokens from [default: public instance]"),
+        Arg::new("trusted-root")
+            .long("trusted-root")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_TRUSTED_ROOT")
+            .help("Sigstore trusted_root.json with the CA certificates and log keys of a private instance, used instead of the TUF trust root"),
+        Arg::new("tuf-url")
+            .long("tuf-url")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_TUF_URL")
+            .help("TUF repository to fetch the trust root from [default: that of the public or staging instance, none with --fulcio-url or --rekor-url]"),
+        Arg::new("tuf-root")
+            .long("tuf-root")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_TUF_ROOT")
+            .help("Initial root.json of the TUF repository, required for repositories other than the public one"),
+        Arg::new("proxy")
+            .long("proxy")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_PROXY")
+            .help("HTTP(S) proxy for all requests, hosts in NO_PROXY bypass it [default: HTTPS_PROXY]"),
+        Arg::new("cacert")
+            .long("cacert")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_CACERT")
+            .help("PEM bundle of CA certificates to trust in addition to the system ones, for private instances"),
+        Arg::new("insecure-skip-tls-verify")
+            .long("insecure-skip-tls-verify")
+            .global(true)
+            .env("FERRIS_SIGN_INSECURE_SKIP_TLS_VERIFY")
+            .help("Do not verify the TLS certificates of Fulcio, Rekor and other services. Insecure, for testing only"),
+        Arg::new("client-cert")
+            .long("client-cert")
+            .global(true)
+            .takes_value(true)
+            .requires("client-key")
+            .env("FERRIS_SIGN_CLIENT_CERT")
+            .help("PEM certificate to authenticate to services requiring mutual TLS"),
+        Arg::new("client-key")
+            .long("client-key")
+            .global(true)
+            .takes_value(true)
+            .requires("client-cert")
+            .env("FERRIS_SIGN_CLIENT_KEY")
+            .help("PEM private key of --client-cert"),
+        Arg::new("timeout")
+            .long("timeout")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_TIMEOUT")
+            .help("Seconds to wait for each request to Fulcio, Rekor and other services [default: no limit]"),
+        Arg::new("fulcio-timeout")
+            .long("fulcio-timeout")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_FULCIO_TIMEOUT")
+            .help("Seconds to wait for each request to Fulcio [default: --timeout]"),
+        Arg::new("rekor-timeout")
+            .long("rekor-timeout")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_REKOR_TIMEOUT")
+            .help("Seconds to wait for each request to Rekor [default: --timeout]"),
+        Arg::new("oidc-timeout")
+            .long("oidc-timeout")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_OIDC_TIMEOUT")
+            .help("Seconds to wait for the OIDC issuer, and for the browser login to complete [default: --timeout, no limit for the login]"),
+        Arg::new("user-agent")
+            .long("user-agent")
+            .global(true)
+            .takes_value(true)
+            .env("FERRIS_SIGN_USER_AGENT")
+            .help("User agent sent with every request [default: ferris-sign/<version>]"),
+        Arg::new("redact-identity")
+            .long("redact-identity")
+            .global(true)
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .default_missing_value("hide")
+            .possible_values(["hide", "hash"])
+            .env("FERRIS_SIGN_REDACT_IDENTITY")
+            .help("Keep the identity off the terminal and out of traces, hidden or as the start of its sha256 with =hash. The certificate still names it"),
+        Arg::new("staging")
+            .long("staging")
+            .global(true)
+            .takes_value(false)
+            .env("FERRIS_SIGN_STAGING")
+            .help("Use the sigstore staging instance and its trust root"),
+        Arg::new("local")
+            .long("local")
+            .global(true)
+            .takes_value(false)
+            .conflicts_with("staging")
+            .env("FERRIS_SIGN_LOCAL")
+            .help("Use a development deployment on localhost: Fulcio on port 5555, Rekor on 3000 and Dex on 8888, over https when --cacert trusts their certificates"),
+        Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .global(true)
+            .multiple_occurrences(true)
+            .help("Trace requests to Fulcio, Rekor and OIDC issuers, -vv adds headers and bodies"),
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .global(true)
+            .env("FERRIS_SIGN_QUIET")
+            .help("Only print errors and the requested output, no progress bars"),
+    ]
+}
+
+// how sign and attest obtain an identity and check what fulcio and rekor
+// hand back
+fn signing_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("identity-token")
+            .long("identity-token")
+            .takes_value(true)
+            .conflicts_with("identity-token-file")
+            .env("FERRIS_SIGN_IDENTITY_TOKEN")
+            .help("OIDC identity token, issued by --oidc-issuer, to use instead of the browser flow"),
+        Arg::new("identity-token-file")
+            .long("identity-token-file")
+            .takes_value(true)
+            .env("FERRIS_SIGN_IDENTITY_TOKEN_FILE")
+            .help("File containing an OIDC identity token issued by --oidc-issuer"),
+        Arg::new("oidc-redirect-port")
+            .long("oidc-redirect-port")
+            .takes_value(true)
+            .conflicts_with("device-flow")
+            .env("FERRIS_SIGN_OIDC_REDIRECT_PORT")
+            .help("Local port for the OAuth redirect [default: any free port]"),
+        Arg::new("no-browser")
+            .long("no-browser")
+            .conflicts_with("device-flow")
+            .env("FERRIS_SIGN_NO_BROWSER")
+            .help("Print the login URL instead of opening a browser"),
+        Arg::new("qr")
+            .long("qr")
+            .conflicts_with("device-flow")
+            .env("FERRIS_SIGN_QR")
+            .help("Also show the login URL as a QR code"),
+        Arg::new("no-token-cache")
+            .long("no-token-cache")
+            .env("FERRIS_SIGN_NO_TOKEN_CACHE")
+            .help("Always log in, do not read or write the token cache"),
+        Arg::new("device-flow")
+            .long("device-flow")
+            .env("FERRIS_SIGN_DEVICE_FLOW")
+            .help("Log in with a device code instead of a local browser"),
+        Arg::new("kubernetes-token-path")
+            .long("kubernetes-token-path")
+            .takes_value(true)
+            .env(ambient::KUBERNETES_TOKEN_PATH_VAR)
+            .help("Projected service account token with the sigstore audience to sign with in a Kubernetes pod [default: /var/run/sigstore/cosign/oidc-token]"),
+        Arg::new("insecure-skip-token-verify")
+            .long("insecure-skip-token-verify")
+            .env("FERRIS_SIGN_INSECURE_SKIP_TOKEN_VERIFY")
+            .help("Send the identity token to Fulcio without checking its signature against the issuer's keys first"),
+        Arg::new("oidc-client-id")
+            .long("oidc-client-id")
+            .takes_value(true)
+            .env("FERRIS_SIGN_OIDC_CLIENT_ID")
+            .help("OAuth client to log in as, also the audience identity tokens must have [default: sigstore]"),
+        Arg::new("oidc-client-secret")
+            .long("oidc-client-secret")
+            .takes_value(true)
+            .env("FERRIS_SIGN_OIDC_CLIENT_SECRET")
+            .help("Secret of a confidential OAuth client, better passed in the environment"),
+        Arg::new("oidc-scopes")
+            .long("oidc-scopes")
+            .takes_value(true)
+            .multiple_values(true)
+            .use_value_delimiter(true)
+            .env("FERRIS_SIGN_OIDC_SCOPES")
+            .help("Comma separated scopes to request besides openid [default: email]"),
+        Arg::new("oidc-login-hint")
+            .long("oidc-login-hint")
+            .takes_value(true)
+            .conflicts_with("device-flow")
+            .env("FERRIS_SIGN_OIDC_LOGIN_HINT")
+            .help("Account to log in with when the browser knows several, e.g. the email address the certificate should be for"),
+        Arg::new("oidc-select-account")
+            .long("oidc-select-account")
+            .conflicts_with("device-flow")
+            .env("FERRIS_SIGN_OIDC_SELECT_ACCOUNT")
+            .help("Ask which account to log in with instead of using the one the browser is logged in with"),
+        Arg::new("oidc-connector")
+            .long("oidc-connector")
+            .takes_value(true)
+            .env("FERRIS_SIGN_OIDC_CONNECTOR")
+            .help("Dex connector to log in with instead of choosing in the browser, github, google, microsoft or a connector ID"),
+        Arg::new("yes")
+            .short('y')
+            .long("yes")
+            .env("FERRIS_SIGN_YES")
+            .help("Do not ask before publishing the identity in the transparency logs"),
+        Arg::new("algorithm")
+            .long("algorithm")
+            .takes_value(true)
+            .possible_values(["ecdsa-p256", "ecdsa-p384", "ed25519", "rsa-pss"])
+            .default_value("ecdsa-p256")
+            .env("FERRIS_SIGN_ALGORITHM")
+            .help("Algorithm of the ephemeral signing key"),
+        Arg::new("ctlog-key")
+            .long("ctlog-key")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CTLOG_KEY")
+            .help("CT log public key, the issued certificate's SCT must verify [default: from the trust root]"),
+        Arg::new("rekor-key")
+            .long("rekor-key")
+            .alias("rekor-pubkey")
+            .takes_value(true)
+            .env("FERRIS_SIGN_REKOR_KEY")
+            .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+        Arg::new("concurrency")
+            .long("concurrency")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CONCURRENCY")
+            .help("Number of files to hash, sign and upload at once [default: number of CPUs]"),
+    ]
+}
+
+// how the image subcommands log in to the registry, docker's
+// configuration is used when these are not given
+fn registry_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("registry-username")
+            .long("registry-username")
+            .takes_value(true)
+            .requires("registry-token")
+            .env("FERRIS_SIGN_REGISTRY_USERNAME")
+            .help("Username to log in to the registry with"),
+        Arg::new("registry-token")
+            .long("registry-token")
+            .takes_value(true)
+            .requires("registry-username")
+            .env("FERRIS_SIGN_REGISTRY_TOKEN")
+            .help("Password or access token to log in to the registry with"),
+    ]
+}
+
+// key=value pairs signed along with image signatures and attestations
+fn annotation_arg() -> Arg<'static> {
+    Arg::new("annotation")
+        .short('a')
+        .long("annotation")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .env("FERRIS_SIGN_ANNOTATION")
+        .help("Annotation key=value to sign along, e.g. a build ID or git SHA, may be repeated")
+}
+
+fn require_annotation_arg() -> Arg<'static> {
+    Arg::new("require-annotation")
+        .long("require-annotation")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .env("FERRIS_SIGN_REQUIRE_ANNOTATION")
+        .help("Annotation key=value that must have been signed along, may be repeated")
+}
+
+fn policy_arg() -> Arg<'static> {
+    Arg::new("policy")
+        .long("policy")
+        .takes_value(true)
+        .env("FERRIS_SIGN_POLICY")
+        .help("YAML policy file with the allowed identities, required annotations and attestations, and the maximum signature age")
+}
+
+// which identities verify and verify-attestation accept certificates for
+fn identity_policy_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("certificate-identity")
+            .long("certificate-identity")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CERTIFICATE_IDENTITY")
+            .conflicts_with("certificate-identity-regexp")
+            .help("Identity (email or URI) the certificate must be issued to"),
+        Arg::new("certificate-identity-regexp")
+            .long("certificate-identity-regexp")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CERTIFICATE_IDENTITY_REGEXP")
+            .help("Regular expression the certificate identity must match"),
+        Arg::new("certificate-oidc-issuer")
+            .long("certificate-oidc-issuer")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CERTIFICATE_OIDC_ISSUER")
+            .conflicts_with("certificate-oidc-issuer-regexp")
+            .help("OIDC issuer the certificate identity must come from"),
+        Arg::new("certificate-oidc-issuer-regexp")
+            .long("certificate-oidc-issuer-regexp")
+            .takes_value(true)
+            .env("FERRIS_SIGN_CERTIFICATE_OIDC_ISSUER_REGEXP")
+            .help("Regular expression the certificate OIDC issuer must match"),
+    ]
+}
+
+fn cli() -> Command<'static> {
+    Command::new("ferris-sign")
         .version("0.1")
         .author("Luke Hinds")
         .about("Simple rust based example of sigstore signing")
-        .arg(
-            Arg::new("generate-keys")
-                .short('k')
-                .long("generate-keys")
-                .takes_value(false)
-                .help("Generate key pair"),
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .args(global_args())
+        .subcommand(
+            Command::new("sign")
+                .about("Sign a file with an ephemeral key and a Fulcio certificate, or with a key of your own")
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .required(true)
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("File or glob pattern to sign, may be repeated"),
+                )
+                .arg(
+                    Arg::new("sig-out")
+                        .short('s')
+                        .long("sig-out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_SIG_OUT")
+                        .help("Output signature file, - for stdout"),
+                )
+                .arg(
+                    Arg::new("cert-out")
+                        .short('c')
+                        .long("cert-out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT_OUT")
+                        .help("Output signing certificate, - for stdout"),
+                )
+                .arg(
+                    Arg::new("chain-out")
+                        .long("chain-out")
+                        .takes_value(true)
+                        .conflicts_with("key")
+                        .env("FERRIS_SIGN_CHAIN_OUT")
+                        .help("Output signing certificate followed by the Fulcio intermediates, - for stdout"),
+                )
+                .arg(
+                    Arg::new("bundle-out")
+                        .short('b')
+                        .long("bundle-out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_BUNDLE_OUT")
+                        .help("Output Sigstore bundle (.sigstore.json), - for stdout"),
+                )
+                .arg(
+                    Arg::new("bundle-format")
+                        .long("bundle-format")
+                        .takes_value(true)
+                        .possible_values(["json", "protobuf"])
+                        .default_value("json")
+                        .env("FERRIS_SIGN_BUNDLE_FORMAT")
+                        .help("Bundle output encoding, protobuf writes a binary version 0.3 bundle (.sigstore.pb) as sigstore-go reads them"),
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .short('o')
+                        .long("output-dir")
+                        .takes_value(true)
+                        .conflicts_with_all(&["sig-out", "cert-out", "chain-out", "bundle-out"])
+                        .env("FERRIS_SIGN_OUTPUT_DIR")
+                        .help("Write <file>.sig, <file>.pem, <file>.chain.pem and <file>.sigstore.json (or .sigstore.pb) here"),
+                )
+                .arg(
+                    Arg::new("ssh-sig-out")
+                        .long("ssh-sig-out")
+                        .takes_value(true)
+                        .conflicts_with("output-dir")
+                        .env("FERRIS_SIGN_SSH_SIG_OUT")
+                        .help("Also sign in the OpenSSH format, for ssh-keygen -Y verify, and write the signature here, - for stdout. Needs an ECDSA or Ed25519 key"),
+                )
+                .arg(
+                    Arg::new("ssh-namespace")
+                        .long("ssh-namespace")
+                        .takes_value(true)
+                        .requires("ssh-sig-out")
+                        .env("FERRIS_SIGN_SSH_NAMESPACE")
+                        .help("Namespace of the OpenSSH signature, verifiers pass the same to ssh-keygen -n [default: file]"),
+                )
+                .arg(
+                    Arg::new("minisign-out")
+                        .long("minisign-out")
+                        .takes_value(true)
+                        .requires("key")
+                        .conflicts_with("output-dir")
+                        .env("FERRIS_SIGN_MINISIGN_OUT")
+                        .help("Also write a minisign signature (.minisig) for minisign -V, - for stdout. Needs an Ed25519 --key"),
+                )
+                .arg(
+                    Arg::new("minisign-public-key-out")
+                        .long("minisign-public-key-out")
+                        .takes_value(true)
+                        .requires("key")
+                        .env("FERRIS_SIGN_MINISIGN_PUBLIC_KEY_OUT")
+                        .help("Write the --key public key as a minisign public key file for minisign -V -p, - for stdout"),
+                )
+                .arg(
+                    Arg::new("embed")
+                        .long("embed")
+                        .conflicts_with_all(&["key", "bundle-out", "output-dir", "dry-run"])
+                        .env("FERRIS_SIGN_EMBED")
+                        .help("Write the bundle into the file itself, an uncompressed tar archive as its last member .sigstore/bundle.sigstore.json or a zip archive as its comment"),
+                )
+                .arg(
+                    Arg::new("watch")
+                        .long("watch")
+                        .conflicts_with_all(&["key", "dry-run", "embed"])
+                        .env("FERRIS_SIGN_WATCH")
+                        .help("Keep running and sign the files again whenever they change, e.g. against --staging while iterating on a build"),
+                )
+                .arg(
+                    Arg::new("reproducible")
+                        .long("reproducible")
+                        .requires("key")
+                        .env("FERRIS_SIGN_REPRODUCIBLE")
+                        .help("Write the same signatures for the same files and key every time: ECDSA signs with RFC 6979 nonces, files are signed in sorted order and the minisign timestamp is SOURCE_DATE_EPOCH or left out"),
+                )
+                .args(signing_args())
+                .arg(
+                    Arg::new("sig-format")
+                        .long("sig-format")
+                        .takes_value(true)
+                        .possible_values(SIGNATURE_FORMATS)
+                        .env("FERRIS_SIGN_SIG_FORMAT")
+                        .help("Signature output encoding, raw is r || s for ECDSA and base64 is what cosign writes [default: der]"),
+                )
+                .arg(
+                    Arg::new("cert-format")
+                        .long("cert-format")
+                        .takes_value(true)
+                        .possible_values(["pem", "der"])
+                        .default_value("pem")
+                        .env("FERRIS_SIGN_CERT_FORMAT")
+                        .help("Certificate output encoding, --chain-out is always PEM"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["raw", "cosign"])
+                        .env("FERRIS_SIGN_FORMAT")
+                        .hide(true)
+                        .help("Deprecated, --sig-format der or base64"),
+                )
+                .arg(
+                    Arg::new("timestamp-url")
+                        .long("timestamp-url")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_TIMESTAMP_URL")
+                        .help("RFC 3161 timestamp authority to timestamp the signature with, e.g. https://timestamp.sigstore.dev/api/v1/timestamp"),
+                )
+                .arg(
+                    Arg::new("key")
+                        .long("key")
+                        .takes_value(true)
+                        .conflicts_with_all(&["cert-out", "bundle-out", "timestamp-url"])
+                        .env("FERRIS_SIGN_KEY")
+                        .help("Sign with this PEM private key, or a pkcs11:, tpm://<handle>, keychain://<label> or certstore://<thumbprint> hardware key, instead of a Fulcio certificate. Passphrases and PINs are read from FERRIS_SIGN_KEY_PASSWORD or FERRIS_SIGN_PKCS11_PIN, or a prompt. TPM and Keychain keys are created if missing"),
+                )
+                .arg(
+                    Arg::new("digest-algorithm")
+                        .long("digest-algorithm")
+                        .takes_value(true)
+                        .possible_values(DIGEST_ALGORITHMS)
+                        .env("FERRIS_SIGN_DIGEST_ALGORITHM")
+                        .help("Digest to sign and log the file by [default: the one of the key type], bundles cannot carry blake3"),
+                )
+                .arg(
+                    Arg::new("no-upload")
+                        .long("no-upload")
+                        .env("FERRIS_SIGN_NO_UPLOAD")
+                        .help("Do not record the signature in Rekor. Without a log entry, verifiers need --allow-missing-tlog and a timestamp from --timestamp-url"),
+                )
+                .arg(
+                    Arg::new("expires")
+                        .long("expires")
+                        .takes_value(true)
+                        .conflicts_with("key")
+                        .env("FERRIS_SIGN_EXPIRES")
+                        .help("Sign a claim into the bundle that the signature expires after this long, e.g. 90d or 12h"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .env("FERRIS_SIGN_DRY_RUN")
+                        .help("Log in and sign, but print what would be sent to Fulcio and Rekor instead of sending it. No files are written"),
+                )
+                .group(
+                    ArgGroup::new("outputs")
+                        .args(&[
+                            "sig-out",
+                            "cert-out",
+                            "bundle-out",
+                            "output-dir",
+                            "ssh-sig-out",
+                            "minisign-out",
+                            "embed",
+                        ])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("attest")
+                .about("Sign an in-toto attestation about files or an image and record it in Rekor")
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .required_unless_present("image")
+                        .conflicts_with("image")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("File or glob pattern the attestation is about, may be repeated"),
+                )
+                .arg(
+                    Arg::new("image")
+                        .long("image")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_IMAGE")
+                        .help("Image the attestation is about, it is pushed to the image's registry"),
+                )
+                .arg(
+                    Arg::new("predicate")
+                        .long("predicate")
+                        .required_unless_present("slsa")
+                        .conflicts_with("slsa")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_PREDICATE")
+                        .help("JSON file with the predicate"),
+                )
+                .arg(
+                    Arg::new("predicate-type")
+                        .long("predicate-type")
+                        .required_unless_present("slsa")
+                        .conflicts_with("slsa")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_PREDICATE_TYPE")
+                        .help("Predicate type URI, or one of slsaprovenance, cyclonedx, spdx"),
+                )
+                .arg(
+                    Arg::new("slsa")
+                        .long("slsa")
+                        .env("FERRIS_SIGN_SLSA")
+                        .help("Generate SLSA v1 provenance for the current build as the predicate"),
+                )
+                .arg(
+                    Arg::new("builder-id")
+                        .long("builder-id")
+                        .takes_value(true)
+                        .requires("slsa")
+                        .env("FERRIS_SIGN_BUILDER_ID")
+                        .help("Builder id to record in the provenance [default: detected]"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .required_unless_present("image")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_OUT")
+                        .help("Output DSSE envelope, - for stdout"),
+                )
+                .arg(
+                    Arg::new("cert-out")
+                        .short('c')
+                        .long("cert-out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT_OUT")
+                        .help("Output signing certificate, - for stdout"),
+                )
+                .arg(annotation_arg())
+                .args(registry_args())
+                .args(signing_args()),
+        )
+        .subcommand(
+            Command::new("sign-image")
+                .about("Sign a container image and push the signature to its registry, the way cosign does")
+                .arg(
+                    Arg::new("image")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Image reference, e.g. ghcr.io/org/app:tag, tags are resolved to a digest"),
+                )
+                .arg(annotation_arg())
+                .args(registry_args())
+                .args(signing_args()),
+        )
+        .subcommand(
+            Command::new("sign-release")
+                .about("Sign the assets of a GitHub release, optionally uploading the signatures to it")
+                .arg(
+                    Arg::new("repo")
+                        .long("repo")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REPO")
+                        .help("Repository of the release, as owner/name"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .long("tag")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_TAG")
+                        .help("Tag of the release"),
+                )
+                .arg(
+                    Arg::new("asset")
+                        .takes_value(true)
+                        .multiple_values(true)
+                        .help("Local files to sign instead of downloading the release assets"),
+                )
+                .arg(
+                    Arg::new("upload")
+                        .long("upload")
+                        .requires("github-token")
+                        .env("FERRIS_SIGN_UPLOAD")
+                        .help("Upload the .sig, .pem and .sigstore.json files to the release"),
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .default_value(".")
+                        .env("FERRIS_SIGN_OUTPUT_DIR")
+                        .help("Directory to write the signing outputs to"),
+                )
+                .arg(
+                    Arg::new("github-token")
+                        .long("github-token")
+                        .takes_value(true)
+                        .env("GITHUB_TOKEN")
+                        .help("GitHub token, needed for uploads and private repositories"),
+                )
+                .arg(
+                    Arg::new("github-api-url")
+                        .long("github-api-url")
+                        .takes_value(true)
+                        .env("GITHUB_API_URL")
+                        .default_value(github::GITHUB_API_URL)
+                        .help("GitHub API to talk to, for GitHub Enterprise"),
+                )
+                .args(signing_args()),
+        )
+        .subcommand(
+            Command::new("countersign")
+                .about("Sign the signature in a bundle, endorsing it, and append the countersignature to the bundle")
+                .arg(
+                    Arg::new("bundle")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Bundle with the signature to countersign"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_OUT")
+                        .help("Output bundle, - for stdout [default: the bundle, rewritten in place]"),
+                )
+                .args(signing_args()),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Convert a signature, certificate and Rekor UUID into a bundle, or a bundle back into them")
+                .arg(
+                    Arg::new("bundle")
+                        .short('b')
+                        .long("bundle")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_BUNDLE")
+                        .help("Bundle to take apart, JSON or protobuf"),
+                )
+                .arg(
+                    Arg::new("signature")
+                        .short('s')
+                        .long("signature")
+                        .takes_value(true)
+                        .required_unless_present("bundle")
+                        .conflicts_with("bundle")
+                        .env("FERRIS_SIGN_SIGNATURE")
+                        .help("Signature file to bundle"),
+                )
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .takes_value(true)
+                        .required_unless_present("bundle")
+                        .conflicts_with("bundle")
+                        .env("FERRIS_SIGN_CERT")
+                        .help("Signing certificate to bundle, PEM or DER"),
+                )
+                .arg(
+                    Arg::new("rekor-uuid")
+                        .long("rekor-uuid")
+                        .takes_value(true)
+                        .required_unless_present("bundle")
+                        .conflicts_with("bundle")
+                        .env("FERRIS_SIGN_REKOR_UUID")
+                        .help("UUID of the Rekor entry of the signature, it is fetched into the bundle"),
+                )
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .takes_value(true)
+                        .conflicts_with("bundle")
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("Signed file, to take its digest from rather than from the Rekor entry"),
+                )
+                .arg(
+                    Arg::new("bundle-out")
+                        .long("bundle-out")
+                        .takes_value(true)
+                        .required_unless_present("bundle")
+                        .conflicts_with("bundle")
+                        .env("FERRIS_SIGN_BUNDLE_OUT")
+                        .help("Output bundle, - for stdout"),
+                )
+                .arg(
+                    Arg::new("bundle-format")
+                        .long("bundle-format")
+                        .takes_value(true)
+                        .possible_values(["json", "protobuf"])
+                        .default_value("json")
+                        .env("FERRIS_SIGN_BUNDLE_FORMAT")
+                        .help("Output bundle encoding"),
+                )
+                .arg(
+                    Arg::new("sig-out")
+                        .long("sig-out")
+                        .takes_value(true)
+                        .requires("bundle")
+                        .env("FERRIS_SIGN_SIG_OUT")
+                        .help("Output signature file, - for stdout. The UUID of the Rekor entry is printed"),
+                )
+                .arg(
+                    Arg::new("cert-out")
+                        .long("cert-out")
+                        .takes_value(true)
+                        .requires("bundle")
+                        .env("FERRIS_SIGN_CERT_OUT")
+                        .help("Output signing certificate, - for stdout"),
+                )
+                .arg(
+                    Arg::new("sig-format")
+                        .long("sig-format")
+                        .takes_value(true)
+                        .possible_values(SIGNATURE_FORMATS)
+                        .default_value("der")
+                        .env("FERRIS_SIGN_SIG_FORMAT")
+                        .help("Encoding of the signature file read or written"),
+                )
+                .arg(
+                    Arg::new("cert-format")
+                        .long("cert-format")
+                        .takes_value(true)
+                        .possible_values(["pem", "der"])
+                        .default_value("pem")
+                        .env("FERRIS_SIGN_CERT_FORMAT")
+                        .help("Output certificate encoding"),
+                )
+                .arg(
+                    Arg::new("rekor-key")
+                        .long("rekor-key")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REKOR_KEY")
+                        .help("Rekor public key to check the fetched entry with [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Verify a signature against a Fulcio signing certificate or a public key")
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("File to verify"),
+                )
+                .arg(
+                    Arg::new("signature")
+                        .short('s')
+                        .long("signature")
+                        .required_unless_present("bundles")
+                        .conflicts_with("bundles")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_SIGNATURE")
+                        .help("Signature file"),
+                )
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .required_unless_present_any(["bundles", "public-key"])
+                        .conflicts_with_all(&["bundles", "public-key"])
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT")
+                        .help("Signing certificate"),
+                )
+                .arg(
+                    Arg::new("public-key")
+                        .long("public-key")
+                        .conflicts_with("bundles")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_PUBLIC_KEY")
+                        .help("PEM public key, for signatures made with sign --key"),
+                )
+                .arg(
+                    Arg::new("rekor-lookup")
+                        .long("rekor-lookup")
+                        .requires("public-key")
+                        .env("FERRIS_SIGN_REKOR_LOOKUP")
+                        .help("Also require a Rekor entry for the signature logged with the public key"),
+                )
+                .arg(
+                    Arg::new("bundle")
+                        .short('b')
+                        .long("bundle")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .env("FERRIS_SIGN_BUNDLE")
+                        .help("Sigstore bundle (.sigstore.json or a binary .sigstore.pb), may be repeated to meet a policy threshold"),
+                )
+                .arg(
+                    Arg::new("embedded")
+                        .long("embedded")
+                        .env("FERRIS_SIGN_EMBEDDED")
+                        .help("Verify the bundle embedded in the tar or zip archive by sign --embed"),
+                )
+                .group(ArgGroup::new("bundles").args(&["bundle", "embedded"]))
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .takes_value(false)
+                        .requires("bundles")
+                        .env("FERRIS_SIGN_OFFLINE")
+                        .help("Verify the bundle without any network access"),
+                )
+                .arg(
+                    Arg::new("allow-missing-tlog")
+                        .long("allow-missing-tlog")
+                        .requires("bundles")
+                        .env("FERRIS_SIGN_ALLOW_MISSING_TLOG")
+                        .help("Accept a bundle without a Rekor entry, as written by sign --no-upload. Its signing time then comes from its timestamps"),
+                )
+                .arg(
+                    Arg::new("rekor-key")
+                        .long("rekor-key")
+                        .alias("rekor-pubkey")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REKOR_KEY")
+                        .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                )
+                .arg(
+                    Arg::new("ctlog-key")
+                        .long("ctlog-key")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CTLOG_KEY")
+                        .help("CT log public key used to verify the embedded SCT [default: from the trust root]"),
+                )
+                .args(identity_policy_args())
+                .arg(policy_arg().conflicts_with("public-key"))
+                .arg(
+                    Arg::new("root")
+                        .short('r')
+                        .long("root")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_FULCIO_ROOT")
+                        .help("Fulcio root certificate chain [default: from the trust root, else fetched from Fulcio]"),
+                )
+                .arg(
+                    Arg::new("digest-algorithm")
+                        .long("digest-algorithm")
+                        .takes_value(true)
+                        .possible_values(DIGEST_ALGORITHMS)
+                        .conflicts_with("bundles")
+                        .env("FERRIS_SIGN_DIGEST_ALGORITHM")
+                        .help("Digest the signature was made over [default: the one of the key type]"),
+                )
+                .arg(
+                    Arg::new("timestamp-root")
+                        .long("timestamp-root")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_TIMESTAMP_ROOT")
+                        .help("Timestamp authority certificate chain to verify bundled timestamps [default: from the trust root]"),
+                )
+                .arg(
+                    Arg::new("sig-format")
+                        .long("sig-format")
+                        .takes_value(true)
+                        .possible_values(SIGNATURE_FORMATS)
+                        .env("FERRIS_SIGN_SIG_FORMAT")
+                        .help("Signature input encoding, raw is r || s for ECDSA and base64 is what cosign writes [default: der]"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["raw", "cosign"])
+                        .env("FERRIS_SIGN_FORMAT")
+                        .hide(true)
+                        .help("Deprecated, --sig-format der or base64"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-image")
+                .about("Verify the signatures of a container image stored in its registry")
+                .arg(
+                    Arg::new("image")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Image reference, e.g. ghcr.io/org/app:tag, tags are resolved to a digest"),
+                )
+                .args(registry_args())
+                .arg(
+                    Arg::new("rekor-key")
+                        .long("rekor-key")
+                        .alias("rekor-pubkey")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REKOR_KEY")
+                        .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                )
+                .arg(
+                    Arg::new("ctlog-key")
+                        .long("ctlog-key")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CTLOG_KEY")
+                        .help("CT log public key used to verify the embedded SCT [default: from the trust root]"),
+                )
+                .args(identity_policy_args())
+                .arg(require_annotation_arg())
+                .arg(policy_arg())
+                .arg(
+                    Arg::new("root")
+                        .short('r')
+                        .long("root")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_FULCIO_ROOT")
+                        .help("Fulcio root certificate chain [default: from the trust root, else fetched from Fulcio]"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-attestation")
+                .about("Verify an attestation and check conditions on its predicate")
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("File the attestation must be about"),
+                )
+                .arg(
+                    Arg::new("attestation")
+                        .short('a')
+                        .long("attestation")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_ATTESTATION")
+                        .help("DSSE envelope written by attest"),
+                )
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT")
+                        .help("Signing certificate"),
+                )
+                .arg(
+                    Arg::new("predicate-type")
+                        .long("predicate-type")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_PREDICATE_TYPE")
+                        .help("Predicate type the attestation must have"),
+                )
+                .arg(
+                    Arg::new("policy")
+                        .long("policy")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_POLICY")
+                        .help("JSON file with values the predicate must contain"),
+                )
+                .arg(
+                    Arg::new("root")
+                        .short('r')
+                        .long("root")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_FULCIO_ROOT")
+                        .help("Fulcio root certificate chain [default: from the trust root, else fetched from Fulcio]"),
+                )
+                .arg(
+                    Arg::new("rekor-key")
+                        .long("rekor-key")
+                        .alias("rekor-pubkey")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REKOR_KEY")
+                        .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                )
+                .arg(
+                    Arg::new("ctlog-key")
+                        .long("ctlog-key")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CTLOG_KEY")
+                        .help("CT log public key used to verify the embedded SCT [default: from the trust root]"),
+                )
+                .args(identity_policy_args())
+                .arg(require_annotation_arg()),
+        )
+        .subcommand(
+            Command::new("cargo")
+                .about("Sign and verify .crate packages made by cargo package")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("sign")
+                        .about("Attest to .crate files and their name and version, recording the attestation in Rekor")
+                        .arg(
+                            Arg::new("crate")
+                                .takes_value(true)
+                                .multiple_values(true)
+                                .help(".crate files to sign [default: those in target/package]"),
+                        )
+                        .args(signing_args()),
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Verify a downloaded .crate file against its attestation and Rekor entry")
+                        .arg(
+                            Arg::new("crate")
+                                .required(true)
+                                .takes_value(true)
+                                .help(".crate file to verify"),
+                        )
+                        .arg(
+                            Arg::new("attestation")
+                                .short('a')
+                                .long("attestation")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_ATTESTATION")
+                                .help("DSSE envelope written by cargo sign [default: <crate>.intoto.json]"),
+                        )
+                        .arg(
+                            Arg::new("cert")
+                                .short('c')
+                                .long("cert")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_CERT")
+                                .help("Signing certificate [default: <crate>.pem]"),
+                        )
+                        .arg(
+                            Arg::new("root")
+                                .short('r')
+                                .long("root")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_FULCIO_ROOT")
+                                .help("Fulcio root certificate chain [default: from the trust root, else fetched from Fulcio]"),
+                        )
+                        .arg(
+                            Arg::new("rekor-key")
+                                .long("rekor-key")
+                                .alias("rekor-pubkey")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_REKOR_KEY")
+                                .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                        )
+                        .arg(
+                            Arg::new("ctlog-key")
+                                .long("ctlog-key")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_CTLOG_KEY")
+                                .help("CT log public key used to verify the embedded SCT [default: from the trust root]"),
+                        )
+                        .args(identity_policy_args()),
+                ),
+        )
+        .subcommand(
+            Command::new("monitor")
+                .about("Watch Rekor for entries signed for an identity that were not created here")
+                .arg(
+                    Arg::new("identity")
+                        .long("identity")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_IDENTITY")
+                        .help("Email address the signing certificates were issued to"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .takes_value(true)
+                        .default_value("300")
+                        .env("FERRIS_SIGN_INTERVAL")
+                        .help("Seconds between polls"),
+                )
+                .arg(
+                    Arg::new("once")
+                        .long("once")
+                        .env("FERRIS_SIGN_ONCE")
+                        .help("Poll once and exit with an error if unknown entries appeared"),
+                )
+                .arg(
+                    Arg::new("state")
+                        .long("state")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_STATE")
+                        .help("File remembering entries already reported [default: in the cache dir]"),
+                )
+                .arg(
+                    Arg::new("rekor-key")
+                        .long("rekor-key")
+                        .alias("rekor-pubkey")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_REKOR_KEY")
+                        .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect-cert")
+                .about("Show the identity, validity and transparency details of a signing certificate")
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT")
+                        .help("Signing certificate, PEM or DER"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .takes_value(true)
+                        .possible_values(["text", "json", "public-key"])
+                        .default_value("text")
+                        .env("FERRIS_SIGN_OUTPUT")
+                        .help("What to print, public-key writes the certificate's key as PEM"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_OUT")
+                        .help("Output file (stdout if not set)"),
+                ),
         )
+        .subcommand(
+            Command::new("inspect")
+                .about("Summarize a signature or bundle without verifying it")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Signature (.sig) or Sigstore bundle (.sigstore.json)"),
+                )
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT")
+                        .help("Signing certificate of a detached signature"),
+                )
+                .arg(
+                    Arg::new("in-file")
+                        .short('f')
+                        .long("in-file")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_IN_FILE")
+                        .help("Signed file, to show the digest of a detached signature"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .takes_value(true)
+                        .possible_values(["text", "json"])
+                        .default_value("text")
+                        .env("FERRIS_SIGN_OUTPUT")
+                        .help("Output format"),
+                ),
+        )
+        // the old name, which only extracted the public key
+        .subcommand(
+            Command::new("extract")
+                .hide(true)
+                .arg(
+                    Arg::new("cert")
+                        .short('c')
+                        .long("cert")
+                        .required(true)
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_CERT"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .takes_value(true)
+                        .possible_values(["public-key"])
+                        .default_value("public-key"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .takes_value(true)
+                        .env("FERRIS_SIGN_OUT"),
+                ),
+        )
+        .subcommand(
+            Command::new("rekor")
+                .about("Interact with the Rekor transparency log")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("get")
+                        .about("Fetch a log entry and verify its inclusion proof")
+                        .arg(
+                            Arg::new("uuid")
+                                .short('u')
+                                .long("uuid")
+                                .required_unless_present("log-index")
+                                .conflicts_with("log-index")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_UUID")
+                                .help("UUID of the log entry"),
+                        )
+                        .arg(
+                            Arg::new("log-index")
+                                .long("log-index")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_LOG_INDEX")
+                                .help("Index of the log entry"),
+                        )
+                        .arg(
+                            Arg::new("raw")
+                                .long("raw")
+                                .env("FERRIS_SIGN_RAW")
+                                .help("Print the entry as returned by Rekor instead of decoding it"),
+                        )
+                        .arg(
+                            Arg::new("rekor-key")
+                                .long("rekor-key")
+                                .alias("rekor-pubkey")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_REKOR_KEY")
+                                .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("search")
+                        .about("Find log entries by artifact, public key or identity")
+                        .arg(
+                            Arg::new("sha256")
+                                .long("sha256")
+                                .takes_value(true)
+                                .conflicts_with("artifact")
+                                .env("FERRIS_SIGN_SHA256")
+                                .help("Hex sha256 digest of the artifact"),
+                        )
+                        .arg(
+                            Arg::new("artifact")
+                                .long("artifact")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_ARTIFACT")
+                                .help("Artifact to search for by digest"),
+                        )
+                        .arg(
+                            Arg::new("public-key")
+                                .long("public-key")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_PUBLIC_KEY")
+                                .help("PEM public key or certificate the entries were signed with"),
+                        )
+                        .arg(
+                            Arg::new("email")
+                                .long("email")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_EMAIL")
+                                .help("Identity the signing certificates were issued to"),
+                        )
+                        .group(
+                            ArgGroup::new("query")
+                                .args(&["sha256", "artifact", "public-key", "email"])
+                                .multiple(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("consistency")
+                        .about("Verify that the log only grew since the tree head seen last")
+                        .arg(
+                            Arg::new("rekor-key")
+                                .long("rekor-key")
+                                .alias("rekor-pubkey")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_REKOR_KEY")
+                                .help("Rekor public key [default: from the trust root, else fetched from Rekor with --local or --staging]"),
+                        )
+                        .arg(
+                            Arg::new("state")
+                                .long("state")
+                                .takes_value(true)
+                                .env("FERRIS_SIGN_STATE")
+                                .help("File keeping the latest verified checkpoint [default: in the cache dir]"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("trust")
+                .about("Carry the trust root into networks without access to its TUF repository")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Write the trust root and its TUF metadata to a single file")
+                        .arg(
+                            Arg::new("out")
+                                .short('o')
+                                .long("out")
+                                .takes_value(true)
+                                .default_value("-")
+                                .env("FERRIS_SIGN_OUT")
+                                .help("Output file, - for stdout"),
+                        )
+                        .arg(
+                            Arg::new("offline")
+                                .long("offline")
+                                .env("FERRIS_SIGN_OFFLINE")
+                                .help("Export the cached trust root without updating it first"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Cache a trust root written by trust export, for verify --offline")
+                        .arg(
+                            Arg::new("file")
+                                .required(true)
+                                .takes_value(true)
+                                .help("File written by trust export"),
+                        ),
+                ),
+        )
+}
+
+// the gpgsm command line git uses for its gpg.x509.program, signing reads
+// the object from stdin and verifying reads it from the file named "-"
+fn git_cli() -> Command<'static> {
+    Command::new("ferris-sign")
+        .about("Sign and verify git commits and tags, run by git as its gpg.x509.program")
+        .args(global_args())
+        .args(signing_args())
         .arg(
-            Arg::new("sign")
-                .short('s')
-                .long("sign")
-                .takes_value(false)
-                .help("OIDC sign"),
+            Arg::new("status-fd")
+                .long("status-fd")
+                .required(true)
+                .takes_value(true)
+                .help("File descriptor to write gpg status lines to"),
         )
+        .arg(Arg::new("detach-sign").short('b').long("detach-sign"))
+        .arg(Arg::new("sign").short('s').long("sign"))
+        .arg(Arg::new("armor").short('a').long("armor"))
         .arg(
-            Arg::new("cert")
-                .short('c')
-                .long("cert")
+            Arg::new("local-user")
+                .short('u')
+                .long("local-user")
                 .takes_value(true)
-                .help("Output signing certificate"),
+                .help("Ignored, the signing identity comes from the OIDC login"),
         )
         .arg(
-            Arg::new("file")
-                .short('f')
-                .long("file")
-                .required(true)
+            Arg::new("verify")
+                .long("verify")
                 .takes_value(true)
-                .help("Output signature file"),
+                .help("Detached signature to verify"),
         )
+        .arg(Arg::new("data").help("Signed object, - for stdin"))
         .arg(
-            Arg::new("signature")
-                .short('n')
-                .long("signature")
-                .required(true)
+            Arg::new("root")
+                .long("root")
                 .takes_value(true)
-                .help("Output signature"),
+                .env("FERRIS_SIGN_FULCIO_ROOT")
+                .help("Fulcio root certificate chain [default: from the trust root, else fetched from Fulcio]"),
         )
-        .get_matches();
+        .group(
+            ArgGroup::new("mode")
+                .args(&["sign", "verify"])
+                .required(true),
+        )
+}
 
-    let (private_key, public_key_pem) = crypto::create_keys()?;
-    let mut scope_signer = crypto::create_signer(&private_key)?;
+// flags a configured value gives way to, clap would report the two as
+// conflicting otherwise
+const CONFIG_CONFLICTS: [(&str, &str); 2] = [
+    ("certificate-identity", "certificate-identity-regexp"),
+    ("certificate-oidc-issuer", "certificate-oidc-issuer-regexp"),
+];
 
-    if matches.is_present("sign") {
-        // use tokio::task::spawn_blocking to call OpenIDAuthorize in a blocking thread
-        let oidc_url = task::spawn_blocking(move || {
-            oauth::openidflow::OpenIDAuthorize::new(
-                "sigstore",
-                "",
-                SIGSTORE_OAUTH_URL,
-                "http://localhost:8080",
-            )
-            .auth_url()
-            .unwrap()
-        })
-        .await?;
+// the environment variable of every flag that has one, by long flag name
+fn flag_env_vars() -> BTreeMap<String, String> {
+    fn collect(command: &Command, vars: &mut BTreeMap<String, String>) {
+        for arg in command.get_arguments() {
+            if let (Some(long), Some(var)) = (arg.get_long(), arg.get_env()) {
+                vars.insert(long.to_string(), var.to_string_lossy().into_owned());
+            }
+        }
+        for subcommand in command.get_subcommands() {
+            collect(subcommand, vars);
+        }
+    }
+    let mut vars = BTreeMap::new();
+    collect(&cli(), &mut vars);
+    collect(&git_cli(), &mut vars);
+    vars
+}
 
-        if open::that(oidc_url.0.to_string()).is_ok() {
-            println!(
-                "Open this URL in a browser if it does not automatically open for you:\n{}\n",
-                oidc_url.0.to_string()
-            );
+// configured values are passed to clap as the environment variables of
+// their flags, which gives flags and the environment precedence over them
+fn apply_config(config: &Config) -> Result<(), anyhow::Error> {
+    let vars = flag_env_vars();
+    let args: Vec<String> = env::args().collect();
+    let given = |flag: &str| {
+        let long = format!("--{}", flag);
+        vars.get(flag).is_some_and(|var| env::var_os(var).is_some())
+            || args
+                .iter()
+                .any(|arg| arg == &long || arg.starts_with(&format!("{}=", long)))
+    };
+    for (key, value) in config.iter() {
+        let var = match vars.get(key) {
+            Some(var) => var,
+            None => anyhow::bail!(
+                "{} is not a setting of {}",
+                key,
+                config::config_path()?.display()
+            ),
+        };
+        let conflicting = CONFIG_CONFLICTS
+            .iter()
+            .filter_map(|&(a, b)| {
+                if key == a {
+                    Some(b)
+                } else if key == b {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+            .any(given);
+        if env::var_os(var).is_none() && !conflicting {
+            env::set_var(var, value);
         }
+    }
+    Ok(())
+}
 
-        // use tokio::task::spawn_blocking to call RedirectListener in a blocking thread
-        let result = task::spawn_blocking(move || {
-            oauth::openidflow::RedirectListener::new(
-                "127.0.0.1:8080",
-                oidc_url.1, // client
-                oidc_url.2, // nonce
-                oidc_url.3, //
-            )
-            .redirect_listener()
-            .unwrap()
-        })
-        .await?;
+// clap has not parsed the command line yet when the configuration is read
+fn profile_name(config: &Config) -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    env::var("FERRIS_SIGN_PROFILE")
+        .ok()
+        .or_else(|| config.get("profile").map(str::to_string))
+}
+
+fn configure() -> Result<(), anyhow::Error> {
+    let config = Config::load()?;
+    let config = match profile_name(&config) {
+        Some(name) => config.profile(&name)?,
+        None => config,
+    };
+    apply_config(&config)
+}
+
+fn main() {
+    // the environment is only safe to change before the runtime starts its
+    // threads
+    let result = configure()
+        .map_err(|e| anyhow::Error::from(UsageError::new(format!("{:#}", e))))
+        .and_then(|()| {
+            // git passes the status descriptor first, no subcommand starts
+            // that way
+            let git = env::args()
+                .nth(1)
+                .is_some_and(|arg| arg.starts_with("--status-fd"));
+            let matches = if git { git_cli() } else { cli() }.get_matches();
+            set_proxy(&matches)?;
+            set_tls(&matches)?;
+            set_timeout(&matches)?;
+            init_tracing(&matches);
+            QUIET.store(matches.is_present("quiet"), Ordering::Relaxed);
+
+            let runtime = tokio::runtime::Runtime::new()?;
+            let result = runtime.block_on(async {
+                if git {
+                    git_gpg(&matches).await
+                } else {
+                    run(&matches).await
+                }
+            });
+            // a timed out login leaves its redirect listener blocked
+            runtime.shutdown_background();
+            result
+        });
+    if let Err(e) = result {
+        eprintln!("Error: {:?}", e);
+        process::exit(exit::code(&e));
+    }
+}
+
+async fn run(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    match matches.subcommand() {
+        Some(("sign", sub_matches)) => sign(sub_matches).await,
+        Some(("attest", sub_matches)) => attest(sub_matches).await,
+        Some(("sign-image", sub_matches)) => sign_image(sub_matches).await,
+        Some(("sign-release", sub_matches)) => sign_release(sub_matches).await,
+        Some(("countersign", sub_matches)) => countersign(sub_matches).await,
+        Some(("convert", sub_matches)) => convert(sub_matches).await,
+        Some(("verify", sub_matches)) => verify(sub_matches).await.context(VerificationFailed),
+        Some(("verify-image", sub_matches)) => {
+            verify_image(sub_matches).await.context(VerificationFailed)
+        }
+        Some(("verify-attestation", sub_matches)) => verify_attestation(sub_matches)
+            .await
+            .context(VerificationFailed),
+        Some(("cargo", sub_matches)) => cargo(sub_matches).await,
+        // entries nobody here created fail like a bad signature would
+        Some(("monitor", sub_matches)) => monitor(sub_matches).await.context(VerificationFailed),
+        Some(("inspect", sub_matches)) => inspect(sub_matches),
+        Some(("inspect-cert", sub_matches)) | Some(("extract", sub_matches)) => {
+            inspect_cert(sub_matches)
+        }
+        Some(("rekor", sub_matches)) => rekor(sub_matches).await,
+        Some(("trust", sub_matches)) => trust(sub_matches).await,
+        _ => unreachable!("subcommand_required prevents this"),
+    }
+}
+
+// explicit keys take precedence over those of the trust root
+async fn signer(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+) -> Result<KeylessSigner, anyhow::Error> {
+    let mut signer = KeylessSigner::new(&endpoints.fulcio_url, &endpoints.rekor_url)
+        .with_fulcio_api(matches.value_of_t("fulcio-api")?)
+        .with_rekor_version(rekor_version(matches, endpoints).await?)
+        .with_algorithm(matches.value_of_t("algorithm")?)
+        .with_progress(progress(matches));
+    match instance_trust_root(matches, endpoints, false).await? {
+        Some(trust_root) => signer = signer.with_trust_root(trust_root),
+        // the trust root of a development deployment is what its fulcio serves
+        None if matches.is_present("local") => {
+            let root = fulcio::fetch_root(&endpoints.fulcio_url, matches.value_of_t("fulcio-api")?)
+                .await?;
+            signer = signer.with_fulcio_chain(X509::stack_from_pem(&root)?);
+        }
+        None => anyhow::bail!(UsageError::new(
+            "the instance has no trust root to check signing certificates against, give one with --trusted-root or --tuf-url"
+        )),
+    }
+    if let Some(key_filename) = matches.value_of("ctlog-key") {
+        signer = signer.with_ctlog_key(read_public_key(key_filename)?);
+    }
+    // pinned so the entries Rekor returns are checked against a known key
+    signer = signer.with_rekor_key(rekor_key(matches, endpoints, false).await?);
+    Ok(signer)
+}
 
-        // use tokio::task::spawn_blocking to call RedirectListener in a blocking thread
-        let result = task::spawn_blocking(move || result).await?;
+async fn identity(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+) -> Result<IdentityToken, anyhow::Error> {
+    // ambient detection only looks at the environment
+    if let Some(path) = matches.value_of("kubernetes-token-path") {
+        env::set_var(ambient::KUBERNETES_TOKEN_PATH_VAR, path);
+    }
+    let client_id = oidc_client(matches).client_id;
+    // the issuer is never taken from the token, whose keys would then vouch
+    // for it. tokens brought along have to come from --oidc-issuer, ambient
+    // ones from their provider's issuer and are always requested for sigstore
+    let (identity, issuer, audience) = match (
+        matches.value_of("identity-token"),
+        matches.value_of("identity-token-file"),
+    ) {
+        (Some(token), _) => (
+            IdentityToken::from_jwt_for(token, &client_id)?,
+            Ok(endpoints.oidc_issuer.clone()),
+            client_id.as_str(),
+        ),
+        (None, Some(token_filename)) => (
+            IdentityToken::from_jwt_for(&fs::read_to_string(token_filename)?, &client_id)?,
+            Ok(endpoints.oidc_issuer.clone()),
+            client_id.as_str(),
+        ),
+        (None, None) => match ambient::detect().await? {
+            Some((provider, identity)) => {
+                eprintln!("Using ambient credentials from {}", provider.name());
+                let issuer = provider
+                    .issuer()
+                    .or_else(|| matches.value_of("oidc-issuer").map(String::from))
+                    .ok_or_else(|| {
+                        UsageError::new(format!(
+                            "the issuer of {} tokens is not known, give it with --oidc-issuer",
+                            provider.name()
+                        ))
+                    });
+                (identity, issuer, oauth::SIGSTORE_AUDIENCE)
+            }
+            None => (
+                login(matches, &endpoints.oidc_issuer).await?,
+                Ok(endpoints.oidc_issuer.clone()),
+                client_id.as_str(),
+            ),
+        },
+    };
+    if !matches.is_present("insecure-skip-token-verify") {
+        let issuer = issuer?;
+        let expected = jwt::Expected {
+            issuer: &issuer,
+            audience,
+            nonce: None,
+        };
+        jwt::verify(&identity.token, &expected)
+            .await
+            .context("the identity token was not sent to Fulcio")?;
+    }
+    Ok(identity)
+}
 
-        let (token_response, id_token) = result;
-        let email = token_response.email().unwrap();
-        println!("Received token for email scope: {}", email.to_string());
+// the certificate names the identity and ends up in the CT log, the
+// signature in rekor, neither can be taken back
+fn confirm_publication(
+    matches: &ArgMatches,
+    identity: &IdentityToken,
+) -> Result<(), anyhow::Error> {
+    // nobody else sees a local log
+    if matches.is_present("yes") || matches.is_present("local") {
+        return Ok(());
+    }
+    let answer = Question::new(&format!(
+        "Your identity {} will be recorded in a public transparency log and cannot be removed. Continue?",
+        oauth::display_identity(&identity.email)
+    ))
+    .default(Answer::NO)
+    .show_defaults()
+    .confirm();
+    if answer != Answer::YES {
+        anyhow::bail!("Aborted, nothing was signed");
+    }
+    Ok(())
+}
 
-        scope_signer.update(&email.to_string().as_bytes()).unwrap();
+fn oidc_client(matches: &ArgMatches) -> OidcClient {
+    let mut client = OidcClient::default();
+    if let Some(client_id) = matches.value_of("oidc-client-id") {
+        client.client_id = client_id.to_string();
+    }
+    if let Some(client_secret) = matches.value_of("oidc-client-secret") {
+        client.client_secret = client_secret.to_string();
+    }
+    if let Some(scopes) = matches.values_of("oidc-scopes") {
+        client.scopes = scopes.map(String::from).collect();
+    }
+    client.connector_id = matches
+        .value_of("oidc-connector")
+        .map(|name| oauth::connector_id(name).to_string());
+    client
+}
 
-        let signature = scope_signer.sign_to_vec().unwrap();
+// interactive login, going through the token cache unless disabled
+async fn login(matches: &ArgMatches, issuer: &str) -> Result<IdentityToken, anyhow::Error> {
+    let client = oidc_client(matches);
+    let cache = if matches.is_present("no-token-cache") {
+        None
+    } else {
+        Some(TokenCache::open_default()?)
+    };
+    let login_hint = matches.value_of("oidc-login-hint");
+    // a cached token may be for another account than the one asked for
+    let is_wanted = |identity: &IdentityToken| match login_hint {
+        Some(login_hint) => identity.email.eq_ignore_ascii_case(login_hint),
+        None => true,
+    };
+    if let Some(cache) = &cache {
+        match cache.get(issuer, &client.client_id).await {
+            Ok(Some(identity))
+                if is_wanted(&identity) && !matches.is_present("oidc-select-account") =>
+            {
+                eprintln!("Using cached identity token");
+                return Ok(identity);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Ignoring token cache: {}", e),
+        }
+    }
 
-        let params = FulcioPayload {
-            public_key: PubKey {
-                content: encode(&public_key_pem),
-                algorithm: String::from("ecdsa"),
-            },
-            signed_email_address: encode(&signature),
+    let identity = if matches.is_present("device-flow") {
+        oauth::device_flow(issuer, &client).await?
+    } else {
+        let options = InteractiveOptions {
+            redirect_port: matches
+                .value_of("oidc-redirect-port")
+                .map(str::parse)
+                .transpose()?,
+            no_browser: matches.is_present("no-browser"),
+            qr_code: matches.is_present("qr"),
+            progress: progress(matches),
+            login_timeout: seconds(matches, "oidc-timeout")?,
+            client,
+            login_hint: login_hint.map(String::from),
+            select_account: matches.is_present("oidc-select-account"),
         };
+        oauth::interactive_flow(issuer, &options).await?
+    };
+    // issuers are free to ignore the hint
+    if !is_wanted(&identity) {
+        anyhow::bail!(
+            "Logged in as {} rather than {}, nothing was signed",
+            oauth::display_identity(&identity.email),
+            oauth::display_identity(login_hint.unwrap_or_default())
+        );
+    }
+    if let Some(cache) = &cache {
+        if let Err(e) = cache.put(issuer, &identity) {
+            eprintln!("Could not cache identity token: {}", e);
+        }
+    }
+    Ok(identity)
+}
+
+async fn sign(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let mut filenames = input_files(matches)?;
+    if matches.is_present("reproducible") {
+        filenames.sort();
+        filenames.dedup();
+    }
+    let embed = matches.is_present("embed");
+    if filenames.len() > 1 && !matches.is_present("output-dir") && !embed {
+        anyhow::bail!(UsageError::new("Signing several files needs --output-dir"));
+    }
+    if embed && matches.value_of_t::<BundleFormat>("bundle-format")? == BundleFormat::Protobuf {
+        anyhow::bail!(UsageError::new("Embedded bundles are always JSON"));
+    }
+
+    let endpoints = endpoints(matches);
+    if let Some(key_filename) = matches.value_of("key") {
+        return sign_with_key(matches, &endpoints, key_filename, &filenames).await;
+    }
+    let dry_run = matches.is_present("dry-run");
+    // nothing is certified or logged in a dry run, so there is nothing to
+    // check against the trust root
+    let mut signer = if dry_run {
+        KeylessSigner::new(&endpoints.fulcio_url, &endpoints.rekor_url)
+            .with_fulcio_api(matches.value_of_t("fulcio-api")?)
+            .with_algorithm(matches.value_of_t("algorithm")?)
+    } else {
+        signer(matches, &endpoints).await?
+    };
+    if matches.is_present("no-upload") {
+        signer = signer.without_rekor();
+    } else if !dry_run
+        && signer.rekor_version() == RekorVersion::V2
+        && !matches.is_present("timestamp-url")
+    {
+        // the certificate only proves the identity at the time of signing,
+        // and v2 logs do not say when that was
+        anyhow::bail!(UsageError::new(
+            "Rekor v2 does not timestamp entries, signing with it needs --timestamp-url"
+        ));
+    }
+    if let Some(timestamp_url) = matches.value_of("timestamp-url") {
+        signer = signer.with_timestamp_url(timestamp_url);
+    }
+    if matches.is_present("ssh-sig-out") {
+        signer = signer.with_ssh_namespace(ssh_namespace(matches));
+    }
+    if let Some(expires) = matches.value_of("expires") {
+        // the claim only travels in json bundles
+        if !matches.is_present("bundle-out") && !matches.is_present("output-dir") && !embed {
+            anyhow::bail!(UsageError::new(
+                "--expires needs --bundle-out, --output-dir or --embed, the claim is kept in the bundle"
+            ));
+        }
+        if matches.value_of_t::<BundleFormat>("bundle-format")? == BundleFormat::Protobuf {
+            anyhow::bail!(UsageError::new(
+                "--expires needs --bundle-format json, protobuf bundles have no place for the claim"
+            ));
+        }
+        signer = signer.with_validity(policy::parse_duration(expires)?);
+    }
+    if let Some(digest_algorithm) = digest_algorithm(matches)? {
+        // fail before anything is logged
+        if matches.is_present("bundle-out") || matches.is_present("output-dir") || embed {
+            digest_algorithm.bundle_name()?;
+        }
+        signer = signer.with_digest_algorithm(digest_algorithm);
+    }
+    check_ssh_signature(matches, signer.algorithm(), signer.digest_algorithm())?;
+    // hash before logging in, the identity token is short lived. embedded
+    // signatures are over the archive without its bundle, not the file
+    let digests = if embed {
+        vec![None; filenames.len()]
+    } else {
+        digest_inputs(
+            matches,
+            &filenames,
+            signer.algorithm(),
+            signer.digest_algorithm(),
+        )
+        .await?
+    };
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    // fulcio logs the certificate even when rekor is skipped
+    if !dry_run {
+        confirm_publication(matches, &identity)?;
+    }
+
+    if dry_run {
+        for (filename, digest) in filenames.iter().zip(digests) {
+            let planned = match &digest {
+                Some(digest) => signer.dry_run_digest(&identity, digest)?,
+                None => signer.dry_run_file(&identity, filename)?,
+            };
+            println!("{}:", filename.display());
+            println!(
+                "Would request a certificate for {} from {} for",
+                oauth::display_identity(&identity.email),
+                endpoints.fulcio_url
+            );
+            print!("{}", planned.public_key_pem);
+            if let Some(proof) = &planned.proof {
+                println!("with proof of possession {}", base64::encode(proof));
+            }
+            if let Some(csr) = &planned.csr {
+                print!("with the certificate signing request\n{}", csr);
+            }
+            if let Some(timestamp_url) = matches.value_of("timestamp-url") {
+                println!("Would timestamp the signature with {}", timestamp_url);
+            }
+            print_proposed_entry(matches, &endpoints, &planned)?;
+        }
+        info!("Dry run, nothing was uploaded or written");
+        return anyhow::Ok(());
+    }
 
-        let body = serde_json::to_string(&params).unwrap();
-        println!("Requesting signing certificate from Fulcio...");
+    // one certificate for the whole batch while it lasts
+    let mut session = signer.session(identity);
+    sign_batch(matches, &signer, &mut session, &filenames, digests).await?;
+    if !matches.is_present("watch") {
+        return anyhow::Ok(());
+    }
 
-        let client = reqwest::Client::new();
-        let response = client
-            .post(FULCIO_URL)
-            .header("Authorization", format!("Bearer {}", id_token.to_string()))
-            .header("Content-Type", "application/json")
-            .body(body)
-            .send()
+    let mut watcher = Watcher::new(&filenames)?;
+    info!("Watching {} files for changes", filenames.len());
+    loop {
+        let changed = watcher.changed().await?;
+        // a failed build or an expired login should not end the session
+        let signed = async {
+            let digests = digest_inputs(
+                matches,
+                &changed,
+                signer.algorithm(),
+                signer.digest_algorithm(),
+            )
             .await?;
-        let certs = response.text().await?;
-
-        let mut cert_pem = String::new();
-
-        let cert_re =
-            Regex::new(r#"-----BEGIN CERTIFICATE-----([^-]*)-----END CERTIFICATE-----"#).unwrap();
-        for capture in cert_re.find_iter(&String::from_utf8(certs.as_bytes().to_vec()).unwrap()) {
-            let cert = openssl::x509::X509::from_pem(capture.as_str().as_bytes()).unwrap();
-            for jk in cert.issuer_name().entries() {
-                if matches.is_present("cert") {
-                    // print the value of file
-                    if jk.data().as_slice() == b"sigstore-intermediate" {
-                        let filename = matches.value_of("cert").unwrap();
-                        let mut file = File::create(filename).unwrap();
-                        cert_pem.push_str(capture.as_str());
-                        file.write_all(capture.as_str().as_bytes()).unwrap();
-                    }
+            sign_batch(matches, &signer, &mut session, &changed, digests).await
+        };
+        if let Err(e) = signed.await {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+}
+
+// signs the files, up to --concurrency at a time, and writes their outputs
+// with the certificate of `session` while it lasts. a file that fails does
+// not stop the others, the failures are reported together at the end
+async fn sign_batch(
+    matches: &ArgMatches,
+    signer: &KeylessSigner,
+    session: &mut KeylessSession<'_>,
+    filenames: &[PathBuf],
+    digests: Vec<Option<Vec<u8>>>,
+) -> Result<(), anyhow::Error> {
+    let embed = matches.is_present("embed");
+    let concurrency = concurrency(matches)?;
+    let files: Vec<_> = filenames.iter().zip(digests).collect();
+    let mut failures = Vec::new();
+    for batch in files.chunks(concurrency) {
+        let mut pending = Vec::with_capacity(batch.len());
+        let mut artifacts = Vec::with_capacity(batch.len());
+        for (filename, digest) in batch {
+            match signed_content(filename, digest.as_deref(), embed) {
+                Ok((archive, artifact)) => {
+                    info!(
+                        "Requesting signing certificate from Fulcio and uploading {} to rekor...",
+                        filename.display()
+                    );
+                    pending.push((*filename, archive));
+                    artifacts.push(artifact);
                 }
+                Err(e) => failures.push((*filename, e)),
             }
         }
-        println!(
-            "Saving signing cerificate to {}",
-            matches.value_of("cert").unwrap()
+        // the digests are there for all files of a batch or for none
+        let results = if batch.iter().any(|(_, digest)| digest.is_some()) {
+            session.sign_digests(artifacts, concurrency).await?
+        } else {
+            session.sign_blobs(artifacts, concurrency).await?
+        };
+        for ((filename, archive), signed) in pending.into_iter().zip(results) {
+            let written = signed.and_then(|signed| {
+                write_signed(matches, signer, filename, archive.as_deref(), &signed)
+            });
+            if let Err(e) = written {
+                failures.push((filename, e));
+            }
+        }
+    }
+
+    if filenames.len() == 1 {
+        if let Some((_, e)) = failures.pop() {
+            return Err(e);
+        }
+    }
+    for (filename, e) in &failures {
+        eprintln!("Error: could not sign {}: {:#}", filename.display(), e);
+    }
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "{} of {} files could not be signed",
+            failures.len(),
+            filenames.len()
         );
+    }
+    anyhow::Ok(())
+}
+
+// what is signed for `filename`, with the archive it is embedded in: the
+// digest if there is one, else the archive without its bundle or the whole
+// file
+fn signed_content(
+    filename: &Path,
+    digest: Option<&[u8]>,
+    embed: bool,
+) -> Result<(Option<Vec<u8>>, Vec<u8>), anyhow::Error> {
+    if let Some(digest) = digest {
+        return Ok((None, digest.to_vec()));
+    }
+    let contents = fs::read(filename)
+        .map_err(|e| anyhow::anyhow!("could not open {}: {}", filename.display(), e))?;
+    if embed {
+        let content = archive::split(&contents)?.content;
+        return Ok((Some(contents), content));
+    }
+    Ok((None, contents))
+}
+
+fn write_signed(
+    matches: &ArgMatches,
+    signer: &KeylessSigner,
+    filename: &Path,
+    archive: Option<&[u8]>,
+    signed: &KeylessSignature,
+) -> Result<(), anyhow::Error> {
+    let outputs = Outputs::for_file(matches, filename)?;
+    let format = signature_format(matches)?;
+    let cert_format: CertificateFormat = matches.value_of_t("cert-format")?;
+    let bundle_format: BundleFormat = matches.value_of_t("bundle-format")?;
+    if let Some(archive) = archive {
+        let bundle = BundleFormat::Json.encode_bundle(&signed.bundle()?)?;
+        fs::write(filename, archive::embed(archive, &bundle)?)?;
+        info!("Embedding bundle in {}", filename.display());
+    }
+    if let Some(cert_filename) = &outputs.cert {
+        write_output(
+            cert_filename,
+            cert_format.encode_certificate(&signed.cert_pem)?,
+        )?;
+        info!("Saving signing cerificate to {}", cert_filename.display());
+    }
+    if let Some(chain_filename) = &outputs.chain {
+        write_output(
+            chain_filename,
+            fulcio::chain_pem(&signed.cert_pem, &signed.chain)?,
+        )?;
+        info!("Saving certificate chain to {}", chain_filename.display());
+    }
+    if let Some(signature_filename) = &outputs.signature {
+        write_output(
+            signature_filename,
+            format.encode_signature(&signed.signature, signer.algorithm())?,
+        )?;
+        info!("Saving signature to {}", signature_filename.display());
+    }
+    if let Some(bundle_filename) = &outputs.bundle {
+        write_output(
+            bundle_filename,
+            bundle_format.encode_bundle(&signed.bundle()?)?,
+        )?;
+        info!("Saving bundle to {}", bundle_filename.display());
+    }
+    if let (Some(ssh_filename), Some(ssh_signature)) =
+        (&outputs.ssh_signature, &signed.ssh_signature)
+    {
+        let public_key = X509::from_pem(signed.cert_pem.as_bytes())?.public_key()?;
+        write_ssh_signature(matches, ssh_filename, ssh_signature, &public_key)?;
+    }
+    match &signed.log_entry {
+        Some(log_entry) => {
+            record_created(log_entry);
+            info!("{}", log_entry.describe()?);
+        }
+        None => info!(
+            "Signature of {} was not uploaded to Rekor",
+            filename.display()
+        ),
+    }
+    Ok(())
+}
+
+// keys are read from PEM files unless a hardware token URI is given.
+// encrypted keys are decrypted with a passphrase from the environment, or
+// one typed in at a prompt
+fn key_signer(matches: &ArgMatches, key_filename: &str) -> Result<KeySigner, anyhow::Error> {
+    let hardware = [
+        pkcs11::URI_SCHEME,
+        certstore::URI_SCHEME,
+        keychain::URI_SCHEME,
+        tpm::URI_SCHEME,
+    ]
+    .iter()
+    .any(|scheme| key_filename.starts_with(scheme));
+    let reproducible = matches.is_present("reproducible");
+    if hardware && reproducible {
+        anyhow::bail!(UsageError::new(
+            "--reproducible needs a key file, hardware keys pick their own ECDSA nonces"
+        ));
+    }
+    if key_filename.starts_with(pkcs11::URI_SCHEME) {
+        return pkcs11_signer(key_filename);
+    }
+    if key_filename.starts_with(certstore::URI_SCHEME) {
+        return certstore_signer(key_filename);
+    }
+    if key_filename.starts_with(keychain::URI_SCHEME) {
+        return keychain_signer(key_filename);
+    }
+    if key_filename.starts_with(tpm::URI_SCHEME) {
+        return tpm_signer(key_filename);
+    }
+    let pem = fs::read(key_filename)?;
+    let key = if String::from_utf8_lossy(&pem).contains("ENCRYPTED") {
+        let passphrase = match std::env::var("FERRIS_SIGN_KEY_PASSWORD") {
+            Ok(passphrase) => passphrase,
+            Err(_) => rpassword::prompt_password(format!("Passphrase for {}: ", key_filename))?,
+        };
+        key::private_key_from_pem(&pem, Some(passphrase.as_bytes()))?
+    } else {
+        key::private_key_from_pem(&pem, None)?
+    };
+    if reproducible {
+        return KeySigner::from_signing_key(Arc::new(DeterministicKey::new(key)?));
+    }
+    KeySigner::new(key)
+}
+
+// the module and PIN can come from the URI or the environment, the PIN is
+// prompted for as a last resort
+fn pkcs11_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    let uri = Pkcs11Uri::parse(uri)?;
+    let module_path = match &uri.module_path {
+        Some(module_path) => PathBuf::from(module_path),
+        None => std::env::var_os("FERRIS_SIGN_PKCS11_MODULE")
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                anyhow::anyhow!("set module-path in the URI or FERRIS_SIGN_PKCS11_MODULE")
+            })?,
+    };
+    let pin = match &uri.pin_value {
+        Some(pin) => pin.clone(),
+        None => match std::env::var("FERRIS_SIGN_PKCS11_PIN") {
+            Ok(pin) => pin,
+            Err(_) => rpassword::prompt_password("PIN for the PKCS#11 token: ")?,
+        },
+    };
+    let key = Pkcs11Key::open(&uri, &module_path, Some(&pin))?;
+    KeySigner::from_signing_key(Arc::new(key))
+}
+
+#[cfg(windows)]
+fn certstore_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    let key = certstore::CertStoreKey::open(&certstore::parse_uri(uri)?)?;
+    KeySigner::from_signing_key(Arc::new(key))
+}
+
+#[cfg(not(windows))]
+fn certstore_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    certstore::parse_uri(uri)?;
+    anyhow::bail!(UsageError::new(
+        "certificate store keys are only available on Windows"
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    let key = keychain::KeychainKey::open_or_create(keychain::parse_uri(uri)?)?;
+    KeySigner::from_signing_key(Arc::new(key))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    keychain::parse_uri(uri)?;
+    anyhow::bail!(UsageError::new("Keychain keys are only available on macOS"))
+}
+
+#[cfg(feature = "tpm")]
+fn tpm_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    let key = tpm::TpmKey::open_or_create(tpm::parse_uri(uri)?)?;
+    KeySigner::from_signing_key(Arc::new(key))
+}
+
+#[cfg(not(feature = "tpm"))]
+fn tpm_signer(uri: &str) -> Result<KeySigner, anyhow::Error> {
+    tpm::parse_uri(uri)?;
+    anyhow::bail!(UsageError::new(
+        "TPM keys need ferris-sign built with the tpm feature"
+    ))
+}
+
+// SOURCE_DATE_EPOCH as reproducible builds define it, none for reproducible
+// signatures without it
+fn minisign_timestamp(matches: &ArgMatches) -> Result<Option<u64>, anyhow::Error> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        return Ok(Some(epoch.parse().map_err(|_| {
+            UsageError::new(format!("SOURCE_DATE_EPOCH is not a number: {}", epoch))
+        })?));
+    }
+    if matches.is_present("reproducible") {
+        return Ok(None);
+    }
+    Ok(Some(
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    ))
+}
+
+async fn sign_with_key(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    key_filename: &str,
+    filenames: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    let mut signer = key_signer(matches, key_filename)?.with_progress(progress(matches));
+    if let Some(digest_algorithm) = digest_algorithm(matches)? {
+        signer = signer.with_digest_algorithm(digest_algorithm);
+    }
+    if matches.is_present("ssh-sig-out") {
+        signer = signer.with_ssh_namespace(ssh_namespace(matches));
+    }
+    check_ssh_signature(matches, signer.algorithm(), signer.digest_algorithm())?;
+    let minisign_outputs =
+        matches.is_present("minisign-out") || matches.is_present("minisign-public-key-out");
+    if minisign_outputs && signer.algorithm() != SigningAlgorithm::Ed25519 {
+        anyhow::bail!(UsageError::new("minisign signatures need an Ed25519 key"));
+    }
+    let dry_run = matches.is_present("dry-run");
+    if !matches.is_present("no-upload") && !dry_run {
+        signer = signer
+            .with_rekor(&endpoints.rekor_url)
+            .with_rekor_version(rekor_version(matches, endpoints).await?)
+            .with_rekor_key(rekor_key(matches, endpoints, false).await?);
+    }
 
-        let filename = matches.value_of("file").unwrap();
+    let digests = digest_inputs(
+        matches,
+        filenames,
+        signer.algorithm(),
+        signer.digest_algorithm(),
+    )
+    .await?;
+    if dry_run {
+        for (filename, digest) in filenames.iter().zip(digests) {
+            let planned = match &digest {
+                Some(digest) => signer.dry_run_digest(digest)?,
+                None => signer.dry_run_file(filename)?,
+            };
+            println!("{}:", filename.display());
+            print_proposed_entry(matches, endpoints, &planned)?;
+        }
+        info!("Dry run, nothing was uploaded or written");
+        return anyhow::Ok(());
+    }
+    if let Some(public_key_filename) = matches.value_of("minisign-public-key-out") {
+        let public_key = PKey::public_key_from_pem(signer.public_key_pem().as_bytes())?;
+        write_output(public_key_filename, minisign::public_key(&public_key)?)?;
+        info!("Saving minisign public key to {}", public_key_filename);
+    }
+    let format = signature_format(matches)?;
+    for (filename, digest) in filenames.iter().zip(digests) {
+        let outputs = Outputs::for_file(matches, filename)?;
+        let signed = match &digest {
+            Some(digest) => signer.sign_digest(digest).await?,
+            None => signer.sign_file(filename).await?,
+        };
+        // there is no certificate, so only the signature is written
+        if let Some(signature_filename) = &outputs.signature {
+            write_output(
+                signature_filename,
+                format.encode_signature(&signed.signature, signer.algorithm())?,
+            )?;
+            info!("Saving signature to {}", signature_filename.display());
+        }
+        if let (Some(ssh_filename), Some(ssh_signature)) =
+            (&outputs.ssh_signature, &signed.ssh_signature)
+        {
+            let public_key = PKey::public_key_from_pem(signer.public_key_pem().as_bytes())?;
+            write_ssh_signature(matches, ssh_filename, ssh_signature, &public_key)?;
+        }
+        if let Some(minisign_filename) = &outputs.minisign {
+            // what minisign itself puts in the trusted comment
+            let file = filename
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            let trusted_comment = match minisign_timestamp(matches)? {
+                Some(timestamp) => format!("timestamp:{}\tfile:{}", timestamp, file),
+                None => format!("file:{}", file),
+            };
+            write_output(
+                minisign_filename,
+                signer.minisign(&signed, &trusted_comment)?,
+            )?;
+            info!(
+                "Saving minisign signature to {}",
+                minisign_filename.display()
+            );
+        }
+        match &signed.log_entry {
+            Some(log_entry) => info!("{}", log_entry.describe()?),
+            None => info!(
+                "Signature of {} was not uploaded to Rekor",
+                filename.display()
+            ),
+        }
+    }
+    anyhow::Ok(())
+}
+
+fn ssh_namespace(matches: &ArgMatches) -> &str {
+    matches.value_of("ssh-namespace").unwrap_or(ssh::NAMESPACE)
+}
+
+// fail before anything is logged for what openssh cannot verify
+fn check_ssh_signature(
+    matches: &ArgMatches,
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+) -> Result<(), anyhow::Error> {
+    if !matches.is_present("ssh-sig-out") {
+        return Ok(());
+    }
+    if algorithm == SigningAlgorithm::RsaPssSha256 {
+        anyhow::bail!(UsageError::new(
+            "--ssh-sig-out needs an ECDSA or Ed25519 key"
+        ));
+    }
+    if !matches!(
+        digest_algorithm,
+        HashAlgorithm::Sha256 | HashAlgorithm::Sha512
+    ) {
+        anyhow::bail!(UsageError::new(format!(
+            "--ssh-sig-out needs a sha256 or sha512 digest, not {}, see --digest-algorithm",
+            digest_algorithm
+        )));
+    }
+    Ok(())
+}
+
+// ssh-keygen only trusts keys listed in an allowed signers file, so the
+// line to list the signing key with is printed along with the signature
+fn write_ssh_signature(
+    matches: &ArgMatches,
+    filename: &Path,
+    ssh_signature: &str,
+    public_key: &PKeyRef<Public>,
+) -> Result<(), anyhow::Error> {
+    write_output(filename, ssh_signature)?;
+    info!("Saving SSH signature to {}", filename.display());
+    info!(
+        "Verify it with ssh-keygen -Y verify -n {} and this allowed signers line, with the signer's identity in place of <principal>:\n<principal> {}",
+        ssh_namespace(matches),
+        ssh::public_key(public_key)?
+    );
+    Ok(())
+}
+
+fn print_proposed_entry(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    dry_run: &DryRun,
+) -> Result<(), anyhow::Error> {
+    if matches.is_present("no-upload") {
+        println!("Would not upload anything to Rekor");
+        return Ok(());
+    }
+    let entry: serde_json::Value = serde_json::from_str(&dry_run.proposed_entry)?;
+    println!("Would upload to {}", endpoints.rekor_url);
+    println!("{}", serde_json::to_string_pretty(&entry)?);
+    Ok(())
+}
+
+async fn sign_image(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let image = Reference::parse(matches.value_of("image").unwrap())?;
+    let client = registry_client(matches, &image).await?;
+    // the signature covers the digest, so the tag may move on afterwards
+    let digest = match &image.digest {
+        Some(digest) => digest.clone(),
+        None => client.resolve(&image).await?,
+    };
+    let image = image.with_digest(&digest);
+    info!("Signing {}", image);
+
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints).await?;
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    confirm_publication(matches, &identity)?;
+
+    info!("Requesting signing certificate from Fulcio and uploading the signature to rekor...");
+    let payload = annotations(matches, "annotation")?
+        .iter()
+        .fold(
+            SimpleSigning::new(&image.name(), &digest),
+            |payload, (key, value)| payload.with_annotation(key, value),
+        )
+        .to_json()?;
+    let signed = signer.sign_blob(&identity, &payload).await?;
+    let log_entry = signed.logged_entry()?;
+    record_created(log_entry);
+
+    let layer = cosign::signature_layer(&payload, &signed.signature, &signed.cert_pem, log_entry)?;
+    let signatures = cosign::attach_signature(&client, &image, &payload, layer).await?;
+    info!("Pushed signature to {}", signatures);
+    info!("{}", log_entry.describe()?);
+    Ok(())
+}
+
+// the countersignature covers the signature in the bundle rather than the
+// artifact, so it endorses that one signature
+async fn countersign(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let bundle_filename = matches.value_of("bundle").unwrap();
+    let data = fs::read(bundle_filename)?;
+    let out = match matches.value_of("out") {
+        Some(out) => out,
+        None if data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') => bundle_filename,
+        None => anyhow::bail!(UsageError::new(
+            "Countersignatures are only kept in JSON bundles, give --out for the countersigned bundle"
+        )),
+    };
+    let mut bundle = Bundle::parse(&data)?;
+    info!(
+        "Countersigning the signature by {}",
+        certificate::san_identities(&bundle.signing_cert()?).join(", ")
+    );
+
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints).await?;
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    confirm_publication(matches, &identity)?;
 
-        let signature_filename = matches.value_of("signature").unwrap();
-        // sign filename
-        let mut file = File::open(filename).unwrap();
+    info!(
+        "Requesting signing certificate from Fulcio and uploading the countersignature to rekor..."
+    );
+    let signed = signer.sign_blob(&identity, &bundle.signature()?).await?;
+    let log_entry = signed.logged_entry()?;
+    record_created(log_entry);
+    bundle.add_countersignature(signed.bundle()?);
 
-        let mut file_signer = crypto::create_signer(&private_key)?;
-        let mut file_bytes = Vec::new();
-        file.read_to_end(&mut file_bytes).unwrap();
-        file_signer.update(&file_bytes).unwrap();
-        let signature = file_signer.sign_to_vec().unwrap();
+    write_output(out, bundle.to_json()?)?;
+    info!("Saving countersigned bundle to {}", out);
+    info!("{}", log_entry.describe()?);
+    Ok(())
+}
+
+// the rekor entry is checked to be for the signature and certificate, so a
+// mismatched triplet does not turn into a bundle that never verifies
+async fn convert(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let sig_format: SignatureFormat = matches.value_of_t("sig-format")?;
+    if let Some(bundle_filename) = matches.value_of("bundle") {
+        let bundle = Bundle::parse(&fs::read(bundle_filename)?)?;
+        let cert = bundle.signing_cert()?;
+        if let Some(signature_filename) = matches.value_of("sig-out") {
+            let public_key = cert.public_key()?;
+            let algorithm = SigningAlgorithm::for_key(&public_key)?;
+            write_output(
+                signature_filename,
+                sig_format.encode_signature(&bundle.signature()?, algorithm)?,
+            )?;
+            info!("Saving signature to {}", signature_filename);
+        }
+        if let Some(cert_filename) = matches.value_of("cert-out") {
+            let cert_format: CertificateFormat = matches.value_of_t("cert-format")?;
+            let cert_pem = String::from_utf8(cert.to_pem()?)?;
+            write_output(cert_filename, cert_format.encode_certificate(&cert_pem)?)?;
+            info!("Saving signing certificate to {}", cert_filename);
+        }
+        match bundle.verification_material.tlog_entries.first() {
+            Some(entry) => println!("{}", entry.uuid()?),
+            None => eprintln!("{} has no Rekor entry", bundle_filename),
+        }
+        return Ok(());
+    }
+
+    let cert = format::decode_certificate(&fs::read(matches.value_of("cert").unwrap())?)?;
+    let public_key = cert.public_key()?;
+    let algorithm = SigningAlgorithm::for_key(&public_key)?;
+    let signature = sig_format.decode_signature(
+        &fs::read(matches.value_of("signature").unwrap())?,
+        algorithm,
+    )?;
+    let endpoints = endpoints(matches);
+    let log_entry = rekor_api::get_entry_by_uuid(
+        &endpoints.rekor_url,
+        matches.value_of("rekor-uuid").unwrap(),
+    )
+    .await?;
+    let entry = TransparencyLogEntry::from_log_entry(&log_entry)?;
+    let (digest_algorithm, digest) = match matches.value_of("in-file") {
+        Some(filename) => {
+            let digest_algorithm = verify::default_digest_algorithm(&cert)?;
+            (
+                digest_algorithm,
+                digest_algorithm.digest(&fs::read(filename)?)?,
+            )
+        }
+        None => entry.artifact_digest()?,
+    };
+    let rekor_key = rekor_key(matches, &endpoints, false).await?;
+    verify::verify_tlog_entry(&entry, &cert, &signature, &digest, &rekor_key)?;
+
+    let bundle = Bundle::new(
+        &signature,
+        &cert.to_der()?,
+        digest_algorithm,
+        &digest,
+        Some(&log_entry),
+    )?;
+    let bundle_format: BundleFormat = matches.value_of_t("bundle-format")?;
+    let bundle_filename = matches.value_of("bundle-out").unwrap();
+    write_output(bundle_filename, bundle_format.encode_bundle(&bundle)?)?;
+    info!("Saving bundle to {}", bundle_filename);
+    Ok(())
+}
+
+async fn verify_image(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let image = Reference::parse(matches.value_of("image").unwrap())?;
+    let client = registry_client(matches, &image).await?;
+    let digest = match &image.digest {
+        Some(digest) => digest.clone(),
+        None => client.resolve(&image).await?,
+    };
+    let image = image.with_digest(&digest);
+    let signatures = cosign::signatures(&client, &image).await?;
 
-        let mut file = File::create(signature_filename).unwrap();
-        // write signature to file
-        file.write_all(&signature).unwrap();
-        println!("Saving signature to {}", signature_filename);
-        // print signature to stdout
+    let endpoints = endpoints(matches);
+    let policy = identity_policy(matches)?;
+    let fulcio_chain = fulcio_chain(matches, &endpoints, false).await?;
+    let ctlog_key = ctlog_key(matches, &endpoints, false).await?;
+    if ctlog_key.is_none() {
+        eprintln!("No CT log key given, embedded SCTs are not checked");
+    }
+    let rekor_key = rekor_key(matches, &endpoints, false).await?;
+    let verification_policy = verification_policy(matches)?;
+    let mut required = annotations(matches, "require-annotation")?;
+    required.extend(verification_policy.annotations.clone());
+
+    // one signature that holds up is enough, the others may be from signers
+    // the policy does not accept
+    let mut signers = BTreeSet::new();
+    for signature in &signatures {
+        let checked = async {
+            let payload = SimpleSigning::from_json(&signature.payload)?;
+            check_annotations(&required, |key| payload.annotation(key))?;
+            let (cert, signed_at) = verify_image_signature(
+                &endpoints,
+                &image,
+                signature,
+                &fulcio_chain,
+                ctlog_key.as_deref(),
+                &rekor_key,
+                &policy,
+            )
+            .await?;
+            verification_policy.verify(&cert, signed_at)?;
+            anyhow::Ok(cert)
+        }
+        .await;
+        match checked {
+            Ok(cert) => {
+                let signer = certificate::san_identities(&cert).join(", ");
+                info!("Verified signature by {}", signer);
+                signers.insert(signer);
+            }
+            Err(e) => eprintln!("Skipping signature: {}", e),
+        }
+    }
+    if signers.is_empty() {
+        anyhow::bail!("No valid signature found for {}", image);
+    }
+    check_threshold(&verification_policy, &signers)?;
 
-        // convert signature to base64
-        let signature_base64 = encode(&signature);
-        let public_key_base64 = encode(&public_key_pem);
+    if !verification_policy.attestations.is_empty() {
+        let mut attested = Vec::new();
+        for attestation in cosign::attestations(&client, &image).await? {
+            let checked = async {
+                let (statement, cert) = verify_image_attestation(
+                    &endpoints,
+                    &image,
+                    &attestation,
+                    &fulcio_chain,
+                    ctlog_key.as_deref(),
+                    &rekor_key,
+                )
+                .await?;
+                policy.verify(&cert)?;
+                verification_policy.verify_identity(&cert)?;
+                anyhow::Ok(statement.predicate_type)
+            }
+            .await;
+            match checked {
+                Ok(predicate_type) => attested.push(predicate_type),
+                Err(e) => eprintln!("Skipping attestation: {}", e),
+            }
+        }
+        for predicate_type in &verification_policy.attestations {
+            if !attested
+                .iter()
+                .any(|attested| attested == predicate_type.uri())
+            {
+                anyhow::bail!(
+                    "No valid {} attestation found for {}",
+                    predicate_type,
+                    image
+                );
+            }
+            info!("Verified {} attestation", predicate_type);
+        }
+    }
+    info!("Verified OK");
+    Ok(())
+}
 
-        // send to rekor
-        let hash = crypto::sha256_digest(PathBuf::from(filename))?;
+// countersignatures are reported, but a bundle verifies without them
+fn report_countersignatures(bundle: &Bundle, trust_root: &TrustRoot) {
+    for countersignature in &bundle.countersignatures {
+        let verified = verify::verify_countersignature(bundle, countersignature, trust_root)
+            .and_then(|_| countersignature.signing_cert());
+        match verified {
+            Ok(cert) => info!(
+                "Countersigned by {}",
+                certificate::san_identities(&cert).join(", ")
+            ),
+            Err(e) => eprintln!("Countersignature does not verify: {}", e),
+        }
+    }
+}
 
-        println!("Sending signature artifacts to rekor...");
-        let log_entry = rekor_api::create_log(&hash, &public_key_base64, &signature_base64).await;
-        println!("{:#?}", log_entry);
+// several signatures by the same identity count once
+fn check_threshold(policy: &Policy, signers: &BTreeSet<String>) -> Result<(), anyhow::Error> {
+    policy.check_threshold(signers)?;
+    if policy.threshold > 1 {
+        info!(
+            "Threshold of {} met by {}",
+            policy.threshold,
+            signers.iter().cloned().collect::<Vec<_>>().join("; ")
+        );
+    }
+    Ok(())
+}
+
+// checks an attestation the way verify-attestation checks one, returns the
+// statement and the signing certificate
+async fn verify_image_attestation(
+    endpoints: &Endpoints,
+    image: &Reference,
+    attestation: &ImageAttestation,
+    fulcio_chain: &[X509],
+    ctlog_key: Option<&PKeyRef<Public>>,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<(Statement, X509), anyhow::Error> {
+    let envelope = Envelope::from_json(&attestation.envelope)?;
+    let cert_pem = attestation
+        .cert_pem
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("attestation has no certificate"))?;
+    let cert = X509::from_pem(cert_pem.as_bytes())?;
+    let statement = verify::verify_attestation(&envelope, &cert, fulcio_chain)?;
+    if let Some(ctlog_key) = ctlog_key {
+        verify::verify_embedded_sct(&cert, fulcio_chain, ctlog_key)?;
+    }
+    let digest = image.digest.as_deref().unwrap_or_default();
+    let hex = digest.trim_start_matches("sha256:");
+    if !statement
+        .subject
+        .iter()
+        .any(|subject| subject.digest.get("sha256").map(String::as_str) == Some(hex))
+    {
+        anyhow::bail!("attestation is not about {}", digest);
+    }
+
+    let log_entries = match &attestation.bundle {
+        Some(bundle) => vec![bundle.to_log_entry()],
+        None => {
+            let rekor_url = &endpoints.rekor_url;
+            let payload_hash = crypto::sha256_hex(&envelope.payload()?);
+            let mut log_entries = Vec::new();
+            for uuid in rekor_api::search_by_hash(rekor_url, &payload_hash).await? {
+                log_entries.push(rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?);
+            }
+            log_entries
+        }
+    };
+    let signed_at = log_entries.iter().find_map(|log_entry| {
+        let entry = TransparencyLogEntry::from_log_entry(log_entry).ok()?;
+        verify::verify_dsse_tlog_entry(&entry, &cert, &envelope, rekor_key).ok()
+    });
+    match signed_at {
+        None => anyhow::bail!("no verified Rekor entry found"),
+        Some(None) => anyhow::bail!("Rekor entry has no signed integrated time"),
+        Some(Some(time)) => verify::verify_cert_valid_at(&cert, time)?,
+    }
+    Ok((statement, cert))
+}
+
+// checks one signature the way verify checks a detached one, returns the
+// signing certificate and the time the signature was logged at
+async fn verify_image_signature(
+    endpoints: &Endpoints,
+    image: &Reference,
+    signature: &ImageSignature,
+    fulcio_chain: &[X509],
+    ctlog_key: Option<&PKeyRef<Public>>,
+    rekor_key: &PKeyRef<Public>,
+    policy: &IdentityPolicy,
+) -> Result<(X509, i64), anyhow::Error> {
+    let payload = SimpleSigning::from_json(&signature.payload)?;
+    let digest = image.digest.as_deref().unwrap_or_default();
+    if payload.critical.image.docker_manifest_digest != digest {
+        anyhow::bail!(
+            "signature is for {}",
+            payload.critical.image.docker_manifest_digest
+        );
+    }
+    let cert_pem = signature
+        .cert_pem
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("signature has no certificate"))?;
+    let cert = X509::from_pem(cert_pem.as_bytes())?;
+
+    let digest_algorithm = verify::default_digest_algorithm(&cert)?;
+    verify::verify_blob(
+        &cert,
+        fulcio_chain,
+        digest_algorithm,
+        &signature.payload,
+        &signature.signature,
+    )?;
+    if let Some(ctlog_key) = ctlog_key {
+        verify::verify_embedded_sct(&cert, fulcio_chain, ctlog_key)?;
+    }
+    policy.verify(&cert)?;
+
+    // the bundle spares the lookup, without one search rekor by digest
+    let digest = digest_algorithm.digest(&signature.payload)?;
+    let log_entries = match &signature.bundle {
+        Some(bundle) => vec![bundle.to_log_entry()],
+        None => {
+            let rekor_url = &endpoints.rekor_url;
+            let query = SearchIndex {
+                hash: Some(format!(
+                    "{}:{}",
+                    digest_algorithm.name(),
+                    HEXLOWER.encode(&digest)
+                )),
+                ..SearchIndex::default()
+            };
+            let mut log_entries = Vec::new();
+            for uuid in rekor_api::search_index(rekor_url, &query).await? {
+                log_entries.push(rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?);
+            }
+            log_entries
+        }
+    };
+    let signed_at = log_entries.iter().find_map(|log_entry| {
+        let entry = TransparencyLogEntry::from_log_entry(log_entry).ok()?;
+        verify::verify_tlog_entry(&entry, &cert, &signature.signature, &digest, rekor_key).ok()
+    });
+    match signed_at {
+        None => anyhow::bail!("no verified Rekor entry found"),
+        Some(None) => anyhow::bail!("Rekor entry has no signed integrated time"),
+        Some(Some(time)) => {
+            verify::verify_cert_valid_at(&cert, time)?;
+            Ok((cert, time))
+        }
+    }
+}
+
+// signatures of earlier runs are release assets too, they are not signed again
+const SIGNING_OUTPUTS: [&str; 3] = [".sig", ".pem", ".sigstore.json"];
+
+async fn sign_release(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let repo = matches.value_of("repo").unwrap();
+    let tag = matches.value_of("tag").unwrap();
+    let mut github = github::Client::new(matches.value_of("github-api-url").unwrap());
+    if let Some(token) = matches.value_of("github-token") {
+        github = github.with_token(token);
+    }
+    let release = github.get_release(repo, tag).await?;
+    info!("Signing release {}", release.html_url);
+
+    // everything is fetched before logging in, the identity token is short
+    // lived
+    let mut assets = Vec::new();
+    match matches.values_of("asset") {
+        Some(filenames) => {
+            for filename in filenames {
+                let path = Path::new(filename);
+                let name = path
+                    .file_name()
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a file", filename))?
+                    .to_string_lossy()
+                    .into_owned();
+                assets.push((name, fs::read(path)?));
+            }
+        }
+        None => {
+            for asset in &release.assets {
+                if SIGNING_OUTPUTS
+                    .iter()
+                    .any(|suffix| asset.name.ends_with(suffix))
+                {
+                    continue;
+                }
+                info!("Downloading {}...", asset.name);
+                assets.push((asset.name.clone(), github.download_asset(asset).await?));
+            }
+        }
+    }
+    if assets.is_empty() {
+        anyhow::bail!("Release {} has no assets to sign", tag);
+    }
+
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints).await?;
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    confirm_publication(matches, &identity)?;
+
+    let output_dir = Path::new(matches.value_of("output-dir").unwrap());
+    fs::create_dir_all(output_dir)?;
+    let mut session = signer.session(identity);
+    for (name, data) in &assets {
+        info!("Signing {}...", name);
+        let signed = session.sign_blob(data).await?;
+        let log_entry = signed.logged_entry()?;
+        record_created(log_entry);
+        let outputs = [
+            (
+                format!("{}.sig", name),
+                "application/octet-stream",
+                signed.signature.clone(),
+            ),
+            (
+                format!("{}.pem", name),
+                "application/x-pem-file",
+                signed.cert_pem.clone().into_bytes(),
+            ),
+            (
+                format!("{}.sigstore.json", name),
+                "application/json",
+                signed.bundle()?.to_json()?.into_bytes(),
+            ),
+        ];
+        for (output_name, content_type, content) in &outputs {
+            fs::write(output_dir.join(output_name), content)?;
+            if matches.is_present("upload") {
+                github
+                    .upload_asset(repo, &release, output_name, content_type, content)
+                    .await?;
+                info!("Uploaded {}", output_name);
+            }
+        }
+        info!("{}", log_entry.describe()?);
+    }
+    Ok(())
+}
+
+async fn verify_attestation(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let endpoints = endpoints(matches);
+    let policy = identity_policy(matches)?;
+    let fulcio_chain = fulcio_chain(matches, &endpoints, false).await?;
+    let envelope = Envelope::from_json(&fs::read(matches.value_of("attestation").unwrap())?)?;
+    let cert = X509::from_pem(&fs::read(matches.value_of("cert").unwrap())?)?;
+
+    let statement = verify::verify_attestation(&envelope, &cert, &fulcio_chain)?;
+    match ctlog_key(matches, &endpoints, false).await? {
+        Some(ctlog_key) => verify::verify_embedded_sct(&cert, &fulcio_chain, &ctlog_key)?,
+        None => eprintln!("No CT log key given, embedded SCT was not checked"),
+    }
+    policy.verify(&cert)?;
+
+    let filename = matches.value_of("in-file").unwrap();
+    let file_digest = crypto::sha256_hex(&fs::read(filename)?);
+    let subject = statement
+        .subject
+        .iter()
+        .find(|subject| subject.digest.get("sha256") == Some(&file_digest))
+        .ok_or_else(|| anyhow::anyhow!("Attestation is not about {}", filename))?;
+    check_annotations(&annotations(matches, "require-annotation")?, |key| {
+        subject.annotation(key)
+    })?;
+    if matches.is_present("predicate-type") {
+        let predicate_type: PredicateType = matches.value_of_t("predicate-type")?;
+        if statement.predicate_type != predicate_type.uri() {
+            anyhow::bail!(
+                "Attestation predicate type is {}, expected {}",
+                statement.predicate_type,
+                predicate_type
+            );
+        }
+    }
+    if let Some(policy_filename) = matches.value_of("policy") {
+        let predicate_policy = serde_json::from_slice(&fs::read(policy_filename)?)?;
+        predicate::check_policy(&statement.predicate, &predicate_policy)?;
+    }
+
+    verify_attestation_logged(matches, &endpoints, &envelope, &cert).await?;
+    info!("Verified OK");
+    Ok(())
+}
+
+// dsse entries are indexed by the sha256 of the payload
+async fn verify_attestation_logged(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    envelope: &Envelope,
+    cert: &X509,
+) -> Result<(), anyhow::Error> {
+    let rekor_url = &endpoints.rekor_url;
+    let rekor_key = rekor_key(matches, endpoints, false).await?;
+    let payload_hash = crypto::sha256_hex(&envelope.payload()?);
+    let mut signed_at = None;
+    for uuid in rekor_api::search_by_hash(rekor_url, &payload_hash).await? {
+        let log_entry = rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?;
+        let entry = TransparencyLogEntry::from_log_entry(&log_entry)?;
+        if let Ok(time) = verify::verify_dsse_tlog_entry(&entry, cert, envelope, &rekor_key) {
+            info!("Found verified log entry {}", uuid);
+            signed_at = Some(time);
+            break;
+        }
+    }
+    match signed_at {
+        None => anyhow::bail!("No verified Rekor entry found for this attestation"),
+        Some(None) => anyhow::bail!("Rekor entry has no signed integrated time"),
+        Some(Some(time)) => verify::verify_cert_valid_at(cert, time),
+    }
+}
+
+async fn cargo(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    match matches.subcommand() {
+        Some(("sign", sub_matches)) => cargo_sign(sub_matches).await,
+        Some(("verify", sub_matches)) => {
+            cargo_verify(sub_matches).await.context(VerificationFailed)
+        }
+        _ => unreachable!("subcommand_required prevents this"),
+    }
+}
+
+// the attestation and certificate are written next to each crate, so they
+// can be published along with it
+async fn cargo_sign(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let paths = match matches.values_of("crate") {
+        Some(values) => values.map(PathBuf::from).collect(),
+        None => {
+            let pattern = format!("{}/*.crate", cargo::PACKAGE_DIR);
+            glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    if paths.is_empty() {
+        anyhow::bail!("No .crate files found, run cargo package first");
+    }
+    let crates = paths
+        .iter()
+        .map(|path| CrateFile::from_path(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let digests = HashAlgorithm::Sha256
+        .digest_files(&paths, concurrency(matches)?, progress(matches))
+        .await?;
+
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints).await?;
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    confirm_publication(matches, &identity)?;
+
+    let mut session = signer.session(identity);
+    for ((path, krate), digest) in paths.iter().zip(&crates).zip(digests) {
+        let statement = krate.statement(&HEXLOWER.encode(&digest));
+        let attestation = session.sign_statement(&statement).await?;
+        record_created(&attestation.log_entry);
+
+        let envelope_path = sidecar(path, "intoto.json");
+        fs::write(&envelope_path, attestation.envelope.to_json()?)?;
+        let cert_path = sidecar(path, "pem");
+        fs::write(&cert_path, &attestation.cert_pem)?;
+        info!(
+            "Signed {} {}, saved {} and {}",
+            krate.name,
+            krate.version,
+            envelope_path.display(),
+            cert_path.display()
+        );
+        info!("{}", attestation.log_entry.describe()?);
+    }
+    Ok(())
+}
+
+async fn cargo_verify(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let path = Path::new(matches.value_of("crate").unwrap());
+    let krate = CrateFile::from_path(path)?;
+    let envelope_path = match matches.value_of("attestation") {
+        Some(filename) => PathBuf::from(filename),
+        None => sidecar(path, "intoto.json"),
+    };
+    let cert_path = match matches.value_of("cert") {
+        Some(filename) => PathBuf::from(filename),
+        None => sidecar(path, "pem"),
+    };
+    let envelope = Envelope::from_json(&fs::read(&envelope_path)?)?;
+    let cert = X509::from_pem(&fs::read(&cert_path)?)?;
+
+    let endpoints = endpoints(matches);
+    let policy = identity_policy(matches)?;
+    let fulcio_chain = fulcio_chain(matches, &endpoints, false).await?;
+    let statement = verify::verify_attestation(&envelope, &cert, &fulcio_chain)?;
+    match ctlog_key(matches, &endpoints, false).await? {
+        Some(ctlog_key) => verify::verify_embedded_sct(&cert, &fulcio_chain, &ctlog_key)?,
+        None => eprintln!("No CT log key given, embedded SCT was not checked"),
+    }
+    policy.verify(&cert)?;
+
+    let digest = HashAlgorithm::Sha256.digest_file(path)?;
+    krate.check_statement(&statement, &HEXLOWER.encode(&digest))?;
+    verify_attestation_logged(matches, &endpoints, &envelope, &cert).await?;
+    info!("Verified OK: {} {}", krate.name, krate.version);
+    Ok(())
+}
+
+// `<path>.<extension>`, keeping the .crate extension
+fn sidecar(path: &Path, extension: &str) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(extension);
+    PathBuf::from(sidecar)
+}
+
+// git reads the signature from stdout and judges the outcome by the status
+// lines, what is printed on stderr is only shown to the user
+async fn git_gpg(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let status = GitStatus::new(matches.value_of("status-fd").unwrap())?;
+    let mut object = Vec::new();
+    io::stdin().read_to_end(&mut object)?;
+    match matches.value_of("verify") {
+        Some(signature_filename) => git_verify(matches, &status, signature_filename, &object)
+            .await
+            .context(VerificationFailed),
+        None => git_sign(matches, &status, &object).await,
+    }
+}
+
+async fn git_sign(
+    matches: &ArgMatches,
+    status: &GitStatus,
+    object: &[u8],
+) -> Result<(), anyhow::Error> {
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints)
+        .await?
+        .with_progress(Progress::default());
+    // git hands the object over stdin, so there is no asking for consent,
+    // configuring ferris-sign as the signing program is taken as such
+    let identity = identity(matches, &endpoints).await?;
+    status.write("BEGIN_SIGNING")?;
+    let (signed_data, signature) = signer.sign_git(&identity, object).await?;
+    let log_entry = signature.logged_entry()?;
+    record_created(log_entry);
+
+    let cert = X509::from_pem(signature.cert_pem.as_bytes())?;
+    // ecdsa and sha256 in gpg's numbering, 00 for a binary document
+    status.write(&format!(
+        "SIG_CREATED D 19 8 00 {} {}",
+        log_entry.integrated_time,
+        git::fingerprint(&cert)?
+    ))?;
+    io::stdout().write_all(git::armor(&signed_data).as_bytes())?;
+    eprintln!(
+        "Signed as {}",
+        certificate::san_identities(&cert).join(", ")
+    );
+    eprintln!("{}", log_entry.describe()?);
+    Ok(())
+}
+
+async fn git_verify(
+    matches: &ArgMatches,
+    status: &GitStatus,
+    signature_filename: &str,
+    object: &[u8],
+) -> Result<(), anyhow::Error> {
+    let message =
+        git::SignedMessage::from_der(&git::dearmor(&fs::read_to_string(signature_filename)?)?)?;
+    let cert = &message.certificate;
+    let fingerprint = git::fingerprint(cert)?;
+    let identities = certificate::san_identities(cert).join(", ");
+    status.write("NEWSIG")?;
+    match verify_git_signature(matches, &message, object).await {
+        Ok(signed_at) => {
+            status.write(&format!("GOODSIG {} {}", fingerprint, identities))?;
+            status.write(&format!(
+                "VALIDSIG {} {} {} 0 - - 19 8 00 {}",
+                fingerprint, signed_at, signed_at, fingerprint
+            ))?;
+            status.write("TRUST_FULLY 0 shell")?;
+            eprintln!("Good signature from {}", identities);
+            if let Some(issuer) = certificate::oidc_issuer(cert)? {
+                eprintln!("Identity issued by {}", issuer);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            status.write(&format!("BADSIG {} {}", fingerprint, identities))?;
+            eprintln!("Bad signature from {}: {}", identities, e);
+            Err(e)
+        }
+    }
+}
+
+// the same checks verify makes of a detached signature, returns when the
+// log integrated the signature
+async fn verify_git_signature(
+    matches: &ArgMatches,
+    message: &git::SignedMessage,
+    object: &[u8],
+) -> Result<i64, anyhow::Error> {
+    let endpoints = endpoints(matches);
+    let cert = &message.certificate;
+    let fulcio_chain = fulcio_chain(matches, &endpoints, false).await?;
+    let digest_algorithm = HashAlgorithm::Sha256;
+    verify::verify_blob(
+        cert,
+        &fulcio_chain,
+        digest_algorithm,
+        object,
+        &message.signature,
+    )?;
+    if let Some(ctlog_key) = ctlog_key(matches, &endpoints, false).await? {
+        verify::verify_embedded_sct(cert, &fulcio_chain, &ctlog_key)?;
+    }
+
+    let rekor_url = &endpoints.rekor_url;
+    let rekor_key = rekor_key(matches, &endpoints, false).await?;
+    let digest = digest_algorithm.digest(object)?;
+    let query = SearchIndex {
+        hash: Some(format!(
+            "{}:{}",
+            digest_algorithm.name(),
+            HEXLOWER.encode(&digest)
+        )),
+        ..SearchIndex::default()
+    };
+    let mut signed_at = None;
+    for uuid in rekor_api::search_index(rekor_url, &query).await? {
+        let log_entry = rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?;
+        let entry = TransparencyLogEntry::from_log_entry(&log_entry)?;
+        if let Ok(time) =
+            verify::verify_tlog_entry(&entry, cert, &message.signature, &digest, &rekor_key)
+        {
+            signed_at = Some(time);
+            break;
+        }
+    }
+    match signed_at {
+        None => anyhow::bail!("no verified Rekor entry found for this signature"),
+        Some(None) => anyhow::bail!("Rekor entry has no signed integrated time"),
+        Some(Some(time)) => {
+            verify::verify_cert_valid_at(cert, time)?;
+            Ok(time)
+        }
+    }
+}
+
+// where git wants its "[GNUPG:] " prefixed status lines
+enum GitStatus {
+    Stdout,
+    Stderr,
+}
+
+impl GitStatus {
+    fn new(fd: &str) -> Result<Self, anyhow::Error> {
+        match fd {
+            "1" => Ok(GitStatus::Stdout),
+            "2" => Ok(GitStatus::Stderr),
+            _ => anyhow::bail!(UsageError::new(format!(
+                "unsupported status file descriptor {}",
+                fd
+            ))),
+        }
+    }
+
+    fn write(&self, line: &str) -> Result<(), anyhow::Error> {
+        let line = format!("[GNUPG:] {}\n", line);
+        match self {
+            GitStatus::Stdout => io::stdout().write_all(line.as_bytes())?,
+            GitStatus::Stderr => io::stderr().write_all(line.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+// notes an entry created here so the monitor does not report it
+fn record_created(log_entry: &rekor_api::LogEntry) {
+    let recorded =
+        cache::cache_dir().and_then(|dir| monitor::record_created(&dir, &log_entry.uuid));
+    if let Err(e) = recorded {
+        eprintln!("Could not record log entry for the monitor: {}", e);
+    }
+}
+
+async fn monitor(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let endpoints = endpoints(matches);
+    let identity = matches.value_of("identity").unwrap();
+    let interval = std::time::Duration::from_secs(matches.value_of_t("interval")?);
+    let cache_dir = cache::cache_dir()?;
+    let state_path = match matches.value_of("state") {
+        Some(state_path) => PathBuf::from(state_path),
+        None => cache_dir.join(format!(
+            "monitor-{}.json",
+            crypto::sha256_hex(identity.as_bytes())
+        )),
+    };
+    let monitor = Monitor::new(&endpoints.rekor_url, identity, state_path);
+    let witness = Witness::open_default(
+        &endpoints.rekor_url,
+        rekor_key(matches, &endpoints, false).await?,
+    )?;
+
+    loop {
+        check_consistency(&witness).await?;
+        let known = monitor::created_entries(&cache_dir)?;
+        let mut unknown = 0;
+        for entry in monitor.poll(&known).await? {
+            if entry.known {
+                println!("New entry {} (created here)", entry.uuid);
+                continue;
+            }
+            unknown += 1;
+            println!(
+                "ALERT: entry {} for {} was not created here",
+                entry.uuid, identity
+            );
+            let log_entry = rekor_api::get_entry_by_uuid(&endpoints.rekor_url, &entry.uuid).await?;
+            println!("{}", log_entry.describe()?);
+        }
+        if matches.is_present("once") {
+            if unknown > 0 {
+                anyhow::bail!(
+                    "{} log entries for {} were not created here",
+                    unknown,
+                    identity
+                );
+            }
+            return Ok(());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn check_consistency(witness: &Witness) -> Result<(), anyhow::Error> {
+    let consistency = witness.update().await?;
+    let current = &consistency.current;
+    match &consistency.previous {
+        Some(previous) if previous.tree_size < current.tree_size => info!(
+            "Tree head of size {} is consistent with the one of size {} seen last",
+            current.tree_size, previous.tree_size
+        ),
+        Some(_) => info!("Tree head of size {} is unchanged", current.tree_size),
+        None => info!(
+            "No earlier tree head for {}, recorded the one of size {}",
+            current.origin, current.tree_size
+        ),
+    }
+    Ok(())
+}
+
+async fn attest(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let (predicate_type, predicate) = if matches.is_present("slsa") {
+        let provenance = Provenance::from_env(matches.value_of("builder-id"));
+        (PredicateType::SlsaProvenance, provenance.to_value()?)
+    } else {
+        let predicate_filename = matches.value_of("predicate").unwrap();
+        let predicate_type: PredicateType = matches.value_of_t("predicate-type")?;
+        let predicate = serde_json::from_slice(&fs::read(predicate_filename)?)
+            .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", predicate_filename, e))?;
+        predicate_type.validate(&predicate)?;
+        (predicate_type, predicate)
+    };
+    // an image is its own single subject, named without tag or digest
+    let (subjects, image) = match matches.value_of("image") {
+        Some(image) => {
+            let image = Reference::parse(image)?;
+            let client = registry_client(matches, &image).await?;
+            let digest = match &image.digest {
+                Some(digest) => digest.clone(),
+                None => client.resolve(&image).await?,
+            };
+            let hex = digest.trim_start_matches("sha256:");
+            let subjects = vec![Subject::sha256(&image.name(), hex)];
+            (subjects, Some((client, image.with_digest(&digest))))
+        }
+        None => (file_subjects(matches).await?, None),
+    };
+    let annotations = annotations(matches, "annotation")?;
+    let subjects = subjects
+        .into_iter()
+        .map(|subject| {
+            annotations.iter().fold(subject, |subject, (key, value)| {
+                subject.with_annotation(key, value)
+            })
+        })
+        .collect();
+    let statement = Statement::new(subjects, predicate_type.uri(), predicate);
+
+    let endpoints = endpoints(matches);
+    let signer = signer(matches, &endpoints).await?;
+    let identity = identity(matches, &endpoints).await?;
+    info!(
+        "Received token for email scope: {}",
+        oauth::display_identity(&identity.email)
+    );
+    confirm_publication(matches, &identity)?;
+
+    info!("Requesting signing certificate from Fulcio and uploading attestation to rekor...");
+    let attestation = signer.sign_statement(&identity, &statement).await?;
+
+    let envelope = attestation.envelope.to_json()?;
+    if let Some(envelope_filename) = matches.value_of("out") {
+        write_output(envelope_filename, &envelope)?;
+        info!("Saving attestation to {}", envelope_filename);
+    }
+    if let Some(cert_filename) = matches.value_of("cert-out") {
+        write_output(cert_filename, &attestation.cert_pem)?;
+        info!("Saving signing certificate to {}", cert_filename);
+    }
+    record_created(&attestation.log_entry);
+    if let Some((client, image)) = &image {
+        let layer = cosign::attestation_layer(
+            envelope.as_bytes(),
+            &statement.predicate_type,
+            &attestation.cert_pem,
+            &attestation.log_entry,
+        )?;
+        let pushed = cosign::attach_attestation(client, image, envelope.as_bytes(), layer).await?;
+        info!("Pushed attestation to {}", pushed);
+    }
+    info!("{}", attestation.log_entry.describe()?);
+    Ok(())
+}
+
+// every input file becomes a subject of the one statement
+async fn file_subjects(matches: &ArgMatches) -> Result<Vec<Subject>, anyhow::Error> {
+    let filenames = input_files(matches)?;
+    let digests = HashAlgorithm::Sha256
+        .digest_files(&filenames, concurrency(matches)?, progress(matches))
+        .await?;
+    let mut subjects = Vec::new();
+    for (filename, digest) in filenames.iter().zip(digests) {
+        let name = filename
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| filename.display().to_string());
+        subjects.push(Subject::sha256(&name, &HEXLOWER.encode(&digest)));
+    }
+    Ok(subjects)
+}
+
+// expands the --in-file values, anything with glob metacharacters is treated
+// as a pattern
+fn input_files(matches: &ArgMatches) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut filenames = Vec::new();
+    for value in matches.values_of("in-file").unwrap() {
+        if !value.contains(['*', '?', '[']) {
+            filenames.push(PathBuf::from(value));
+            continue;
+        }
+        let matched = glob::glob(value)?
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            anyhow::bail!(UsageError::new(format!(
+                "{} does not match any files",
+                value
+            )));
+        }
+        filenames.extend(matched);
+    }
+    Ok(filenames)
+}
+
+// "-" writes to stdout, to pipe signatures and bundles elsewhere
+fn write_output(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    if path.as_ref() == Path::new("-") {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(contents.as_ref())?;
+        stdout.flush()
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+// only our own events, those of hyper and friends are rarely what is wanted
+fn init_tracing(matches: &ArgMatches) {
+    let level = match matches.occurrences_of("verbose") {
+        0 => return,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(io::stderr)
+                .with_target(false),
+        )
+        .with(Targets::new().with_target("ferris_sign", level))
+        .init();
+}
+
+// sigstore's browser login makes its own requests, which only honour the
+// proxy environment variables
+fn set_proxy(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if let Some(proxy) = matches.value_of("proxy") {
+        http::set_proxy(proxy)
+            .map_err(|e| UsageError::new(format!("invalid --proxy {}: {}", proxy, e)))?;
+        env::set_var("HTTPS_PROXY", proxy);
+        env::set_var("HTTP_PROXY", proxy);
+    }
+    Ok(())
+}
+
+// --format predates --sig-format, its raw is what is now der
+fn signature_format(matches: &ArgMatches) -> Result<SignatureFormat, anyhow::Error> {
+    if matches.is_present("sig-format") {
+        return matches
+            .value_of_t("sig-format")
+            .map_err(anyhow::Error::from);
+    }
+    match matches.value_of("format") {
+        Some("cosign") => Ok(SignatureFormat::Base64),
+        _ => Ok(SignatureFormat::Der),
+    }
+}
+
+fn set_tls(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let mut tls = http::Tls::default();
+    if let Some(filename) = matches.value_of("cacert") {
+        tls = tls
+            .with_ca_bundle(&fs::read(filename)?)
+            .map_err(|e| UsageError::new(format!("invalid --cacert {}: {}", filename, e)))?;
+    }
+    if let (Some(cert), Some(key)) = (
+        matches.value_of("client-cert"),
+        matches.value_of("client-key"),
+    ) {
+        tls = tls
+            .with_client_identity(&fs::read(cert)?, &fs::read(key)?)
+            .map_err(|e| UsageError::new(format!("invalid client certificate {}: {}", cert, e)))?;
+    }
+    if matches.is_present("insecure-skip-tls-verify") {
+        eprintln!(
+            "WARNING: TLS certificate verification is disabled, anyone on the network can \
+             impersonate Fulcio, Rekor and the OIDC issuer. Never use this outside of tests."
+        );
+        tls = tls.with_insecure_skip_verify();
+    }
+    http::set_tls(tls);
+    Ok(())
+}
+
+fn seconds(matches: &ArgMatches, name: &str) -> Result<Option<Duration>, anyhow::Error> {
+    match matches.value_of(name) {
+        Some(_) => Ok(Some(Duration::from_secs(matches.value_of_t(name)?))),
+        None => Ok(None),
+    }
+}
+
+fn set_timeout(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    http::set_timeout(Service::Other, seconds(matches, "timeout")?);
+    http::set_timeout(Service::Fulcio, seconds(matches, "fulcio-timeout")?);
+    http::set_timeout(Service::Rekor, seconds(matches, "rekor-timeout")?);
+    http::set_timeout(Service::Oidc, seconds(matches, "oidc-timeout")?);
+    if let Some(user_agent) = matches.value_of("user-agent") {
+        http::set_user_agent(user_agent)
+            .map_err(|e| UsageError::new(format!("invalid --user-agent: {}", e)))?;
+    }
+    if matches.is_present("redact-identity") {
+        oauth::set_redaction(Some(matches.value_of_t("redact-identity")?));
+    }
+    Ok(())
+}
+
+fn progress(matches: &ArgMatches) -> Progress {
+    Progress::new(!matches.is_present("quiet"))
+}
+
+fn concurrency(matches: &ArgMatches) -> Result<usize, anyhow::Error> {
+    if !matches.is_present("concurrency") {
+        return Ok(std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1));
+    }
+    let concurrency: usize = matches.value_of_t("concurrency")?;
+    if concurrency == 0 {
+        anyhow::bail!(UsageError::new("--concurrency must be at least 1"));
+    }
+    Ok(concurrency)
+}
+
+// hashes all files up front on a pool of workers. algorithms that sign the
+// whole artifact get no digests, their files are read when they are signed
+async fn digest_inputs(
+    matches: &ArgMatches,
+    filenames: &[PathBuf],
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+) -> Result<Vec<Option<Vec<u8>>>, anyhow::Error> {
+    if !algorithm.is_prehashed() {
+        return Ok(vec![None; filenames.len()]);
+    }
+    let digests = digest_algorithm
+        .digest_files(filenames, concurrency(matches)?, progress(matches))
+        .await?;
+    Ok(digests.into_iter().map(Some).collect())
+}
+
+// where sign writes its results for one input file
+struct Outputs {
+    signature: Option<PathBuf>,
+    cert: Option<PathBuf>,
+    chain: Option<PathBuf>,
+    bundle: Option<PathBuf>,
+    ssh_signature: Option<PathBuf>,
+    minisign: Option<PathBuf>,
+}
+
+impl Outputs {
+    fn for_file(matches: &ArgMatches, filename: &Path) -> Result<Self, anyhow::Error> {
+        match matches.value_of("output-dir") {
+            Some(dir) => {
+                let name = filename
+                    .file_name()
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a file", filename.display()))?
+                    .to_string_lossy();
+                let dir = Path::new(dir);
+                let bundle_format: BundleFormat = matches.value_of_t("bundle-format")?;
+                Ok(Outputs {
+                    signature: Some(dir.join(format!("{}.sig", name))),
+                    cert: Some(dir.join(format!("{}.pem", name))),
+                    chain: Some(dir.join(format!("{}.chain.pem", name))),
+                    bundle: Some(dir.join(format!("{}.{}", name, bundle_format.extension()))),
+                    ssh_signature: None,
+                    minisign: None,
+                })
+            }
+            None => Ok(Outputs {
+                signature: matches.value_of("sig-out").map(PathBuf::from),
+                cert: matches.value_of("cert-out").map(PathBuf::from),
+                chain: matches.value_of("chain-out").map(PathBuf::from),
+                bundle: matches.value_of("bundle-out").map(PathBuf::from),
+                ssh_signature: matches.value_of("ssh-sig-out").map(PathBuf::from),
+                minisign: matches.value_of("minisign-out").map(PathBuf::from),
+            }),
+        }
+    }
+}
+
+// explicit urls take precedence over the instance selected by --staging
+// or --local
+fn endpoints(matches: &ArgMatches) -> Endpoints {
+    let mut endpoints = if matches.is_present("staging") {
+        Endpoints::staging()
+    } else if matches.is_present("local") {
+        Endpoints::local(matches.is_present("cacert"))
+    } else {
+        Endpoints::production()
+    };
+    // the trust root of the public instances says nothing about others
+    if let Some(fulcio_url) = matches.value_of("fulcio-url") {
+        endpoints.fulcio_url = fulcio_url.to_string();
+        endpoints.tuf_url = None;
+    }
+    if let Some(rekor_url) = matches.value_of("rekor-url") {
+        endpoints.rekor_url = rekor_url.to_string();
+        endpoints.tuf_url = None;
+    }
+    if let Some(tuf_url) = matches.value_of("tuf-url") {
+        endpoints.tuf_url = Some(tuf_url.to_string());
+    }
+    if let Some(oidc_issuer) = matches.value_of("oidc-issuer") {
+        endpoints.oidc_issuer = oidc_issuer.to_string();
+    }
+    endpoints
+}
+
+fn digest_algorithm(matches: &ArgMatches) -> Result<Option<HashAlgorithm>, anyhow::Error> {
+    matches
+        .value_of("digest-algorithm")
+        .map(str::parse)
+        .transpose()
+}
+
+fn read_public_key(filename: &str) -> Result<PKey<Public>, anyhow::Error> {
+    Ok(PKey::public_key_from_pem(&fs::read(filename)?)?)
+}
+
+// the trust root of --trusted-root, or from the TUF repository of the
+// instance, loaded once per run
+static TRUST_ROOT: OnceCell<Option<TrustRoot>> = OnceCell::const_new();
+
+async fn instance_trust_root(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    offline: bool,
+) -> Result<Option<&'static TrustRoot>, anyhow::Error> {
+    let trust_root = TRUST_ROOT
+        .get_or_try_init(|| async {
+            if let Some(root_filename) = matches.value_of("trusted-root") {
+                let json = fs::read(root_filename)?;
+                return Ok(Some(TrustedRoot::from_json(&json)?.trust_root()?));
+            }
+            let tuf_url = match &endpoints.tuf_url {
+                Some(tuf_url) => tuf_url,
+                None => return anyhow::Ok(None),
+            };
+            let mut repository = tuf::Repository::open_default(tuf_url)?;
+            if let Some(root_filename) = matches.value_of("tuf-root") {
+                repository = repository.with_initial_root(&fs::read(root_filename)?);
+            }
+            let json = if offline {
+                match repository.cached_target(trusted_root::TRUSTED_ROOT_TARGET) {
+                    Ok(json) => json,
+                    Err(_) => return anyhow::Ok(None),
+                }
+            } else {
+                info!("Fetching trust root from {}...", tuf_url);
+                repository
+                    .fetch_target(trusted_root::TRUSTED_ROOT_TARGET)
+                    .await?
+            };
+            Ok(Some(TrustedRoot::from_json(&json)?.trust_root()?))
+        })
+        .await?;
+    Ok(trust_root.as_ref())
+}
+
+// the log is only asked which api it speaks when --rekor-api is not given
+async fn rekor_version(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+) -> Result<RekorVersion, anyhow::Error> {
+    match matches.value_of("rekor-api") {
+        Some(version) => version.parse(),
+        None => rekor_api::probe_version(&endpoints.rekor_url).await,
+    }
+}
+
+async fn rekor_key(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    offline: bool,
+) -> Result<PKey<Public>, anyhow::Error> {
+    if let Some(key_filename) = matches.value_of("rekor-key") {
+        return read_public_key(key_filename);
+    }
+    if let Some(rekor_key) = instance_trust_root(matches, endpoints, offline)
+        .await?
+        .and_then(|trust_root| trust_root.rekor_key.clone())
+    {
+        return Ok(rekor_key);
+    }
+    if offline {
+        anyhow::bail!(UsageError::new(
+            "--offline requires --rekor-key, or a trust root cached by an earlier verification"
+        ));
+    }
+    // only test deployments are trusted to serve their own key
+    if !matches.is_present("local") && !matches.is_present("staging") {
+        anyhow::bail!(UsageError::new(format!(
+            "no Rekor key given or in a trust root for {}, give one with --rekor-key",
+            endpoints.rekor_url
+        )));
+    }
+    eprintln!(
+        "No Rekor key given or in a trust root, trusting the key served by {}",
+        endpoints.rekor_url
+    );
+    let pem = rekor_api::get_public_key(&endpoints.rekor_url).await?;
+    Ok(PKey::public_key_from_pem(pem.as_bytes())?)
+}
+
+// without a key from either source embedded SCTs are not checked
+async fn ctlog_key(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    offline: bool,
+) -> Result<Option<PKey<Public>>, anyhow::Error> {
+    if let Some(key_filename) = matches.value_of("ctlog-key") {
+        return Ok(Some(read_public_key(key_filename)?));
+    }
+    Ok(instance_trust_root(matches, endpoints, offline)
+        .await?
+        .and_then(|trust_root| trust_root.ctlog_key.clone()))
+}
+
+// explicit credentials take precedence over the docker configuration
+async fn registry_client(
+    matches: &ArgMatches,
+    image: &Reference,
+) -> Result<registry::Client, anyhow::Error> {
+    let client = registry::Client::new();
+    if let (Some(username), Some(token)) = (
+        matches.value_of("registry-username"),
+        matches.value_of("registry-token"),
+    ) {
+        return Ok(client.with_credentials(username, token));
+    }
+    match DockerConfig::load()?.credentials(&image.registry).await? {
+        Some((username, password)) => Ok(client.with_credentials(&username, &password)),
+        None => Ok(client),
+    }
+}
+
+// applied on top of the identity flags, without --policy everything passes
+fn verification_policy(matches: &ArgMatches) -> Result<Policy, anyhow::Error> {
+    match matches.value_of("policy") {
+        Some(policy_filename) => Policy::from_yaml(&fs::read(policy_filename)?)
+            .with_context(|| format!("Could not read the policy in {}", policy_filename)),
+        None => Ok(Policy::default()),
+    }
+}
+
+fn identity_policy(matches: &ArgMatches) -> Result<IdentityPolicy, anyhow::Error> {
+    Ok(IdentityPolicy {
+        identity: matcher(
+            matches,
+            "certificate-identity",
+            "certificate-identity-regexp",
+        )?,
+        issuer: matcher(
+            matches,
+            "certificate-oidc-issuer",
+            "certificate-oidc-issuer-regexp",
+        )?,
+    })
+}
+
+async fn fulcio_chain(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    offline: bool,
+) -> Result<Vec<X509>, anyhow::Error> {
+    if let Some(root_filename) = matches.value_of("root") {
+        return Ok(X509::stack_from_pem(&fs::read(root_filename)?)?);
+    }
+    match instance_trust_root(matches, endpoints, offline).await? {
+        Some(trust_root) if !trust_root.fulcio_chain.is_empty() => {
+            return Ok(trust_root.fulcio_chain.clone())
+        }
+        _ => {}
+    }
+    if offline {
+        anyhow::bail!(UsageError::new(
+            "--offline requires --root, or a trust root cached by an earlier verification"
+        ));
+    }
+    if !matches.is_present("local") {
+        anyhow::bail!(UsageError::new(
+            "the instance has no trust root to check signing certificates against, give one with --root, --trusted-root or --tuf-url"
+        ));
+    }
+    info!("Fetching Fulcio root certificate...");
+    Ok(X509::stack_from_pem(
+        &fulcio::fetch_root(&endpoints.fulcio_url, matches.value_of_t("fulcio-api")?).await?,
+    )?)
+}
+
+fn matcher(
+    matches: &ArgMatches,
+    exact: &str,
+    regexp: &str,
+) -> Result<Option<Matcher>, anyhow::Error> {
+    if let Some(value) = matches.value_of(exact) {
+        return Ok(Some(Matcher::Exact(value.to_string())));
+    }
+    matches.value_of(regexp).map(Matcher::regex).transpose()
+}
+
+// the key=value pairs given to `name`
+fn annotations(
+    matches: &ArgMatches,
+    name: &str,
+) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let mut annotations = BTreeMap::new();
+    for annotation in matches.values_of(name).into_iter().flatten() {
+        let (key, value) = annotation.split_once('=').ok_or_else(|| {
+            UsageError::new(format!(
+                "annotation {} is not of the form key=value",
+                annotation
+            ))
+        })?;
+        annotations.insert(key.to_string(), value.to_string());
+    }
+    Ok(annotations)
+}
+
+fn check_annotations<'a>(
+    required: &BTreeMap<String, String>,
+    annotation: impl Fn(&str) -> Option<&'a str>,
+) -> Result<(), anyhow::Error> {
+    for (key, value) in required {
+        match annotation(key) {
+            Some(signed) if signed == value => {}
+            Some(signed) => anyhow::bail!("annotation {} is {}, expected {}", key, signed, value),
+            None => anyhow::bail!("annotation {} is missing", key),
+        }
+    }
+    Ok(())
+}
+
+async fn verify(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let endpoints = endpoints(matches);
+    let filename = matches.value_of("in-file").unwrap();
+    if let Some(key_filename) = matches.value_of("public-key") {
+        return verify_with_key(matches, &endpoints, filename, key_filename).await;
+    }
+    let offline = matches.is_present("offline");
+    let policy = identity_policy(matches)?;
+    let verification_policy = verification_policy(matches)?;
+    if !verification_policy.annotations.is_empty() || !verification_policy.attestations.is_empty() {
+        anyhow::bail!(UsageError::new(
+            "Only image signatures carry annotations and attestations, use verify-image with this policy"
+        ));
+    }
+    let fulcio_chain = fulcio_chain(matches, &endpoints, offline).await?;
+    let ctlog_key = ctlog_key(matches, &endpoints, offline).await?;
+    let mut file_bytes = fs::read(filename)?;
+
+    if matches.is_present("bundles") {
+        let bundles = match matches.values_of("bundle") {
+            Some(bundle_filenames) => bundle_filenames
+                .map(|bundle_filename| {
+                    Ok((bundle_filename, Bundle::parse(&fs::read(bundle_filename)?)?))
+                })
+                .collect::<Result<Vec<_>, anyhow::Error>>()?,
+            None => {
+                let embedded = archive::split(&file_bytes)?;
+                let bundle = embedded
+                    .bundle
+                    .ok_or_else(|| anyhow::anyhow!("{} has no embedded bundle", filename))?;
+                let bundle = Bundle::parse(bundle)?;
+                file_bytes = embedded.content;
+                vec![(filename, bundle)]
+            }
+        };
+        let allow_missing_tlog = matches.is_present("allow-missing-tlog");
+        let rekor_key = if allow_missing_tlog
+            && bundles
+                .iter()
+                .all(|(_, bundle)| bundle.verification_material.tlog_entries.is_empty())
+        {
+            None
+        } else {
+            Some(rekor_key(matches, &endpoints, offline).await?)
+        };
+        if offline && ctlog_key.is_none() {
+            anyhow::bail!(UsageError::new(
+                "--offline requires --ctlog-key, or a trust root cached by an earlier verification"
+            ));
+        }
+        let tsa_chain = match matches.value_of("timestamp-root") {
+            Some(root_filename) => X509::stack_from_pem(&fs::read(root_filename)?)?,
+            None => instance_trust_root(matches, &endpoints, offline)
+                .await?
+                .map(|trust_root| trust_root.tsa_chain.clone())
+                .unwrap_or_default(),
+        };
+        // keys and authorities given as flags replace those of the instance,
+        // which are otherwise picked by log ID and validity period
+        let instance = instance_trust_root(matches, &endpoints, offline).await?;
+        let from_instance = |flag: &str| instance.filter(|_| !matches.is_present(flag));
+        let trust_root = TrustRoot {
+            fulcio_chain,
+            rekor_key,
+            ctlog_key,
+            tsa_chain,
+            tlog_keys: from_instance("rekor-key")
+                .map(|trust_root| trust_root.tlog_keys.clone())
+                .unwrap_or_default(),
+            ctlog_keys: from_instance("ctlog-key")
+                .map(|trust_root| trust_root.ctlog_keys.clone())
+                .unwrap_or_default(),
+            fulcio_authorities: from_instance("root")
+                .map(|trust_root| trust_root.fulcio_authorities.clone())
+                .unwrap_or_default(),
+        };
+
+        // with several bundles, as for a threshold of signers, the ones that
+        // do not verify are skipped
+        let mut signers = BTreeSet::new();
+        for (bundle_filename, bundle) in &bundles {
+            let checked = (|| {
+                let signed_at = if allow_missing_tlog {
+                    verify::verify_bundle_without_tlog(bundle, &file_bytes, &trust_root)?
+                } else {
+                    verify::verify_bundle(bundle, &file_bytes, &trust_root)?
+                };
+                let cert = bundle.signing_cert()?;
+                policy.verify(&cert)?;
+                verification_policy.verify(&cert, signed_at)?;
+                anyhow::Ok(cert)
+            })();
+            match checked {
+                Ok(cert) => {
+                    let signer = certificate::san_identities(&cert).join(", ");
+                    if bundles.len() > 1 {
+                        info!("Verified {} by {}", bundle_filename, signer);
+                    }
+                    signers.insert(signer);
+                    report_countersignatures(bundle, &trust_root);
+                }
+                Err(e) if bundles.len() == 1 => return Err(e),
+                Err(e) => eprintln!("Skipping {}: {}", bundle_filename, e),
+            }
+        }
+        if signers.is_empty() {
+            anyhow::bail!("No valid bundle found for {}", filename);
+        }
+        check_threshold(&verification_policy, &signers)?;
+        if trust_root.ctlog_key.is_none() {
+            eprintln!("No CT log key given, embedded SCT was not checked");
+        }
+        info!("Verified OK");
+        return anyhow::Ok(());
+    }
+
+    let cert = format::decode_certificate(&fs::read(matches.value_of("cert").unwrap())?)?;
+    let public_key = cert.public_key()?;
+    let signature = signature_format(matches)?.decode_signature(
+        &fs::read(matches.value_of("signature").unwrap())?,
+        SigningAlgorithm::for_key(&public_key)?,
+    )?;
+    let digest_algorithm = match digest_algorithm(matches)? {
+        Some(digest_algorithm) => digest_algorithm,
+        None => verify::default_digest_algorithm(&cert)?,
+    };
+    verify::verify_blob(
+        &cert,
+        &fulcio_chain,
+        digest_algorithm,
+        &file_bytes,
+        &signature,
+    )?;
+    match &ctlog_key {
+        Some(ctlog_key) => verify::verify_embedded_sct(&cert, &fulcio_chain, ctlog_key)?,
+        None => eprintln!("No CT log key given, embedded SCT was not checked"),
+    }
+    policy.verify(&cert)?;
+
+    // without a bundle the log entry has to be looked up by artifact digest
+    let rekor_url = &endpoints.rekor_url;
+    let rekor_key = rekor_key(matches, &endpoints, false).await?;
+    // the entry is indexed by the digest the signature was made over
+    let digest = digest_algorithm.digest(&file_bytes)?;
+    let query = SearchIndex {
+        hash: Some(format!(
+            "{}:{}",
+            digest_algorithm.name(),
+            HEXLOWER.encode(&digest)
+        )),
+        ..SearchIndex::default()
+    };
+    let uuids = rekor_api::search_index(rekor_url, &query).await?;
+    let mut signed_at = None;
+    for uuid in uuids {
+        let log_entry = rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?;
+        let entry = TransparencyLogEntry::from_log_entry(&log_entry)?;
+        if let Ok(time) = verify::verify_tlog_entry(&entry, &cert, &signature, &digest, &rekor_key)
+        {
+            info!("Found verified log entry {}", uuid);
+            signed_at = Some(time);
+            break;
+        }
+    }
+    match signed_at {
+        None => anyhow::bail!("No verified Rekor entry found for this signature"),
+        Some(None) => anyhow::bail!("Rekor entry has no signed integrated time"),
+        // the entry was logged while the certificate was valid
+        Some(Some(time)) => {
+            verify::verify_cert_valid_at(&cert, time)?;
+            verification_policy.verify(&cert, time)?;
+        }
+    }
+    verification_policy.check_threshold(&BTreeSet::from([
+        certificate::san_identities(&cert).join(", ")
+    ]))?;
+    info!("Verified OK");
+    anyhow::Ok(())
+}
+
+// self managed keys carry no identity, so there is no certificate chain or
+// identity policy to check
+async fn verify_with_key(
+    matches: &ArgMatches,
+    endpoints: &Endpoints,
+    filename: &str,
+    key_filename: &str,
+) -> Result<(), anyhow::Error> {
+    let public_key = read_public_key(key_filename)?;
+    let file_bytes = fs::read(filename)?;
+    let signature = signature_format(matches)?.decode_signature(
+        &fs::read(matches.value_of("signature").unwrap())?,
+        SigningAlgorithm::for_key(&public_key)?,
+    )?;
+    let digest_algorithm = match digest_algorithm(matches)? {
+        Some(digest_algorithm) => digest_algorithm,
+        None => SigningAlgorithm::for_key(&public_key)?.hash(),
+    };
+    if !verify::verify_signature_with_key(&public_key, digest_algorithm, &file_bytes, &signature)? {
+        anyhow::bail!("Signature verification failed");
+    }
+
+    if matches.is_present("rekor-lookup") {
+        let rekor_url = &endpoints.rekor_url;
+        let rekor_key = rekor_key(matches, endpoints, false).await?;
+        let digest = digest_algorithm.digest(&file_bytes)?;
+        let query = SearchIndex {
+            hash: Some(format!(
+                "{}:{}",
+                digest_algorithm.name(),
+                HEXLOWER.encode(&digest)
+            )),
+            public_key: Some(SearchPublicKey {
+                format: String::from("x509"),
+                content: base64::encode(public_key.public_key_to_pem()?),
+            }),
+            ..SearchIndex::default()
+        };
+        let mut found = false;
+        for uuid in rekor_api::search_index(rekor_url, &query).await? {
+            let log_entry = rekor_api::get_entry_by_uuid(rekor_url, &uuid).await?;
+            let entry = TransparencyLogEntry::from_log_entry(&log_entry)?;
+            let verified =
+                verify::verify_key_tlog_entry(&entry, &public_key, &signature, &digest, &rekor_key);
+            if verified.is_ok() {
+                info!("Found verified log entry {}", uuid);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            anyhow::bail!("No verified Rekor entry found for this signature");
+        }
+    }
+    info!("Verified OK");
+    anyhow::Ok(())
+}
+
+fn inspect_cert(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let cert = format::decode_certificate(&fs::read(matches.value_of("cert").unwrap())?)?;
+    let inspection = certificate::inspect(&cert)?;
+    let output = match matches.value_of("output").unwrap() {
+        "json" => format!("{}\n", serde_json::to_string_pretty(&inspection)?),
+        "public-key" => inspection.public_key,
+        _ => inspection_text(&inspection),
+    };
+
+    match matches.value_of("out") {
+        Some(out_filename) => {
+            write_output(out_filename, &output)?;
+            info!("Saving to {}", out_filename);
+        }
+        None => print!("{}", output),
+    }
+    anyhow::Ok(())
+}
+
+fn inspect(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let cert = match matches.value_of("cert") {
+        Some(cert_filename) => Some(format::decode_certificate(&fs::read(cert_filename)?)?),
+        None => None,
+    };
+    let mut inspection =
+        inspect::inspect(&fs::read(matches.value_of("file").unwrap())?, cert.as_ref())?;
+    if let (None, Some(in_filename)) = (&inspection.digest, matches.value_of("in-file")) {
+        let digest_algorithm = match &cert {
+            Some(cert) => verify::default_digest_algorithm(cert)?,
+            None => HashAlgorithm::Sha256,
+        };
+        let digest = digest_algorithm.digest_file(Path::new(in_filename))?;
+        inspection.digest = Some(inspect::Digest::new(digest_algorithm, &digest));
+    }
+
+    if matches.value_of("output") == Some("json") {
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+        return anyhow::Ok(());
+    }
+    println!("Kind:        {}", inspection.kind);
+    if let Some(media_type) = &inspection.media_type {
+        println!("Media type:  {}", media_type);
+    }
+    println!("Encoding:    {}", inspection.encoding);
+    println!(
+        "Algorithm:   {}",
+        inspection
+            .algorithm
+            .as_deref()
+            .unwrap_or("unknown, no certificate")
+    );
+    if let Some(digest) = &inspection.digest {
+        println!("Digest:      {}:{}", digest.algorithm, digest.value);
+    }
+    if let Some(identity) = &inspection.identity {
+        for san in &identity.san {
+            println!("SAN:         {}", san);
+        }
+        if let Some(oidc_issuer) = &identity.oidc_issuer {
+            println!("OIDC issuer: {}", oidc_issuer);
+        }
+    }
+    for entry in &inspection.tlog_entries {
+        println!(
+            "Rekor:       index {} in log {}, integrated at {}, {} {}",
+            entry.log_index, entry.log_id, entry.integrated_time, entry.kind, entry.version
+        );
+        println!(
+            "             inclusion proof: {}, inclusion promise: {}",
+            yes_no(entry.inclusion_proof),
+            yes_no(entry.inclusion_promise)
+        );
+    }
+    if inspection.kind == "bundle" {
+        println!("Timestamps:  {}", inspection.timestamps);
+    }
+    anyhow::Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn inspection_text(inspection: &certificate::Inspection) -> String {
+    let mut lines = vec![
+        format!("Subject:     {}", inspection.subject),
+        format!("Issuer:      {}", inspection.issuer),
+        format!("Not before:  {}", inspection.not_before),
+        format!("Not after:   {}", inspection.not_after),
+    ];
+    for san in &inspection.san {
+        lines.push(format!("SAN:         {}", san));
+    }
+    if let Some(oidc_issuer) = &inspection.oidc_issuer {
+        lines.push(format!("OIDC issuer: {}", oidc_issuer));
+    }
+    let workflow = &inspection.workflow;
+    for (label, value) in [
+        ("Trigger", &workflow.trigger),
+        ("SHA", &workflow.sha),
+        ("Workflow", &workflow.name),
+        ("Repository", &workflow.repository),
+        ("Ref", &workflow.git_ref),
+    ] {
+        if let Some(value) = value {
+            lines.push(format!("{:<12} {}", format!("{}:", label), value));
+        }
+    }
+    for sct in &inspection.sct {
+        lines.push(format!(
+            "SCT:         log {} at {} ms",
+            sct.log_id, sct.timestamp
+        ));
+    }
+    if inspection.sct.is_empty() {
+        lines.push("SCT:         none".to_string());
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+async fn rekor(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    match matches.subcommand() {
+        Some(("get", sub_matches)) => {
+            let endpoints = endpoints(sub_matches);
+            let log_entry = match sub_matches.value_of("uuid") {
+                Some(uuid) => rekor_api::get_entry_by_uuid(&endpoints.rekor_url, uuid).await?,
+                None => {
+                    let index = sub_matches.value_of_t("log-index")?;
+                    rekor_api::get_entry_by_index(&endpoints.rekor_url, index).await?
+                }
+            };
+            if sub_matches.is_present("raw") {
+                println!("{}", serde_json::to_string_pretty(&log_entry)?);
+            } else {
+                println!("{}", log_entry.describe()?);
+            }
+            let rekor_key = rekor_key(sub_matches, &endpoints, false).await?;
+            tlog::verify_log_entry_inclusion(&log_entry, &rekor_key).context(VerificationFailed)?;
+            info!("Inclusion proof verified against the signed tree head");
+            if log_entry
+                .verification
+                .as_ref()
+                .is_some_and(|v| v.signed_entry_timestamp.is_some())
+            {
+                tlog::verify_log_entry_set(&log_entry, &rekor_key).context(VerificationFailed)?;
+                info!("Signed entry timestamp verified");
+            }
+        }
+        Some(("consistency", sub_matches)) => {
+            let endpoints = endpoints(sub_matches);
+            let rekor_key = rekor_key(sub_matches, &endpoints, false).await?;
+            let witness = match sub_matches.value_of("state") {
+                Some(path) => Witness::new(&endpoints.rekor_url, rekor_key, path),
+                None => Witness::open_default(&endpoints.rekor_url, rekor_key)?,
+            };
+            check_consistency(&witness)
+                .await
+                .context(VerificationFailed)?;
+        }
+        Some(("search", sub_matches)) => {
+            let endpoints = endpoints(sub_matches);
+            let hash = match (
+                sub_matches.value_of("sha256"),
+                sub_matches.value_of("artifact"),
+            ) {
+                (Some(digest), _) => Some(digest.to_lowercase()),
+                (None, Some(filename)) => Some(crypto::sha256_hex(&fs::read(filename)?)),
+                (None, None) => None,
+            };
+            let public_key = match sub_matches.value_of("public-key") {
+                Some(filename) => Some(SearchPublicKey {
+                    format: String::from("x509"),
+                    content: base64::encode(fs::read(filename)?),
+                }),
+                None => None,
+            };
+            let query = SearchIndex {
+                hash: hash.map(|digest| format!("sha256:{}", digest)),
+                public_key,
+                email: sub_matches.value_of("email").map(String::from),
+            };
+            let uuids = rekor_api::search_index(&endpoints.rekor_url, &query).await?;
+            if uuids.is_empty() {
+                info!("No matching log entries");
+            }
+            for uuid in uuids {
+                let log_entry = rekor_api::get_entry_by_uuid(&endpoints.rekor_url, &uuid).await?;
+                println!("{}  {}", log_entry.log_index, uuid);
+            }
+        }
+        _ => unreachable!("subcommand_required prevents this"),
+    }
+    anyhow::Ok(())
+}
+
+async fn trust(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    match matches.subcommand() {
+        Some(("export", sub_matches)) => {
+            let endpoints = endpoints(sub_matches);
+            let tuf_url = endpoints.tuf_url.as_deref().ok_or_else(|| {
+                UsageError::new("the instance has no TUF repository, select one with --tuf-url")
+            })?;
+            let mut repository = tuf::Repository::open_default(tuf_url)?;
+            if let Some(root_filename) = sub_matches.value_of("tuf-root") {
+                repository = repository.with_initial_root(&fs::read(root_filename)?);
+            }
+            if !sub_matches.is_present("offline") {
+                info!("Updating trust root from {}...", tuf_url);
+                repository
+                    .fetch_target(trusted_root::TRUSTED_ROOT_TARGET)
+                    .await?;
+            }
+            let export = repository.export(&[trusted_root::TRUSTED_ROOT_TARGET])?;
+            let out_filename = sub_matches.value_of("out").unwrap();
+            write_output(out_filename, format!("{}\n", export.to_json()?))?;
+            if out_filename != "-" {
+                info!("Saving trust root to {}", out_filename);
+            }
+        }
+        Some(("import", sub_matches)) => {
+            let export = tuf::Export::from_json(&fs::read(sub_matches.value_of("file").unwrap())?)?;
+            // a broken trust root is better caught now than at the next
+            // verification, its hashes are checked on import
+            let json = export
+                .targets
+                .get(trusted_root::TRUSTED_ROOT_TARGET)
+                .ok_or_else(|| anyhow::anyhow!("the export has no trust root"))?;
+            TrustedRoot::from_json(&base64::decode(json)?)?.trust_root()?;
+            let mut repository = tuf::Repository::open_default(&export.url)?;
+            if let Some(root_filename) = sub_matches.value_of("tuf-root") {
+                repository = repository.with_initial_root(&fs::read(root_filename)?);
+            }
+            repository.import(&export)?;
+            info!(
+                "Imported the trust root of {} into {}",
+                export.url,
+                repository.dir().display()
+            );
+        }
+        _ => unreachable!("subcommand_required prevents this"),
     }
     anyhow::Ok(())
 }
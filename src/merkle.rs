@@ -0,0 +1,219 @@
+//! RFC 6962 Merkle tree hashing and proof verification.
+
+use sha2::{Digest, Sha256};
+
+pub fn leaf_hash(leaf: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(leaf);
+    hasher.finalize().to_vec()
+}
+
+pub fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// Computes the root implied by an inclusion proof for the leaf at `index` in
+/// a tree of `tree_size` leaves (RFC 9162, section 2.1.3.2).
+pub fn root_from_inclusion_proof(
+    index: u64,
+    tree_size: u64,
+    leaf_hash: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Vec<u8>, anyhow::Error> {
+    if index >= tree_size {
+        anyhow::bail!("leaf index {} is outside tree of size {}", index, tree_size);
+    }
+    let mut fn_ = index;
+    let mut sn = tree_size - 1;
+    let mut root = leaf_hash.to_vec();
+    for p in proof {
+        if sn == 0 {
+            anyhow::bail!("inclusion proof is too long");
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            root = node_hash(p, &root);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            root = node_hash(&root, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 {
+        anyhow::bail!("inclusion proof is too short");
+    }
+    Ok(root)
+}
+
+/// Verifies that `leaf` is included at `index` in the tree with `root_hash`.
+pub fn verify_inclusion(
+    index: u64,
+    tree_size: u64,
+    leaf: &[u8],
+    proof: &[Vec<u8>],
+    root_hash: &[u8],
+) -> Result<(), anyhow::Error> {
+    let root = root_from_inclusion_proof(index, tree_size, &leaf_hash(leaf), proof)?;
+    if root != root_hash {
+        anyhow::bail!("inclusion proof does not match the root hash");
+    }
+    Ok(())
+}
+
+/// Verifies that the tree of `size1` leaves with `root1` is a prefix of the
+/// tree of `size2` leaves with `root2` (RFC 9162, section 2.1.4.2).
+pub fn verify_consistency(
+    size1: u64,
+    size2: u64,
+    proof: &[Vec<u8>],
+    root1: &[u8],
+    root2: &[u8],
+) -> Result<(), anyhow::Error> {
+    if size1 > size2 {
+        anyhow::bail!("tree of size {} cannot extend one of size {}", size2, size1);
+    }
+    if size1 == size2 {
+        if !proof.is_empty() || root1 != root2 {
+            anyhow::bail!("trees of equal size {} differ", size1);
+        }
+        return Ok(());
+    }
+    // the empty tree is a prefix of every tree
+    if size1 == 0 {
+        return Ok(());
+    }
+    let mut proof = proof.to_vec();
+    if size1.is_power_of_two() {
+        proof.insert(0, root1.to_vec());
+    }
+    let (first, rest) = proof
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("consistency proof is empty"))?;
+    let mut fn_ = size1 - 1;
+    let mut sn = size2 - 1;
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let mut fr = first.clone();
+    let mut sr = first.clone();
+    for c in rest {
+        if sn == 0 {
+            anyhow::bail!("consistency proof is too long");
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 {
+        anyhow::bail!("consistency proof is too short");
+    }
+    if fr != root1 || sr != root2 {
+        anyhow::bail!("consistency proof does not match the root hashes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference implementation of the merkle tree hash from RFC 6962
+    fn tree_hash(leaves: &[Vec<u8>]) -> Vec<u8> {
+        if leaves.len() == 1 {
+            return leaf_hash(&leaves[0]);
+        }
+        let k = leaves.len().next_power_of_two() / 2;
+        node_hash(&tree_hash(&leaves[..k]), &tree_hash(&leaves[k..]))
+    }
+
+    fn inclusion_path(index: usize, leaves: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        if leaves.len() == 1 {
+            return Vec::new();
+        }
+        let k = leaves.len().next_power_of_two() / 2;
+        if index < k {
+            let mut path = inclusion_path(index, &leaves[..k]);
+            path.push(tree_hash(&leaves[k..]));
+            path
+        } else {
+            let mut path = inclusion_path(index - k, &leaves[k..]);
+            path.push(tree_hash(&leaves[..k]));
+            path
+        }
+    }
+
+    fn consistency_path(m: usize, leaves: &[Vec<u8>], complete: bool) -> Vec<Vec<u8>> {
+        if m == leaves.len() {
+            return if complete {
+                Vec::new()
+            } else {
+                vec![tree_hash(leaves)]
+            };
+        }
+        let k = leaves.len().next_power_of_two() / 2;
+        if m <= k {
+            let mut path = consistency_path(m, &leaves[..k], complete);
+            path.push(tree_hash(&leaves[k..]));
+            path
+        } else {
+            let mut path = consistency_path(m - k, &leaves[k..], false);
+            path.push(tree_hash(&leaves[..k]));
+            path
+        }
+    }
+
+    #[test]
+    fn test_verify_inclusion() {
+        for size in 1..=9usize {
+            let leaves: Vec<Vec<u8>> = (0..size).map(|i| vec![i as u8]).collect();
+            let root = tree_hash(&leaves);
+            for index in 0..size {
+                let proof = inclusion_path(index, &leaves);
+                verify_inclusion(index as u64, size as u64, &leaves[index], &proof, &root).unwrap();
+                assert!(
+                    verify_inclusion(index as u64, size as u64, b"lolwut", &proof, &root).is_err()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_consistency() {
+        for size in 1..=9usize {
+            let leaves: Vec<Vec<u8>> = (0..size).map(|i| vec![i as u8]).collect();
+            let root = tree_hash(&leaves);
+            for m in 1..=size {
+                let old_root = tree_hash(&leaves[..m]);
+                let proof = consistency_path(m, &leaves, true);
+                verify_consistency(m as u64, size as u64, &proof, &old_root, &root).unwrap();
+                if m < size {
+                    assert!(
+                        verify_consistency(m as u64, size as u64, &proof, &root, &root).is_err()
+                    );
+                    assert!(
+                        verify_consistency(size as u64, m as u64, &proof, &root, &old_root)
+                            .is_err()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! minisign signatures for self managed Ed25519 keys, so releases can be
+//! verified with `minisign -V` while projects move to Sigstore.
+//!
+//! The signatures are of the original, not prehashed, kind: the Ed25519
+//! signature over the file itself that signing with the key already makes.
+//! The public key file and the first two lines of a signature are also
+//! what signify reads.
+
+use openssl::pkey::{Id, PKeyRef, Public};
+
+use crate::algorithm::HashAlgorithm;
+use crate::key::SigningKey;
+
+const ALGORITHM: &[u8] = b"Ed";
+const ED25519_SPKI_PREFIX_LEN: usize = 12;
+const SIGNATURE_LEN: usize = 64;
+
+fn raw_public_key(key: &PKeyRef<Public>) -> Result<Vec<u8>, anyhow::Error> {
+    if key.id() != Id::ED25519 {
+        anyhow::bail!("minisign signatures need an Ed25519 key");
+    }
+    Ok(key.public_key_to_der()?[ED25519_SPKI_PREFIX_LEN..].to_vec())
+}
+
+/// The key id. minisign picks one at random when it creates a key, one
+/// brought along gets the start of the sha256 of its public key.
+pub fn key_id(key: &PKeyRef<Public>) -> Result<[u8; 8], anyhow::Error> {
+    let digest = HashAlgorithm::Sha256.digest(&raw_public_key(key)?)?;
+    let mut key_id = [0; 8];
+    key_id.copy_from_slice(&digest[..8]);
+    Ok(key_id)
+}
+
+// minisign shows key ids as little endian numbers
+fn display_key_id(key_id: [u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(key_id))
+}
+
+/// The contents of a minisign public key file for `key`.
+pub fn public_key(key: &PKeyRef<Public>) -> Result<String, anyhow::Error> {
+    let key_id = key_id(key)?;
+    let mut blob = ALGORITHM.to_vec();
+    blob.extend_from_slice(&key_id);
+    blob.extend_from_slice(&raw_public_key(key)?);
+    Ok(format!(
+        "untrusted comment: minisign public key {}\n{}\n",
+        display_key_id(key_id),
+        base64::encode(blob)
+    ))
+}
+
+/// The contents of a minisign signature file, from the Ed25519 `signature`
+/// `key` made over a file. `trusted_comment` is signed along with it.
+pub fn signature(
+    key: &dyn SigningKey,
+    signature: &[u8],
+    trusted_comment: &str,
+) -> Result<String, anyhow::Error> {
+    if signature.len() != SIGNATURE_LEN {
+        anyhow::bail!(
+            "expected an Ed25519 signature, got {} bytes",
+            signature.len()
+        );
+    }
+    if trusted_comment.contains(['\r', '\n']) {
+        anyhow::bail!("the trusted comment has to be a single line");
+    }
+    let public_key = key.public_key()?;
+    let mut blob = ALGORITHM.to_vec();
+    blob.extend_from_slice(&key_id(&public_key)?);
+    blob.extend_from_slice(signature);
+
+    let mut global = signature.to_vec();
+    global.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = key.sign(&global)?;
+    Ok(format!(
+        "untrusted comment: signature from ferris-sign secret key\n{}\ntrusted comment: {}\n{}\n",
+        base64::encode(blob),
+        trusted_comment,
+        base64::encode(global_signature)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::SigningAlgorithm;
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn test_signature() {
+        let (key, _) = SigningAlgorithm::Ed25519.generate().unwrap();
+        let verifying_key = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        let key_id = key_id(&verifying_key).unwrap();
+
+        let public_key = public_key(&verifying_key).unwrap();
+        let lines: Vec<&str> = public_key.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                "untrusted comment: minisign public key {}",
+                display_key_id(key_id)
+            )
+        );
+        let blob = base64::decode(lines[1]).unwrap();
+        assert_eq!(&blob[..2], b"Ed");
+        assert_eq!(blob[2..10], key_id);
+        assert_eq!(blob[10..], raw_public_key(&verifying_key).unwrap());
+
+        let message = b"lolwut";
+        let file_signature = key.sign(message).unwrap();
+        let signature = signature(&key, &file_signature, "timestamp:0\tfile:lolwut").unwrap();
+        let lines: Vec<&str> = signature.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let blob = base64::decode(lines[1]).unwrap();
+        assert_eq!(blob[2..10], key_id);
+        assert!(SigningAlgorithm::Ed25519
+            .verify(&verifying_key, message, &blob[10..])
+            .unwrap());
+        assert_eq!(lines[2], "trusted comment: timestamp:0\tfile:lolwut");
+        let global = [&blob[10..], &b"timestamp:0\tfile:lolwut"[..]].concat();
+        assert!(SigningAlgorithm::Ed25519
+            .verify(&verifying_key, &global, &base64::decode(lines[3]).unwrap())
+            .unwrap());
+
+        assert!(super::signature(&key, &file_signature, "two\nlines").is_err());
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let verifying_key = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        assert!(super::public_key(&verifying_key).is_err());
+    }
+
+    #[test]
+    fn test_display_key_id() {
+        assert_eq!(
+            display_key_id([0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01]),
+            "0123456789ABCDEF"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Watching Rekor for entries signed with certificates for an identity.
+//!
+//! Every entry ferris-sign creates is noted in a local list. The monitor
+//! compares what the log holds for an identity against that list, so an entry
+//! that shows up without having been created here points at someone else
+//! obtaining certificates for the identity.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::rekor_api::{self, SearchIndex};
+
+const CREATED_FILE: &str = "created-entries";
+
+/// Appends `uuid` to the list of entries created on this machine in `dir`.
+pub fn record_created(dir: &Path, uuid: &str) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(dir)?;
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(dir.join(CREATED_FILE))?;
+    writeln!(file, "{}", uuid)?;
+    Ok(())
+}
+
+/// The entries created on this machine according to `dir`.
+pub fn created_entries(dir: &Path) -> Result<BTreeSet<String>, anyhow::Error> {
+    match fs::read_to_string(dir.join(CREATED_FILE)) {
+        Ok(created) => Ok(created.lines().map(String::from).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct MonitorState {
+    seen: BTreeSet<String>,
+}
+
+/// An entry that appeared since the last poll.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewEntry {
+    pub uuid: String,
+    /// Whether the entry was created on this machine.
+    pub known: bool,
+}
+
+/// Polls Rekor for entries for one identity, remembering what was already
+/// reported in a state file.
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    rekor_url: String,
+    identity: String,
+    state_path: PathBuf,
+}
+
+impl Monitor {
+    pub fn new(rekor_url: &str, identity: &str, state_path: impl Into<PathBuf>) -> Self {
+        Monitor {
+            rekor_url: rekor_url.to_string(),
+            identity: identity.to_string(),
+            state_path: state_path.into(),
+        }
+    }
+
+    fn load_state(&self) -> Result<Option<MonitorState>, anyhow::Error> {
+        match fs::read(&self.state_path) {
+            Ok(state) => Ok(Some(serde_json::from_slice(&state)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_state(&self, state: &MonitorState) -> Result<(), anyhow::Error> {
+        if let Some(dir) = self.state_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.state_path, serde_json::to_vec_pretty(state)?)?;
+        Ok(())
+    }
+
+    /// Fetches the entries for the identity and returns those not seen
+    /// before. The first poll only records a baseline and returns nothing.
+    pub async fn poll(&self, known: &BTreeSet<String>) -> Result<Vec<NewEntry>, anyhow::Error> {
+        let query = SearchIndex {
+            email: Some(self.identity.clone()),
+            ..SearchIndex::default()
+        };
+        let uuids = rekor_api::search_index(&self.rekor_url, &query).await?;
+
+        let (mut state, baseline) = match self.load_state()? {
+            Some(state) => (state, false),
+            None => (MonitorState::default(), true),
+        };
+        let new_entries = new_entries(&mut state, uuids, known);
+        self.save_state(&state)?;
+        if baseline {
+            return Ok(Vec::new());
+        }
+        Ok(new_entries)
+    }
+}
+
+// rekor reports entry ids with or without the 16 hex digit tree id prefix,
+// the last 64 digits are the entry uuid proper
+fn entry_uuid(id: &str) -> &str {
+    &id[id.len().saturating_sub(64)..]
+}
+
+// marks `uuids` as seen, returning the ones that were not seen before
+fn new_entries(
+    state: &mut MonitorState,
+    uuids: Vec<String>,
+    known: &BTreeSet<String>,
+) -> Vec<NewEntry> {
+    uuids
+        .into_iter()
+        .filter(|uuid| state.seen.insert(uuid.clone()))
+        .map(|uuid| NewEntry {
+            known: known
+                .iter()
+                .any(|created| entry_uuid(created) == entry_uuid(&uuid)),
+            uuid,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_entries() {
+        let mut state = MonitorState::default();
+        let uuid = "a".repeat(64);
+        let known: BTreeSet<String> = [format!("24296fb24b8ad77a{}", uuid)].into_iter().collect();
+        let first = new_entries(&mut state, vec![uuid, String::from("bbbb")], &known);
+        assert_eq!(first.len(), 2);
+        assert!(first[0].known);
+        assert!(!first[1].known);
+
+        let second = new_entries(
+            &mut state,
+            vec![String::from("bbbb"), String::from("cccc")],
+            &known,
+        );
+        assert_eq!(
+            second,
+            vec![NewEntry {
+                uuid: String::from("cccc"),
+                known: false
+            }]
+        );
+    }
+}
@@ -0,0 +1,652 @@
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sigstore::oauth::openidflow::{OpenIDAuthorize, RedirectListener};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{task, time};
+use url::Url;
+
+use crate::http::{self, Service};
+use crate::jwt;
+use crate::progress::Progress;
+
+/// Public sigstore OAuth issuer.
+pub const SIGSTORE_OAUTH_URL: &str = "https://oauth2.sigstore.dev/auth";
+/// Audience Fulcio expects identity tokens to be issued for.
+pub const SIGSTORE_AUDIENCE: &str = "sigstore";
+/// Client ID ferris-sign logs in to the public sigstore issuer as.
+pub const SIGSTORE_CLIENT_ID: &str = "sigstore";
+
+/// An OIDC identity token along with the email address it was issued for.
+#[derive(Debug, Clone)]
+pub struct IdentityToken {
+    pub token: String,
+    pub email: String,
+    /// Set when the issuer handed out a refresh token along with the ID token.
+    pub refresh: Option<RefreshToken>,
+}
+
+/// A refresh token and the endpoint it can be redeemed at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub token_endpoint: String,
+    pub refresh_token: String,
+    /// The client the token was issued to, refresh tokens are bound to it.
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub client_secret: String,
+}
+
+// tokens cached before clients could be chosen were issued to sigstore's
+fn default_client_id() -> String {
+    SIGSTORE_CLIENT_ID.to_string()
+}
+
+/// The OAuth client to log in as, and what it asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcClient {
+    pub client_id: String,
+    /// Empty for public clients.
+    pub client_secret: String,
+    /// Scopes requested besides `openid`.
+    pub scopes: Vec<String>,
+    /// Dex connector to log in with, rather than letting the user choose.
+    pub connector_id: Option<String>,
+}
+
+impl Default for OidcClient {
+    fn default() -> Self {
+        OidcClient {
+            client_id: SIGSTORE_CLIENT_ID.to_string(),
+            client_secret: String::new(),
+            scopes: vec!["email".to_string()],
+            connector_id: None,
+        }
+    }
+}
+
+impl OidcClient {
+    // the scope parameter, openid always comes first
+    fn scope(&self) -> String {
+        std::iter::once("openid")
+            .chain(
+                self.scopes
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|scope| *scope != "openid"),
+            )
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // client_secret is only sent by confidential clients
+    fn credentials(&self) -> Vec<(&'static str, &str)> {
+        let mut credentials = vec![("client_id", self.client_id.as_str())];
+        if !self.client_secret.is_empty() {
+            credentials.push(("client_secret", self.client_secret.as_str()));
+        }
+        credentials
+    }
+}
+
+/// The Dex connector ID for the issuers the public sigstore instance logs in
+/// with, by a short name. Other names are taken as connector IDs.
+pub fn connector_id(name: &str) -> &str {
+    match name {
+        "github" => "https://github.com/login/oauth",
+        "google" => "https://accounts.google.com",
+        "microsoft" => "https://login.microsoftonline.com",
+        _ => name,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    aud: Audience,
+    exp: u64,
+    sub: String,
+    email: Option<String>,
+}
+
+impl IdentityToken {
+    /// Wraps a pre-obtained JWT, checking that it was issued for the sigstore
+    /// audience and has not expired.
+    ///
+    /// The signature is not checked here, Fulcio does that.
+    pub fn from_jwt(token: &str) -> Result<Self, anyhow::Error> {
+        Self::from_jwt_for(token, SIGSTORE_AUDIENCE)
+    }
+
+    /// Like [`IdentityToken::from_jwt`], for tokens issued to the client
+    /// `audience` of a private instance.
+    pub fn from_jwt_for(token: &str, audience: &str) -> Result<Self, anyhow::Error> {
+        let token = token.trim();
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| anyhow::anyhow!("identity token is not a JWT"))?;
+        let claims: Claims =
+            serde_json::from_slice(&base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?)?;
+
+        let audience_ok = match &claims.aud {
+            Audience::One(aud) => aud == audience,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == audience),
+        };
+        if !audience_ok {
+            anyhow::bail!(
+                "identity token audience {:?} does not include {}",
+                claims.aud,
+                audience
+            );
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if claims.exp <= now {
+            anyhow::bail!("identity token expired");
+        }
+
+        Ok(IdentityToken {
+            token: token.to_string(),
+            // workload identities have no email, fulcio then expects the
+            // subject to be signed instead
+            email: claims.email.unwrap_or(claims.sub),
+            refresh: None,
+        })
+    }
+}
+
+/// How identities are shown on the terminal and in traces. Certificates
+/// always name the identity, this only keeps it off the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    /// Shows `<redacted>`.
+    Hide,
+    /// Shows the start of the sha256 of the identity, so that different
+    /// identities can still be told apart.
+    Hash,
+}
+
+impl FromStr for Redaction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hide" => Ok(Redaction::Hide),
+            "hash" => Ok(Redaction::Hash),
+            _ => anyhow::bail!("unknown redaction {}, expected hide or hash", s),
+        }
+    }
+}
+
+impl fmt::Display for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Redaction::Hide => "hide",
+            Redaction::Hash => "hash",
+        })
+    }
+}
+
+static REDACTION: RwLock<Option<Redaction>> = RwLock::new(None);
+
+/// Shows identities with `redaction` from now on, as they are if none.
+pub fn set_redaction(redaction: Option<Redaction>) {
+    if let Ok(mut current) = REDACTION.write() {
+        *current = redaction;
+    }
+}
+
+/// `identity` as it may be shown.
+pub fn display_identity(identity: &str) -> String {
+    match REDACTION.read().ok().and_then(|redaction| *redaction) {
+        None => identity.to_string(),
+        Some(redaction) => redact(identity, redaction),
+    }
+}
+
+fn redact(identity: &str, redaction: Redaction) -> String {
+    match redaction {
+        Redaction::Hide => "<redacted>".to_string(),
+        Redaction::Hash => format!(
+            "sha256:{}",
+            &HEXLOWER.encode(&Sha256::digest(identity.as_bytes()))[..16]
+        ),
+    }
+}
+
+/// Options for [`interactive_flow`].
+#[derive(Debug, Clone, Default)]
+pub struct InteractiveOptions {
+    /// Port for the local redirect listener, a free one is picked if unset.
+    pub redirect_port: Option<u16>,
+    /// Only print the authorization URL, for sessions without a local browser.
+    pub no_browser: bool,
+    /// Also print the authorization URL as a terminal QR code.
+    pub qr_code: bool,
+    /// Shows a spinner while waiting for the redirect.
+    pub progress: Progress,
+    /// How long to wait for the redirect, no limit if unset.
+    pub login_timeout: Option<Duration>,
+    pub client: OidcClient,
+    /// Account the issuer should log in with, sent as `login_hint`.
+    pub login_hint: Option<String>,
+    /// Makes the issuer ask which account to use rather than taking the one
+    /// the browser is logged in with.
+    pub select_account: bool,
+}
+
+// binding to port 0 lets the OS pick a free port, the listener is dropped
+// again straight away so the redirect listener can take it over
+fn free_port() -> Result<u16, anyhow::Error> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port())
+}
+
+// sigstore always asks for the email scope and knows nothing of dex or
+// account selection, so its authorization URL is rewritten. the nonce and
+// pkce challenge stay as they are
+fn authorization_url(url: &str, options: &InteractiveOptions) -> Result<Url, anyhow::Error> {
+    let client = &options.client;
+    let mut url = Url::parse(url)?;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !["scope", "connector_id", "login_hint", "prompt"].contains(&&**key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    {
+        let mut query = url.query_pairs_mut();
+        query.clear();
+        for (key, value) in &pairs {
+            query.append_pair(key, value);
+        }
+        query.append_pair("scope", &client.scope());
+        if let Some(connector_id) = &client.connector_id {
+            query.append_pair("connector_id", connector_id);
+        }
+        if let Some(login_hint) = &options.login_hint {
+            query.append_pair("login_hint", login_hint);
+        }
+        if options.select_account {
+            query.append_pair("prompt", "select_account");
+        }
+    }
+    Ok(url)
+}
+
+fn qr_code(url: &str) -> Result<String, anyhow::Error> {
+    let code = qrcode::QrCode::new(url)?;
+    Ok(code
+        .render::<qrcode::render::unicode::Dense1x2>()
+        .quiet_zone(true)
+        .build())
+}
+
+/// Runs the interactive browser based OAuth flow against `issuer`.
+pub async fn interactive_flow(
+    issuer: &str,
+    options: &InteractiveOptions,
+) -> Result<IdentityToken, anyhow::Error> {
+    let port = match options.redirect_port {
+        Some(port) => port,
+        None => free_port()?,
+    };
+    let issuer_url = issuer.to_string();
+    let redirect_uri = format!("http://localhost:{}", port);
+    let client = options.client.clone();
+    // use tokio::task::spawn_blocking to call OpenIDAuthorize in a blocking thread
+    let oidc_url = task::spawn_blocking(move || {
+        OpenIDAuthorize::new(
+            &client.client_id,
+            &client.client_secret,
+            &issuer_url,
+            &redirect_uri,
+        )
+        .auth_url()
+        .map_err(anyhow::Error::from)
+    });
+    // sigstore fetches the issuer's discovery document with its own client
+    let oidc_url = match http::timeout(Service::Oidc) {
+        Some(limit) => time::timeout(limit, oidc_url)
+            .await
+            .map_err(|_| anyhow::anyhow!("OIDC issuer did not respond within {:?}", limit))?,
+        None => oidc_url.await,
+    }??;
+    let url = authorization_url(oidc_url.0.as_str(), options)?;
+
+    // prompts go to stderr, stdout may be a signature git is reading
+    if options.no_browser || open::that(url.to_string()).is_err() {
+        eprintln!("Open this URL in a browser to log in:\n{}\n", url);
+    } else {
+        eprintln!(
+            "Open this URL in a browser if it does not automatically open for you:\n{}\n",
+            url
+        );
+    }
+    if options.qr_code {
+        eprintln!("{}", qr_code(url.as_str())?);
+    }
+
+    let spinner = options.progress.spinner("Waiting for the browser login...");
+    // use tokio::task::spawn_blocking to call RedirectListener in a blocking thread
+    let listen_addr = format!("127.0.0.1:{}", port);
+    let nonce = oidc_url.2.secret().clone();
+    let redirect = task::spawn_blocking(move || {
+        RedirectListener::new(
+            &listen_addr,
+            oidc_url.1, // client
+            oidc_url.2, // nonce
+            oidc_url.3, // pkce verifier
+        )
+        .redirect_listener()
+        .map_err(anyhow::Error::from)
+    });
+    let redirect = match options.login_timeout {
+        Some(limit) => time::timeout(limit, redirect).await,
+        None => Ok(redirect.await),
+    };
+    spinner.finish_and_clear();
+    let redirect =
+        redirect.map_err(|_| anyhow::anyhow!("browser login did not complete in time"))?;
+    let (token_response, id_token) = redirect??;
+    let id_token = id_token.to_string();
+    jwt::check_claims(
+        &id_token,
+        &jwt::Expected {
+            issuer,
+            audience: &options.client.client_id,
+            nonce: Some(&nonce),
+        },
+    )?;
+
+    let email = token_response
+        .email()
+        .ok_or_else(|| anyhow::anyhow!("identity token has no email claim"))?;
+    // the code exchange happens inside sigstore, only its outcome is traced
+    tracing::debug!(
+        "browser login returned an identity token for {}",
+        display_identity(email)
+    );
+    Ok(IdentityToken {
+        token: id_token,
+        email: email.to_string(),
+        refresh: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    device_authorization_endpoint: Option<String>,
+    token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    #[serde(default)]
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    #[serde(default = "default_poll_interval")]
+    interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    refresh_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Runs the OAuth device authorization grant (RFC 8628) against `issuer`, for
+/// machines without a browser. The user completes the login on another device.
+pub async fn device_flow(
+    issuer: &str,
+    oidc_client: &OidcClient,
+) -> Result<IdentityToken, anyhow::Error> {
+    let client = http::client(Service::Oidc);
+    let metadata: ProviderMetadata = http::send(client.get(format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    )))
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    let device_endpoint = metadata
+        .device_authorization_endpoint
+        .ok_or_else(|| anyhow::anyhow!("{} does not support the device flow", issuer))?;
+
+    let scope = format!("{} offline_access", oidc_client.scope());
+    let mut form = oidc_client.credentials();
+    form.push(("scope", &scope));
+    if let Some(connector_id) = &oidc_client.connector_id {
+        form.push(("connector_id", connector_id));
+    }
+    let authorization: DeviceAuthorization = http::send(client.post(device_endpoint).form(&form))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    match &authorization.verification_uri_complete {
+        Some(uri) => eprintln!("Open this URL on any device to log in:\n{}\n", uri),
+        None => eprintln!(
+            "Open this URL on any device and enter the code {}:\n{}\n",
+            authorization.user_code, authorization.verification_uri
+        ),
+    }
+
+    let mut interval = Duration::from_secs(authorization.interval);
+    let deadline = time::Instant::now() + Duration::from_secs(authorization.expires_in);
+    while time::Instant::now() < deadline {
+        time::sleep(interval).await;
+        let mut form = vec![
+            ("grant_type", DEVICE_CODE_GRANT),
+            ("device_code", authorization.device_code.as_str()),
+        ];
+        form.extend(oidc_client.credentials());
+        let response: TokenResponse = http::send(client.post(&metadata.token_endpoint).form(&form))
+            .await?
+            .json()
+            .await?;
+        match (response.id_token, response.error.as_deref()) {
+            (Some(id_token), _) => {
+                let mut identity = IdentityToken::from_jwt_for(&id_token, &oidc_client.client_id)?;
+                identity.refresh = response.refresh_token.map(|refresh_token| RefreshToken {
+                    token_endpoint: metadata.token_endpoint.clone(),
+                    refresh_token,
+                    client_id: oidc_client.client_id.clone(),
+                    client_secret: oidc_client.client_secret.clone(),
+                });
+                return Ok(identity);
+            }
+            (None, Some("authorization_pending")) => {}
+            (None, Some("slow_down")) => interval += Duration::from_secs(5),
+            (None, error) => anyhow::bail!(
+                "device authorization failed: {}",
+                response
+                    .error_description
+                    .as_deref()
+                    .or(error)
+                    .unwrap_or("no identity token returned")
+            ),
+        }
+    }
+    anyhow::bail!("device code expired before the login was completed")
+}
+
+/// Redeems `refresh` for a fresh identity token.
+pub async fn refresh(refresh: &RefreshToken) -> Result<IdentityToken, anyhow::Error> {
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh.refresh_token.as_str()),
+        ("client_id", refresh.client_id.as_str()),
+    ];
+    if !refresh.client_secret.is_empty() {
+        form.push(("client_secret", refresh.client_secret.as_str()));
+    }
+    let response: TokenResponse = http::send(
+        http::client(Service::Oidc)
+            .post(&refresh.token_endpoint)
+            .form(&form),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    let id_token = response
+        .id_token
+        .ok_or_else(|| anyhow::anyhow!("token refresh returned no identity token"))?;
+    let mut identity = IdentityToken::from_jwt_for(&id_token, &refresh.client_id)?;
+    identity.refresh = Some(RefreshToken {
+        token_endpoint: refresh.token_endpoint.clone(),
+        client_id: refresh.client_id.clone(),
+        client_secret: refresh.client_secret.clone(),
+        // issuers that rotate refresh tokens return a new one
+        refresh_token: response
+            .refresh_token
+            .unwrap_or_else(|| refresh.refresh_token.clone()),
+    });
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: serde_json::Value) -> String {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+        format!(
+            "{}.{}.{}",
+            encode(br#"{"alg":"RS256"}"#),
+            encode(claims.to_string().as_bytes()),
+            encode(b"signature")
+        )
+    }
+
+    #[test]
+    fn test_identity_token_from_jwt() {
+        let token = jwt(serde_json::json!({
+            "aud": "sigstore",
+            "exp": u64::MAX / 2,
+            "sub": "1234",
+            "email": "ferris@example.com",
+        }));
+        let identity = IdentityToken::from_jwt(&token).unwrap();
+        assert_eq!(identity.email, "ferris@example.com");
+        assert_eq!(identity.token, token);
+
+        let token = jwt(serde_json::json!({
+            "aud": ["other", "sigstore"],
+            "exp": u64::MAX / 2,
+            "sub": "repo:org/proj:ref:refs/heads/main",
+        }));
+        let identity = IdentityToken::from_jwt(&token).unwrap();
+        assert_eq!(identity.email, "repo:org/proj:ref:refs/heads/main");
+        assert!(IdentityToken::from_jwt_for(&token, "other").is_ok());
+        assert!(IdentityToken::from_jwt_for(&token, "corp").is_err());
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let url = "https://oauth2.sigstore.dev/auth/auth?response_type=code&client_id=sigstore&state=s&code_challenge=c&scope=openid+email&nonce=n";
+        let options = InteractiveOptions {
+            client: OidcClient {
+                scopes: vec!["email".to_string(), "groups".to_string()],
+                connector_id: Some(connector_id("github").to_string()),
+                ..OidcClient::default()
+            },
+            login_hint: Some("ferris@example.com".to_string()),
+            select_account: true,
+            ..InteractiveOptions::default()
+        };
+        let rewritten = authorization_url(url, &options).unwrap();
+        let pairs: Vec<(String, String)> = rewritten
+            .query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        let value = |name: &str| {
+            pairs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(value("scope"), Some("openid email groups"));
+        assert_eq!(
+            value("connector_id"),
+            Some("https://github.com/login/oauth")
+        );
+        assert_eq!(value("nonce"), Some("n"));
+        assert_eq!(value("code_challenge"), Some("c"));
+        assert_eq!(pairs.iter().filter(|(key, _)| key == "scope").count(), 1);
+        assert_eq!(value("login_hint"), Some("ferris@example.com"));
+        assert_eq!(value("prompt"), Some("select_account"));
+        let plain = authorization_url(url, &InteractiveOptions::default()).unwrap();
+        assert!(!plain.query_pairs().any(|(key, _)| key == "prompt"));
+
+        let client = OidcClient {
+            scopes: vec!["openid".to_string(), "profile".to_string()],
+            ..OidcClient::default()
+        };
+        assert_eq!(client.scope(), "openid profile");
+        assert_eq!(client.credentials(), vec![("client_id", "sigstore")]);
+        assert_eq!(connector_id("corp-ldap"), "corp-ldap");
+    }
+
+    #[test]
+    fn test_refresh_token_without_client() {
+        let refresh: RefreshToken = serde_json::from_str(
+            r#"{"token_endpoint":"https://oauth2.sigstore.dev/auth/token","refresh_token":"s3cret"}"#,
+        )
+        .unwrap();
+        assert_eq!(refresh.client_id, SIGSTORE_CLIENT_ID);
+        assert!(refresh.client_secret.is_empty());
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("ferris@example.com", Redaction::Hide), "<redacted>");
+        let hashed = redact("ferris@example.com", Redaction::Hash);
+        assert_eq!(hashed.len(), "sha256:".len() + 16);
+        assert_ne!(hashed, redact("crab@example.com", Redaction::Hash));
+        assert!(!hashed.contains("ferris"));
+        assert_eq!("hash".parse::<Redaction>().unwrap(), Redaction::Hash);
+        assert!("lolwut".parse::<Redaction>().is_err());
+    }
+
+    #[test]
+    fn test_identity_token_rejected() {
+        let wrong_audience = jwt(serde_json::json!({
+            "aud": "other",
+            "exp": u64::MAX / 2,
+            "sub": "1234",
+        }));
+        assert!(IdentityToken::from_jwt(&wrong_audience).is_err());
+
+        let expired = jwt(serde_json::json!({
+            "aud": "sigstore",
+            "exp": 1,
+            "sub": "1234",
+        }));
+        assert!(IdentityToken::from_jwt(&expired).is_err());
+        assert!(IdentityToken::from_jwt("lolwut").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Signing with keys kept on a PKCS#11 token such as an HSM, addressed by
+//! RFC 7512 `pkcs11:` URIs. Only EC keys on P-256 and P-384 are supported.
+
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, SessionFlags, UserType};
+use cryptoki::slot::Slot;
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+use crate::der;
+use crate::key::{self, SigningKey};
+
+pub const URI_SCHEME: &str = "pkcs11:";
+
+const OID_P256: &str = "1.2.840.10045.3.1.7";
+const OID_P384: &str = "1.3.132.0.34";
+
+/// The parts of a `pkcs11:` URI used to find a key. Other attributes are
+/// ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pkcs11Uri {
+    /// Label of the token holding the key.
+    pub token: Option<String>,
+    /// Label of the key objects.
+    pub object: Option<String>,
+    /// `CKA_ID` of the key objects.
+    pub id: Option<Vec<u8>>,
+    /// PKCS#11 module to load, from the `module-path` query attribute.
+    pub module_path: Option<String>,
+    /// User PIN, from the `pin-value` query attribute.
+    pub pin_value: Option<String>,
+}
+
+impl Pkcs11Uri {
+    pub fn parse(uri: &str) -> Result<Self, anyhow::Error> {
+        let rest = uri
+            .strip_prefix(URI_SCHEME)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a pkcs11: URI", uri))?;
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (rest, ""),
+        };
+        let mut parsed = Pkcs11Uri::default();
+        for attribute in path.split(';').filter(|a| !a.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "token" => parsed.token = Some(String::from_utf8(value)?),
+                "object" => parsed.object = Some(String::from_utf8(value)?),
+                "id" => parsed.id = Some(value),
+                _ => {}
+            }
+        }
+        for attribute in query.split('&').filter(|a| !a.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "module-path" => parsed.module_path = Some(String::from_utf8(value)?),
+                "pin-value" => parsed.pin_value = Some(String::from_utf8(value)?),
+                _ => {}
+            }
+        }
+        if parsed.object.is_none() && parsed.id.is_none() {
+            anyhow::bail!("{} names neither an object nor an id", uri);
+        }
+        Ok(parsed)
+    }
+}
+
+fn split_attribute(attribute: &str) -> Result<(&str, Vec<u8>), anyhow::Error> {
+    let (name, value) = attribute
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("invalid pkcs11: URI attribute {}", attribute))?;
+    Ok((name, percent_decode(value)?))
+}
+
+fn percent_decode(value: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .ok_or_else(|| anyhow::anyhow!("truncated escape in {}", value))?;
+            decoded.push(u8::from_str_radix(hex, 16)?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+/// An EC key on a PKCS#11 token. The private key never leaves the token,
+/// digests are sent to it for signing.
+pub struct Pkcs11Key {
+    session: Mutex<Session>,
+    key: ObjectHandle,
+    algorithm: SigningAlgorithm,
+    public_key: PKey<Public>,
+    // dropped last, the session must not outlive the module
+    _context: Pkcs11,
+}
+
+impl fmt::Debug for Pkcs11Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pkcs11Key")
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Pkcs11Key {
+    /// Loads the module at `module_path` and finds the key named by `uri`,
+    /// logging in with `pin` if one is given.
+    pub fn open(
+        uri: &Pkcs11Uri,
+        module_path: &Path,
+        pin: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let context = Pkcs11::new(module_path).map_err(|e| {
+            anyhow::anyhow!(
+                "could not load PKCS#11 module {}: {}",
+                module_path.display(),
+                e
+            )
+        })?;
+        context.initialize(CInitializeArgs::OsThreads)?;
+        let slot = find_slot(&context, uri.token.as_deref())?;
+
+        let mut flags = SessionFlags::new();
+        flags.set_serial_session(true);
+        let session = context.open_session_no_callback(slot, flags)?;
+        if let Some(pin) = pin {
+            session
+                .login(UserType::User, Some(pin))
+                .map_err(|e| anyhow::anyhow!("could not log in to the token: {}", e))?;
+        }
+
+        let key = find_object(&session, uri, ObjectClass::PRIVATE_KEY)?;
+        let public = find_object(&session, uri, ObjectClass::PUBLIC_KEY)?;
+        let (mut params, mut point) = (None, None);
+        for attribute in session
+            .get_attributes(public, &[AttributeType::EcParams, AttributeType::EcPoint])
+            .map_err(|_| anyhow::anyhow!("only EC keys are supported on PKCS#11 tokens"))?
+        {
+            match attribute {
+                Attribute::EcParams(value) => params = Some(value),
+                Attribute::EcPoint(value) => point = Some(value),
+                _ => {}
+            }
+        }
+        let (algorithm, public_key) = match (params, point) {
+            (Some(params), Some(point)) => ec_public_key(&params, &point)?,
+            _ => anyhow::bail!("only EC keys are supported on PKCS#11 tokens"),
+        };
+
+        Ok(Pkcs11Key {
+            session: Mutex::new(session),
+            key,
+            algorithm,
+            public_key,
+            _context: context,
+        })
+    }
+}
+
+impl SigningKey for Pkcs11Key {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        Ok(self.algorithm)
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        Ok(self.public_key.clone())
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let digest_algorithm = self.algorithm.hash();
+        self.sign_digest(digest_algorithm, &digest_algorithm.digest(data)?)
+    }
+
+    // CKM_ECDSA signs a digest computed by the caller and returns r || s
+    fn sign_digest(
+        &self,
+        _digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let session = self
+            .session
+            .lock()
+            .map_err(|_| anyhow::anyhow!("PKCS#11 session is poisoned"))?;
+        let raw = session.sign(&Mechanism::Ecdsa, self.key, digest)?;
+        key::ecdsa_der_from_raw(&raw)
+    }
+}
+
+fn find_slot(context: &Pkcs11, token: Option<&str>) -> Result<Slot, anyhow::Error> {
+    for slot in context.get_slots_with_token()? {
+        let label = context.get_token_info(slot)?.label().trim_end().to_string();
+        if token.is_none_or(|token| token == label) {
+            return Ok(slot);
+        }
+    }
+    match token {
+        Some(token) => anyhow::bail!("no PKCS#11 token labelled {}", token),
+        None => anyhow::bail!("no PKCS#11 token found"),
+    }
+}
+
+fn find_object(
+    session: &Session,
+    uri: &Pkcs11Uri,
+    class: ObjectClass,
+) -> Result<ObjectHandle, anyhow::Error> {
+    let mut template = vec![Attribute::Class(class)];
+    if let Some(object) = &uri.object {
+        template.push(Attribute::Label(object.as_bytes().to_vec()));
+    }
+    if let Some(id) = &uri.id {
+        template.push(Attribute::Id(id.clone()));
+    }
+    let objects = session.find_objects(&template)?;
+    match objects[..] {
+        [object] => Ok(object),
+        [] => anyhow::bail!("no matching {} on the token", class),
+        _ => anyhow::bail!("the URI matches several {} objects", class),
+    }
+}
+
+// CKA_EC_PARAMS holds the curve OID and CKA_EC_POINT the DER octet string
+// wrapping the encoded point
+fn ec_public_key(
+    params: &[u8],
+    point: &[u8],
+) -> Result<(SigningAlgorithm, PKey<Public>), anyhow::Error> {
+    let oid = der::oid_to_string(der::expect(params, der::TAG_OID)?.value);
+    let (nid, algorithm) = match oid.as_str() {
+        OID_P256 => (Nid::X9_62_PRIME256V1, SigningAlgorithm::EcdsaP256Sha256),
+        OID_P384 => (Nid::SECP384R1, SigningAlgorithm::EcdsaP384Sha384),
+        _ => anyhow::bail!("unsupported curve {}", oid),
+    };
+    let point = der::expect(point, der::TAG_OCTET_STRING)?.value;
+    let group = EcGroup::from_curve_name(nid)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, point, &mut ctx)?;
+    let key = PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?;
+    Ok((algorithm, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::PointConversionForm;
+
+    #[test]
+    fn test_parse_uri() {
+        let uri = Pkcs11Uri::parse(
+            "pkcs11:token=release%20keys;object=signer;id=%01%02?module-path=/usr/lib/softhsm/libsofthsm2.so&pin-value=1234",
+        )
+        .unwrap();
+        assert_eq!(uri.token.as_deref(), Some("release keys"));
+        assert_eq!(uri.object.as_deref(), Some("signer"));
+        assert_eq!(uri.id, Some(vec![1, 2]));
+        assert_eq!(
+            uri.module_path.as_deref(),
+            Some("/usr/lib/softhsm/libsofthsm2.so")
+        );
+        assert_eq!(uri.pin_value.as_deref(), Some("1234"));
+
+        assert!(Pkcs11Uri::parse("pkcs11:token=release").is_err());
+        assert!(Pkcs11Uri::parse("pkcs11:object=%2").is_err());
+        assert!(Pkcs11Uri::parse("file:signer.pem").is_err());
+    }
+
+    #[test]
+    fn test_ec_public_key() {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let ec_key = key.ec_key().unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let point = ec_key
+            .public_key()
+            .to_bytes(ec_key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)
+            .unwrap();
+        let params = der::encode(
+            der::TAG_OID,
+            &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07],
+        );
+
+        let (algorithm, public_key) =
+            ec_public_key(&params, &der::encode(der::TAG_OCTET_STRING, &point)).unwrap();
+        assert_eq!(algorithm, SigningAlgorithm::EcdsaP256Sha256);
+        assert!(public_key.public_eq(&key));
+    }
+}
@@ -0,0 +1,355 @@
+//! Identity checks applied to Fulcio certificates during verification, and
+//! policy files bundling them with the other acceptance rules.
+//!
+//! A policy file is YAML, every key is optional:
+//!
+//! ```yaml
+//! # the certificate has to match one of these
+//! identities:
+//!   - identity: release@example.com
+//!     issuer: https://accounts.google.com
+//!   - identity-regexp: '^https://github\.com/example/.*/release\.yml@'
+//!     issuer: https://token.actions.githubusercontent.com
+//! annotations:
+//!   environment: production
+//! # seconds, or with an s, m, h or d suffix
+//! max-age: 90d
+//! attestations:
+//!   - slsaprovenance
+//!   - https://example.com/review/v1
+//! # signatures by two different allowed identities
+//! threshold: 2
+//! ```
+
+use openssl::x509::X509;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::certificate;
+use crate::predicate::PredicateType;
+
+/// Matches a string either exactly or against a regular expression.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn regex(pattern: &str) -> Result<Self, anyhow::Error> {
+        Ok(Matcher::Regex(Regex::new(pattern)?))
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Matcher::Exact(expected) => expected == value,
+            Matcher::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl std::fmt::Display for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Matcher::Exact(expected) => write!(f, "{}", expected),
+            Matcher::Regex(regex) => write!(f, "/{}/", regex),
+        }
+    }
+}
+
+/// Who a certificate must have been issued to, and by which OIDC issuer.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityPolicy {
+    pub identity: Option<Matcher>,
+    pub issuer: Option<Matcher>,
+}
+
+impl IdentityPolicy {
+    /// Checks the SAN and OIDC issuer extension of `cert` against the policy.
+    pub fn verify(&self, cert: &X509) -> Result<(), anyhow::Error> {
+        if let Some(identity) = &self.identity {
+            let identities = certificate::san_identities(cert);
+            if !identities.iter().any(|i| identity.matches(i)) {
+                anyhow::bail!(
+                    "certificate identity {:?} does not match {}",
+                    identities,
+                    identity
+                );
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            let oidc_issuer = certificate::oidc_issuer(cert)?.unwrap_or_default();
+            if !issuer.matches(&oidc_issuer) {
+                anyhow::bail!(
+                    "certificate OIDC issuer {:?} does not match {}",
+                    oidc_issuer,
+                    issuer
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Acceptance rules for signatures, as read from a policy file.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Certificates have to match one of these, any identity is accepted
+    /// when there are none.
+    pub identities: Vec<IdentityPolicy>,
+    /// Annotations image signatures must have been made with.
+    pub annotations: BTreeMap<String, String>,
+    /// How long before verification signatures may have been made.
+    pub max_age: Option<Duration>,
+    /// Predicate types there must be a valid attestation of.
+    pub attestations: Vec<PredicateType>,
+    /// How many distinct identities must have signed, zero asks for a
+    /// single signature just like one.
+    pub threshold: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    identities: Vec<IdentityEntry>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    max_age: Option<MaxAge>,
+    #[serde(default)]
+    attestations: Vec<String>,
+    #[serde(default)]
+    threshold: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct IdentityEntry {
+    identity: Option<String>,
+    identity_regexp: Option<String>,
+    issuer: Option<String>,
+    issuer_regexp: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaxAge {
+    Seconds(u64),
+    Suffixed(String),
+}
+
+fn entry_matcher(
+    exact: Option<String>,
+    regexp: Option<String>,
+    name: &str,
+) -> Result<Option<Matcher>, anyhow::Error> {
+    match (exact, regexp) {
+        (Some(_), Some(_)) => anyhow::bail!("policy sets both {} and {}-regexp", name, name),
+        (Some(exact), None) => Ok(Some(Matcher::Exact(exact))),
+        (None, Some(regexp)) => Ok(Some(Matcher::regex(&regexp)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn parse_max_age(max_age: MaxAge) -> Result<Duration, anyhow::Error> {
+    match max_age {
+        MaxAge::Seconds(seconds) => Ok(Duration::from_secs(seconds)),
+        MaxAge::Suffixed(value) => parse_duration(&value),
+    }
+}
+
+/// Parses a number of seconds, or of minutes, hours or days with an `m`, `h`
+/// or `d` suffix, such as `90d`.
+pub fn parse_duration(value: &str) -> Result<Duration, anyhow::Error> {
+    let (number, unit) =
+        value.split_at(value.len() - value.trim_start_matches(char::is_numeric).len());
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => anyhow::bail!(
+            "{} is not a number of seconds, minutes, hours or days",
+            value
+        ),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{} does not start with a number", value))?;
+    let seconds = number
+        .checked_mul(unit)
+        .ok_or_else(|| anyhow::anyhow!("{} is too long a duration", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+impl Policy {
+    /// Parses a YAML policy file.
+    pub fn from_yaml(yaml: &[u8]) -> Result<Self, anyhow::Error> {
+        let file: PolicyFile = serde_yaml::from_slice(yaml)?;
+        let identities = file
+            .identities
+            .into_iter()
+            .map(|entry| {
+                Ok(IdentityPolicy {
+                    identity: entry_matcher(entry.identity, entry.identity_regexp, "identity")?,
+                    issuer: entry_matcher(entry.issuer, entry.issuer_regexp, "issuer")?,
+                })
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Policy {
+            identities,
+            annotations: file.annotations,
+            max_age: file.max_age.map(parse_max_age).transpose()?,
+            attestations: file
+                .attestations
+                .iter()
+                .map(|predicate_type| predicate_type.parse())
+                .collect::<Result<_, _>>()?,
+            threshold: file.threshold,
+        })
+    }
+
+    /// Checks that `cert` was issued to one of the allowed identities.
+    pub fn verify_identity(&self, cert: &X509) -> Result<(), anyhow::Error> {
+        if self.identities.is_empty()
+            || self
+                .identities
+                .iter()
+                .any(|identity| identity.verify(cert).is_ok())
+        {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "certificate identity {:?} is not one the policy allows",
+            certificate::san_identities(cert)
+        )
+    }
+
+    /// Checks that a signature made at `signed_at`, in seconds since the
+    /// epoch, is recent enough at `now`.
+    pub fn check_age(&self, signed_at: i64, now: i64) -> Result<(), anyhow::Error> {
+        if let Some(max_age) = self.max_age {
+            let age = now.saturating_sub(signed_at);
+            if age > i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX) {
+                anyhow::bail!(
+                    "signature was made {}s ago, the policy accepts at most {}s",
+                    age,
+                    max_age.as_secs()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the signatures verified were made by enough distinct
+    /// `identities`.
+    pub fn check_threshold(&self, identities: &BTreeSet<String>) -> Result<(), anyhow::Error> {
+        let threshold = self.threshold.max(1);
+        if identities.len() < threshold {
+            anyhow::bail!(
+                "signed by {} identities, the policy requires {}",
+                identities.len(),
+                threshold
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the identity of `cert` and that the signature made at
+    /// `signed_at` is recent enough now.
+    pub fn verify(&self, cert: &X509, signed_at: i64) -> Result<(), anyhow::Error> {
+        self.verify_identity(cert)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.check_age(signed_at, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_cert() -> X509 {
+        X509::from_pem(&std::fs::read("test_data/signing_cert.pem").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_identity_policy() {
+        let cert = signing_cert();
+        assert!(IdentityPolicy::default().verify(&cert).is_ok());
+
+        let policy = IdentityPolicy {
+            identity: Some(Matcher::Exact(String::from("ferris@example.com"))),
+            issuer: Some(Matcher::regex(r"^https://accounts\.example\.com$").unwrap()),
+        };
+        assert!(policy.verify(&cert).is_ok());
+
+        let policy = IdentityPolicy {
+            identity: Some(Matcher::regex(r"@sigstore\.dev$").unwrap()),
+            issuer: None,
+        };
+        assert!(policy.verify(&cert).is_err());
+
+        let policy = IdentityPolicy {
+            identity: None,
+            issuer: Some(Matcher::Exact(String::from(
+                "https://github.com/login/oauth",
+            ))),
+        };
+        assert!(policy.verify(&cert).is_err());
+    }
+
+    #[test]
+    fn test_policy_from_yaml() {
+        let yaml = br#"
+identities:
+  - identity: someone@example.com
+  - identity: ferris@example.com
+    issuer-regexp: '^https://accounts\.example\.com$'
+annotations:
+  environment: production
+max-age: 90d
+attestations:
+  - slsaprovenance
+"#;
+        let policy = Policy::from_yaml(yaml).unwrap();
+        assert_eq!(policy.identities.len(), 2);
+        assert!(policy.verify_identity(&signing_cert()).is_ok());
+        assert_eq!(policy.annotations["environment"], "production");
+        assert_eq!(policy.max_age, Some(Duration::from_secs(90 * 24 * 60 * 60)));
+        assert_eq!(policy.attestations, vec![PredicateType::SlsaProvenance]);
+
+        assert!(policy.check_age(1000, 1000 + 90 * 24 * 60 * 60).is_ok());
+        assert!(policy.check_age(1000, 1001 + 90 * 24 * 60 * 60).is_err());
+        assert!(Policy::default().check_age(0, i64::MAX).is_ok());
+
+        let policy =
+            Policy::from_yaml(b"identities:\n  - identity: someone@example.com\n").unwrap();
+        assert!(policy.verify_identity(&signing_cert()).is_err());
+        assert_eq!(
+            Policy::from_yaml(b"max-age: 3600").unwrap().max_age,
+            Some(Duration::from_secs(3600))
+        );
+        assert!(Policy::from_yaml(b"max-age: 3w").is_err());
+        assert!(Policy::from_yaml(b"max-age: 18446744073709551615d").is_err());
+        let policy = Policy {
+            max_age: Some(parse_duration("18446744073709551615").unwrap()),
+            ..Policy::default()
+        };
+        assert!(policy.check_age(0, 1000).is_ok());
+
+        let policy = Policy::from_yaml(b"threshold: 2").unwrap();
+        let mut identities = BTreeSet::new();
+        identities.insert(String::from("ferris@example.com"));
+        assert!(policy.check_threshold(&identities).is_err());
+        assert!(Policy::default().check_threshold(&identities).is_ok());
+        identities.insert(String::from("crab@example.com"));
+        assert!(policy.check_threshold(&identities).is_ok());
+        assert!(Policy::default().check_threshold(&BTreeSet::new()).is_err());
+        assert!(Policy::from_yaml(b"identity: ferris@example.com").is_err());
+        assert!(
+            Policy::from_yaml(b"identities:\n  - identity: a\n    identity-regexp: b\n").is_err()
+        );
+    }
+}
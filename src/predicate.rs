@@ -0,0 +1,213 @@
+//! Well known attestation predicate types and predicate policies.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::slsa;
+
+/// CycloneDX SBOM predicate type.
+pub const CYCLONEDX_PREDICATE_TYPE: &str = "https://cyclonedx.org/bom";
+/// SPDX SBOM predicate type.
+pub const SPDX_PREDICATE_TYPE: &str = "https://spdx.dev/Document";
+
+/// The type of an attestation predicate, either one of the short names
+/// `slsaprovenance`, `cyclonedx` and `spdx` or a full predicate type URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PredicateType {
+    SlsaProvenance,
+    CycloneDx,
+    Spdx,
+    Custom(String),
+}
+
+impl FromStr for PredicateType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slsaprovenance" | slsa::PREDICATE_TYPE => Ok(PredicateType::SlsaProvenance),
+            "cyclonedx" | CYCLONEDX_PREDICATE_TYPE => Ok(PredicateType::CycloneDx),
+            "spdx" | "spdxjson" | SPDX_PREDICATE_TYPE => Ok(PredicateType::Spdx),
+            // anything else has to be a URI, so that mistyped short names are
+            // not silently accepted as custom types
+            _ => match url::Url::parse(s) {
+                Ok(_) => Ok(PredicateType::Custom(s.to_string())),
+                Err(_) => anyhow::bail!(
+                    "unknown predicate type {}, expected a URI or one of slsaprovenance, cyclonedx, spdx",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PredicateType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.uri())
+    }
+}
+
+impl PredicateType {
+    pub fn uri(&self) -> &str {
+        match self {
+            PredicateType::SlsaProvenance => slsa::PREDICATE_TYPE,
+            PredicateType::CycloneDx => CYCLONEDX_PREDICATE_TYPE,
+            PredicateType::Spdx => SPDX_PREDICATE_TYPE,
+            PredicateType::Custom(uri) => uri,
+        }
+    }
+
+    /// Checks that `predicate` looks like a document of this type. Only the
+    /// SBOM formats have a structure that is checked.
+    pub fn validate(&self, predicate: &serde_json::Value) -> Result<(), anyhow::Error> {
+        match self {
+            PredicateType::CycloneDx => {
+                if predicate["bomFormat"] != "CycloneDX" {
+                    anyhow::bail!("predicate is not a CycloneDX JSON document");
+                }
+                if !predicate["specVersion"].is_string() {
+                    anyhow::bail!("CycloneDX document has no specVersion");
+                }
+            }
+            PredicateType::Spdx => {
+                let version = predicate["spdxVersion"].as_str().unwrap_or_default();
+                if !version.starts_with("SPDX-") {
+                    anyhow::bail!("predicate is not an SPDX JSON document");
+                }
+                if !predicate["SPDXID"].is_string() {
+                    anyhow::bail!("SPDX document has no SPDXID");
+                }
+            }
+            PredicateType::SlsaProvenance | PredicateType::Custom(_) => {
+                if !predicate.is_object() {
+                    anyhow::bail!("predicate must be a JSON object");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// collects the places where `actual` does not contain `expected`
+fn policy_mismatches(
+    path: &str,
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+    mismatches: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual) => policy_mismatches(&path, expected, actual, mismatches),
+                    None => mismatches.push(format!("{}: expected {}, missing", path, expected)),
+                }
+            }
+        }
+        // every expected element has to be matched by some element
+        (Value::Array(expected), Value::Array(actual)) => {
+            for (index, expected) in expected.iter().enumerate() {
+                let matched = actual.iter().any(|actual| {
+                    let mut element_mismatches = Vec::new();
+                    policy_mismatches(path, expected, actual, &mut element_mismatches);
+                    element_mismatches.is_empty()
+                });
+                if !matched {
+                    mismatches.push(format!(
+                        "{}[{}]: no element matches {}",
+                        path, index, expected
+                    ));
+                }
+            }
+        }
+        (expected, actual) if expected != actual => {
+            mismatches.push(format!("{}: expected {}, found {}", path, expected, actual))
+        }
+        _ => {}
+    }
+}
+
+/// Checks that `predicate` contains everything in `policy`: objects must
+/// have at least the policy's keys with matching values, arrays must contain
+/// a match for every policy element. The error lists every mismatch.
+pub fn check_policy(
+    predicate: &serde_json::Value,
+    policy: &serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    let mut mismatches = Vec::new();
+    policy_mismatches("predicate", policy, predicate, &mut mismatches);
+    if !mismatches.is_empty() {
+        anyhow::bail!(
+            "predicate does not satisfy the policy:\n  {}",
+            mismatches.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_predicate_type_aliases() {
+        let cyclonedx: PredicateType = "cyclonedx".parse().unwrap();
+        assert_eq!(cyclonedx.uri(), CYCLONEDX_PREDICATE_TYPE);
+        assert_eq!(
+            "https://slsa.dev/provenance/v1"
+                .parse::<PredicateType>()
+                .unwrap(),
+            PredicateType::SlsaProvenance
+        );
+        assert_eq!(
+            "https://example.com/scan/v1"
+                .parse::<PredicateType>()
+                .unwrap(),
+            PredicateType::Custom(String::from("https://example.com/scan/v1"))
+        );
+        assert!("cyclonedxx".parse::<PredicateType>().is_err());
+    }
+
+    #[test]
+    fn test_check_policy() {
+        let predicate = json!({
+            "buildDefinition": { "buildType": "https://actions.github.io/buildtypes/workflow/v1" },
+            "runDetails": { "builder": { "id": "https://github.com/actions/runner" } },
+            "tags": ["release", "signed"],
+        });
+        check_policy(&predicate, &json!({ "tags": ["signed"] })).unwrap();
+        check_policy(
+            &predicate,
+            &json!({ "runDetails": { "builder": { "id": "https://github.com/actions/runner" } } }),
+        )
+        .unwrap();
+
+        let error = check_policy(
+            &predicate,
+            &json!({
+                "runDetails": { "builder": { "id": "https://evil.example.com" } },
+                "invocation": {},
+            }),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains(
+            "predicate.runDetails.builder.id: expected \"https://evil.example.com\", found \"https://github.com/actions/runner\""
+        ));
+        assert!(error.contains("predicate.invocation: expected {}, missing"));
+    }
+
+    #[test]
+    fn test_validate_sbom() {
+        let cyclonedx = json!({ "bomFormat": "CycloneDX", "specVersion": "1.5", "components": [] });
+        let spdx = json!({ "spdxVersion": "SPDX-2.3", "SPDXID": "SPDXRef-DOCUMENT" });
+        PredicateType::CycloneDx.validate(&cyclonedx).unwrap();
+        PredicateType::Spdx.validate(&spdx).unwrap();
+        assert!(PredicateType::CycloneDx.validate(&spdx).is_err());
+        assert!(PredicateType::Spdx.validate(&cyclonedx).is_err());
+    }
+}
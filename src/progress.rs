@@ -0,0 +1,55 @@
+//! Progress bars for long running operations, drawn on stderr.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+/// Hands out progress bars, or hidden ones unless enabled. The default is
+/// hidden so library users stay in control of the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Progress { enabled }
+    }
+
+    /// Progress bar over `total` bytes.
+    pub fn bytes(&self, total: u64, message: &'static str) -> ProgressBar {
+        if !self.enabled {
+            return ProgressBar::hidden();
+        }
+        let bar = ProgressBar::new(total).with_message(message);
+        // the template is fixed, it cannot fail to parse
+        if let Ok(style) = ProgressStyle::with_template(
+            "{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        ) {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        bar
+    }
+
+    /// Spinner for a step that takes an unknown amount of time, it keeps
+    /// ticking until it is finished.
+    pub fn spinner(&self, message: impl Into<String>) -> ProgressBar {
+        if !self.enabled {
+            return ProgressBar::hidden();
+        }
+        let spinner = ProgressBar::new_spinner().with_message(message.into());
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        spinner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_progress_is_hidden() {
+        let progress = Progress::default();
+        assert!(progress.bytes(42, "Hashing").is_hidden());
+        assert!(progress.spinner("Waiting").is_hidden());
+    }
+}
@@ -0,0 +1,166 @@
+//! Just enough of the protobuf wire format to read and write Sigstore
+//! bundles, which only use varint and length delimited fields.
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// The value of a field, as far as the wire format tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Varint(u64),
+    /// Bytes, a string or an embedded message.
+    Bytes(&'a [u8]),
+}
+
+/// A single field of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    pub number: u64,
+    pub value: Value<'a>,
+}
+
+impl<'a> Field<'a> {
+    pub fn varint(&self) -> Result<u64, anyhow::Error> {
+        match self.value {
+            Value::Varint(value) => Ok(value),
+            Value::Bytes(_) => anyhow::bail!("field {} is not a varint", self.number),
+        }
+    }
+
+    pub fn bytes(&self) -> Result<&'a [u8], anyhow::Error> {
+        match self.value {
+            Value::Bytes(bytes) => Ok(bytes),
+            Value::Varint(_) => anyhow::bail!("field {} is not length delimited", self.number),
+        }
+    }
+
+    pub fn string(&self) -> Result<&'a str, anyhow::Error> {
+        Ok(std::str::from_utf8(self.bytes()?)?)
+    }
+}
+
+fn read_varint(input: &[u8]) -> Result<(u64, &[u8]), anyhow::Error> {
+    let mut value = 0u64;
+    for (i, byte) in input.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, &input[i + 1..]));
+        }
+    }
+    anyhow::bail!("truncated or overlong varint")
+}
+
+/// Parses the fields of a message in the order they appear. Fixed size
+/// fields are skipped, bundles have none.
+pub fn fields(mut input: &[u8]) -> Result<Vec<Field<'_>>, anyhow::Error> {
+    let mut fields = Vec::new();
+    while !input.is_empty() {
+        let (key, rest) = read_varint(input)?;
+        let number = key >> 3;
+        let (value, rest) = match key & 0x7 {
+            WIRE_VARINT => {
+                let (value, rest) = read_varint(rest)?;
+                (Some(Value::Varint(value)), rest)
+            }
+            WIRE_LEN => {
+                let (len, rest) = read_varint(rest)?;
+                let len = usize::try_from(len)
+                    .ok()
+                    .filter(|len| *len <= rest.len())
+                    .ok_or_else(|| anyhow::anyhow!("field {} overruns its message", number))?;
+                (Some(Value::Bytes(&rest[..len])), &rest[len..])
+            }
+            WIRE_FIXED64 if rest.len() >= 8 => (None, &rest[8..]),
+            WIRE_FIXED32 if rest.len() >= 4 => (None, &rest[4..]),
+            wire_type => anyhow::bail!("unsupported wire type {} of field {}", wire_type, number),
+        };
+        if let Some(value) = value {
+            fields.push(Field { number, value });
+        }
+        input = rest;
+    }
+    Ok(fields)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Encodes a message field by field. Scalars with their default value are
+/// left out as proto3 does, embedded messages are always written.
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    pub fn varint(mut self, number: u64, value: u64) -> Self {
+        if value != 0 {
+            write_varint(&mut self.out, (number << 3) | WIRE_VARINT);
+            write_varint(&mut self.out, value);
+        }
+        self
+    }
+
+    pub fn bytes(self, number: u64, value: &[u8]) -> Self {
+        if value.is_empty() {
+            return self;
+        }
+        self.message(number, value)
+    }
+
+    pub fn string(self, number: u64, value: &str) -> Self {
+        self.bytes(number, value.as_bytes())
+    }
+
+    /// Writes an encoded embedded message.
+    pub fn message(mut self, number: u64, message: &[u8]) -> Self {
+        write_varint(&mut self.out, (number << 3) | WIRE_LEN);
+        write_varint(&mut self.out, message.len() as u64);
+        self.out.extend_from_slice(message);
+        self
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        // field 1 = 150, field 2 = "testing", from the protobuf encoding guide
+        let message = [
+            0x08, 0x96, 0x01, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g',
+        ];
+        let fields = fields(&message).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].number, 1);
+        assert_eq!(fields[0].varint().unwrap(), 150);
+        assert_eq!(fields[1].string().unwrap(), "testing");
+        assert!(fields[1].varint().is_err());
+
+        let encoded = Writer::new()
+            .varint(1, 150)
+            .string(2, "testing")
+            .varint(3, 0)
+            .bytes(4, b"")
+            .finish();
+        assert_eq!(encoded, message);
+        assert_eq!(Writer::new().message(5, b"").finish(), vec![0x2a, 0x00]);
+        assert!(super::fields(&[0x12, 0x07, b't']).is_err());
+    }
+}
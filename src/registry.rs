@@ -0,0 +1,625 @@
+//! Just enough of the OCI distribution API to resolve image references,
+//! read and write manifests and blobs, and list the referrers of a manifest.
+
+use regex::Regex;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::crypto;
+use crate::http::{self, Service};
+
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const OCI_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+pub const OCI_EMPTY: &str = "application/vnd.oci.empty.v1+json";
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+pub const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+
+const DOCKER_HUB: &str = "docker.io";
+// docker.io is only the name, the API lives elsewhere
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+
+/// An image reference such as `ghcr.io/org/app:tag` or
+/// `alpine@sha256:...`, normalized the way docker does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    /// `sha256:` prefixed manifest digest.
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(reference: &str) -> Result<Self, anyhow::Error> {
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => {
+                if !digest.starts_with("sha256:") {
+                    anyhow::bail!("unsupported digest in {}", reference);
+                }
+                (name, Some(digest.to_string()))
+            }
+            None => (reference, None),
+        };
+        // a colon after the last slash starts the tag, earlier ones are ports
+        let (name, tag) = match name.rfind(':') {
+            Some(colon) if colon > name.rfind('/').unwrap_or(0) => {
+                (&name[..colon], Some(name[colon + 1..].to_string()))
+            }
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest.to_string())
+            }
+            _ if name.contains('/') => (DOCKER_HUB.to_string(), name.to_string()),
+            _ => (DOCKER_HUB.to_string(), format!("library/{}", name)),
+        };
+        if repository.is_empty() || tag.as_deref() == Some("") {
+            anyhow::bail!("invalid image reference {}", reference);
+        }
+        let tag = match (&tag, &digest) {
+            (None, None) => Some("latest".to_string()),
+            _ => tag,
+        };
+        Ok(Reference {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// The same repository, pinned to `digest`.
+    pub fn with_digest(&self, digest: &str) -> Self {
+        Reference {
+            digest: Some(digest.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// The same repository with another `tag`.
+    pub fn with_tag(&self, tag: &str) -> Self {
+        Reference {
+            tag: Some(tag.to_string()),
+            digest: None,
+            ..self.clone()
+        }
+    }
+
+    /// `registry/repository`, without tag or digest.
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+
+    // the digest wins over the tag, it is what the reference is pinned to
+    fn manifest_reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+
+    fn base_url(&self) -> String {
+        let host = if self.registry == DOCKER_HUB {
+            DOCKER_HUB_API
+        } else {
+            &self.registry
+        };
+        // local test registries rarely have TLS
+        let scheme = if host.starts_with("localhost") || host.starts_with("127.0.0.1") {
+            "http"
+        } else {
+            "https"
+        };
+        format!("{}://{}/v2/{}", scheme, host, self.repository)
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Content descriptor as used in manifests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    /// Set on the descriptors of artifacts listed as referrers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+impl Descriptor {
+    /// Describes `data` as content of `media_type`.
+    pub fn for_data(media_type: &str, data: &[u8]) -> Self {
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest: format!("sha256:{}", crypto::sha256_hex(data)),
+            size: data.len() as u64,
+            artifact_type: None,
+            annotations: None,
+        }
+    }
+}
+
+/// An OCI image manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageManifest {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    /// The manifest this one is about, which lists it as a referrer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+impl ImageManifest {
+    pub fn new(config: Descriptor, layers: Vec<Descriptor>) -> Self {
+        ImageManifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_string()),
+            artifact_type: None,
+            config,
+            layers,
+            subject: None,
+            annotations: None,
+        }
+    }
+
+    /// Makes the manifest an artifact of `artifact_type` about `subject`.
+    pub fn with_subject(mut self, artifact_type: &str, subject: Descriptor) -> Self {
+        self.artifact_type = Some(artifact_type.to_string());
+        self.subject = Some(subject);
+        self
+    }
+}
+
+/// An OCI image index, also what the referrers API answers with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+}
+
+/// A manifest as served by the registry.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub media_type: String,
+    /// `sha256:` prefixed digest of `body`.
+    pub digest: String,
+    pub body: Vec<u8>,
+}
+
+impl Manifest {
+    pub fn image_manifest(&self) -> Result<ImageManifest, anyhow::Error> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// Describes the manifest, to point at it as a subject.
+    pub fn descriptor(&self) -> Descriptor {
+        Descriptor::for_data(&self.media_type, &self.body)
+    }
+}
+
+/// Talks to registries, getting bearer tokens as they are asked for.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    credentials: Option<(String, String)>,
+    // bearer tokens by repository
+    tokens: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client {
+            http: http::client(Service::Other),
+            credentials: None,
+            tokens: Arc::default(),
+        }
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client::default()
+    }
+
+    /// Authenticates as `username` with `password`, or a token standing in
+    /// for one.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Resolves `reference` to the digest of its manifest.
+    pub async fn resolve(&self, reference: &Reference) -> Result<String, anyhow::Error> {
+        match self.get_manifest(reference).await? {
+            Some(manifest) => Ok(manifest.digest),
+            None => anyhow::bail!("{} does not exist", reference),
+        }
+    }
+
+    /// Fetches the manifest `reference` points to, `None` if there is none.
+    pub async fn get_manifest(
+        &self,
+        reference: &Reference,
+    ) -> Result<Option<Manifest>, anyhow::Error> {
+        let url = format!(
+            "{}/manifests/{}",
+            reference.base_url(),
+            reference.manifest_reference()
+        );
+        let accept = [
+            OCI_MANIFEST,
+            OCI_INDEX,
+            DOCKER_MANIFEST,
+            DOCKER_MANIFEST_LIST,
+        ]
+        .join(", ");
+        let response = self
+            .send(reference, |http| {
+                http.request(Method::GET, &url).header("Accept", &accept)
+            })
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let media_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or(OCI_MANIFEST)
+            .to_string();
+        let body = response.bytes().await?.to_vec();
+        let digest = format!("sha256:{}", crypto::sha256_hex(&body));
+        if let Some(expected) = &reference.digest {
+            if expected != &digest {
+                anyhow::bail!(
+                    "registry served a manifest for {} with digest {}",
+                    reference,
+                    digest
+                );
+            }
+        }
+        Ok(Some(Manifest {
+            media_type,
+            digest,
+            body,
+        }))
+    }
+
+    /// Lists the manifests that name `reference`, which must be pinned to a
+    /// digest, as their subject. Only referrers of `artifact_type` are kept.
+    ///
+    /// Returns `None` if the registry does not implement the referrers API.
+    pub async fn get_referrers(
+        &self,
+        reference: &Reference,
+        artifact_type: &str,
+    ) -> Result<Option<Vec<Descriptor>>, anyhow::Error> {
+        let digest = reference
+            .digest
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("{} is not pinned to a digest", reference))?;
+        let url = format!("{}/referrers/{}", reference.base_url(), digest);
+        let response = self
+            .send(reference, |http| {
+                http.get(&url)
+                    .query(&[("artifactType", artifact_type)])
+                    .header("Accept", OCI_INDEX)
+            })
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let index: Index = response.error_for_status()?.json().await?;
+        // the filter is optional for registries, so apply it again
+        Ok(Some(
+            index
+                .manifests
+                .into_iter()
+                .filter(|referrer| referrer.artifact_type.as_deref() == Some(artifact_type))
+                .collect(),
+        ))
+    }
+
+    /// Fetches the blob with `digest` from the repository of `reference`.
+    pub async fn get_blob(
+        &self,
+        reference: &Reference,
+        digest: &str,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let url = format!("{}/blobs/{}", reference.base_url(), digest);
+        let blob = self
+            .send(reference, |http| http.get(&url))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec();
+        if format!("sha256:{}", crypto::sha256_hex(&blob)) != digest {
+            anyhow::bail!("registry served blob {} with the wrong content", digest);
+        }
+        Ok(blob)
+    }
+
+    /// Uploads `data` as a blob to the repository of `reference`, unless it
+    /// is there already.
+    pub async fn put_blob(
+        &self,
+        reference: &Reference,
+        media_type: &str,
+        data: &[u8],
+    ) -> Result<Descriptor, anyhow::Error> {
+        let descriptor = Descriptor::for_data(media_type, data);
+        let blob_url = format!("{}/blobs/{}", reference.base_url(), descriptor.digest);
+        let exists = self.send(reference, |http| http.head(&blob_url)).await?;
+        if exists.status().is_success() {
+            return Ok(descriptor);
+        }
+
+        let uploads_url = format!("{}/blobs/uploads/", reference.base_url());
+        let started = self
+            .send(reference, |http| http.post(&uploads_url))
+            .await?
+            .error_for_status()?;
+        let location = started
+            .headers()
+            .get("Location")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("registry did not say where to upload to"))?;
+        let location = started.url().join(location)?;
+        let separator = if location.query().is_some() { '&' } else { '?' };
+        let upload_url = format!("{}{}digest={}", location, separator, descriptor.digest);
+        self.send(reference, |http| {
+            http.put(&upload_url)
+                .header("Content-Type", "application/octet-stream")
+                .body(data.to_vec())
+        })
+        .await?
+        .error_for_status()?;
+        Ok(descriptor)
+    }
+
+    /// Uploads a manifest under the tag or digest of `reference`, returning
+    /// its digest.
+    pub async fn put_manifest(
+        &self,
+        reference: &Reference,
+        media_type: &str,
+        body: &[u8],
+    ) -> Result<String, anyhow::Error> {
+        let url = format!(
+            "{}/manifests/{}",
+            reference.base_url(),
+            reference.manifest_reference()
+        );
+        self.send(reference, |http| {
+            http.put(&url)
+                .header("Content-Type", media_type)
+                .body(body.to_vec())
+        })
+        .await?
+        .error_for_status()?;
+        Ok(format!("sha256:{}", crypto::sha256_hex(body)))
+    }
+
+    // sends the request, answering an authentication challenge once
+    async fn send<F>(&self, reference: &Reference, request: F) -> Result<Response, anyhow::Error>
+    where
+        F: Fn(&reqwest::Client) -> RequestBuilder,
+    {
+        let repository = reference.name();
+        let token = self.tokens.lock().unwrap().get(&repository).cloned();
+        let response = http::send(self.authorize(request(&self.http), token.as_deref())).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let challenge = response
+            .headers()
+            .get("WWW-Authenticate")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let token = match Challenge::parse(&challenge) {
+            Some(Challenge::Bearer {
+                realm,
+                service,
+                scope,
+            }) => {
+                let scope =
+                    scope.unwrap_or_else(|| format!("repository:{}:pull", reference.repository));
+                let token = self.fetch_token(&realm, service.as_deref(), &scope).await?;
+                self.tokens
+                    .lock()
+                    .unwrap()
+                    .insert(repository, token.clone());
+                Some(token)
+            }
+            Some(Challenge::Basic) if self.credentials.is_some() => None,
+            _ => return Ok(response),
+        };
+        Ok(http::send(self.authorize(request(&self.http), token.as_deref())).await?)
+    }
+
+    fn authorize(&self, request: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+        match (token, &self.credentials) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some((username, password))) => request.basic_auth(username, Some(password)),
+            (None, None) => request,
+        }
+    }
+
+    async fn fetch_token(
+        &self,
+        realm: &str,
+        service: Option<&str>,
+        scope: &str,
+    ) -> Result<String, anyhow::Error> {
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = service {
+            query.push(("service", service));
+        }
+        let mut request = self.http.get(realm).query(&query);
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response: TokenResponse = http::send(request)
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow::anyhow!("registry refused a token: {}", e))?
+            .json()
+            .await?;
+        response
+            .token
+            .or(response.access_token)
+            .ok_or_else(|| anyhow::anyhow!("registry token response has no token"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Challenge {
+    Bearer {
+        realm: String,
+        service: Option<String>,
+        scope: Option<String>,
+    },
+    Basic,
+}
+
+impl Challenge {
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.split_once(' ').unwrap_or((header, ""));
+        if scheme.eq_ignore_ascii_case("basic") {
+            return Some(Challenge::Basic);
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        let param = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+        let params: HashMap<_, _> = param
+            .captures_iter(params)
+            .map(|captures| (captures[1].to_string(), captures[2].to_string()))
+            .collect();
+        Some(Challenge::Bearer {
+            realm: params.get("realm")?.clone(),
+            service: params.get("service").cloned(),
+            scope: params.get("scope").cloned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let reference = Reference::parse("ghcr.io/org/app:v1").unwrap();
+        assert_eq!(reference.registry, "ghcr.io");
+        assert_eq!(reference.repository, "org/app");
+        assert_eq!(reference.tag.as_deref(), Some("v1"));
+        assert_eq!(reference.to_string(), "ghcr.io/org/app:v1");
+
+        let reference = Reference::parse("alpine").unwrap();
+        assert_eq!(reference.to_string(), "docker.io/library/alpine:latest");
+        assert_eq!(
+            reference.base_url(),
+            "https://registry-1.docker.io/v2/library/alpine"
+        );
+
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let reference = Reference::parse(&format!("localhost:5000/app@{}", digest)).unwrap();
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.manifest_reference(), digest);
+        assert_eq!(reference.base_url(), "http://localhost:5000/v2/app");
+
+        assert!(Reference::parse("ghcr.io/org/app:").is_err());
+        assert!(Reference::parse("app@md5:lolwut").is_err());
+    }
+
+    #[test]
+    fn test_referrer_manifest() {
+        let config = Descriptor::for_data(OCI_EMPTY, b"{}");
+        let manifest = ImageManifest::new(config.clone(), Vec::new());
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert!(json.get("subject").is_none());
+        assert!(json.get("artifactType").is_none());
+
+        let subject = Descriptor::for_data(OCI_MANIFEST, b"image");
+        let manifest = manifest.with_subject("application/example", subject.clone());
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["artifactType"], "application/example");
+        assert_eq!(json["subject"]["digest"], subject.digest.as_str());
+        assert_eq!(json["config"]["mediaType"], OCI_EMPTY);
+
+        let index: Index = serde_json::from_str(
+            r#"{"schemaVersion":2,"manifests":[{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:ab","size":1,"artifactType":"application/example"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            index.manifests[0].artifact_type.as_deref(),
+            Some("application/example")
+        );
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        assert_eq!(
+            Challenge::parse(
+                r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:org/app:pull""#
+            ),
+            Some(Challenge::Bearer {
+                realm: "https://ghcr.io/token".to_string(),
+                service: Some("ghcr.io".to_string()),
+                scope: Some("repository:org/app:pull".to_string()),
+            })
+        );
+        assert_eq!(
+            Challenge::parse(r#"Basic realm="registry""#),
+            Some(Challenge::Basic)
+        );
+        assert_eq!(Challenge::parse("Bearer service=\"ghcr.io\""), None);
+    }
+}
@@ -1,31 +1,823 @@
-use rekor::apis::{configuration::Configuration, entries_api};
-use rekor::models::{
-    hashedrekord::{AlgorithmKind, Data, Hash, PublicKey, Signature, Spec},
-    LogEntry, ProposedEntry,
-};
-use url::Url;
-
-pub async fn create_log(
-    hash: &str,
-    public_key: &str,
-    signature: &str,
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use openssl::asn1::Asn1Time;
+use openssl::pkey::{PKey, Public};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::algorithm::SigningAlgorithm;
+use crate::bundle::TransparencyLogEntry;
+use crate::canonical;
+use crate::certificate;
+use crate::http::{self, Service};
+
+/// Public Rekor instance.
+pub const REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+pub(crate) const ENTRIES_PATH: &str = "/api/v1/log/entries";
+pub(crate) const PUBLIC_KEY_PATH: &str = "/api/v1/log/publicKey";
+pub(crate) const LOG_INFO_PATH: &str = "/api/v1/log";
+const CONSISTENCY_PROOF_PATH: &str = "/api/v1/log/proof";
+const INDEX_RETRIEVE_PATH: &str = "/api/v1/index/retrieve";
+const ENTRIES_V2_PATH: &str = "/api/v2/log/entries";
+const CHECKPOINT_PATH: &str = "/checkpoint";
+
+/// The API a Rekor log is written and read through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RekorVersion {
+    /// The REST API of the Trillian backed log, with search and entries
+    /// retrievable by UUID.
+    #[default]
+    V1,
+    /// The tile backed log, which returns entries with their inclusion
+    /// proof but without a signed timestamp, and serves its tree as
+    /// tlog-tiles.
+    V2,
+}
+
+impl FromStr for RekorVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(RekorVersion::V1),
+            "v2" => Ok(RekorVersion::V2),
+            _ => anyhow::bail!("unknown Rekor API version: {}", s),
+        }
+    }
+}
+
+/// Finds out which API the log at `rekor_url` serves, v1 logs answer with
+/// their log info and v2 logs with a checkpoint.
+pub async fn probe_version(rekor_url: &str) -> Result<RekorVersion, anyhow::Error> {
+    let client = http::client(Service::Rekor);
+    let v1 = http::send(client.get(format!("{}{}", rekor_url, LOG_INFO_PATH))).await;
+    if matches!(&v1, Ok(response) if response.status().is_success()) {
+        return Ok(RekorVersion::V1);
+    }
+    let v2 = http::send(client.get(format!("{}{}", rekor_url, CHECKPOINT_PATH))).await?;
+    if v2.status().is_success() {
+        return Ok(RekorVersion::V2);
+    }
+    anyhow::bail!("{} serves neither the Rekor v1 nor the v2 API", rekor_url)
+}
+
+/// A Rekor log along with the API it speaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RekorClient {
+    pub url: String,
+    pub version: RekorVersion,
+}
+
+impl RekorClient {
+    pub fn new(url: &str, version: RekorVersion) -> Self {
+        RekorClient {
+            url: url.to_string(),
+            version,
+        }
+    }
+
+    /// Uploads a proposed entry. Entries created in a v2 log have no UUID
+    /// and come with their inclusion proof.
+    pub async fn create_log<E: ProposedEntry>(&self, spec: &E) -> Result<LogEntry, anyhow::Error> {
+        match self.version {
+            RekorVersion::V1 => create_log(&self.url, spec).await,
+            RekorVersion::V2 => create_log_v2(&self.url, spec).await,
+        }
+    }
+
+    /// The checkpoint of the current tree.
+    pub async fn checkpoint(&self) -> Result<String, anyhow::Error> {
+        match self.version {
+            RekorVersion::V1 => Ok(get_log_info(&self.url).await?.signed_tree_head),
+            RekorVersion::V2 => get_checkpoint(&self.url).await,
+        }
+    }
+}
+
+/// A transparency log entry as returned by the Rekor REST API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    /// Not part of the entry itself, Rekor keys entries by UUID.
+    #[serde(skip)]
+    pub uuid: String,
+    /// Base64 encoded canonicalized entry body.
+    pub body: String,
+    pub integrated_time: i64,
+    #[serde(rename = "logID")]
+    pub log_id: String,
+    pub log_index: i64,
+    #[serde(default)]
+    pub verification: Option<Verification>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    #[serde(default)]
+    pub inclusion_proof: Option<InclusionProof>,
+    #[serde(default)]
+    pub signed_entry_timestamp: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProof {
+    pub hashes: Vec<String>,
+    pub log_index: i64,
+    pub root_hash: String,
+    pub tree_size: i64,
+    #[serde(default)]
+    pub checkpoint: Option<String>,
+}
+
+impl LogEntry {
+    /// Decodes the entry body, returning `(kind, api_version, body)`.
+    pub fn decode_body(&self) -> Result<(String, String, serde_json::Value), anyhow::Error> {
+        let body: serde_json::Value = serde_json::from_slice(&base64::decode(&self.body)?)?;
+        let kind = body["kind"].as_str().unwrap_or_default().to_string();
+        let api_version = body["apiVersion"].as_str().unwrap_or_default().to_string();
+        Ok((kind, api_version, body))
+    }
+
+    /// Certificates the entry was signed with. Entries signed with bare
+    /// public keys have none.
+    pub fn certificates(&self) -> Result<Vec<X509>, anyhow::Error> {
+        let (kind, _, body) = self.decode_body()?;
+        let spec = &body["spec"];
+        let verifiers: Vec<&str> = match kind.as_str() {
+            "hashedrekord" | "rekord" => spec["signature"]["publicKey"]["content"]
+                .as_str()
+                .into_iter()
+                .collect(),
+            "dsse" => spec["signatures"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|signature| signature["verifier"].as_str())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut certs = Vec::new();
+        for verifier in verifiers {
+            if let Ok(cert) = X509::from_pem(&base64::decode(verifier)?) {
+                certs.push(cert);
+            }
+        }
+        Ok(certs)
+    }
+
+    /// A human readable description of the entry and its decoded body.
+    pub fn describe(&self) -> Result<String, anyhow::Error> {
+        let (kind, api_version, body) = self.decode_body()?;
+        let mut description = String::new();
+        if !self.uuid.is_empty() {
+            description += &format!("UUID:            {}\n", self.uuid);
+        }
+        description += &format!("Log index:       {}\n", self.log_index);
+        // v2 logs do not record when an entry was integrated
+        if self.integrated_time != 0 {
+            let integrated_time = Asn1Time::from_unix(self.integrated_time)?;
+            description += &format!("Integrated time: {}\n", &*integrated_time);
+        }
+        description += &format!("Kind:            {} {}\n", kind, api_version);
+        let spec = &body["spec"];
+        match kind.as_str() {
+            "hashedrekord" | "rekord" => {
+                description += &format!(
+                    "Artifact {}: {}\n",
+                    spec["data"]["hash"]["algorithm"]
+                        .as_str()
+                        .unwrap_or_default(),
+                    spec["data"]["hash"]["value"].as_str().unwrap_or_default()
+                )
+            }
+            "dsse" => {
+                description += &format!(
+                    "Payload sha256:  {}\n",
+                    spec["payloadHash"]["value"].as_str().unwrap_or_default()
+                )
+            }
+            _ => {}
+        }
+        for cert in self.certificates()? {
+            for identity in certificate::san_identities(&cert) {
+                description += &format!("Identity:        {}\n", identity);
+            }
+            if let Some(issuer) = certificate::oidc_issuer(&cert)? {
+                description += &format!("OIDC issuer:     {}\n", issuer);
+            }
+        }
+        description += &format!("Body:\n{}", serde_json::to_string_pretty(spec)?);
+        Ok(description)
+    }
+}
+
+// the bundle encodes hashes and log ids in base64, the v1 api in hex
+fn base64_to_hex(value: &str) -> Result<String, anyhow::Error> {
+    Ok(HEXLOWER.encode(&base64::decode(value)?))
+}
+
+impl LogEntry {
+    /// Converts an entry in the protobuf-specs encoding, as Rekor v2 returns
+    /// them, to the v1 representation. The entry has no UUID.
+    pub fn from_transparency_log_entry(
+        entry: &TransparencyLogEntry,
+    ) -> Result<Self, anyhow::Error> {
+        let inclusion_proof = match &entry.inclusion_proof {
+            Some(proof) => Some(InclusionProof {
+                hashes: proof
+                    .hashes
+                    .iter()
+                    .map(|hash| base64_to_hex(hash))
+                    .collect::<Result<_, _>>()?,
+                log_index: proof.log_index.parse()?,
+                root_hash: base64_to_hex(&proof.root_hash)?,
+                tree_size: proof.tree_size.parse()?,
+                checkpoint: proof
+                    .checkpoint
+                    .as_ref()
+                    .map(|checkpoint| checkpoint.envelope.clone()),
+            }),
+            None => None,
+        };
+        Ok(LogEntry {
+            uuid: String::new(),
+            body: entry.canonicalized_body.clone(),
+            integrated_time: entry.integrated_time.parse()?,
+            log_id: base64_to_hex(&entry.log_id.key_id)?,
+            log_index: entry.log_index.parse()?,
+            verification: Some(Verification {
+                inclusion_proof,
+                signed_entry_timestamp: entry
+                    .inclusion_promise
+                    .as_ref()
+                    .map(|promise| promise.signed_entry_timestamp.clone()),
+            }),
+        })
+    }
+}
+
+// rekor returns entries as a map of uuid to entry
+fn single_entry(entries: HashMap<String, LogEntry>) -> Result<LogEntry, anyhow::Error> {
+    let (uuid, mut log_entry) = entries
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Rekor returned no log entry"))?;
+    log_entry.uuid = uuid;
+    Ok(log_entry)
+}
+
+/// The spec of an entry kind that can be proposed to Rekor.
+pub trait ProposedEntry: Serialize {
+    const KIND: &'static str;
+    const API_VERSION: &'static str;
+
+    /// The request creating the same entry in a v2 log, which only takes
+    /// hashedrekord and dsse entries.
+    fn v2_request(&self) -> Result<Value, anyhow::Error> {
+        anyhow::bail!("Rekor v2 does not accept {} entries", Self::KIND)
+    }
+}
+
+// v2 logs want the key or certificate in DER along with the signature
+// scheme it is used with
+fn v2_verifier(public_key: &str) -> Result<Value, anyhow::Error> {
+    let pem = base64::decode(public_key)?;
+    let (mut verifier, key) = match X509::from_pem(&pem) {
+        Ok(cert) => (
+            json!({"x509Certificate": {"rawBytes": base64::encode(cert.to_der()?)}}),
+            cert.public_key()?,
+        ),
+        Err(_) => {
+            let key = PKey::public_key_from_pem(&pem)?;
+            (
+                json!({"publicKey": {"rawBytes": base64::encode(key.public_key_to_der()?)}}),
+                key,
+            )
+        }
+    };
+    verifier["keyDetails"] = Value::from(key_details(&key)?);
+    Ok(verifier)
+}
+
+// protobuf-specs PublicKeyDetails of the keys ferris-sign signs with
+fn key_details(key: &PKey<Public>) -> Result<String, anyhow::Error> {
+    let details = match SigningAlgorithm::for_key(key)? {
+        SigningAlgorithm::EcdsaP256Sha256 => String::from("PKIX_ECDSA_P256_SHA_256"),
+        SigningAlgorithm::EcdsaP384Sha384 => String::from("PKIX_ECDSA_P384_SHA_384"),
+        SigningAlgorithm::Ed25519 => String::from("PKIX_ED25519"),
+        SigningAlgorithm::RsaPssSha256 => format!("PKIX_RSA_PSS_{}_SHA256", key.bits()),
+    };
+    Ok(details)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hash {
+    pub algorithm: String,
+    pub value: String,
+}
+
+/// Spec of a hashedrekord entry, a signature over an artifact digest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashedRekord {
+    pub data: HashedRekordData,
+    pub signature: HashedRekordSignature,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashedRekordData {
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashedRekordSignature {
+    /// Base64 encoded signature.
+    pub content: String,
+    pub public_key: PublicKeyContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeyContent {
+    /// Base64 encoded PEM public key or certificate.
+    pub content: String,
+}
+
+impl HashedRekord {
+    /// A hashedrekord for the artifact with the given hex `hash`, made with
+    /// `algorithm` such as `sha256`. `public_key` and `signature` are base64
+    /// encoded.
+    pub fn new(algorithm: &str, hash: &str, public_key: &str, signature: &str) -> Self {
+        HashedRekord {
+            data: HashedRekordData {
+                hash: Hash {
+                    algorithm: algorithm.to_string(),
+                    value: hash.to_string(),
+                },
+            },
+            signature: HashedRekordSignature {
+                content: signature.to_string(),
+                public_key: PublicKeyContent {
+                    content: public_key.to_string(),
+                },
+            },
+        }
+    }
+}
+
+impl ProposedEntry for HashedRekord {
+    const KIND: &'static str = "hashedrekord";
+    const API_VERSION: &'static str = "0.0.1";
+
+    fn v2_request(&self) -> Result<Value, anyhow::Error> {
+        Ok(json!({
+            "hashedRekordRequestV002": {
+                "digest": base64::encode(HEXLOWER_PERMISSIVE.decode(self.data.hash.value.as_bytes())?),
+                "signature": {
+                    "content": self.signature.content,
+                    "verifier": v2_verifier(&self.signature.public_key.content)?,
+                },
+            }
+        }))
+    }
+}
+
+/// Spec of a rekord entry, a signature over the artifact itself rather than
+/// its digest, as made by Ed25519.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rekord {
+    pub data: RekordData,
+    pub signature: RekordSignature,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RekordData {
+    /// Base64 encoded artifact.
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RekordSignature {
+    pub format: String,
+    /// Base64 encoded signature.
+    pub content: String,
+    pub public_key: PublicKeyContent,
+}
+
+impl Rekord {
+    /// `public_key` and `signature` are base64 encoded.
+    pub fn new(artifact: &[u8], public_key: &str, signature: &str) -> Self {
+        Rekord {
+            data: RekordData {
+                content: base64::encode(artifact),
+            },
+            signature: RekordSignature {
+                format: String::from("x509"),
+                content: signature.to_string(),
+                public_key: PublicKeyContent {
+                    content: public_key.to_string(),
+                },
+            },
+        }
+    }
+}
+
+impl ProposedEntry for Rekord {
+    const KIND: &'static str = "rekord";
+    const API_VERSION: &'static str = "0.0.1";
+}
+
+/// Spec of a dsse entry, a signed DSSE envelope.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dsse {
+    pub proposed_content: DsseProposedContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DsseProposedContent {
+    /// JSON encoded envelope.
+    pub envelope: String,
+    /// Base64 encoded PEM certificates or keys the envelope was signed with.
+    pub verifiers: Vec<String>,
+}
+
+impl Dsse {
+    pub fn new(envelope: &str, verifier: &str) -> Self {
+        Dsse {
+            proposed_content: DsseProposedContent {
+                envelope: envelope.to_string(),
+                verifiers: vec![verifier.to_string()],
+            },
+        }
+    }
+}
+
+impl ProposedEntry for Dsse {
+    const KIND: &'static str = "dsse";
+    const API_VERSION: &'static str = "0.0.1";
+
+    fn v2_request(&self) -> Result<Value, anyhow::Error> {
+        // the envelope is sent as a message rather than as a string
+        let envelope: Value = serde_json::from_str(&self.proposed_content.envelope)?;
+        let verifiers = self
+            .proposed_content
+            .verifiers
+            .iter()
+            .map(|verifier| v2_verifier(verifier))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(json!({
+            "dsseRequestV002": {
+                "envelope": envelope,
+                "verifiers": verifiers,
+            }
+        }))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryRequest<'a, E> {
+    api_version: &'static str,
+    kind: &'static str,
+    spec: &'a E,
+}
+
+/// The RFC 8785 canonical JSON of the proposed entry for `spec`, as Rekor
+/// stores it.
+pub fn canonical_entry<E: ProposedEntry>(spec: &E) -> Result<String, anyhow::Error> {
+    canonical::to_string(&EntryRequest {
+        api_version: E::API_VERSION,
+        kind: E::KIND,
+        spec,
+    })
+}
+
+/// Uploads a proposed entry of any kind.
+pub async fn create_log<E: ProposedEntry>(
+    rekor_url: &str,
+    spec: &E,
 ) -> Result<LogEntry, anyhow::Error> {
-    let configuration = Configuration::default();
-
-    const KEY_FORMAT: &str = "x509";
-    const API_VERSION: &str = "0.0.1";
-    const URL: &str = "https://example.com";
-
-    let hash = Hash::new(AlgorithmKind::sha256, hash.to_string());
-    let data = Data::new(hash, Url::parse(URL)?);
-    let public_key = PublicKey::new(public_key.to_string());
-    let signature = Signature::new(KEY_FORMAT.to_string(), signature.to_string(), public_key);
-    let spec = Spec::new(signature, data);
-    let proposed_entry = ProposedEntry::Hashedrekord {
-        api_version: API_VERSION.to_string(),
-        spec: spec,
+    let client = http::client(Service::Rekor);
+    let response = http::send(
+        client
+            .post(format!("{}{}", rekor_url, ENTRIES_PATH))
+            .header("Content-Type", "application/json")
+            .body(canonical_entry(spec)?),
+    )
+    .await?;
+    // deterministic signatures make the same entry again, rekor points at
+    // the one it already has
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        if let Some(uuid) = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| location.rsplit('/').next())
+        {
+            return get_entry_by_uuid(rekor_url, uuid).await;
+        }
+    }
+    let entries = response.error_for_status()?.json().await?;
+    single_entry(entries)
+}
+
+/// Uploads a proposed entry to a v2 log.
+pub async fn create_log_v2<E: ProposedEntry>(
+    rekor_url: &str,
+    spec: &E,
+) -> Result<LogEntry, anyhow::Error> {
+    let client = http::client(Service::Rekor);
+    let entry: TransparencyLogEntry = http::send(
+        client
+            .post(format!("{}{}", rekor_url, ENTRIES_V2_PATH))
+            .header("Content-Type", "application/json")
+            .body(canonical::to_string(&spec.v2_request()?)?),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    LogEntry::from_transparency_log_entry(&entry)
+}
+
+/// Fetches the checkpoint of a v2 log, which commits to its current tree.
+pub async fn get_checkpoint(rekor_url: &str) -> Result<String, anyhow::Error> {
+    let checkpoint =
+        http::send(http::client(Service::Rekor).get(format!("{}{}", rekor_url, CHECKPOINT_PATH)))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+    Ok(checkpoint)
+}
+
+/// Fetches a log entry by its UUID.
+pub async fn get_entry_by_uuid(rekor_url: &str, uuid: &str) -> Result<LogEntry, anyhow::Error> {
+    let entries = http::send(
+        http::client(Service::Rekor).get(format!("{}{}/{}", rekor_url, ENTRIES_PATH, uuid)),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    single_entry(entries)
+}
+
+/// Fetches a log entry by its index in the log.
+pub async fn get_entry_by_index(rekor_url: &str, index: u64) -> Result<LogEntry, anyhow::Error> {
+    let entries = http::send(
+        http::client(Service::Rekor)
+            .get(format!("{}{}?logIndex={}", rekor_url, ENTRIES_PATH, index)),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    single_entry(entries)
+}
+
+/// Fetches the PEM encoded public key the log signs with.
+pub async fn get_public_key(rekor_url: &str) -> Result<String, anyhow::Error> {
+    let public_key =
+        http::send(http::client(Service::Rekor).get(format!("{}{}", rekor_url, PUBLIC_KEY_PATH)))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+    Ok(public_key)
+}
+
+/// The current state of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogInfo {
+    /// Hex encoded.
+    pub root_hash: String,
+    pub tree_size: u64,
+    /// Checkpoint for `root_hash` and `tree_size`.
+    pub signed_tree_head: String,
+    #[serde(rename = "treeID")]
+    pub tree_id: String,
+}
+
+/// Proof that a tree is a prefix of a larger one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyProof {
+    /// Hex encoded root hash of the larger tree.
+    pub root_hash: String,
+    /// Hex encoded.
+    #[serde(default)]
+    pub hashes: Vec<String>,
+}
+
+/// Fetches the tree head of the active shard of the log.
+pub async fn get_log_info(rekor_url: &str) -> Result<LogInfo, anyhow::Error> {
+    let info =
+        http::send(http::client(Service::Rekor).get(format!("{}{}", rekor_url, LOG_INFO_PATH)))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+    Ok(info)
+}
+
+/// Fetches the proof that the tree of `first_size` entries of the shard
+/// `tree_id` is a prefix of its tree of `last_size` entries.
+pub async fn get_consistency_proof(
+    rekor_url: &str,
+    first_size: u64,
+    last_size: u64,
+    tree_id: &str,
+) -> Result<ConsistencyProof, anyhow::Error> {
+    let proof = http::send(
+        http::client(Service::Rekor)
+            .get(format!("{}{}", rekor_url, CONSISTENCY_PROOF_PATH))
+            .query(&[
+                ("firstSize", first_size.to_string()),
+                ("lastSize", last_size.to_string()),
+                ("treeID", tree_id.to_string()),
+            ]),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    Ok(proof)
+}
+
+/// A query against Rekor's search index. Set fields must all match.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchIndex {
+    /// `sha256:` prefixed hex digest of an artifact.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<SearchPublicKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchPublicKey {
+    /// Key format, `x509` covers PEM keys as well as certificates.
+    pub format: String,
+    /// Base64 encoded key or certificate.
+    pub content: String,
+}
+
+/// Looks up the UUIDs of entries matching `query`.
+pub async fn search_index(
+    rekor_url: &str,
+    query: &SearchIndex,
+) -> Result<Vec<String>, anyhow::Error> {
+    let client = http::client(Service::Rekor);
+    let uuids = http::send(
+        client
+            .post(format!("{}{}", rekor_url, INDEX_RETRIEVE_PATH))
+            .json(query),
+    )
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    Ok(uuids)
+}
+
+/// Looks up the UUIDs of entries for the artifact with the given sha256
+/// `hash`.
+pub async fn search_by_hash(rekor_url: &str, hash: &str) -> Result<Vec<String>, anyhow::Error> {
+    let query = SearchIndex {
+        hash: Some(format!("sha256:{}", hash)),
+        ..SearchIndex::default()
     };
+    search_index(rekor_url, &query).await
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_hashedrekord() {
+        let entry = HashedRekord::new("sha256", "6c3b0448", "LS0tLS1CRUdJTg==", "MEUCIQ==");
+        assert_eq!(
+            canonical_entry(&entry).unwrap(),
+            r#"{"apiVersion":"0.0.1","kind":"hashedrekord","spec":{"data":{"hash":{"algorithm":"sha256","value":"6c3b0448"}},"signature":{"content":"MEUCIQ==","publicKey":{"content":"LS0tLS1CRUdJTg=="}}}}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_rekord() {
+        let entry = Rekord::new(b"lolwut", "LS0tLS1CRUdJTg==", "MEUCIQ==");
+        assert_eq!(
+            canonical_entry(&entry).unwrap(),
+            r#"{"apiVersion":"0.0.1","kind":"rekord","spec":{"data":{"content":"bG9sd3V0"},"signature":{"content":"MEUCIQ==","format":"x509","publicKey":{"content":"LS0tLS1CRUdJTg=="}}}}"#
+        );
+    }
+
+    #[test]
+    fn test_describe_entry() {
+        let cert = std::fs::read("test_data/signing_cert.pem").unwrap();
+        let spec = HashedRekord::new("sha256", "6c3b0448", &base64::encode(cert), "MEUCIQ==");
+        let log_entry = LogEntry {
+            uuid: String::from("24296fb24b8ad77a"),
+            body: base64::encode(canonical_entry(&spec).unwrap()),
+            integrated_time: 1659355200,
+            log_id: String::from("c0d23d6ad406973f"),
+            log_index: 42,
+            verification: None,
+        };
+        assert_eq!(log_entry.certificates().unwrap().len(), 1);
+        let description = log_entry.describe().unwrap();
+        assert!(description.contains("Log index:       42\n"));
+        assert!(description.contains("Kind:            hashedrekord 0.0.1\n"));
+        assert!(description.contains("Artifact sha256: 6c3b0448\n"));
+        assert!(description.contains("Identity:        ferris@example.com\n"));
+        assert!(description.contains("OIDC issuer:     https://accounts.example.com\n"));
+    }
+
+    #[test]
+    fn test_v2_request() {
+        let cert = std::fs::read("test_data/signing_cert.pem").unwrap();
+        let spec = HashedRekord::new("sha256", "6c3b0448", &base64::encode(&cert), "MEUCIQ==");
+        let request = spec.v2_request().unwrap();
+        let request = &request["hashedRekordRequestV002"];
+        assert_eq!(request["digest"], "bDsESA==");
+        assert_eq!(request["signature"]["content"], "MEUCIQ==");
+        let verifier = &request["signature"]["verifier"];
+        assert_eq!(verifier["keyDetails"], "PKIX_ECDSA_P256_SHA_256");
+        assert_eq!(
+            verifier["x509Certificate"]["rawBytes"],
+            base64::encode(X509::from_pem(&cert).unwrap().to_der().unwrap())
+        );
+
+        let envelope = r#"{"payload":"","payloadType":"text/plain","signatures":[]}"#;
+        let request = Dsse::new(envelope, &base64::encode(&cert))
+            .v2_request()
+            .unwrap();
+        assert_eq!(
+            request["dsseRequestV002"]["envelope"]["payloadType"],
+            "text/plain"
+        );
+        assert!(Rekord::new(b"lolwut", &base64::encode(&cert), "MEUCIQ==")
+            .v2_request()
+            .is_err());
+    }
+
+    #[test]
+    fn test_from_transparency_log_entry() {
+        let json = r#"{
+            "logIndex": "7",
+            "logId": {"keyId": "wNI9atQGlz8="},
+            "kindVersion": {"kind": "hashedrekord", "version": "0.0.2"},
+            "inclusionProof": {
+                "logIndex": "7",
+                "rootHash": "q80=",
+                "treeSize": "8",
+                "hashes": ["AP8="],
+                "checkpoint": {"envelope": "log.example.com\n8\nq80=\n"}
+            },
+            "canonicalizedBody": "e30="
+        }"#;
+        let entry: TransparencyLogEntry = serde_json::from_str(json).unwrap();
+        let log_entry = LogEntry::from_transparency_log_entry(&entry).unwrap();
+        assert_eq!(log_entry.log_index, 7);
+        assert_eq!(log_entry.integrated_time, 0);
+        assert_eq!(log_entry.log_id, "c0d23d6ad406973f");
+        let verification = log_entry.verification.unwrap();
+        assert!(verification.signed_entry_timestamp.is_none());
+        let proof = verification.inclusion_proof.unwrap();
+        assert_eq!(proof.root_hash, "abcd");
+        assert_eq!(proof.hashes, vec!["00ff"]);
+        assert_eq!(proof.tree_size, 8);
+    }
+
+    #[test]
+    fn test_search_index_json() {
+        let query = SearchIndex {
+            email: Some(String::from("ferris@example.com")),
+            ..SearchIndex::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&query).unwrap(),
+            r#"{"email":"ferris@example.com"}"#
+        );
+    }
 
-    let log_entry = entries_api::create_log_entry(&configuration, proposed_entry).await;
-    Ok(log_entry?)
+    #[test]
+    fn test_canonical_dsse() {
+        let entry = Dsse::new(r#"{"payload":""}"#, "LS0tLS1CRUdJTg==");
+        assert_eq!(
+            canonical_entry(&entry).unwrap(),
+            r#"{"apiVersion":"0.0.1","kind":"dsse","spec":{"proposedContent":{"envelope":"{\"payload\":\"\"}","verifiers":["LS0tLS1CRUdJTg=="]}}}"#
+        );
+    }
 }
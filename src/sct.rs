@@ -0,0 +1,147 @@
+//! Signed certificate timestamps (RFC 6962) embedded in Fulcio certificates.
+
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{PKeyRef, Public};
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+
+use crate::certificate::{self, OID_SCT_LIST};
+use crate::der;
+
+/// A single v1 signed certificate timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sct {
+    pub version: u8,
+    pub log_id: Vec<u8>,
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub extensions: Vec<u8>,
+    pub hash_algorithm: u8,
+    pub signature_algorithm: u8,
+    pub signature: Vec<u8>,
+}
+
+// cursor over the TLS encoded structures
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], anyhow::Error> {
+        if self.0.len() < n {
+            anyhow::bail!("truncated SCT");
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64, anyhow::Error> {
+        Ok(self
+            .take(n)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn vector(&mut self, length_bytes: usize) -> Result<&'a [u8], anyhow::Error> {
+        let length = self.uint(length_bytes)? as usize;
+        self.take(length)
+    }
+}
+
+fn parse_sct(data: &[u8]) -> Result<Sct, anyhow::Error> {
+    let mut reader = Reader(data);
+    Ok(Sct {
+        version: reader.uint(1)? as u8,
+        log_id: reader.take(32)?.to_vec(),
+        timestamp: reader.uint(8)?,
+        extensions: reader.vector(2)?.to_vec(),
+        hash_algorithm: reader.uint(1)? as u8,
+        signature_algorithm: reader.uint(1)? as u8,
+        signature: reader.vector(2)?.to_vec(),
+    })
+}
+
+/// Parses the value of the SCT list extension.
+pub fn parse_sct_list(extension_value: &[u8]) -> Result<Vec<Sct>, anyhow::Error> {
+    // the extension value is an octet string wrapping the TLS encoded list
+    let list = der::expect(extension_value, der::TAG_OCTET_STRING)?;
+    let mut reader = Reader(list.value);
+    let mut scts = Reader(reader.vector(2)?);
+    let mut parsed = Vec::new();
+    while !scts.0.is_empty() {
+        parsed.push(parse_sct(scts.vector(2)?)?);
+    }
+    Ok(parsed)
+}
+
+/// Returns the SCTs embedded in `cert`.
+pub fn embedded_scts(cert: &X509) -> Result<Vec<Sct>, anyhow::Error> {
+    match certificate::extension(&cert.to_der()?, OID_SCT_LIST)? {
+        Some(extension) => parse_sct_list(&extension.value),
+        None => Ok(Vec::new()),
+    }
+}
+
+// digitally-signed struct for a precert_entry
+fn signed_data(sct: &Sct, issuer_key_hash: &[u8], tbs: &[u8]) -> Vec<u8> {
+    let mut data = vec![sct.version, 0]; // certificate_timestamp
+    data.extend_from_slice(&sct.timestamp.to_be_bytes());
+    data.extend_from_slice(&1u16.to_be_bytes()); // precert_entry
+    data.extend_from_slice(issuer_key_hash);
+    data.extend_from_slice(&(tbs.len() as u32).to_be_bytes()[1..]);
+    data.extend_from_slice(tbs);
+    data.extend_from_slice(&(sct.extensions.len() as u16).to_be_bytes());
+    data.extend_from_slice(&sct.extensions);
+    data
+}
+
+/// Verifies that `cert` carries a valid SCT from the CT log with public key
+/// `ctlog_key`. `issuer` is the certificate that issued `cert`.
+pub fn verify_sct(
+    cert: &X509,
+    issuer: &X509,
+    ctlog_key: &PKeyRef<Public>,
+) -> Result<Sct, anyhow::Error> {
+    let log_id = hash(MessageDigest::sha256(), &ctlog_key.public_key_to_der()?)?;
+    let sct = embedded_scts(cert)?
+        .into_iter()
+        .find(|sct| sct.log_id == log_id.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("Certificate has no SCT from the trusted CT log"))?;
+
+    let issuer_key_hash = hash(
+        MessageDigest::sha256(),
+        &issuer.public_key()?.public_key_to_der()?,
+    )?;
+    let tbs = certificate::tbs_without_extension(&cert.to_der()?, OID_SCT_LIST)?;
+
+    let mut verifier = Verifier::new(MessageDigest::sha256(), ctlog_key)?;
+    verifier.update(&signed_data(&sct, &issuer_key_hash, &tbs))?;
+    if !verifier.verify(&sct.signature)? {
+        anyhow::bail!("SCT signature verification failed");
+    }
+    Ok(sct)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sct_list() {
+        let mut sct = vec![0u8];
+        sct.extend_from_slice(&[7u8; 32]);
+        sct.extend_from_slice(&1660000000000u64.to_be_bytes());
+        sct.extend_from_slice(&[0, 0, 4, 3, 0, 2, 0xaa, 0xbb]);
+        let mut list = (sct.len() as u16).to_be_bytes().to_vec();
+        list.extend_from_slice(&sct);
+        let mut tls = (list.len() as u16).to_be_bytes().to_vec();
+        tls.extend_from_slice(&list);
+
+        let scts = parse_sct_list(&der::encode(der::TAG_OCTET_STRING, &tls)).unwrap();
+        assert_eq!(scts.len(), 1);
+        assert_eq!(scts[0].log_id, vec![7u8; 32]);
+        assert_eq!(scts[0].timestamp, 1660000000000);
+        assert_eq!(scts[0].hash_algorithm, 4);
+        assert_eq!(scts[0].signature_algorithm, 3);
+        assert_eq!(scts[0].signature, vec![0xaa, 0xbb]);
+    }
+}
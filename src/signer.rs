@@ -0,0 +1,1372 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::encode;
+use data_encoding::HEXLOWER;
+use openssl::pkey::{PKey, Private, Public};
+use openssl::x509::{X509VerifyResult, X509};
+
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+use crate::bundle::{Bundle, HashOutput};
+use crate::dsse::Envelope;
+use crate::expiry::Claim;
+use crate::fulcio::{self, FulcioApi, SigningCertificate};
+use crate::intoto::{self, Statement};
+use crate::key::{self, SigningKey};
+use crate::oauth::{self, IdentityToken};
+use crate::progress::Progress;
+use crate::rekor_api::{
+    self, Dsse, HashedRekord, LogEntry, RekorClient, RekorVersion, Rekord, Verification,
+};
+use crate::verify::TrustRoot;
+use crate::{certificate, git, minisign, ssh, timestamp, tlog, verify};
+
+/// Signs artifacts with an ephemeral key certified by Fulcio and records the
+/// signatures in Rekor.
+#[derive(Debug, Clone)]
+pub struct KeylessSigner {
+    fulcio_url: String,
+    fulcio_api: FulcioApi,
+    rekor_url: String,
+    rekor_version: RekorVersion,
+    fulcio_chain: Vec<X509>,
+    ctlog_key: Option<PKey<Public>>,
+    rekor_key: Option<PKey<Public>>,
+    timestamp_url: Option<String>,
+    validity: Option<Duration>,
+    ssh_namespace: Option<String>,
+    upload: bool,
+    algorithm: SigningAlgorithm,
+    digest_algorithm: Option<HashAlgorithm>,
+    progress: Progress,
+}
+
+/// Everything produced by signing an attestation.
+#[derive(Debug)]
+pub struct KeylessAttestation {
+    /// DSSE envelope carrying the in-toto statement.
+    pub envelope: Envelope,
+    /// Fulcio issued signing certificate, PEM encoded.
+    pub cert_pem: String,
+    /// The rest of the chain Fulcio returned, see [`fulcio::chain_pem`].
+    pub chain: Vec<X509>,
+    /// Entry recorded in the transparency log.
+    pub log_entry: LogEntry,
+}
+
+/// Everything produced by signing a single artifact.
+#[derive(Debug)]
+pub struct KeylessSignature {
+    /// Signature over the artifact, DER encoded for ECDSA.
+    pub signature: Vec<u8>,
+    /// Fulcio issued signing certificate, PEM encoded.
+    pub cert_pem: String,
+    /// The rest of the chain Fulcio returned, see [`fulcio::chain_pem`].
+    pub chain: Vec<X509>,
+    /// Hex encoded digest of the artifact.
+    pub digest: String,
+    pub digest_algorithm: HashAlgorithm,
+    /// Entry recorded in the transparency log, none if the signer was set up
+    /// [`KeylessSigner::without_rekor`].
+    pub log_entry: Option<LogEntry>,
+    /// DER encoded RFC 3161 timestamp token over the signature, when a
+    /// timestamp authority was configured.
+    pub timestamp: Option<Vec<u8>>,
+    /// Signed expiration claim, when signatures were given a validity.
+    pub expiry: Option<Envelope>,
+    /// Armored OpenSSH signature, when the signer was given an SSH
+    /// namespace.
+    pub ssh_signature: Option<String>,
+}
+
+/// What signing an artifact would send to Fulcio and Rekor, produced
+/// without contacting either.
+#[derive(Debug)]
+pub struct DryRun {
+    /// Signature over the artifact, DER encoded for ECDSA.
+    pub signature: Vec<u8>,
+    /// Hex encoded digest of the artifact.
+    pub digest: String,
+    pub digest_algorithm: HashAlgorithm,
+    /// PEM encoded public key, the one Fulcio would be asked to certify for
+    /// keyless signing.
+    pub public_key_pem: String,
+    /// Signature over the identity proving possession of the key to the v1
+    /// Fulcio API, none when signing with a key of one's own.
+    pub proof: Option<Vec<u8>>,
+    /// PEM encoded certificate signing request for the v2 Fulcio API.
+    pub csr: Option<String>,
+    /// Canonical JSON of the entry that would be uploaded to Rekor.
+    pub proposed_entry: String,
+}
+
+impl Default for KeylessSigner {
+    fn default() -> Self {
+        KeylessSigner::new(fulcio::FULCIO_URL, rekor_api::REKOR_URL)
+    }
+}
+
+impl KeylessSigner {
+    pub fn new(fulcio_url: &str, rekor_url: &str) -> Self {
+        KeylessSigner {
+            fulcio_url: fulcio_url.to_string(),
+            fulcio_api: FulcioApi::default(),
+            rekor_url: rekor_url.to_string(),
+            rekor_version: RekorVersion::default(),
+            fulcio_chain: Vec::new(),
+            ctlog_key: None,
+            rekor_key: None,
+            timestamp_url: None,
+            validity: None,
+            ssh_namespace: None,
+            upload: true,
+            algorithm: SigningAlgorithm::default(),
+            digest_algorithm: None,
+            progress: Progress::default(),
+        }
+    }
+
+    /// Requests certificates through `fulcio_api` rather than the v2 API.
+    pub fn with_fulcio_api(mut self, fulcio_api: FulcioApi) -> Self {
+        self.fulcio_api = fulcio_api;
+        self
+    }
+
+    /// Records signatures through `rekor_version` of the Rekor API rather
+    /// than v1.
+    pub fn with_rekor_version(mut self, rekor_version: RekorVersion) -> Self {
+        self.rekor_version = rekor_version;
+        self
+    }
+
+    /// Generates ephemeral keys for `algorithm` rather than ECDSA P-256.
+    pub fn with_algorithm(mut self, algorithm: SigningAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Signs and logs artifacts by their `digest_algorithm` digest rather
+    /// than the one the signing algorithm uses by default.
+    pub fn with_digest_algorithm(mut self, digest_algorithm: HashAlgorithm) -> Self {
+        self.digest_algorithm = Some(digest_algorithm);
+        self
+    }
+
+    /// Timestamps signatures with the RFC 3161 timestamp authority at
+    /// `timestamp_url`, so they can be verified after the certificate expired.
+    pub fn with_timestamp_url(mut self, timestamp_url: &str) -> Self {
+        self.timestamp_url = Some(timestamp_url.to_string());
+        self
+    }
+
+    /// Verifies inclusion proofs against this Rekor public key. Signatures
+    /// are not uploaded without one, the log is not trusted to serve its own.
+    pub fn with_rekor_key(mut self, rekor_key: PKey<Public>) -> Self {
+        self.rekor_key = Some(rekor_key);
+        self
+    }
+
+    /// Signs an expiration claim along with artifacts, so that their
+    /// bundles stop verifying `validity` after they were signed.
+    pub fn with_validity(mut self, validity: Duration) -> Self {
+        self.validity = Some(validity);
+        self
+    }
+
+    /// Also signs artifacts in the OpenSSH format, in `namespace`, with the
+    /// same key. See [`ssh`].
+    pub fn with_ssh_namespace(mut self, namespace: &str) -> Self {
+        self.ssh_namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Does not record signatures over artifacts and git objects in Rekor,
+    /// leaving only the certificate and the signature. Such signatures
+    /// cannot be tied to a time the certificate was valid at unless they
+    /// are timestamped. Attestations are always recorded.
+    pub fn without_rekor(mut self) -> Self {
+        self.upload = false;
+        self
+    }
+
+    /// Requires certificates issued by Fulcio to carry a valid SCT from the
+    /// CT log with this public key.
+    pub fn with_ctlog_key(mut self, ctlog_key: PKey<Public>) -> Self {
+        self.ctlog_key = Some(ctlog_key);
+        self
+    }
+
+    /// Requires certificates issued by Fulcio to chain up to a root in
+    /// `fulcio_chain`.
+    pub fn with_fulcio_chain(mut self, fulcio_chain: Vec<X509>) -> Self {
+        self.fulcio_chain = fulcio_chain;
+        self
+    }
+
+    /// Requires certificates issued by Fulcio to chain up to the
+    /// certificate authorities of `trust_root`, and takes the CT log and
+    /// Rekor keys from it if it has them.
+    pub fn with_trust_root(mut self, trust_root: &TrustRoot) -> Self {
+        self.fulcio_chain = trust_root.fulcio_chain.clone();
+        if let Some(ctlog_key) = &trust_root.ctlog_key {
+            self.ctlog_key = Some(ctlog_key.clone());
+        }
+        if let Some(rekor_key) = &trust_root.rekor_key {
+            self.rekor_key = Some(rekor_key.clone());
+        }
+        self
+    }
+
+    /// Shows spinners while waiting on Fulcio and Rekor.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // generates an ephemeral key and has fulcio certify it for `identity`
+    async fn certify(
+        &self,
+        identity: &IdentityToken,
+    ) -> Result<(PKey<Private>, SigningCertificate), anyhow::Error> {
+        let (private_key, public_key_pem) = self.algorithm.generate()?;
+
+        let spinner = self
+            .progress
+            .spinner("Requesting signing certificate from Fulcio...");
+        let signing_cert = async {
+            match self.fulcio_api {
+                FulcioApi::V2 => {
+                    // the csr signature proves possession of the private key
+                    let csr = fulcio::csr(&private_key, &identity.email)?;
+                    let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+                    fulcio::request_signing_cert_v2(
+                        &self.fulcio_url,
+                        &identity.token,
+                        &csr,
+                        &public_key,
+                    )
+                    .await
+                }
+                FulcioApi::V1 => {
+                    // prove possession of the private key by signing the email
+                    let proof = self
+                        .algorithm
+                        .sign(&private_key, identity.email.as_bytes())?;
+                    fulcio::request_signing_cert(
+                        &self.fulcio_url,
+                        &identity.token,
+                        &public_key_pem,
+                        self.algorithm,
+                        &proof,
+                    )
+                    .await
+                }
+            }
+        }
+        .await;
+        spinner.finish_and_clear();
+        let signing_cert = signing_cert?;
+        let cert = X509::from_pem(signing_cert.cert_pem.as_bytes())?;
+        // fail before anything is signed or logged if the cert is not what
+        // was asked for, or was not submitted to the CT log
+        check_signing_cert(
+            &cert,
+            &signing_cert.chain,
+            &self.fulcio_chain,
+            &identity.email,
+            &private_key,
+        )?;
+        verify::verify_cert_valid_at(&cert, now()?)?;
+        if let Some(ctlog_key) = &self.ctlog_key {
+            verify::verify_embedded_sct(&cert, &signing_cert.chain, ctlog_key)?;
+        }
+        Ok((private_key, signing_cert))
+    }
+
+    /// Algorithm of the ephemeral signing keys.
+    pub fn algorithm(&self) -> SigningAlgorithm {
+        self.algorithm
+    }
+
+    /// Rekor API signatures are recorded through.
+    pub fn rekor_version(&self) -> RekorVersion {
+        self.rekor_version
+    }
+
+    /// Digest artifacts are signed and logged by.
+    pub fn digest_algorithm(&self) -> HashAlgorithm {
+        self.digest_algorithm
+            .unwrap_or_else(|| self.algorithm.hash())
+    }
+
+    /// Starts signing a batch of artifacts on behalf of `identity` with one
+    /// key and certificate, see [`KeylessSession`].
+    pub fn session(&self, identity: IdentityToken) -> KeylessSession<'_> {
+        KeylessSession {
+            signer: self,
+            identity,
+            certified: None,
+        }
+    }
+
+    /// Signs `blob` on behalf of `identity`.
+    ///
+    /// A fresh key pair is generated for every call and is discarded once the
+    /// signature has been uploaded to Rekor.
+    pub async fn sign_blob(
+        &self,
+        identity: &IdentityToken,
+        blob: &[u8],
+    ) -> Result<KeylessSignature, anyhow::Error> {
+        self.sign(identity, Artifact::Blob(blob)).await
+    }
+
+    /// Signs an artifact by its `digest` on behalf of `identity`, without the
+    /// artifact itself. Only supported by algorithms that sign digests.
+    pub async fn sign_digest(
+        &self,
+        identity: &IdentityToken,
+        digest: &[u8],
+    ) -> Result<KeylessSignature, anyhow::Error> {
+        self.sign(identity, Artifact::Digest(digest)).await
+    }
+
+    /// Signs the file at `path` on behalf of `identity`, hashing it as it is
+    /// read so that memory use does not depend on the size of the file.
+    pub async fn sign_file(
+        &self,
+        identity: &IdentityToken,
+        path: &Path,
+    ) -> Result<KeylessSignature, anyhow::Error> {
+        // ed25519 signs, and rekor logs, the whole artifact
+        if !self.algorithm.is_prehashed() {
+            return self.sign_blob(identity, &fs::read(path)?).await;
+        }
+        let digest = self.digest_algorithm().digest_file(path)?;
+        self.sign_digest(identity, &digest).await
+    }
+
+    async fn sign(
+        &self,
+        identity: &IdentityToken,
+        artifact: Artifact<'_>,
+    ) -> Result<KeylessSignature, anyhow::Error> {
+        // no point in asking fulcio for a certificate that cannot be used
+        check_artifact(self.algorithm, self.digest_algorithm(), artifact)?;
+        let (private_key, signing_cert) = self.certify(identity).await?;
+        self.sign_certified(&private_key, signing_cert, artifact)
+            .await
+    }
+
+    // signs with a key fulcio already certified
+    async fn sign_certified(
+        &self,
+        private_key: &PKey<Private>,
+        SigningCertificate { cert_pem, chain }: SigningCertificate,
+        artifact: Artifact<'_>,
+    ) -> Result<KeylessSignature, anyhow::Error> {
+        let digest_algorithm = self.digest_algorithm();
+        let (digest, signature) =
+            sign_artifact(self.algorithm, digest_algorithm, private_key, artifact)?;
+        let expiry = match self.validity {
+            Some(validity) => {
+                let now = now()?;
+                let expires = i64::try_from(validity.as_secs())
+                    .ok()
+                    .and_then(|validity| now.checked_add(validity))
+                    .ok_or_else(|| anyhow::anyhow!("{:?} is too long a validity", validity))?;
+                Some(
+                    Claim {
+                        digest: HashOutput {
+                            algorithm: digest_algorithm.bundle_name()?.to_string(),
+                            digest: base64::encode(HEXLOWER.decode(digest.as_bytes())?),
+                        },
+                        expires,
+                    }
+                    .sign(private_key)?,
+                )
+            }
+            None => None,
+        };
+        let ssh_signature = ssh_signature(
+            self.ssh_namespace.as_deref(),
+            private_key,
+            digest_algorithm,
+            &digest,
+        )?;
+        // timestamp while the certificate is still valid
+        let timestamp = match &self.timestamp_url {
+            Some(timestamp_url) => {
+                Some(timestamp::request_timestamp(timestamp_url, &signature).await?)
+            }
+            None => None,
+        };
+
+        // the certificate rather than the bare key goes into the log so the
+        // entry can be tied back to the signing identity
+        let log_entry = self
+            .upload_signature(artifact, digest_algorithm, &digest, &cert_pem, &signature)
+            .await?;
+
+        Ok(KeylessSignature {
+            signature,
+            cert_pem,
+            chain,
+            digest,
+            digest_algorithm,
+            log_entry,
+            timestamp,
+            expiry,
+            ssh_signature,
+        })
+    }
+
+    /// Generates a key and signs an artifact by its `digest` like
+    /// [`KeylessSigner::sign_digest`], without requesting a certificate or
+    /// uploading anything.
+    pub fn dry_run_digest(
+        &self,
+        identity: &IdentityToken,
+        digest: &[u8],
+    ) -> Result<DryRun, anyhow::Error> {
+        self.dry_run(identity, Artifact::Digest(digest))
+    }
+
+    /// Like [`KeylessSigner::dry_run_digest`] for the file at `path`.
+    pub fn dry_run_file(
+        &self,
+        identity: &IdentityToken,
+        path: &Path,
+    ) -> Result<DryRun, anyhow::Error> {
+        if !self.algorithm.is_prehashed() {
+            return self.dry_run(identity, Artifact::Blob(&fs::read(path)?));
+        }
+        let digest = self.digest_algorithm().digest_file(path)?;
+        self.dry_run_digest(identity, &digest)
+    }
+
+    fn dry_run(
+        &self,
+        identity: &IdentityToken,
+        artifact: Artifact<'_>,
+    ) -> Result<DryRun, anyhow::Error> {
+        let digest_algorithm = self.digest_algorithm();
+        check_artifact(self.algorithm, digest_algorithm, artifact)?;
+        let (private_key, public_key_pem) = self.algorithm.generate()?;
+        let (proof, csr) = match self.fulcio_api {
+            FulcioApi::V2 => (None, Some(fulcio::csr(&private_key, &identity.email)?)),
+            FulcioApi::V1 => (
+                Some(
+                    self.algorithm
+                        .sign(&private_key, identity.email.as_bytes())?,
+                ),
+                None,
+            ),
+        };
+        let (digest, signature) =
+            sign_artifact(self.algorithm, digest_algorithm, &private_key, artifact)?;
+        // without fulcio there is no certificate, the entry carries the key
+        // it would have certified instead
+        let proposed_entry = proposed_entry(
+            self.algorithm,
+            digest_algorithm,
+            artifact,
+            &digest,
+            &encode(&public_key_pem),
+            &signature,
+        )?;
+        Ok(DryRun {
+            signature,
+            digest,
+            digest_algorithm,
+            public_key_pem,
+            proof,
+            csr,
+            proposed_entry,
+        })
+    }
+
+    /// Signs a git commit or tag object on behalf of `identity`, returning
+    /// the DER encoded signed data git stores along with what was logged.
+    pub async fn sign_git(
+        &self,
+        identity: &IdentityToken,
+        object: &[u8],
+    ) -> Result<(Vec<u8>, KeylessSignature), anyhow::Error> {
+        // pkcs#7 signs with the default digest of the key, sha256 for p-256
+        if self.algorithm != SigningAlgorithm::EcdsaP256Sha256
+            || self.digest_algorithm() != HashAlgorithm::Sha256
+        {
+            anyhow::bail!("git signatures need an ecdsa-p256 key and sha256");
+        }
+        let digest_algorithm = self.digest_algorithm();
+        let (private_key, SigningCertificate { cert_pem, chain }) = self.certify(identity).await?;
+        let cert = X509::from_pem(cert_pem.as_bytes())?;
+        let signed_data = git::sign(&cert, &private_key, object)?;
+        let signature = git::SignedMessage::from_der(&signed_data)?.signature;
+        let digest = HEXLOWER.encode(&digest_algorithm.digest(object)?);
+
+        let log_entry = self
+            .upload_signature(
+                Artifact::Blob(object),
+                digest_algorithm,
+                &digest,
+                &cert_pem,
+                &signature,
+            )
+            .await?;
+
+        let signature = KeylessSignature {
+            signature,
+            cert_pem,
+            chain,
+            digest,
+            digest_algorithm,
+            log_entry,
+            timestamp: None,
+            expiry: None,
+            ssh_signature: None,
+        };
+        Ok((signed_data, signature))
+    }
+
+    // records a signature made with the key certified in `cert_pem`, unless
+    // uploads are turned off
+    async fn upload_signature(
+        &self,
+        artifact: Artifact<'_>,
+        digest_algorithm: HashAlgorithm,
+        digest: &str,
+        cert_pem: &str,
+        signature: &[u8],
+    ) -> Result<Option<LogEntry>, anyhow::Error> {
+        if !self.upload {
+            return Ok(None);
+        }
+        let rekor_key = required_rekor_key(self.rekor_key.as_ref())?;
+        let spinner = self.progress.spinner("Uploading signature to Rekor...");
+        let rekor = RekorClient::new(&self.rekor_url, self.rekor_version);
+        let log_entry = async {
+            let log_entry = log_signature(
+                &rekor,
+                self.algorithm,
+                digest_algorithm,
+                artifact,
+                digest,
+                &encode(cert_pem),
+                signature,
+            )
+            .await?;
+            confirm_inclusion(&rekor, rekor_key, &log_entry).await
+        }
+        .await;
+        spinner.finish_and_clear();
+        Ok(Some(log_entry?))
+    }
+
+    /// Signs an in-toto `statement` on behalf of `identity`, wrapping it in a
+    /// DSSE envelope that is recorded in Rekor as a dsse entry.
+    pub async fn sign_statement(
+        &self,
+        identity: &IdentityToken,
+        statement: &Statement,
+    ) -> Result<KeylessAttestation, anyhow::Error> {
+        let (private_key, signing_cert) = self.certify(identity).await?;
+        self.sign_statement_certified(&private_key, signing_cert, statement)
+            .await
+    }
+
+    async fn sign_statement_certified(
+        &self,
+        private_key: &PKey<Private>,
+        SigningCertificate { cert_pem, chain }: SigningCertificate,
+        statement: &Statement,
+    ) -> Result<KeylessAttestation, anyhow::Error> {
+        let envelope = Envelope::sign(intoto::PAYLOAD_TYPE, &statement.to_json()?, private_key)?;
+
+        let entry = Dsse::new(&envelope.to_json()?, &encode(&cert_pem));
+        let rekor_key = required_rekor_key(self.rekor_key.as_ref())?;
+        let spinner = self.progress.spinner("Uploading attestation to Rekor...");
+        let rekor = RekorClient::new(&self.rekor_url, self.rekor_version);
+        let log_entry = async {
+            let log_entry = rekor.create_log(&entry).await?;
+            confirm_inclusion(&rekor, rekor_key, &log_entry).await
+        }
+        .await;
+        spinner.finish_and_clear();
+        let log_entry = log_entry?;
+
+        Ok(KeylessAttestation {
+            envelope,
+            cert_pem,
+            chain,
+            log_entry,
+        })
+    }
+}
+
+/// Signs many artifacts with a single ephemeral key, so the OIDC and
+/// Fulcio round trips are made once per batch rather than once per
+/// artifact. A new key is certified when the certificate is about to expire
+/// mid-batch, with a refreshed identity token if there is a refresh token.
+#[derive(Debug)]
+pub struct KeylessSession<'a> {
+    signer: &'a KeylessSigner,
+    identity: IdentityToken,
+    certified: Option<(PKey<Private>, SigningCertificate)>,
+}
+
+impl KeylessSession<'_> {
+    /// Signs `blob` like [`KeylessSigner::sign_blob`].
+    pub async fn sign_blob(&mut self, blob: &[u8]) -> Result<KeylessSignature, anyhow::Error> {
+        self.sign(Artifact::Blob(blob)).await
+    }
+
+    /// Signs an artifact by its `digest` like [`KeylessSigner::sign_digest`].
+    pub async fn sign_digest(&mut self, digest: &[u8]) -> Result<KeylessSignature, anyhow::Error> {
+        self.sign(Artifact::Digest(digest)).await
+    }
+
+    /// Signs the file at `path` like [`KeylessSigner::sign_file`].
+    pub async fn sign_file(&mut self, path: &Path) -> Result<KeylessSignature, anyhow::Error> {
+        if !self.signer.algorithm.is_prehashed() {
+            return self.sign_blob(&fs::read(path)?).await;
+        }
+        let digest = self.signer.digest_algorithm().digest_file(path)?;
+        self.sign_digest(&digest).await
+    }
+
+    /// Signs `blobs` like [`KeylessSession::sign_blob`], up to `concurrency`
+    /// at a time. Each result is in the position of its blob, an error
+    /// signing one does not stop the others.
+    pub async fn sign_blobs(
+        &mut self,
+        blobs: Vec<Vec<u8>>,
+        concurrency: usize,
+    ) -> Result<Vec<Result<KeylessSignature, anyhow::Error>>, anyhow::Error> {
+        self.sign_all(blobs, false, concurrency).await
+    }
+
+    /// Signs artifacts by their `digests` like
+    /// [`KeylessSession::sign_digest`], up to `concurrency` at a time.
+    pub async fn sign_digests(
+        &mut self,
+        digests: Vec<Vec<u8>>,
+        concurrency: usize,
+    ) -> Result<Vec<Result<KeylessSignature, anyhow::Error>>, anyhow::Error> {
+        self.sign_all(digests, true, concurrency).await
+    }
+
+    // the certificate is checked for renewal before each group of
+    // `concurrency` artifacts, failing to certify fails all of them
+    async fn sign_all(
+        &mut self,
+        artifacts: Vec<Vec<u8>>,
+        digests: bool,
+        concurrency: usize,
+    ) -> Result<Vec<Result<KeylessSignature, anyhow::Error>>, anyhow::Error> {
+        let mut signer = self.signer.clone();
+        // spinners of concurrent uploads would draw over each other
+        if concurrency > 1 && artifacts.len() > 1 {
+            signer.progress = Progress::new(false);
+        }
+        let signer = Arc::new(signer);
+        let mut results = Vec::with_capacity(artifacts.len());
+        let mut artifacts = artifacts.into_iter().peekable();
+        while artifacts.peek().is_some() {
+            let (private_key, signing_cert) = self.certified().await?;
+            let mut tasks = Vec::with_capacity(concurrency);
+            for artifact in artifacts.by_ref().take(concurrency.max(1)) {
+                let signer = signer.clone();
+                let private_key = private_key.clone();
+                let signing_cert = signing_cert.clone();
+                tasks.push(tokio::spawn(async move {
+                    let artifact = if digests {
+                        Artifact::Digest(&artifact)
+                    } else {
+                        Artifact::Blob(&artifact)
+                    };
+                    check_artifact(signer.algorithm, signer.digest_algorithm(), artifact)?;
+                    signer
+                        .sign_certified(&private_key, signing_cert, artifact)
+                        .await
+                }));
+            }
+            for task in tasks {
+                results.push(task.await?);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Signs an in-toto `statement` like [`KeylessSigner::sign_statement`].
+    pub async fn sign_statement(
+        &mut self,
+        statement: &Statement,
+    ) -> Result<KeylessAttestation, anyhow::Error> {
+        let (private_key, signing_cert) = self.certified().await?;
+        self.signer
+            .sign_statement_certified(&private_key, signing_cert, statement)
+            .await
+    }
+
+    async fn sign(&mut self, artifact: Artifact<'_>) -> Result<KeylessSignature, anyhow::Error> {
+        check_artifact(
+            self.signer.algorithm,
+            self.signer.digest_algorithm(),
+            artifact,
+        )?;
+        let (private_key, signing_cert) = self.certified().await?;
+        self.signer
+            .sign_certified(&private_key, signing_cert, artifact)
+            .await
+    }
+
+    // the key and certificate to sign with, certifying a new key when there
+    // is none yet or the certificate expires before rekor could log the
+    // signature
+    async fn certified(&mut self) -> Result<(PKey<Private>, SigningCertificate), anyhow::Error> {
+        if let Some((private_key, signing_cert)) = &self.certified {
+            let cert = X509::from_pem(signing_cert.cert_pem.as_bytes())?;
+            if verify::verify_cert_valid_at(&cert, now()? + RENEWAL_MARGIN).is_ok() {
+                return Ok((private_key.clone(), signing_cert.clone()));
+            }
+            // the identity token is usually no longer valid either
+            if let Some(refresh) = &self.identity.refresh {
+                self.identity = oauth::refresh(refresh).await?;
+            }
+        }
+        let certified = self.signer.certify(&self.identity).await?;
+        self.certified = Some(certified.clone());
+        Ok(certified)
+    }
+}
+
+// seconds a session certificate has to remain valid for to sign with it,
+// covering the timestamp and rekor round trips
+const RENEWAL_MARGIN: i64 = 60;
+
+// an artifact to sign, either in full or only by its digest
+#[derive(Debug, Clone, Copy)]
+enum Artifact<'a> {
+    Blob(&'a [u8]),
+    Digest(&'a [u8]),
+}
+
+// prehashed schemes sign the digest itself, so any digest algorithm works
+// for them and the artifact is not needed
+fn check_artifact(
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+    artifact: Artifact<'_>,
+) -> Result<(), anyhow::Error> {
+    match artifact {
+        Artifact::Digest(digest) if digest.len() != digest_algorithm.digest_len() => {
+            anyhow::bail!(
+                "expected a {} byte {} digest, got {} bytes",
+                digest_algorithm.digest_len(),
+                digest_algorithm,
+                digest.len()
+            );
+        }
+        _ if algorithm.is_prehashed() => {}
+        Artifact::Digest(_) => {
+            anyhow::bail!("{} cannot sign a digest, it needs the artifact", algorithm);
+        }
+        Artifact::Blob(_) if digest_algorithm != HashAlgorithm::Sha256 => {
+            anyhow::bail!(
+                "{} signs the artifact itself, which Rekor identifies by its sha256",
+                algorithm
+            );
+        }
+        Artifact::Blob(_) => {}
+    }
+    Ok(())
+}
+
+// returns the hex digest and the signature
+fn sign_artifact(
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+    key: &dyn SigningKey,
+    artifact: Artifact<'_>,
+) -> Result<(String, Vec<u8>), anyhow::Error> {
+    check_artifact(algorithm, digest_algorithm, artifact)?;
+    let (digest, signature) = match artifact {
+        Artifact::Blob(blob) if !algorithm.is_prehashed() => {
+            (digest_algorithm.digest(blob)?, key.sign(blob)?)
+        }
+        Artifact::Blob(blob) => {
+            let digest = digest_algorithm.digest(blob)?;
+            let signature = key.sign_digest(digest_algorithm, &digest)?;
+            (digest, signature)
+        }
+        Artifact::Digest(digest) => (digest.to_vec(), key.sign_digest(digest_algorithm, digest)?),
+    };
+    Ok((HEXLOWER.encode(&digest), signature))
+}
+
+// signs the hex `digest` in the openssh format too, if there is a namespace
+fn ssh_signature(
+    namespace: Option<&str>,
+    key: &dyn SigningKey,
+    digest_algorithm: HashAlgorithm,
+    digest: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    match namespace {
+        Some(namespace) => Ok(Some(ssh::sign(
+            key,
+            namespace,
+            digest_algorithm,
+            &HEXLOWER.decode(digest.as_bytes())?,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+fn now() -> Result<i64, anyhow::Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+// checks the certificate fulcio issued against the trusted `fulcio_chain`,
+// and that it certifies `key` for `identity`. the roots fulcio returned are
+// never trusted, its intermediates are needed though
+fn check_signing_cert(
+    cert: &X509,
+    chain: &[X509],
+    fulcio_chain: &[X509],
+    identity: &str,
+    key: &PKey<Private>,
+) -> Result<(), anyhow::Error> {
+    // a certificate checked against what fulcio sent along is not checked
+    if fulcio_chain.is_empty() {
+        anyhow::bail!("no trusted Fulcio root to check the signing certificate against");
+    }
+    let chain: Vec<X509> = chain
+        .iter()
+        .filter(|ca| ca.issued(ca) != X509VerifyResult::OK)
+        .chain(fulcio_chain)
+        .cloned()
+        .collect();
+    if !verify::verify_cert_chain(cert, &chain)? {
+        anyhow::bail!("the signing certificate was not issued by a trusted Fulcio");
+    }
+    // workload identities are certified by a URI fulcio derives from the
+    // token, their subject is not in the certificate
+    let sans = certificate::san_identities(cert);
+    if identity.contains('@') && !sans.iter().any(|san| san == identity) {
+        anyhow::bail!(
+            "the signing certificate is for {}, not for {}",
+            sans.join(", "),
+            identity
+        );
+    }
+    if !cert.public_key()?.public_eq(key) {
+        anyhow::bail!("the signing certificate does not certify the generated key");
+    }
+    Ok(())
+}
+
+// hashedrekord entries only carry the artifact digest, schemes that sign the
+// artifact itself need a rekord entry with the whole artifact instead
+async fn log_signature(
+    rekor: &RekorClient,
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+    artifact: Artifact<'_>,
+    digest: &str,
+    public_key: &str,
+    signature: &[u8],
+) -> Result<LogEntry, anyhow::Error> {
+    match artifact {
+        Artifact::Blob(blob) if !algorithm.is_prehashed() => {
+            let entry = Rekord::new(blob, public_key, &encode(signature));
+            rekor.create_log(&entry).await
+        }
+        _ => {
+            let entry = HashedRekord::new(
+                digest_algorithm.name(),
+                digest,
+                public_key,
+                &encode(signature),
+            );
+            rekor.create_log(&entry).await
+        }
+    }
+}
+
+// the canonical json of the entry log_signature would upload
+fn proposed_entry(
+    algorithm: SigningAlgorithm,
+    digest_algorithm: HashAlgorithm,
+    artifact: Artifact<'_>,
+    digest: &str,
+    public_key: &str,
+    signature: &[u8],
+) -> Result<String, anyhow::Error> {
+    match artifact {
+        Artifact::Blob(blob) if !algorithm.is_prehashed() => {
+            rekor_api::canonical_entry(&Rekord::new(blob, public_key, &encode(signature)))
+        }
+        _ => rekor_api::canonical_entry(&HashedRekord::new(
+            digest_algorithm.name(),
+            digest,
+            public_key,
+            &encode(signature),
+        )),
+    }
+}
+
+// checks the signed entry timestamp of a freshly created entry, then
+// fetches it back with its inclusion proof and makes sure the log really
+// contains it. v2 logs return the proof right away and sign no timestamp.
+// checked before uploading, so that nothing is logged that can't be verified
+fn required_rekor_key(rekor_key: Option<&PKey<Public>>) -> Result<&PKey<Public>, anyhow::Error> {
+    rekor_key.ok_or_else(|| {
+        anyhow::anyhow!(
+            "no Rekor public key to verify log entries with, give one with with_rekor_key"
+        )
+    })
+}
+
+async fn confirm_inclusion(
+    rekor: &RekorClient,
+    rekor_key: &PKey<Public>,
+    log_entry: &LogEntry,
+) -> Result<LogEntry, anyhow::Error> {
+    if rekor.version == RekorVersion::V2 {
+        tlog::verify_log_entry_inclusion(log_entry, rekor_key)?;
+        return Ok(log_entry.clone());
+    }
+    tlog::verify_log_entry_set(log_entry, rekor_key)?;
+    let mut fetched = rekor_api::get_entry_by_uuid(&rekor.url, &log_entry.uuid).await?;
+    if fetched.body != log_entry.body {
+        anyhow::bail!("Rekor returned a different entry for {}", log_entry.uuid);
+    }
+    tlog::verify_log_entry_inclusion(&fetched, rekor_key)?;
+    keep_inclusion_promise(&mut fetched, log_entry);
+    Ok(fetched)
+}
+
+// bundles carry both the inclusion proof and the promise so they can be
+// verified offline, rekor does not always return the promise again along
+// with the proof
+fn keep_inclusion_promise(fetched: &mut LogEntry, created: &LogEntry) {
+    let promise = created
+        .verification
+        .as_ref()
+        .and_then(|verification| verification.signed_entry_timestamp.clone());
+    let verification = fetched.verification.get_or_insert(Verification {
+        inclusion_proof: None,
+        signed_entry_timestamp: None,
+    });
+    if verification.signed_entry_timestamp.is_none() {
+        verification.signed_entry_timestamp = promise;
+    }
+}
+
+/// Signs artifacts with a long lived, self managed key. Fulcio is not
+/// involved, the signature is only recorded in Rekor if a log is configured.
+#[derive(Debug, Clone)]
+pub struct KeySigner {
+    key: Arc<dyn SigningKey>,
+    algorithm: SigningAlgorithm,
+    digest_algorithm: Option<HashAlgorithm>,
+    public_key_pem: String,
+    rekor_url: Option<String>,
+    rekor_version: RekorVersion,
+    rekor_key: Option<PKey<Public>>,
+    ssh_namespace: Option<String>,
+    progress: Progress,
+}
+
+/// Everything produced by signing an artifact with a [`KeySigner`].
+#[derive(Debug)]
+pub struct KeySignature {
+    /// Signature over the artifact, DER encoded for ECDSA.
+    pub signature: Vec<u8>,
+    /// Hex encoded digest of the artifact.
+    pub digest: String,
+    pub digest_algorithm: HashAlgorithm,
+    /// Entry recorded in the transparency log, if the signature was uploaded.
+    pub log_entry: Option<LogEntry>,
+    /// Armored OpenSSH signature, when the signer was given an SSH
+    /// namespace.
+    pub ssh_signature: Option<String>,
+}
+
+impl KeySigner {
+    /// The signing algorithm follows from the type of `private_key`.
+    pub fn new(private_key: PKey<Private>) -> Result<Self, anyhow::Error> {
+        KeySigner::from_signing_key(Arc::new(private_key))
+    }
+
+    /// Signs with a key that is not held in memory, such as one on a
+    /// hardware token.
+    pub fn from_signing_key(key: Arc<dyn SigningKey>) -> Result<Self, anyhow::Error> {
+        let public_key_pem = String::from_utf8(key.public_key()?.public_key_to_pem()?)?;
+        Ok(KeySigner {
+            algorithm: key.algorithm()?,
+            digest_algorithm: None,
+            key,
+            public_key_pem,
+            rekor_url: None,
+            rekor_version: RekorVersion::default(),
+            rekor_key: None,
+            ssh_namespace: None,
+            progress: Progress::default(),
+        })
+    }
+
+    /// Loads a PEM encoded private key, either PKCS#8 or in the traditional
+    /// format. `passphrase` is only used if the key is encrypted.
+    pub fn from_pem(pem: &[u8], passphrase: Option<&[u8]>) -> Result<Self, anyhow::Error> {
+        KeySigner::new(key::private_key_from_pem(pem, passphrase)?)
+    }
+
+    /// Signs and logs artifacts by their `digest_algorithm` digest rather
+    /// than the one the key's signing algorithm uses by default.
+    pub fn with_digest_algorithm(mut self, digest_algorithm: HashAlgorithm) -> Self {
+        self.digest_algorithm = Some(digest_algorithm);
+        self
+    }
+
+    /// Records signatures in the Rekor instance at `rekor_url`.
+    pub fn with_rekor(mut self, rekor_url: &str) -> Self {
+        self.rekor_url = Some(rekor_url.to_string());
+        self
+    }
+
+    /// Records signatures through `rekor_version` of the Rekor API rather
+    /// than v1.
+    pub fn with_rekor_version(mut self, rekor_version: RekorVersion) -> Self {
+        self.rekor_version = rekor_version;
+        self
+    }
+
+    /// Verifies inclusion proofs against this Rekor public key. Signatures
+    /// are not uploaded without one, the log is not trusted to serve its own.
+    pub fn with_rekor_key(mut self, rekor_key: PKey<Public>) -> Self {
+        self.rekor_key = Some(rekor_key);
+        self
+    }
+
+    /// Also signs artifacts in the OpenSSH format, in `namespace`. See
+    /// [`ssh`].
+    pub fn with_ssh_namespace(mut self, namespace: &str) -> Self {
+        self.ssh_namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Shows a spinner while waiting on Rekor.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// The minisign signature file for `signed`, made with an Ed25519 key,
+    /// with `trusted_comment` signed along. See [`minisign`].
+    pub fn minisign(
+        &self,
+        signed: &KeySignature,
+        trusted_comment: &str,
+    ) -> Result<String, anyhow::Error> {
+        minisign::signature(self.key.as_ref(), &signed.signature, trusted_comment)
+    }
+
+    /// PEM encoded public key matching the signing key.
+    pub fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    /// Signing algorithm of the key.
+    pub fn algorithm(&self) -> SigningAlgorithm {
+        self.algorithm
+    }
+
+    /// Digest artifacts are signed and logged by.
+    pub fn digest_algorithm(&self) -> HashAlgorithm {
+        self.digest_algorithm
+            .unwrap_or_else(|| self.algorithm.hash())
+    }
+
+    /// Signs `blob`, uploading the signature along with the public key to
+    /// Rekor if a log was configured.
+    pub async fn sign_blob(&self, blob: &[u8]) -> Result<KeySignature, anyhow::Error> {
+        self.sign(Artifact::Blob(blob)).await
+    }
+
+    /// Signs an artifact by its `digest`, without the artifact itself. Only
+    /// supported by keys whose algorithm signs digests.
+    pub async fn sign_digest(&self, digest: &[u8]) -> Result<KeySignature, anyhow::Error> {
+        self.sign(Artifact::Digest(digest)).await
+    }
+
+    /// Signs the file at `path`, hashing it as it is read so that memory use
+    /// does not depend on the size of the file.
+    pub async fn sign_file(&self, path: &Path) -> Result<KeySignature, anyhow::Error> {
+        if !self.algorithm.is_prehashed() {
+            return self.sign_blob(&fs::read(path)?).await;
+        }
+        let digest = self.digest_algorithm().digest_file(path)?;
+        self.sign_digest(&digest).await
+    }
+
+    /// Signs an artifact by its `digest` like [`KeySigner::sign_digest`],
+    /// returning the entry that would be uploaded instead of uploading it.
+    pub fn dry_run_digest(&self, digest: &[u8]) -> Result<DryRun, anyhow::Error> {
+        self.dry_run(Artifact::Digest(digest))
+    }
+
+    /// Like [`KeySigner::dry_run_digest`] for the file at `path`.
+    pub fn dry_run_file(&self, path: &Path) -> Result<DryRun, anyhow::Error> {
+        if !self.algorithm.is_prehashed() {
+            return self.dry_run(Artifact::Blob(&fs::read(path)?));
+        }
+        let digest = self.digest_algorithm().digest_file(path)?;
+        self.dry_run_digest(&digest)
+    }
+
+    fn dry_run(&self, artifact: Artifact<'_>) -> Result<DryRun, anyhow::Error> {
+        let digest_algorithm = self.digest_algorithm();
+        let (digest, signature) = sign_artifact(
+            self.algorithm,
+            digest_algorithm,
+            self.key.as_ref(),
+            artifact,
+        )?;
+        let proposed_entry = proposed_entry(
+            self.algorithm,
+            digest_algorithm,
+            artifact,
+            &digest,
+            &encode(&self.public_key_pem),
+            &signature,
+        )?;
+        Ok(DryRun {
+            signature,
+            digest,
+            digest_algorithm,
+            public_key_pem: self.public_key_pem.clone(),
+            proof: None,
+            csr: None,
+            proposed_entry,
+        })
+    }
+
+    async fn sign(&self, artifact: Artifact<'_>) -> Result<KeySignature, anyhow::Error> {
+        let digest_algorithm = self.digest_algorithm();
+        let (digest, signature) = sign_artifact(
+            self.algorithm,
+            digest_algorithm,
+            self.key.as_ref(),
+            artifact,
+        )?;
+        let ssh_signature = ssh_signature(
+            self.ssh_namespace.as_deref(),
+            self.key.as_ref(),
+            digest_algorithm,
+            &digest,
+        )?;
+
+        let log_entry = match &self.rekor_url {
+            Some(rekor_url) => {
+                let rekor_key = required_rekor_key(self.rekor_key.as_ref())?;
+                let spinner = self.progress.spinner("Uploading signature to Rekor...");
+                let rekor = RekorClient::new(rekor_url, self.rekor_version);
+                let log_entry = async {
+                    let log_entry = log_signature(
+                        &rekor,
+                        self.algorithm,
+                        digest_algorithm,
+                        artifact,
+                        &digest,
+                        &encode(&self.public_key_pem),
+                        &signature,
+                    )
+                    .await?;
+                    confirm_inclusion(&rekor, rekor_key, &log_entry).await
+                }
+                .await;
+                spinner.finish_and_clear();
+                Some(log_entry?)
+            }
+            None => None,
+        };
+
+        Ok(KeySignature {
+            signature,
+            digest,
+            digest_algorithm,
+            log_entry,
+            ssh_signature,
+        })
+    }
+}
+
+impl KeylessSignature {
+    /// The entry recorded in the transparency log, for uses that need one.
+    pub fn logged_entry(&self) -> Result<&LogEntry, anyhow::Error> {
+        self.log_entry
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("the signature was not recorded in Rekor"))
+    }
+
+    /// Packages the signature, certificate and log entry as a Sigstore bundle.
+    pub fn bundle(&self) -> Result<Bundle, anyhow::Error> {
+        let cert = X509::from_pem(self.cert_pem.as_bytes())?.to_der()?;
+        let digest = HEXLOWER.decode(self.digest.as_bytes())?;
+        let mut bundle = Bundle::new(
+            &self.signature,
+            &cert,
+            self.digest_algorithm,
+            &digest,
+            self.log_entry.as_ref(),
+        )?;
+        if let Some(timestamp) = &self.timestamp {
+            bundle.add_timestamp(timestamp);
+        }
+        bundle.expiry = self.expiry.clone();
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::symm::Cipher;
+
+    #[test]
+    fn test_key_signer_from_pem() {
+        let (private_key, public_key_pem) = SigningAlgorithm::default().generate().unwrap();
+
+        let pem = private_key.private_key_to_pem_pkcs8().unwrap();
+        let signer = KeySigner::from_pem(&pem, None).unwrap();
+        assert_eq!(signer.public_key_pem(), public_key_pem);
+
+        let encrypted = private_key
+            .private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"hunter2")
+            .unwrap();
+        let signer = KeySigner::from_pem(&encrypted, Some(b"hunter2")).unwrap();
+        assert_eq!(signer.public_key_pem(), public_key_pem);
+        assert!(KeySigner::from_pem(&encrypted, Some(b"lolwut")).is_err());
+    }
+
+    #[test]
+    fn test_key_signer_dry_run() {
+        let (private_key, public_key_pem) = SigningAlgorithm::default().generate().unwrap();
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+        let signer = KeySigner::new(private_key).unwrap();
+        let digest = HashAlgorithm::Sha256.digest(b"lolwut").unwrap();
+
+        let dry_run = signer.dry_run_digest(&digest).unwrap();
+        assert!(dry_run.proof.is_none());
+        assert!(SigningAlgorithm::default()
+            .verify(&public_key, b"lolwut", &dry_run.signature)
+            .unwrap());
+        let entry: serde_json::Value = serde_json::from_str(&dry_run.proposed_entry).unwrap();
+        assert_eq!(entry["kind"], "hashedrekord");
+        assert_eq!(
+            entry["spec"]["data"]["hash"]["value"],
+            dry_run.digest.as_str()
+        );
+        assert_eq!(
+            entry["spec"]["signature"]["publicKey"]["content"],
+            encode(&public_key_pem).as_str()
+        );
+    }
+
+    #[test]
+    fn test_sign_artifact() {
+        let blob = b"lolwut";
+        let algorithm = SigningAlgorithm::EcdsaP384Sha384;
+        let (key, public_key_pem) = algorithm.generate().unwrap();
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+        let digest = HashAlgorithm::Sha384.digest(blob).unwrap();
+
+        let (hex_digest, signature) = sign_artifact(
+            algorithm,
+            HashAlgorithm::Sha384,
+            &key,
+            Artifact::Digest(&digest),
+        )
+        .unwrap();
+        assert_eq!(hex_digest, HashAlgorithm::Sha384.hex_digest(blob).unwrap());
+        assert!(algorithm.verify(&public_key, blob, &signature).unwrap());
+        let (blob_digest, _) =
+            sign_artifact(algorithm, HashAlgorithm::Sha384, &key, Artifact::Blob(blob)).unwrap();
+        assert_eq!(blob_digest, hex_digest);
+        assert!(sign_artifact(
+            algorithm,
+            HashAlgorithm::Sha256,
+            &key,
+            Artifact::Digest(&digest)
+        )
+        .is_err());
+
+        let (key, _) = SigningAlgorithm::Ed25519.generate().unwrap();
+        let digest = HashAlgorithm::Sha256.digest(blob).unwrap();
+        assert!(sign_artifact(
+            SigningAlgorithm::Ed25519,
+            HashAlgorithm::Sha256,
+            &key,
+            Artifact::Digest(&digest)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_keep_inclusion_promise() {
+        let created = LogEntry {
+            uuid: String::from("24296fb24b8ad77a"),
+            body: String::from("e30="),
+            integrated_time: 1660000000,
+            log_id: String::from("c0d23d6ad406973f"),
+            log_index: 42,
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some(String::from("MEUCIQ")),
+            }),
+        };
+        let mut fetched = LogEntry {
+            verification: None,
+            ..created.clone()
+        };
+        keep_inclusion_promise(&mut fetched, &created);
+        assert_eq!(fetched, created);
+
+        let mut fetched = LogEntry {
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some(String::from("MEQCIA")),
+            }),
+            ..created.clone()
+        };
+        keep_inclusion_promise(&mut fetched, &created);
+        let verification = fetched.verification.unwrap();
+        assert_eq!(
+            verification.signed_entry_timestamp.as_deref(),
+            Some("MEQCIA")
+        );
+    }
+
+    #[test]
+    fn test_check_signing_cert() {
+        let cert = X509::from_pem(&fs::read("test_data/signing_cert.pem").unwrap()).unwrap();
+        let chain = X509::stack_from_pem(&fs::read("test_data/fulcio_chain.pem").unwrap()).unwrap();
+        let (key, _) = SigningAlgorithm::default().generate().unwrap();
+        let check = |fulcio_chain: &[X509], identity| {
+            check_signing_cert(&cert, &chain, fulcio_chain, identity, &key)
+                .unwrap_err()
+                .to_string()
+        };
+        // the test certificate's key is not ours, everything else holds
+        assert!(check(&chain, "ferris@example.com").contains("generated key"));
+        assert!(check(&chain, "repo:org/proj:ref:refs/heads/main").contains("generated key"));
+        assert!(check(&[], "ferris@example.com").contains("no trusted Fulcio root"));
+        assert!(check(&chain, "mallory@example.com").contains("not for mallory"));
+        assert!(check(&chain[..1], "ferris@example.com").contains("trusted Fulcio"));
+    }
+}
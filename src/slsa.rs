@@ -0,0 +1,234 @@
+//! SLSA v1 provenance predicates generated from the CI environment.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Predicate type of SLSA v1 provenance.
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+
+const GITHUB_BUILD_TYPE: &str = "https://actions.github.io/buildtypes/workflow/v1";
+const GITLAB_BUILD_TYPE: &str =
+    "https://gitlab.com/gitlab-org/gitlab-runner/-/blob/main/PROVENANCE.md";
+const LOCAL_BUILD_TYPE: &str = "https://github.com/lkatalin/ferris-sign/buildtypes/local/v1";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    pub external_parameters: serde_json::Value,
+    pub internal_parameters: serde_json::Value,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceDescriptor {
+    pub uri: String,
+    pub digest: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunDetails {
+    pub builder: Builder,
+    pub metadata: BuildMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Builder {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+}
+
+fn git_dependency(uri: String, commit: Option<String>) -> Vec<ResourceDescriptor> {
+    match commit {
+        Some(commit) => {
+            let mut digest = BTreeMap::new();
+            digest.insert(String::from("gitCommit"), commit);
+            vec![ResourceDescriptor { uri, digest }]
+        }
+        None => Vec::new(),
+    }
+}
+
+impl Provenance {
+    /// Describes the current build from the process environment.
+    pub fn from_env(builder_id: Option<&str>) -> Self {
+        Provenance::from_vars(|name| std::env::var(name).ok(), builder_id)
+    }
+
+    /// Describes the build from the variables `var` returns. GitHub Actions
+    /// and GitLab CI are recognised, anything else is recorded as a local
+    /// build. `builder_id` overrides the detected builder.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>, builder_id: Option<&str>) -> Self {
+        let get = |name: &str| var(name).unwrap_or_default();
+        let mut provenance = if var("GITHUB_ACTIONS").as_deref() == Some("true") {
+            let server = var("GITHUB_SERVER_URL").unwrap_or_else(|| "https://github.com".into());
+            let repository = format!("{}/{}", server, get("GITHUB_REPOSITORY"));
+            let workflow_path = get("GITHUB_WORKFLOW_REF")
+                .split_once('@')
+                .map(|(path, _)| path.splitn(3, '/').nth(2).unwrap_or_default().to_string())
+                .unwrap_or_default();
+            Provenance {
+                build_definition: BuildDefinition {
+                    build_type: GITHUB_BUILD_TYPE.to_string(),
+                    external_parameters: serde_json::json!({
+                        "workflow": {
+                            "ref": get("GITHUB_REF"),
+                            "repository": repository,
+                            "path": workflow_path,
+                        }
+                    }),
+                    internal_parameters: serde_json::json!({
+                        "github": {
+                            "event_name": get("GITHUB_EVENT_NAME"),
+                            "repository_id": get("GITHUB_REPOSITORY_ID"),
+                            "repository_owner_id": get("GITHUB_REPOSITORY_OWNER_ID"),
+                            "runner_environment": get("RUNNER_ENVIRONMENT"),
+                        }
+                    }),
+                    resolved_dependencies: git_dependency(
+                        format!("git+{}@{}", repository, get("GITHUB_REF")),
+                        var("GITHUB_SHA"),
+                    ),
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: format!("{}/actions/runner", server),
+                    },
+                    metadata: BuildMetadata {
+                        invocation_id: Some(format!(
+                            "{}/actions/runs/{}/attempts/{}",
+                            repository,
+                            get("GITHUB_RUN_ID"),
+                            get("GITHUB_RUN_ATTEMPT")
+                        )),
+                    },
+                },
+            }
+        } else if var("GITLAB_CI").is_some() {
+            Provenance {
+                build_definition: BuildDefinition {
+                    build_type: GITLAB_BUILD_TYPE.to_string(),
+                    external_parameters: serde_json::json!({
+                        "source": get("CI_PROJECT_URL"),
+                        "entryPoint": get("CI_JOB_NAME"),
+                        "ref": get("CI_COMMIT_REF_NAME"),
+                    }),
+                    internal_parameters: serde_json::json!({
+                        "pipelineSource": get("CI_PIPELINE_SOURCE"),
+                        "projectId": get("CI_PROJECT_ID"),
+                    }),
+                    resolved_dependencies: git_dependency(
+                        format!("git+{}", get("CI_PROJECT_URL")),
+                        var("CI_COMMIT_SHA"),
+                    ),
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: format!(
+                            "{}/-/runners/{}",
+                            get("CI_PROJECT_URL"),
+                            get("CI_RUNNER_ID")
+                        ),
+                    },
+                    metadata: BuildMetadata {
+                        invocation_id: var("CI_JOB_URL"),
+                    },
+                },
+            }
+        } else {
+            Provenance {
+                build_definition: BuildDefinition {
+                    build_type: LOCAL_BUILD_TYPE.to_string(),
+                    external_parameters: serde_json::json!({}),
+                    internal_parameters: serde_json::json!({}),
+                    resolved_dependencies: Vec::new(),
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: format!(
+                            "local://{}",
+                            var("HOSTNAME").unwrap_or_else(|| "localhost".into())
+                        ),
+                    },
+                    metadata: BuildMetadata {
+                        invocation_id: None,
+                    },
+                },
+            }
+        };
+        if let Some(builder_id) = builder_id {
+            provenance.run_details.builder.id = builder_id.to_string();
+        }
+        provenance
+    }
+
+    pub fn to_value(&self) -> Result<serde_json::Value, anyhow::Error> {
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_github_provenance() {
+        let vars: HashMap<&str, &str> = [
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_REPOSITORY", "lkatalin/ferris-sign"),
+            ("GITHUB_REF", "refs/heads/main"),
+            ("GITHUB_SHA", "0b9b8a37"),
+            (
+                "GITHUB_WORKFLOW_REF",
+                "lkatalin/ferris-sign/.github/workflows/release.yml@refs/heads/main",
+            ),
+            ("GITHUB_RUN_ID", "42"),
+            ("GITHUB_RUN_ATTEMPT", "1"),
+        ]
+        .into_iter()
+        .collect();
+        let provenance = Provenance::from_vars(|name| vars.get(name).map(|v| v.to_string()), None)
+            .to_value()
+            .unwrap();
+        let definition = &provenance["buildDefinition"];
+        assert_eq!(definition["buildType"], GITHUB_BUILD_TYPE);
+        assert_eq!(
+            definition["externalParameters"]["workflow"]["path"],
+            ".github/workflows/release.yml"
+        );
+        assert_eq!(
+            definition["resolvedDependencies"][0]["digest"]["gitCommit"],
+            "0b9b8a37"
+        );
+        assert_eq!(
+            provenance["runDetails"]["metadata"]["invocationId"],
+            "https://github.com/lkatalin/ferris-sign/actions/runs/42/attempts/1"
+        );
+    }
+
+    #[test]
+    fn test_builder_id_override() {
+        let provenance = Provenance::from_vars(|_| None, Some("https://example.com/builder"));
+        assert_eq!(provenance.build_definition.build_type, LOCAL_BUILD_TYPE);
+        assert_eq!(
+            provenance.run_details.builder.id,
+            "https://example.com/builder"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! JWT-SVIDs from a SPIFFE Workload API, as a SPIRE agent serves it, so
+//! workloads in a SPIFFE mesh sign with their workload identity.
+//!
+//! The Workload API is gRPC over a unix socket. Only `FetchJWTSVID` is
+//! needed, it is called with the request framed by hand over HTTP/2 rather
+//! than through generated gRPC stubs.
+
+use std::path::PathBuf;
+
+use crate::protobuf::{self, Writer};
+
+/// Environment variable SPIFFE workloads find the Workload API through.
+pub const ENDPOINT_SOCKET_VAR: &str = "SPIFFE_ENDPOINT_SOCKET";
+
+#[cfg(unix)]
+const FETCH_JWT_SVID_PATH: &str = "/SpiffeWorkloadAPI/FetchJWTSVID";
+// the workload api refuses requests without it, so that they cannot be
+// forged by a browser
+#[cfg(unix)]
+const SECURITY_HEADER: &str = "workload.spiffe.io";
+
+/// A JWT-SVID and the SPIFFE ID it was issued to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtSvid {
+    pub spiffe_id: String,
+    pub token: String,
+}
+
+/// The socket of a `unix:///path` or `unix:/path` Workload API address.
+pub fn socket_path(address: &str) -> Result<PathBuf, anyhow::Error> {
+    let path = address
+        .strip_prefix("unix://")
+        .or_else(|| address.strip_prefix("unix:"))
+        .filter(|path| path.starts_with('/'))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "unsupported SPIFFE Workload API address {}, expected unix:///path",
+                address
+            )
+        })?;
+    Ok(PathBuf::from(path))
+}
+
+// JWTSVIDRequest, for any of the workload's SPIFFE IDs
+fn jwt_svid_request(audience: &str) -> Vec<u8> {
+    Writer::new().string(1, audience).finish()
+}
+
+// every grpc message is prefixed by a compression flag and its length
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = vec![0];
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+fn first_message(body: &[u8]) -> Result<&[u8], anyhow::Error> {
+    if body.len() < 5 {
+        anyhow::bail!("the Workload API returned no message");
+    }
+    if body[0] != 0 {
+        anyhow::bail!("the Workload API returned a compressed message");
+    }
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    body[5..]
+        .get(..len)
+        .ok_or_else(|| anyhow::anyhow!("the Workload API message is truncated"))
+}
+
+// the svids of a JWTSVIDResponse
+fn parse_jwt_svid_response(message: &[u8]) -> Result<Vec<JwtSvid>, anyhow::Error> {
+    let mut svids = Vec::new();
+    for field in protobuf::fields(message)? {
+        if field.number != 1 {
+            continue;
+        }
+        let mut svid = JwtSvid {
+            spiffe_id: String::new(),
+            token: String::new(),
+        };
+        for field in protobuf::fields(field.bytes()?)? {
+            match field.number {
+                1 => svid.spiffe_id = field.string()?.to_string(),
+                2 => svid.token = field.string()?.to_string(),
+                _ => {}
+            }
+        }
+        svids.push(svid);
+    }
+    Ok(svids)
+}
+
+#[cfg(unix)]
+fn check_status(headers: &hyper::HeaderMap) -> Result<(), anyhow::Error> {
+    match headers.get("grpc-status").map(|status| status.to_str()) {
+        Some(Ok("0")) | None => Ok(()),
+        Some(status) => anyhow::bail!(
+            "the Workload API did not issue a JWT-SVID: {} (gRPC status {})",
+            headers
+                .get("grpc-message")
+                .and_then(|message| message.to_str().ok())
+                .unwrap_or("no message"),
+            status.unwrap_or("?")
+        ),
+    }
+}
+
+/// Fetches a JWT-SVID for `audience` from the Workload API at `address`.
+/// A workload with several SPIFFE IDs gets the first, as SPIRE orders them.
+#[cfg(unix)]
+pub async fn fetch_jwt_svid(address: &str, audience: &str) -> Result<JwtSvid, anyhow::Error> {
+    use hyper::body::HttpBody;
+
+    let stream = tokio::net::UnixStream::connect(socket_path(address)?)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "could not connect to the Workload API at {}: {}",
+                address,
+                e
+            )
+        })?;
+    let (mut sender, connection) = hyper::client::conn::Builder::new()
+        .http2_only(true)
+        .handshake(stream)
+        .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            tracing::debug!("Workload API connection failed: {}", e);
+        }
+    });
+
+    let request = hyper::Request::post(format!("http://localhost{}", FETCH_JWT_SVID_PATH))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .header(SECURITY_HEADER, "true")
+        .body(hyper::Body::from(frame(&jwt_svid_request(audience))))?;
+    let response = sender.send_request(request).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("the Workload API answered {}", response.status());
+    }
+    // errors without a message come in the headers
+    check_status(response.headers())?;
+    let mut body = response.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk?);
+    }
+    if let Some(trailers) = body.trailers().await? {
+        check_status(&trailers)?;
+    }
+    parse_jwt_svid_response(first_message(&data)?)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("the Workload API returned no JWT-SVID"))
+}
+
+#[cfg(not(unix))]
+pub async fn fetch_jwt_svid(address: &str, _audience: &str) -> Result<JwtSvid, anyhow::Error> {
+    socket_path(address)?;
+    anyhow::bail!("the SPIFFE Workload API is only supported over unix sockets")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_path() {
+        assert_eq!(
+            socket_path("unix:///run/spire/sockets/agent.sock").unwrap(),
+            PathBuf::from("/run/spire/sockets/agent.sock")
+        );
+        assert_eq!(
+            socket_path("unix:/tmp/agent.sock").unwrap(),
+            PathBuf::from("/tmp/agent.sock")
+        );
+        assert!(socket_path("tcp://127.0.0.1:8081").is_err());
+        assert!(socket_path("unix:relative.sock").is_err());
+    }
+
+    #[test]
+    fn test_jwt_svid_response() {
+        let svid = |spiffe_id: &str, token: &str| {
+            Writer::new().string(1, spiffe_id).string(2, token).finish()
+        };
+        let response = Writer::new()
+            .message(1, &svid("spiffe://example.org/builder", "eyJhbGciOi.a.b"))
+            .message(1, &svid("spiffe://example.org/other", "eyJhbGciOi.c.d"))
+            .finish();
+        let svids = parse_jwt_svid_response(first_message(&frame(&response)).unwrap()).unwrap();
+        assert_eq!(
+            svids,
+            vec![
+                JwtSvid {
+                    spiffe_id: "spiffe://example.org/builder".to_string(),
+                    token: "eyJhbGciOi.a.b".to_string(),
+                },
+                JwtSvid {
+                    spiffe_id: "spiffe://example.org/other".to_string(),
+                    token: "eyJhbGciOi.c.d".to_string(),
+                },
+            ]
+        );
+
+        let request = frame(&jwt_svid_request("sigstore"));
+        let fields = protobuf::fields(first_message(&request).unwrap()).unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].string().unwrap(), "sigstore");
+        assert!(first_message(&request[..request.len() - 1]).is_err());
+    }
+}
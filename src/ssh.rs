@@ -0,0 +1,233 @@
+//! OpenSSH signatures, as `ssh-keygen -Y sign` makes them, for verifiers
+//! that only have OpenSSH.
+//!
+//! ```text
+//! ssh-keygen -Y verify -f allowed_signers -I signer -n file -s file.sshsig < file
+//! ```
+//!
+//! where `allowed_signers` holds the line `signer <public key>`, with the
+//! key from [`public_key`].
+
+use openssl::bn::{BigNumContext, BigNumRef};
+use openssl::ec::PointConversionForm;
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKeyRef, Public};
+
+use crate::algorithm::HashAlgorithm;
+use crate::key::SigningKey;
+
+/// Namespace `ssh-keygen -Y` signs and verifies files in by convention.
+pub const NAMESPACE: &str = "file";
+
+const MAGIC_PREAMBLE: &[u8] = b"SSHSIG";
+const SIG_VERSION: u32 = 1;
+const ED25519_SPKI_PREFIX_LEN: usize = 12;
+
+// ssh names a key type, curves additionally by their own identifier
+fn key_type(key: &PKeyRef<Public>) -> Result<(&'static str, Option<&'static str>), anyhow::Error> {
+    match key.id() {
+        Id::ED25519 => Ok(("ssh-ed25519", None)),
+        Id::EC => match key.ec_key()?.group().curve_name() {
+            Some(Nid::X9_62_PRIME256V1) => Ok(("ecdsa-sha2-nistp256", Some("nistp256"))),
+            Some(Nid::SECP384R1) => Ok(("ecdsa-sha2-nistp384", Some("nistp384"))),
+            _ => anyhow::bail!("SSH signatures do not support this elliptic curve"),
+        },
+        // ssh signs with rsa-sha2-512, not the RSA-PSS the signers use
+        _ => anyhow::bail!("SSH signatures need an ECDSA or Ed25519 key"),
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+// positive integers get a leading zero byte when their top bit is set
+fn put_mpint(out: &mut Vec<u8>, value: &BigNumRef) {
+    let mut bytes = value.to_vec();
+    if matches!(bytes.first(), Some(byte) if byte & 0x80 != 0) {
+        bytes.insert(0, 0);
+    }
+    put_string(out, &bytes);
+}
+
+/// The key in the SSH wire encoding.
+pub fn public_key_blob(key: &PKeyRef<Public>) -> Result<Vec<u8>, anyhow::Error> {
+    let (name, curve) = key_type(key)?;
+    let mut blob = Vec::new();
+    put_string(&mut blob, name.as_bytes());
+    match curve {
+        Some(curve) => {
+            let ec_key = key.ec_key()?;
+            let mut ctx = BigNumContext::new()?;
+            let point = ec_key.public_key().to_bytes(
+                ec_key.group(),
+                PointConversionForm::UNCOMPRESSED,
+                &mut ctx,
+            )?;
+            put_string(&mut blob, curve.as_bytes());
+            put_string(&mut blob, &point);
+        }
+        // the raw key is all of the subject public key info after its fixed
+        // algorithm identifier
+        None => put_string(
+            &mut blob,
+            &key.public_key_to_der()?[ED25519_SPKI_PREFIX_LEN..],
+        ),
+    }
+    Ok(blob)
+}
+
+/// The key as a line of `authorized_keys` or `allowed_signers`, without a
+/// principal or comment.
+pub fn public_key(key: &PKeyRef<Public>) -> Result<String, anyhow::Error> {
+    Ok(format!(
+        "{} {}",
+        key_type(key)?.0,
+        base64::encode(public_key_blob(key)?)
+    ))
+}
+
+fn hash_name(hash_algorithm: HashAlgorithm) -> Result<&'static str, anyhow::Error> {
+    match hash_algorithm {
+        HashAlgorithm::Sha256 => Ok("sha256"),
+        HashAlgorithm::Sha512 => Ok("sha512"),
+        _ => anyhow::bail!(
+            "SSH signatures are over a sha256 or sha512 digest, not {}",
+            hash_algorithm
+        ),
+    }
+}
+
+/// What the key signs for a message with the `hash_algorithm` `digest`.
+pub fn signed_data(
+    namespace: &str,
+    hash_algorithm: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut data = MAGIC_PREAMBLE.to_vec();
+    put_string(&mut data, namespace.as_bytes());
+    // reserved
+    put_string(&mut data, b"");
+    put_string(&mut data, hash_name(hash_algorithm)?.as_bytes());
+    put_string(&mut data, digest);
+    Ok(data)
+}
+
+/// Signs the message with the `hash_algorithm` `digest` with `key`,
+/// returning the armored signature.
+pub fn sign(
+    key: &dyn SigningKey,
+    namespace: &str,
+    hash_algorithm: HashAlgorithm,
+    digest: &[u8],
+) -> Result<String, anyhow::Error> {
+    let public_key = key.public_key()?;
+    let public_key_blob = public_key_blob(&public_key)?;
+    let (name, curve) = key_type(&public_key)?;
+    let signature = key.sign(&signed_data(namespace, hash_algorithm, digest)?)?;
+
+    let mut signature_blob = Vec::new();
+    put_string(&mut signature_blob, name.as_bytes());
+    match curve {
+        Some(_) => {
+            let signature = EcdsaSig::from_der(&signature)?;
+            let mut components = Vec::new();
+            put_mpint(&mut components, signature.r());
+            put_mpint(&mut components, signature.s());
+            put_string(&mut signature_blob, &components);
+        }
+        None => put_string(&mut signature_blob, &signature),
+    }
+
+    let mut blob = MAGIC_PREAMBLE.to_vec();
+    blob.extend_from_slice(&SIG_VERSION.to_be_bytes());
+    put_string(&mut blob, &public_key_blob);
+    put_string(&mut blob, namespace.as_bytes());
+    put_string(&mut blob, b"");
+    put_string(&mut blob, hash_name(hash_algorithm)?.as_bytes());
+    put_string(&mut blob, &signature_blob);
+
+    // ssh-keygen wraps at 70 columns
+    let encoded = base64::encode(blob);
+    let mut armored = String::from("-----BEGIN SSH SIGNATURE-----\n");
+    for line in encoded.as_bytes().chunks(70) {
+        armored.push_str(std::str::from_utf8(line)?);
+        armored.push('\n');
+    }
+    armored.push_str("-----END SSH SIGNATURE-----\n");
+    Ok(armored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::SigningAlgorithm;
+    use openssl::bn::BigNum;
+
+    // splits off the leading ssh string of `input`
+    fn take_string(input: &[u8]) -> (&[u8], &[u8]) {
+        let len = u32::from_be_bytes(input[..4].try_into().unwrap()) as usize;
+        (&input[4..4 + len], &input[4 + len..])
+    }
+
+    #[test]
+    fn test_sign() {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let verifying_key =
+            openssl::pkey::PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        assert!(public_key(&verifying_key)
+            .unwrap()
+            .starts_with("ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTY"));
+
+        let digest = HashAlgorithm::Sha512.digest(b"lolwut").unwrap();
+        let armored = sign(&key, NAMESPACE, HashAlgorithm::Sha512, &digest).unwrap();
+        let encoded: String = armored
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let blob = base64::decode(encoded).unwrap();
+        assert!(blob.starts_with(b"SSHSIG\0\0\0\x01"));
+
+        let (key_blob, rest) = take_string(&blob[10..]);
+        assert_eq!(key_blob, public_key_blob(&verifying_key).unwrap());
+        let (namespace, rest) = take_string(rest);
+        assert_eq!(namespace, b"file");
+        let (reserved, rest) = take_string(rest);
+        assert!(reserved.is_empty());
+        let (hash, rest) = take_string(rest);
+        assert_eq!(hash, b"sha512");
+        let (signature_blob, rest) = take_string(rest);
+        assert!(rest.is_empty());
+
+        let (name, components) = take_string(signature_blob);
+        assert_eq!(name, b"ecdsa-sha2-nistp256");
+        let (components, _) = take_string(components);
+        let (r, components) = take_string(components);
+        let (s, _) = take_string(components);
+        let signature = EcdsaSig::from_private_components(
+            BigNum::from_slice(r).unwrap(),
+            BigNum::from_slice(s).unwrap(),
+        )
+        .unwrap();
+        let data = signed_data(NAMESPACE, HashAlgorithm::Sha512, &digest).unwrap();
+        assert!(SigningAlgorithm::EcdsaP256Sha256
+            .verify(&verifying_key, &data, &signature.to_der().unwrap())
+            .unwrap());
+
+        assert!(signed_data(NAMESPACE, HashAlgorithm::Sha384, &digest).is_err());
+    }
+
+    #[test]
+    fn test_ed25519_public_key() {
+        let (key, _) = SigningAlgorithm::Ed25519.generate().unwrap();
+        let verifying_key =
+            openssl::pkey::PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        let blob = public_key_blob(&verifying_key).unwrap();
+        assert_eq!(blob.len(), 4 + 11 + 4 + 32);
+        assert!(public_key(&verifying_key)
+            .unwrap()
+            .starts_with("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5"));
+    }
+}
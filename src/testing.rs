@@ -0,0 +1,630 @@
+//! Stand-ins for Fulcio and Rekor, to test signing and verification end to
+//! end without the public instances. Only built with the `testing` feature.
+//!
+//! [`MockFulcio`] certifies the key of any certificate signing request for
+//! the email address it names, from a root of its own. [`MockRekor`] keeps
+//! its log in memory and hands out the same promises, proofs and
+//! checkpoints the real log does, so [`MockSigstore::trust_root`] verifies
+//! what was signed against the two like a bundle from production.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), anyhow::Error> {
+//! use ferris_sign::testing::{self, MockSigstore};
+//!
+//! let sigstore = MockSigstore::start().await?;
+//! let signed = sigstore
+//!     .signer()
+//!     .sign_blob(&testing::identity("ferris@example.com"), b"ohhai")
+//!     .await?;
+//! ferris_sign::verify::verify_bundle(&signed.bundle()?, b"ohhai", &sigstore.trust_root())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use data_encoding::HEXLOWER;
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private, Public};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509Builder, X509NameBuilder, X509Req, X509};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use crate::algorithm::SigningAlgorithm;
+use crate::oauth::IdentityToken;
+use crate::rekor_api::{InclusionProof, LogEntry, Verification};
+use crate::verify::TrustRoot;
+use crate::{canonical, fulcio, merkle, rekor_api, KeylessSigner};
+
+// minutes a mock certificate is valid for, like fulcio's
+const CERT_VALIDITY: i64 = 10 * 60;
+const LOG_ORIGIN: &str = "mock-rekor - 0";
+
+fn now() -> Result<i64, anyhow::Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+// errors become a 400 with the message, which the clients show
+fn respond(result: Result<ResponseTemplate, anyhow::Error>) -> ResponseTemplate {
+    result.unwrap_or_else(|e| ResponseTemplate::new(400).set_body_string(format!("{:#}", e)))
+}
+
+/// An identity token for `email`, which the mocks accept without checking.
+pub fn identity(email: &str) -> IdentityToken {
+    IdentityToken {
+        token: String::from("mock"),
+        email: email.to_string(),
+        refresh: None,
+    }
+}
+
+/// Serves the contents of `fixture` for GET requests to `request_path` on
+/// `server`, to replay responses captured from a real instance.
+pub async fn mount_fixture(
+    server: &MockServer,
+    request_path: &str,
+    fixture: impl AsRef<Path>,
+) -> Result<(), anyhow::Error> {
+    let body = std::fs::read(fixture)?;
+    Mock::given(method("GET"))
+        .and(path(request_path))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+        .mount(server)
+        .await;
+    Ok(())
+}
+
+/// A Fulcio speaking the v2 API.
+pub struct MockFulcio {
+    pub server: MockServer,
+    root: X509,
+}
+
+// the root mock certificates are issued from
+struct Ca {
+    key: PKey<Private>,
+    cert: X509,
+}
+
+impl Ca {
+    fn generate() -> Result<Self, anyhow::Error> {
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate()?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("O", "ferris-sign")?;
+        name.append_entry_by_text("CN", "mock fulcio")?;
+        let name = name.build();
+        let serial = serial()?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(1)?;
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(KeyUsage::new().critical().key_cert_sign().build()?)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+        Ok(Ca {
+            key,
+            cert: builder.build(),
+        })
+    }
+
+    // certifies the key of the csr in `request` for the email address in
+    // its common name, returning the response fulcio would
+    fn issue(&self, request: &[u8]) -> Result<Value, anyhow::Error> {
+        let request: Value = serde_json::from_slice(request)?;
+        let csr = request["certificateSigningRequest"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("no certificateSigningRequest"))?;
+        let csr = X509Req::from_pem(&base64::decode(csr)?)?;
+        let key = csr.public_key()?;
+        if !csr.verify(&key)? {
+            anyhow::bail!("the certificate signing request signature is invalid");
+        }
+        let email = csr
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the certificate signing request has no email"))?
+            .data()
+            .to_string()?;
+
+        let now = now()?;
+        let serial = serial()?;
+        let not_before = Asn1Time::from_unix(now)?;
+        let not_after = Asn1Time::from_unix(now + CERT_VALIDITY)?;
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        let san = SubjectAlternativeName::new()
+            .critical()
+            .email(&email)
+            .build(&builder.x509v3_context(Some(&self.cert), None))?;
+        builder.append_extension(san)?;
+        builder.append_extension(KeyUsage::new().critical().digital_signature().build()?)?;
+        builder.append_extension(ExtendedKeyUsage::new().code_signing().build()?)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+
+        let chain = [builder.build().to_pem()?, self.cert.to_pem()?]
+            .into_iter()
+            .map(String::from_utf8)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(json!({
+            "signedCertificateEmbeddedSct": {"chain": {"certificates": chain}}
+        }))
+    }
+}
+
+fn serial() -> Result<Asn1Integer, anyhow::Error> {
+    let mut serial = BigNum::new()?;
+    serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial.to_asn1_integer()?)
+}
+
+impl MockFulcio {
+    pub async fn start() -> Result<Self, anyhow::Error> {
+        let ca = Arc::new(Ca::generate()?);
+        let root = ca.cert.clone();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(fulcio::SIGNING_CERT_V2_PATH))
+            .respond_with(move |request: &Request| {
+                respond(
+                    ca.issue(&request.body)
+                        .map(|response| ResponseTemplate::new(201).set_body_json(response)),
+                )
+            })
+            .mount(&server)
+            .await;
+        let bundle = json!({
+            "chains": [{"certificates": [String::from_utf8(root.to_pem()?)?]}]
+        });
+        Mock::given(method("GET"))
+            .and(path(fulcio::TRUST_BUNDLE_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bundle))
+            .mount(&server)
+            .await;
+        Ok(MockFulcio { server, root })
+    }
+
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The root the certificates are issued from.
+    pub fn root(&self) -> &X509 {
+        &self.root
+    }
+}
+
+// the entries of a mock log, as the canonical bodies and integrated times
+struct Log {
+    key: PKey<Private>,
+    log_id: String,
+    entries: Mutex<Vec<(Vec<u8>, i64)>>,
+}
+
+impl Log {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        SigningAlgorithm::EcdsaP256Sha256.sign(&self.key, data)
+    }
+
+    // a checkpoint for the first `leaves`, as a signed note
+    fn checkpoint(&self, leaves: &[Vec<u8>]) -> Result<String, anyhow::Error> {
+        let note = format!(
+            "{}\n{}\n{}\n",
+            LOG_ORIGIN,
+            leaves.len(),
+            base64::encode(tree_root(leaves))
+        );
+        let mut signature = HEXLOWER.decode(self.log_id.as_bytes())?[..4].to_vec();
+        signature.extend(self.sign(note.as_bytes())?);
+        Ok(format!(
+            "{}\n\u{2014} {} {}\n",
+            note,
+            LOG_ORIGIN,
+            base64::encode(signature)
+        ))
+    }
+
+    fn leaves(&self) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mock log is poisoned"))?;
+        Ok(entries
+            .iter()
+            .map(|(body, _)| merkle::leaf_hash(body))
+            .collect())
+    }
+
+    // the uuid the proposed entry in `request` has once it is logged
+    fn uuid(request: &[u8]) -> Result<String, anyhow::Error> {
+        let body = canonical::to_vec(&serde_json::from_slice::<Value>(request)?)?;
+        Ok(HEXLOWER.encode(&merkle::leaf_hash(&body)))
+    }
+
+    // appends the proposed entry in `request`, rekor stores it canonicalized
+    fn append(&self, request: &[u8]) -> Result<usize, anyhow::Error> {
+        let body = canonical::to_vec(&serde_json::from_slice::<Value>(request)?)?;
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mock log is poisoned"))?;
+        if entries.iter().any(|(logged, _)| *logged == body) {
+            anyhow::bail!("an equivalent entry already exists in the log");
+        }
+        entries.push((body, now()?));
+        Ok(entries.len() - 1)
+    }
+
+    // the entry at `index` with its promise and a proof for the current tree
+    fn entry(&self, index: usize) -> Result<Value, anyhow::Error> {
+        let (body, integrated_time) = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mock log is poisoned"))?
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no entry at index {}", index))?;
+        let leaves = self.leaves()?;
+        let body = base64::encode(body);
+        let promise = canonical::to_vec(&json!({
+            "body": body,
+            "integratedTime": integrated_time,
+            "logID": self.log_id,
+            "logIndex": index,
+        }))?;
+        let log_entry = LogEntry {
+            uuid: String::new(),
+            body,
+            integrated_time,
+            log_id: self.log_id.clone(),
+            log_index: index as i64,
+            verification: Some(Verification {
+                inclusion_proof: Some(InclusionProof {
+                    hashes: inclusion_path(index, &leaves)
+                        .iter()
+                        .map(|hash| HEXLOWER.encode(hash))
+                        .collect(),
+                    log_index: index as i64,
+                    root_hash: HEXLOWER.encode(&tree_root(&leaves)),
+                    tree_size: leaves.len() as i64,
+                    checkpoint: Some(self.checkpoint(&leaves)?),
+                }),
+                signed_entry_timestamp: Some(base64::encode(self.sign(&promise)?)),
+            }),
+        };
+        let mut entries = HashMap::new();
+        entries.insert(HEXLOWER.encode(&leaves[index]), log_entry);
+        Ok(serde_json::to_value(entries)?)
+    }
+
+    fn find(&self, uuid: &str) -> Result<usize, anyhow::Error> {
+        // uuids may be prefixed with the tree id
+        let leaf = &uuid[uuid.len().saturating_sub(64)..];
+        self.leaves()?
+            .iter()
+            .position(|hash| HEXLOWER.encode(hash) == leaf)
+            .ok_or_else(|| anyhow::anyhow!("no entry with uuid {}", uuid))
+    }
+
+    fn info(&self) -> Result<Value, anyhow::Error> {
+        let leaves = self.leaves()?;
+        Ok(json!({
+            "rootHash": HEXLOWER.encode(&tree_root(&leaves)),
+            "treeSize": leaves.len(),
+            "signedTreeHead": self.checkpoint(&leaves)?,
+            "treeID": "0",
+        }))
+    }
+}
+
+// the largest power of two smaller than `n`, where rfc 6962 splits a tree
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+fn tree_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    match leaves.len() {
+        0 => Sha256::digest(b"").to_vec(),
+        1 => leaves[0].clone(),
+        n => {
+            let k = split(n);
+            merkle::node_hash(&tree_root(&leaves[..k]), &tree_root(&leaves[k..]))
+        }
+    }
+}
+
+// the audit path of rfc 6962, section 2.1.1
+fn inclusion_path(index: usize, leaves: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split(leaves.len());
+    let (mut path, sibling) = if index < k {
+        (inclusion_path(index, &leaves[..k]), tree_root(&leaves[k..]))
+    } else {
+        (
+            inclusion_path(index - k, &leaves[k..]),
+            tree_root(&leaves[..k]),
+        )
+    };
+    path.push(sibling);
+    path
+}
+
+/// A Rekor speaking the v1 API.
+pub struct MockRekor {
+    pub server: MockServer,
+    public_key: PKey<Public>,
+}
+
+impl MockRekor {
+    pub async fn start() -> Result<Self, anyhow::Error> {
+        let (key, public_key_pem) = SigningAlgorithm::EcdsaP256Sha256.generate()?;
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+        let log = Arc::new(Log {
+            key,
+            log_id: HEXLOWER.encode(&Sha256::digest(public_key.public_key_to_der()?)),
+            entries: Mutex::new(Vec::new()),
+        });
+        let server = MockServer::start().await;
+
+        let appended = log.clone();
+        Mock::given(method("POST"))
+            .and(path(rekor_api::ENTRIES_PATH))
+            .respond_with(
+                move |request: &Request| match appended.append(&request.body) {
+                    Ok(index) => respond(
+                        appended
+                            .entry(index)
+                            .map(|entry| ResponseTemplate::new(201).set_body_json(entry)),
+                    ),
+                    Err(e) => {
+                        let conflict = ResponseTemplate::new(409).set_body_string(e.to_string());
+                        match Log::uuid(&request.body) {
+                            Ok(uuid) => {
+                                let location = format!("{}/{}", rekor_api::ENTRIES_PATH, uuid);
+                                conflict.insert_header("Location", location.as_str())
+                            }
+                            Err(_) => conflict,
+                        }
+                    }
+                },
+            )
+            .mount(&server)
+            .await;
+        let by_uuid = log.clone();
+        Mock::given(method("GET"))
+            .and(path_regex(format!(
+                "^{}/[0-9a-f]+$",
+                rekor_api::ENTRIES_PATH
+            )))
+            .respond_with(move |request: &Request| {
+                let uuid = request.url.path().rsplit('/').next().unwrap_or_default();
+                respond(
+                    by_uuid
+                        .find(uuid)
+                        .and_then(|index| by_uuid.entry(index))
+                        .map(|entry| ResponseTemplate::new(200).set_body_json(entry)),
+                )
+            })
+            .mount(&server)
+            .await;
+        let by_index = log.clone();
+        Mock::given(method("GET"))
+            .and(path(rekor_api::ENTRIES_PATH))
+            .and(query_param_present("logIndex"))
+            .respond_with(move |request: &Request| {
+                let index = request
+                    .url
+                    .query_pairs()
+                    .find(|(name, _)| name == "logIndex")
+                    .map(|(_, index)| index.into_owned())
+                    .unwrap_or_default();
+                respond(
+                    index
+                        .parse()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|index| by_index.entry(index))
+                        .map(|entry| ResponseTemplate::new(200).set_body_json(entry)),
+                )
+            })
+            .mount(&server)
+            .await;
+        let info = log.clone();
+        Mock::given(method("GET"))
+            .and(path(rekor_api::LOG_INFO_PATH))
+            .respond_with(move |_: &Request| {
+                respond(
+                    info.info()
+                        .map(|info| ResponseTemplate::new(200).set_body_json(info)),
+                )
+            })
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(rekor_api::PUBLIC_KEY_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_string(public_key_pem))
+            .mount(&server)
+            .await;
+        Ok(MockRekor { server, public_key })
+    }
+
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The key the log signs promises and checkpoints with.
+    pub fn public_key(&self) -> &PKey<Public> {
+        &self.public_key
+    }
+}
+
+fn query_param_present(name: &'static str) -> impl wiremock::Match {
+    move |request: &Request| request.url.query_pairs().any(|(param, _)| param == name)
+}
+
+/// A [`MockFulcio`] and a [`MockRekor`].
+pub struct MockSigstore {
+    pub fulcio: MockFulcio,
+    pub rekor: MockRekor,
+}
+
+impl MockSigstore {
+    pub async fn start() -> Result<Self, anyhow::Error> {
+        Ok(MockSigstore {
+            fulcio: MockFulcio::start().await?,
+            rekor: MockRekor::start().await?,
+        })
+    }
+
+    /// Trusts the mock root and log, and nothing else.
+    pub fn trust_root(&self) -> TrustRoot {
+        TrustRoot {
+            fulcio_chain: vec![self.fulcio.root().clone()],
+            rekor_key: Some(self.rekor.public_key().clone()),
+            ..TrustRoot::default()
+        }
+    }
+
+    /// A signer talking to the mocks and checking what they return against
+    /// [`MockSigstore::trust_root`].
+    pub fn signer(&self) -> KeylessSigner {
+        KeylessSigner::new(&self.fulcio.url(), &self.rekor.url())
+            .with_trust_root(&self.trust_root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify;
+
+    #[tokio::test]
+    async fn test_sign_and_verify() {
+        let sigstore = MockSigstore::start().await.unwrap();
+        let signer = sigstore.signer();
+        let identity = identity("ferris@example.com");
+        let mut session = signer.session(identity);
+        let first = session.sign_blob(b"ohhai").await.unwrap();
+        let second = session.sign_blob(b"lolwut").await.unwrap();
+
+        let trust_root = sigstore.trust_root();
+        verify::verify_bundle(&first.bundle().unwrap(), b"ohhai", &trust_root).unwrap();
+        verify::verify_bundle(&second.bundle().unwrap(), b"lolwut", &trust_root).unwrap();
+        assert!(verify::verify_bundle(&first.bundle().unwrap(), b"lolwut", &trust_root).is_err());
+        // an entry that doesn't verify is passed over for one that does
+        let mut bundle = first.bundle().unwrap();
+        let other = second.bundle().unwrap().verification_material.tlog_entries;
+        bundle
+            .verification_material
+            .tlog_entries
+            .splice(0..0, other.clone());
+        verify::verify_bundle(&bundle, b"ohhai", &trust_root).unwrap();
+        bundle.verification_material.tlog_entries = other;
+        assert!(verify::verify_bundle(&bundle, b"ohhai", &trust_root).is_err());
+        assert!(verify::verify_bundle(
+            &first.bundle().unwrap(),
+            b"ohhai",
+            &MockSigstore::start().await.unwrap().trust_root()
+        )
+        .is_err());
+
+        let log_entry = rekor_api::get_entry_by_index(&sigstore.rekor.url(), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(log_entry.body),
+            second.log_entry.map(|entry| entry.body)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sign_traced() {
+        // responses read for tracing must still reach the signer intact
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_test_writer()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let sigstore = MockSigstore::start().await.unwrap();
+        let signed = sigstore
+            .signer()
+            .session(identity("ferris@example.com"))
+            .sign_blob(b"ohhai")
+            .await
+            .unwrap();
+        verify::verify_bundle(&signed.bundle().unwrap(), b"ohhai", &sigstore.trust_root()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sign_again_with_deterministic_key() {
+        use crate::key::DeterministicKey;
+        use crate::KeySigner;
+
+        let rekor = MockRekor::start().await.unwrap();
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let signer = KeySigner::from_signing_key(Arc::new(DeterministicKey::new(key).unwrap()))
+            .unwrap()
+            .with_rekor(&rekor.url())
+            .with_rekor_key(rekor.public_key().clone());
+        let first = signer.sign_blob(b"ohhai").await.unwrap();
+        let again = signer.sign_blob(b"ohhai").await.unwrap();
+        assert_eq!(first.signature, again.signature);
+        let (first, again) = (first.log_entry.unwrap(), again.log_entry.unwrap());
+        assert_eq!(first.log_index, again.log_index);
+        assert_eq!(first.body, again.body);
+    }
+
+    #[tokio::test]
+    async fn test_sign_without_rekor_key() {
+        use crate::KeySigner;
+
+        let rekor = MockRekor::start().await.unwrap();
+        let (key, _) = SigningAlgorithm::EcdsaP256Sha256.generate().unwrap();
+        let signer = KeySigner::from_signing_key(Arc::new(key))
+            .unwrap()
+            .with_rekor(&rekor.url());
+        assert!(signer.sign_blob(b"ohhai").await.is_err());
+        // nothing was logged
+        assert!(rekor_api::get_entry_by_index(&rekor.url(), 0)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_inclusion_path() {
+        let leaves: Vec<Vec<u8>> = (0..7u8).map(|leaf| merkle::leaf_hash(&[leaf])).collect();
+        let root = tree_root(&leaves);
+        for index in 0..leaves.len() {
+            merkle::verify_inclusion(
+                index as u64,
+                leaves.len() as u64,
+                &[index as u8],
+                &inclusion_path(index, &leaves),
+                &root,
+            )
+            .unwrap();
+        }
+    }
+}
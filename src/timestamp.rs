@@ -0,0 +1,351 @@
+//! RFC 3161 trusted timestamps.
+
+use openssl::hash::MessageDigest;
+use openssl::rand::rand_bytes;
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use sha2::{Digest, Sha256};
+
+use crate::http::{self, Service};
+use crate::{certificate, der, verify};
+
+/// Public sigstore timestamp authority.
+pub const SIGSTORE_TSA_URL: &str = "https://timestamp.sigstore.dev/api/v1/timestamp";
+
+// 2.16.840.1.101.3.4.2.1
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+const OID_EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+const OID_TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+
+/// The parts of a timestamp token's TSTInfo that verification needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TstInfo {
+    /// sha256 digest of the timestamped data.
+    pub message_imprint: Vec<u8>,
+    /// Time of the timestamp in seconds since the epoch.
+    pub gen_time: i64,
+    pub nonce: Option<Vec<u8>>,
+}
+
+/// Encodes a TimeStampReq for the sha256 `digest`, asking for the TSA
+/// certificate to be included in the token.
+pub fn timestamp_request(digest: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let algorithm = der::encode(
+        der::TAG_SEQUENCE,
+        &[
+            der::encode(der::TAG_OID, OID_SHA256),
+            der::encode(der::TAG_NULL, &[]),
+        ]
+        .concat(),
+    );
+    let imprint = der::encode(
+        der::TAG_SEQUENCE,
+        &[algorithm, der::encode(der::TAG_OCTET_STRING, digest)].concat(),
+    );
+    der::encode(
+        der::TAG_SEQUENCE,
+        &[
+            der::encode(der::TAG_INTEGER, &[1]),
+            imprint,
+            der::encode(der::TAG_INTEGER, nonce),
+            der::encode(der::TAG_BOOLEAN, &[0xff]),
+        ]
+        .concat(),
+    )
+}
+
+/// Requests a timestamp over `data` from the TSA at `tsa_url`, returning the
+/// DER encoded timestamp token.
+pub async fn request_timestamp(tsa_url: &str, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let digest = Sha256::digest(data);
+    let mut nonce = [0u8; 8];
+    rand_bytes(&mut nonce)?;
+    // keep the integer positive and minimally encoded
+    nonce[0] = (nonce[0] & 0x7f) | 0x01;
+
+    let response = http::send(
+        http::client(Service::Other)
+            .post(tsa_url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(timestamp_request(&digest, &nonce)),
+    )
+    .await?
+    .error_for_status()?
+    .bytes()
+    .await?;
+
+    let response = der::expect(&response, der::TAG_SEQUENCE)?;
+    let fields = der::children(response.value)?;
+    let status = fields
+        .first()
+        .map(|status| der::expect(status.value, der::TAG_INTEGER))
+        .transpose()?
+        .ok_or_else(|| anyhow::anyhow!("timestamp response has no status"))?;
+    // 0 is granted, 1 granted with modifications
+    if status.value != [0] && status.value != [1] {
+        anyhow::bail!("timestamp authority rejected the request");
+    }
+    let token = fields
+        .get(1)
+        .ok_or_else(|| anyhow::anyhow!("timestamp response has no token"))?
+        .raw
+        .to_vec();
+
+    let info = parse_token(&token)?;
+    if info.message_imprint != digest.as_slice() {
+        anyhow::bail!("timestamp is for different data");
+    }
+    if info.nonce.as_deref() != Some(&nonce[..]) {
+        anyhow::bail!("timestamp response nonce does not match the request");
+    }
+    Ok(token)
+}
+
+// the fields of the SignedData the token wraps
+fn signed_data(token: &[u8]) -> Result<Vec<der::Tlv<'_>>, anyhow::Error> {
+    let content_info = der::children(der::expect(token, der::TAG_SEQUENCE)?.value)?;
+    match content_info.first() {
+        Some(oid)
+            if oid.tag == der::TAG_OID && der::oid_to_string(oid.value) == OID_SIGNED_DATA => {}
+        _ => anyhow::bail!("timestamp token is not CMS signed data"),
+    }
+    let signed_data = content_info
+        .get(1)
+        .filter(|content| content.tag == der::TAG_CONTEXT_0)
+        .ok_or_else(|| anyhow::anyhow!("timestamp token has no content"))?;
+    der::children(der::expect(signed_data.value, der::TAG_SEQUENCE)?.value)
+}
+
+// the TSTInfo is the encapsulated content of the token's SignedData
+fn tst_info_der(token: &[u8]) -> Result<&[u8], anyhow::Error> {
+    let encap_content = signed_data(token)?
+        .into_iter()
+        .nth(2)
+        .ok_or_else(|| anyhow::anyhow!("timestamp token has no encapsulated content"))?;
+    let encap_content = der::children(encap_content.value)?;
+    match encap_content.first() {
+        Some(oid) if oid.tag == der::TAG_OID && der::oid_to_string(oid.value) == OID_TST_INFO => {}
+        _ => anyhow::bail!("timestamp token does not contain a TSTInfo"),
+    }
+    let content = encap_content
+        .get(1)
+        .filter(|content| content.tag == der::TAG_CONTEXT_0)
+        .ok_or_else(|| anyhow::anyhow!("timestamp token has no TSTInfo"))?;
+    Ok(der::expect(content.value, der::TAG_OCTET_STRING)?.value)
+}
+
+fn message_digest(oid: &str) -> Result<MessageDigest, anyhow::Error> {
+    match oid {
+        "2.16.840.1.101.3.4.2.1" => Ok(MessageDigest::sha256()),
+        "2.16.840.1.101.3.4.2.2" => Ok(MessageDigest::sha384()),
+        "2.16.840.1.101.3.4.2.3" => Ok(MessageDigest::sha512()),
+        _ => anyhow::bail!("unsupported timestamp digest algorithm {}", oid),
+    }
+}
+
+// whether the extended key usage of `cert` allows timestamping
+fn is_timestamping_cert(cert: &X509) -> Result<bool, anyhow::Error> {
+    let cert_der = cert.to_der()?;
+    let usage = match certificate::extension(&cert_der, OID_EXTENDED_KEY_USAGE)? {
+        Some(usage) => usage,
+        None => return Ok(false),
+    };
+    let purposes = der::children(der::expect(&usage.value, der::TAG_SEQUENCE)?.value)?;
+    Ok(purposes
+        .iter()
+        .any(|purpose| der::oid_to_string(purpose.value) == OID_TIME_STAMPING))
+}
+
+/// Verifies the CMS signature of a timestamp token over `data`, made by a
+/// timestamping certificate that chains up to `tsa_chain`, and returns its
+/// TSTInfo.
+pub fn verify_token(
+    token: &[u8],
+    data: &[u8],
+    tsa_chain: &[X509],
+) -> Result<TstInfo, anyhow::Error> {
+    let info = parse_token(token)?;
+    if info.message_imprint != Sha256::digest(data).as_slice() {
+        anyhow::bail!("timestamp is for different data");
+    }
+
+    let fields = signed_data(token)?;
+    // certificates [0] IMPLICIT is optional, signerInfos is always last
+    let mut certs = tsa_chain.to_vec();
+    if let Some(embedded) = fields.iter().find(|field| field.tag == der::TAG_CONTEXT_0) {
+        for cert in der::children(embedded.value)? {
+            certs.push(X509::from_der(cert.raw)?);
+        }
+    }
+    let signer_infos = fields
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("timestamp token has no signer"))?;
+    let signer_info = der::children(signer_infos.value)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("timestamp token has no signer"))?;
+    // version, sid, digestAlgorithm, signedAttrs, signatureAlgorithm, signature
+    let signer_info = der::children(signer_info.value)?;
+    if signer_info.len() < 6 || signer_info[3].tag != der::TAG_CONTEXT_0 {
+        anyhow::bail!("timestamp signer has no signed attributes");
+    }
+    let digest_algorithm = der::children(signer_info[2].value)?;
+    let digest = message_digest(&der::oid_to_string(
+        digest_algorithm
+            .first()
+            .map(|oid| oid.value)
+            .unwrap_or_default(),
+    ))?;
+    let signed_attrs = &signer_info[3];
+    let signature = der::expect(signer_info[5].raw, der::TAG_OCTET_STRING)?.value;
+
+    // the signed attributes commit to the TSTInfo through the message digest
+    let tst_info_digest = openssl::hash::hash(digest, tst_info_der(token)?)?;
+    let attested_digest = der::children(signed_attrs.value)?
+        .into_iter()
+        .find_map(|attr| {
+            let attr = der::children(attr.value).ok()?;
+            if der::oid_to_string(attr.first()?.value) != OID_MESSAGE_DIGEST {
+                return None;
+            }
+            let values = der::children(attr.get(1)?.value).ok()?;
+            Some(values.first()?.value.to_vec())
+        })
+        .ok_or_else(|| anyhow::anyhow!("timestamp signer has no message digest"))?;
+    if attested_digest != tst_info_digest[..] {
+        anyhow::bail!("timestamp signature does not cover the TSTInfo");
+    }
+
+    // the signature is over the attributes encoded as a SET
+    let signed = der::encode(der::TAG_SET, signed_attrs.value);
+    let signer = certs
+        .iter()
+        .find(|cert| {
+            cert.public_key()
+                .and_then(|key| {
+                    let mut verifier = Verifier::new(digest, &key)?;
+                    verifier.update(&signed)?;
+                    verifier.verify(signature)
+                })
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| anyhow::anyhow!("timestamp signature verification failed"))?;
+    if !is_timestamping_cert(signer)? {
+        anyhow::bail!("timestamp was not signed by a timestamping certificate");
+    }
+    if !verify::verify_cert_chain(signer, tsa_chain)? {
+        anyhow::bail!("timestamp certificate does not chain up to the TSA root");
+    }
+    verify::verify_cert_valid_at(signer, info.gen_time)?;
+    Ok(info)
+}
+
+/// Extracts the TSTInfo from a DER encoded timestamp token. The token's
+/// signature is not checked here.
+pub fn parse_token(token: &[u8]) -> Result<TstInfo, anyhow::Error> {
+    let tst_info = der::expect(tst_info_der(token)?, der::TAG_SEQUENCE)?;
+    let fields = der::children(tst_info.value)?;
+    // version, policy, messageImprint, serialNumber, genTime, then optional
+    // accuracy, ordering and nonce
+    let imprint = fields
+        .get(2)
+        .ok_or_else(|| anyhow::anyhow!("TSTInfo has no message imprint"))?;
+    let imprint = der::children(imprint.value)?;
+    let algorithm = imprint
+        .first()
+        .map(|algorithm| der::children(algorithm.value))
+        .transpose()?
+        .unwrap_or_default();
+    if algorithm.first().map(|oid| oid.value) != Some(OID_SHA256) {
+        anyhow::bail!("only sha256 timestamps are supported");
+    }
+    let message_imprint = imprint
+        .get(1)
+        .filter(|digest| digest.tag == der::TAG_OCTET_STRING)
+        .ok_or_else(|| anyhow::anyhow!("TSTInfo has no digest"))?
+        .value
+        .to_vec();
+    let gen_time = fields
+        .get(4)
+        .filter(|time| time.tag == der::TAG_GENERALIZED_TIME)
+        .ok_or_else(|| anyhow::anyhow!("TSTInfo has no time"))?;
+    let gen_time = parse_generalized_time(std::str::from_utf8(gen_time.value)?)?;
+    let nonce = fields[5..]
+        .iter()
+        .find(|field| field.tag == der::TAG_INTEGER)
+        .map(|nonce| nonce.value.to_vec());
+
+    Ok(TstInfo {
+        message_imprint,
+        gen_time,
+        nonce,
+    })
+}
+
+// days since the epoch of a proleptic gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parses a `YYYYMMDDHHMMSS[.fff]Z` GeneralizedTime into seconds since the
+/// epoch, fractions of a second are dropped.
+pub fn parse_generalized_time(time: &str) -> Result<i64, anyhow::Error> {
+    let time = time
+        .strip_suffix('Z')
+        .ok_or_else(|| anyhow::anyhow!("timestamp time {} is not in UTC", time))?;
+    let time = time.split('.').next().unwrap_or_default();
+    if time.len() != 14 || !time.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("malformed timestamp time {}", time);
+    }
+    let field = |range: std::ops::Range<usize>| time[range].parse::<i64>().unwrap_or_default();
+    let days = days_from_civil(field(0..4), field(4..6), field(6..8));
+    Ok(days * 86400 + field(8..10) * 3600 + field(10..12) * 60 + field(12..14))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_generalized_time() {
+        assert_eq!(parse_generalized_time("19700101000000Z").unwrap(), 0);
+        assert_eq!(
+            parse_generalized_time("20220801120500.123Z").unwrap(),
+            1659355500
+        );
+        assert!(parse_generalized_time("20220801120500").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_request() {
+        let request = timestamp_request(&[0xab; 32], &[0x2a]);
+        let fields =
+            der::children(der::expect(&request, der::TAG_SEQUENCE).unwrap().value).unwrap();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].value, [1]);
+        assert_eq!(fields[2].value, [0x2a]);
+    }
+
+    #[test]
+    fn test_parse_token() {
+        let token = std::fs::read("test_data/timestamp/token.der").unwrap();
+        let signature =
+            base64::decode(std::fs::read("test_data/cosign/test_digest.txt.sig").unwrap()).unwrap();
+        let info = parse_token(&token).unwrap();
+        assert_eq!(info.message_imprint, Sha256::digest(&signature).as_slice());
+        assert_eq!(info.gen_time, 1659355500);
+        assert_eq!(
+            info.nonce.unwrap(),
+            [0x11, 0xcf, 0x42, 0xc0, 0xa3, 0xdc, 0x95, 0x7b]
+        );
+    }
+}
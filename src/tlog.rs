@@ -0,0 +1,273 @@
+//! Offline verification of Rekor transparency log entries.
+
+use data_encoding::HEXLOWER;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKeyRef, Public};
+use openssl::sign::Verifier;
+use serde::Serialize;
+
+use crate::algorithm::SigningAlgorithm;
+use crate::bundle::TransparencyLogEntry;
+use crate::merkle;
+use crate::rekor_api::LogEntry;
+
+// payload signed by the signed entry timestamp, field order is the canonical
+// (sorted) key order
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SetPayload<'a> {
+    body: &'a str,
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: &'a str,
+    log_index: i64,
+}
+
+fn verify_ecdsa(
+    key: &PKeyRef<Public>,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let mut verifier = Verifier::new(MessageDigest::sha256(), key)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(signature)?)
+}
+
+/// Verifies the signed entry timestamp (inclusion promise) of `entry`.
+pub fn verify_set(
+    entry: &TransparencyLogEntry,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<(), anyhow::Error> {
+    let promise = entry
+        .inclusion_promise
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("log entry has no inclusion promise"))?;
+    let log_id = HEXLOWER.encode(&base64::decode(&entry.log_id.key_id)?);
+    let payload = serde_json::to_vec(&SetPayload {
+        body: &entry.canonicalized_body,
+        integrated_time: entry.integrated_time.parse()?,
+        log_id: &log_id,
+        log_index: entry.log_index.parse()?,
+    })?;
+    let signature = base64::decode(&promise.signed_entry_timestamp)?;
+    if !verify_ecdsa(rekor_key, &payload, &signature)? {
+        anyhow::bail!("signed entry timestamp verification failed");
+    }
+    Ok(())
+}
+
+/// A parsed checkpoint (signed tree head) in the signed note format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub origin: String,
+    pub tree_size: u64,
+    pub root_hash: Vec<u8>,
+    /// The signed portion of the note.
+    pub note: String,
+    /// Signatures with the four byte key hint stripped.
+    pub signatures: Vec<Vec<u8>>,
+}
+
+impl Checkpoint {
+    pub fn parse(envelope: &str) -> Result<Self, anyhow::Error> {
+        let (body, signatures) = envelope
+            .split_once("\n\n")
+            .ok_or_else(|| anyhow::anyhow!("checkpoint has no signatures"))?;
+        let mut lines = body.lines();
+        let origin = lines.next().unwrap_or_default().to_string();
+        let tree_size = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("checkpoint has no tree size"))?
+            .parse()?;
+        let root_hash = base64::decode(
+            lines
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("checkpoint has no root hash"))?,
+        )?;
+
+        let mut parsed = Vec::new();
+        for line in signatures.lines().filter(|l| !l.is_empty()) {
+            let signature = line
+                .strip_prefix("\u{2014} ")
+                .and_then(|l| l.rsplit_once(' '))
+                .map(|(_, signature)| signature)
+                .ok_or_else(|| anyhow::anyhow!("malformed checkpoint signature line"))?;
+            let signature = base64::decode(signature)?;
+            if signature.len() < 5 {
+                anyhow::bail!("checkpoint signature is too short");
+            }
+            parsed.push(signature[4..].to_vec());
+        }
+
+        Ok(Checkpoint {
+            origin,
+            tree_size,
+            root_hash,
+            note: format!("{}\n", body),
+            signatures: parsed,
+        })
+    }
+
+    /// Checks that one of the note signatures was made by `rekor_key`. v1
+    /// logs sign with ECDSA, v2 logs may use Ed25519.
+    pub fn verify(&self, rekor_key: &PKeyRef<Public>) -> Result<(), anyhow::Error> {
+        let algorithm = SigningAlgorithm::for_key(rekor_key)?;
+        for signature in &self.signatures {
+            // signatures by other witnesses may not even parse for this key
+            if algorithm
+                .verify(rekor_key, self.note.as_bytes(), signature)
+                .unwrap_or(false)
+            {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("checkpoint signature verification failed")
+    }
+}
+
+/// Verifies the inclusion proof of `entry` and, when `rekor_key` is given,
+/// the checkpoint that commits to its root hash.
+pub fn verify_inclusion_proof(
+    entry: &TransparencyLogEntry,
+    rekor_key: Option<&PKeyRef<Public>>,
+) -> Result<(), anyhow::Error> {
+    let proof = entry
+        .inclusion_proof
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("log entry has no inclusion proof"))?;
+    let root_hash = base64::decode(&proof.root_hash)?;
+    let tree_size = proof.tree_size.parse()?;
+    let hashes = proof
+        .hashes
+        .iter()
+        .map(base64::decode)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    merkle::verify_inclusion(
+        proof.log_index.parse()?,
+        tree_size,
+        &base64::decode(&entry.canonicalized_body)?,
+        &hashes,
+        &root_hash,
+    )?;
+
+    if let Some(rekor_key) = rekor_key {
+        let checkpoint = proof
+            .checkpoint
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("inclusion proof has no checkpoint"))?;
+        let checkpoint = Checkpoint::parse(&checkpoint.envelope)?;
+        checkpoint.verify(rekor_key)?;
+        if checkpoint.tree_size != tree_size || checkpoint.root_hash != root_hash {
+            anyhow::bail!("checkpoint does not match the inclusion proof");
+        }
+    }
+    Ok(())
+}
+
+/// Verifies the inclusion proof and checkpoint of an entry fetched from the
+/// Rekor API.
+pub fn verify_log_entry_inclusion(
+    log_entry: &LogEntry,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<(), anyhow::Error> {
+    let entry = TransparencyLogEntry::from_log_entry(log_entry)?;
+    verify_inclusion_proof(&entry, Some(rekor_key))
+}
+
+/// Verifies that the tree of `old` is a prefix of the tree of `new`, both
+/// from the same log. The checkpoint signatures are not checked here.
+pub fn verify_consistency(
+    old: &Checkpoint,
+    new: &Checkpoint,
+    proof: &[Vec<u8>],
+) -> Result<(), anyhow::Error> {
+    if old.origin != new.origin {
+        anyhow::bail!(
+            "checkpoints are for different logs, {} and {}",
+            old.origin,
+            new.origin
+        );
+    }
+    merkle::verify_consistency(
+        old.tree_size,
+        new.tree_size,
+        proof,
+        &old.root_hash,
+        &new.root_hash,
+    )
+}
+
+/// Verifies the signed entry timestamp Rekor returns with `log_entry`.
+pub fn verify_log_entry_set(
+    log_entry: &LogEntry,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<(), anyhow::Error> {
+    let entry = TransparencyLogEntry::from_log_entry(log_entry)?;
+    verify_set(&entry, rekor_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checkpoint() {
+        let root = base64::encode([0xabu8; 32]);
+        let signature = base64::encode([1u8, 2, 3, 4, 0x30, 0x45]);
+        let envelope = format!(
+            "rekor.sigstore.dev - 2605736670972794746\n21428036\n{}\nTimestamp: 1689177396617352539\n\n\u{2014} rekor.sigstore.dev {}\n",
+            root, signature
+        );
+        let checkpoint = Checkpoint::parse(&envelope).unwrap();
+        assert_eq!(
+            checkpoint.origin,
+            "rekor.sigstore.dev - 2605736670972794746"
+        );
+        assert_eq!(checkpoint.tree_size, 21428036);
+        assert_eq!(checkpoint.root_hash, vec![0xab; 32]);
+        assert!(checkpoint.note.ends_with("1689177396617352539\n"));
+        assert_eq!(checkpoint.signatures, vec![vec![0x30, 0x45]]);
+    }
+
+    #[test]
+    fn test_verify_log_entry_set() {
+        use crate::rekor_api::Verification;
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::nid::Nid;
+        use openssl::pkey::PKey;
+        use openssl::sign::Signer;
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let other_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let body = base64::encode(r#"{"apiVersion":"0.0.1","kind":"hashedrekord","spec":{}}"#);
+        let log_id = HEXLOWER.encode(&[0x12; 32]);
+        let payload = serde_json::to_vec(&SetPayload {
+            body: &body,
+            integrated_time: 1689177396,
+            log_id: &log_id,
+            log_index: 42,
+        })
+        .unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(&payload).unwrap();
+        let mut log_entry = LogEntry {
+            uuid: String::new(),
+            body,
+            integrated_time: 1689177396,
+            log_id,
+            log_index: 42,
+            verification: Some(Verification {
+                inclusion_proof: None,
+                signed_entry_timestamp: Some(base64::encode(signer.sign_to_vec().unwrap())),
+            }),
+        };
+        let public_key = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        let other_key = PKey::public_key_from_der(&other_key.public_key_to_der().unwrap()).unwrap();
+        verify_log_entry_set(&log_entry, &public_key).unwrap();
+        assert!(verify_log_entry_set(&log_entry, &other_key).is_err());
+        log_entry.log_index = 43;
+        assert!(verify_log_entry_set(&log_entry, &public_key).is_err());
+    }
+}
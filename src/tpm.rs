@@ -0,0 +1,217 @@
+//! Signing with a P-256 key that lives in the local TPM 2.0, addressed as
+//! `tpm://<persistent handle>`. Signatures made this way are tied to the
+//! machine, which is what provenance claims about build hosts need. TPM
+//! support is built with the `tpm` feature.
+
+#[cfg(feature = "tpm")]
+use openssl::bn::BigNumContext;
+#[cfg(feature = "tpm")]
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+#[cfg(feature = "tpm")]
+use openssl::nid::Nid;
+#[cfg(feature = "tpm")]
+use openssl::pkey::{PKey, Public};
+#[cfg(feature = "tpm")]
+use std::convert::{TryFrom, TryInto};
+#[cfg(feature = "tpm")]
+use std::fmt;
+#[cfg(feature = "tpm")]
+use tss_esapi::constants::tss::{TPM2_RH_NULL, TPM2_ST_HASHCHECK};
+#[cfg(feature = "tpm")]
+use tss_esapi::handles::{KeyHandle, PersistentTpmHandle, TpmHandle};
+#[cfg(feature = "tpm")]
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+#[cfg(feature = "tpm")]
+use tss_esapi::interface_types::dynamic_handles::Persistent;
+#[cfg(feature = "tpm")]
+use tss_esapi::interface_types::ecc::EccCurve;
+#[cfg(feature = "tpm")]
+use tss_esapi::interface_types::resource_handles::{Hierarchy, Provision};
+#[cfg(feature = "tpm")]
+use tss_esapi::structures::{
+    Digest, EccScheme, HashScheme, HashcheckTicket, Public as TpmPublic, Signature, SignatureScheme,
+};
+#[cfg(feature = "tpm")]
+use tss_esapi::tss2_esys::TPMT_TK_HASHCHECK;
+#[cfg(feature = "tpm")]
+use tss_esapi::utils::create_unrestricted_signing_ecc_public;
+#[cfg(feature = "tpm")]
+use tss_esapi::{Context, TctiNameConf};
+
+#[cfg(feature = "tpm")]
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+#[cfg(feature = "tpm")]
+use crate::key::{self, SigningKey};
+
+pub const URI_SCHEME: &str = "tpm://";
+
+const PERSISTENT_HANDLES: std::ops::RangeInclusive<u32> = 0x8100_0000..=0x81ff_ffff;
+
+/// Parses the persistent handle out of a `tpm://0x81000001` URI.
+pub fn parse_uri(uri: &str) -> Result<u32, anyhow::Error> {
+    let handle = uri
+        .strip_prefix(URI_SCHEME)
+        .ok_or_else(|| anyhow::anyhow!("{} is not a tpm:// URI", uri))?;
+    let value = handle.strip_prefix("0x").unwrap_or(handle);
+    let handle = u32::from_str_radix(value, 16)
+        .map_err(|_| anyhow::anyhow!("invalid TPM handle {}", handle))?;
+    if !PERSISTENT_HANDLES.contains(&handle) {
+        anyhow::bail!("{:#x} is not a persistent TPM handle", handle);
+    }
+    Ok(handle)
+}
+
+/// A P-256 signing key persisted in the TPM. The private key never leaves
+/// the TPM, digests are sent to it for signing.
+#[cfg(feature = "tpm")]
+pub struct TpmKey {
+    handle: u32,
+    tcti: TctiNameConf,
+    public_key: PKey<Public>,
+}
+
+#[cfg(feature = "tpm")]
+impl fmt::Debug for TpmKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TpmKey")
+            .field("handle", &format_args!("{:#x}", self.handle))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tpm")]
+impl TpmKey {
+    /// Opens the key at the persistent `handle`, creating and persisting a
+    /// new P-256 key under the owner hierarchy if there is none yet. The TPM
+    /// is reached through the TCTI named by `TPM2TOOLS_TCTI`, the kernel
+    /// resource manager by default.
+    pub fn open_or_create(handle: u32) -> Result<Self, anyhow::Error> {
+        let tcti = match std::env::var("TPM2TOOLS_TCTI") {
+            Ok(_) => TctiNameConf::from_environment_variable()?,
+            Err(_) => "device:/dev/tpmrm0".parse()?,
+        };
+        let mut context = Context::new(tcti.clone())
+            .map_err(|e| anyhow::anyhow!("could not connect to the TPM: {}", e))?;
+        let key_handle = match load(&mut context, handle) {
+            Ok(key_handle) => key_handle,
+            Err(_) => create(&mut context, handle)?,
+        };
+        let (public, _, _) = context.read_public(key_handle)?;
+        Ok(TpmKey {
+            handle,
+            tcti,
+            public_key: ec_public_key(&public)?,
+        })
+    }
+}
+
+#[cfg(feature = "tpm")]
+fn load(context: &mut Context, handle: u32) -> Result<KeyHandle, anyhow::Error> {
+    let persistent = PersistentTpmHandle::new(handle)?;
+    let object = context.tr_from_tpm_public(TpmHandle::Persistent(persistent))?;
+    Ok(object.into())
+}
+
+#[cfg(feature = "tpm")]
+fn create(context: &mut Context, handle: u32) -> Result<KeyHandle, anyhow::Error> {
+    let public = create_unrestricted_signing_ecc_public(
+        EccScheme::EcDsa(HashScheme::new(HashingAlgorithm::Sha256)),
+        EccCurve::NistP256,
+    )?;
+    let primary = context.execute_with_nullauth_session(|context| {
+        context.create_primary(Hierarchy::Owner, public, None, None, None, None)
+    })?;
+    let persistent = Persistent::Persistent(PersistentTpmHandle::new(handle)?);
+    context.execute_with_nullauth_session(|context| {
+        context.evict_control(Provision::Owner, primary.key_handle.into(), persistent)
+    })?;
+    load(context, handle)
+}
+
+#[cfg(feature = "tpm")]
+fn ec_public_key(public: &TpmPublic) -> Result<PKey<Public>, anyhow::Error> {
+    let point = match public {
+        TpmPublic::Ecc { unique, .. } => unique,
+        _ => anyhow::bail!("the TPM key is not an EC key"),
+    };
+    let mut encoded = vec![0x04];
+    for coordinate in [point.x().value(), point.y().value()] {
+        if coordinate.len() > 32 {
+            anyhow::bail!("the TPM key is not a P-256 key");
+        }
+        encoded.extend(std::iter::repeat(0).take(32 - coordinate.len()));
+        encoded.extend_from_slice(coordinate);
+    }
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, &encoded, &mut ctx)?;
+    Ok(PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?)
+}
+
+#[cfg(feature = "tpm")]
+impl SigningKey for TpmKey {
+    fn algorithm(&self) -> Result<SigningAlgorithm, anyhow::Error> {
+        Ok(SigningAlgorithm::EcdsaP256Sha256)
+    }
+
+    fn public_key(&self) -> Result<PKey<Public>, anyhow::Error> {
+        Ok(self.public_key.clone())
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        self.sign_digest(HashAlgorithm::Sha256, &HashAlgorithm::Sha256.digest(data)?)
+    }
+
+    // the esapi context is not thread safe, so every signature gets a
+    // connection of its own
+    fn sign_digest(
+        &self,
+        _digest_algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let mut context = Context::new(self.tcti.clone())?;
+        let key_handle = load(&mut context, self.handle)?;
+        // the key is unrestricted, so no ticket proving the TPM made the
+        // digest is needed
+        let validation: HashcheckTicket = TPMT_TK_HASHCHECK {
+            tag: TPM2_ST_HASHCHECK,
+            hierarchy: TPM2_RH_NULL,
+            digest: Default::default(),
+        }
+        .try_into()?;
+        let digest = Digest::try_from(digest[..digest.len().min(32)].to_vec())?;
+        let signature = context.execute_with_nullauth_session(|context| {
+            context.sign(key_handle, digest, SignatureScheme::Null, validation)
+        })?;
+        match signature {
+            Signature::EcDsa(signature) => {
+                let mut raw = pad(signature.signature_r().value());
+                raw.extend(pad(signature.signature_s().value()));
+                key::ecdsa_der_from_raw(&raw)
+            }
+            _ => anyhow::bail!("the TPM did not make an ECDSA signature"),
+        }
+    }
+}
+
+// r and s come without leading zeros
+#[cfg(feature = "tpm")]
+fn pad(value: &[u8]) -> Vec<u8> {
+    let mut padded = vec![0; 32usize.saturating_sub(value.len())];
+    padded.extend_from_slice(value);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(parse_uri("tpm://0x81000001").unwrap(), 0x8100_0001);
+        assert_eq!(parse_uri("tpm://81010002").unwrap(), 0x8101_0002);
+        assert!(parse_uri("tpm://0x80000001").is_err());
+        assert!(parse_uri("tpm://lolwut").is_err());
+        assert!(parse_uri("yubikey://slot/9c").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Sigstore trusted root documents (`trusted_root.json`), which list the
+//! Fulcio certificate authorities, Rekor and CT log keys and timestamp
+//! authorities of an instance, each with the period it is valid for.
+
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{PKey, Public};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::tuf;
+use crate::verify::{Authority, LogKey, TrustRoot};
+
+pub const TRUSTED_ROOT_MEDIA_TYPE: &str =
+    "application/vnd.dev.sigstore.trustedroot+json;version=0.1";
+/// Name of the target in the sigstore TUF repositories.
+pub const TRUSTED_ROOT_TARGET: &str = "trusted_root.json";
+
+/// A trusted root in the protobuf-specs JSON encoding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedRoot {
+    pub media_type: String,
+    #[serde(default)]
+    pub tlogs: Vec<TransparencyLogInstance>,
+    #[serde(default)]
+    pub certificate_authorities: Vec<CertificateAuthority>,
+    #[serde(default)]
+    pub ctlogs: Vec<TransparencyLogInstance>,
+    #[serde(default)]
+    pub timestamp_authorities: Vec<CertificateAuthority>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparencyLogInstance {
+    pub base_url: String,
+    #[serde(default)]
+    pub hash_algorithm: Option<String>,
+    pub public_key: PublicKey,
+    #[serde(default)]
+    pub log_id: Option<LogId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKey {
+    /// Base64 encoded DER SubjectPublicKeyInfo.
+    pub raw_bytes: String,
+    #[serde(default)]
+    pub key_details: Option<String>,
+    #[serde(default)]
+    pub valid_for: Option<ValidFor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogId {
+    pub key_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateAuthority {
+    #[serde(default)]
+    pub uri: Option<String>,
+    pub cert_chain: CertChain,
+    #[serde(default)]
+    pub valid_for: Option<ValidFor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertChain {
+    pub certificates: Vec<Certificate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Certificate {
+    /// Base64 encoded DER certificate.
+    pub raw_bytes: String,
+}
+
+/// An RFC 3339 time range, open ended without `end`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidFor {
+    pub start: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+}
+
+impl ValidFor {
+    /// Whether `time` falls in the range, both ends included.
+    pub fn contains(&self, time: &Asn1TimeRef) -> Result<bool, anyhow::Error> {
+        let start = tuf::parse_time(&self.start)?;
+        if time.compare(&start)? == Ordering::Less {
+            return Ok(false);
+        }
+        match &self.end {
+            Some(end) => {
+                let end = tuf::parse_time(end)?;
+                Ok(time.compare(&end)? != Ordering::Greater)
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+// the log currently accepting entries, new signatures are logged with it
+fn current_key(
+    logs: &[TransparencyLogInstance],
+    now: &Asn1Time,
+) -> Result<Option<PKey<Public>>, anyhow::Error> {
+    for log in logs.iter().rev() {
+        let current = match &log.public_key.valid_for {
+            Some(valid_for) => valid_for.contains(now)?,
+            None => true,
+        };
+        if current {
+            let der = base64::decode(&log.public_key.raw_bytes)?;
+            return Ok(Some(PKey::public_key_from_der(&der)?));
+        }
+    }
+    Ok(None)
+}
+
+// every key of `logs`, the log ID defaults to the hash of the key as it
+// does in rekor and ct
+fn log_keys(logs: &[TransparencyLogInstance]) -> Result<Vec<LogKey>, anyhow::Error> {
+    logs.iter()
+        .map(|log| {
+            let der = base64::decode(&log.public_key.raw_bytes)?;
+            let log_id = match &log.log_id {
+                Some(log_id) => base64::decode(&log_id.key_id)?,
+                None => hash(MessageDigest::sha256(), &der)?.to_vec(),
+            };
+            Ok(LogKey {
+                log_id,
+                key: PKey::public_key_from_der(&der)?,
+                valid_for: log.public_key.valid_for.clone(),
+            })
+        })
+        .collect()
+}
+
+fn authorities(authorities: &[CertificateAuthority]) -> Result<Vec<Authority>, anyhow::Error> {
+    authorities
+        .iter()
+        .map(|authority| {
+            let certificates = authority
+                .cert_chain
+                .certificates
+                .iter()
+                .map(|cert| Ok(X509::from_der(&base64::decode(&cert.raw_bytes)?)?))
+                .collect::<Result<_, anyhow::Error>>()?;
+            Ok(Authority {
+                certificates,
+                valid_for: authority.valid_for.clone(),
+            })
+        })
+        .collect()
+}
+
+fn certificates(authorities: &[Authority]) -> Vec<X509> {
+    authorities
+        .iter()
+        .flat_map(|authority| authority.certificates.iter().cloned())
+        .collect()
+}
+
+impl TrustedRoot {
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        let trusted_root: TrustedRoot = serde_json::from_slice(json)?;
+        if !trusted_root
+            .media_type
+            .starts_with("application/vnd.dev.sigstore.trustedroot")
+        {
+            anyhow::bail!("{} is not a trusted root", trusted_root.media_type);
+        }
+        Ok(trusted_root)
+    }
+
+    /// The certificates and keys to verify with. All certificate authorities
+    /// and log keys are kept, since artifacts outlive the certificates and
+    /// keys they were signed and logged with, and are picked by log ID and
+    /// the period they were valid for. The current Rekor and CT log keys are
+    /// also given on their own, for signing.
+    pub fn trust_root(&self) -> Result<TrustRoot, anyhow::Error> {
+        let now = Asn1Time::days_from_now(0)?;
+        let fulcio_authorities = authorities(&self.certificate_authorities)?;
+        Ok(TrustRoot {
+            fulcio_chain: certificates(&fulcio_authorities),
+            rekor_key: current_key(&self.tlogs, &now)?,
+            ctlog_key: current_key(&self.ctlogs, &now)?,
+            tsa_chain: certificates(&authorities(&self.timestamp_authorities)?),
+            tlog_keys: log_keys(&self.tlogs)?,
+            ctlog_keys: log_keys(&self.ctlogs)?,
+            fulcio_authorities,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+
+    fn log(key: &PKey<openssl::pkey::Private>, start: &str, end: Option<&str>) -> String {
+        format!(
+            r#"{{"baseUrl": "https://rekor.example.com", "hashAlgorithm": "SHA2_256",
+                "publicKey": {{"rawBytes": "{}", "keyDetails": "PKIX_ECDSA_P256_SHA_256",
+                "validFor": {{"start": "{}"{}}}}}}}"#,
+            base64::encode(key.public_key_to_der().unwrap()),
+            start,
+            end.map(|end| format!(r#", "end": "{}""#, end))
+                .unwrap_or_default()
+        )
+    }
+
+    #[test]
+    fn test_trust_root() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let old_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let chain = X509::stack_from_pem(&std::fs::read("test_data/fulcio_chain.pem").unwrap())
+            .unwrap()
+            .iter()
+            .map(|cert| {
+                format!(
+                    r#"{{"rawBytes": "{}"}}"#,
+                    base64::encode(cert.to_der().unwrap())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let json = format!(
+            r#"{{
+                "mediaType": "{}",
+                "tlogs": [{}],
+                "certificateAuthorities": [{{"uri": "https://fulcio.example.com",
+                    "certChain": {{"certificates": [{}]}},
+                    "validFor": {{"start": "2022-04-13T20:06:15.000Z"}}}}],
+                "ctlogs": [{}, {}]
+            }}"#,
+            TRUSTED_ROOT_MEDIA_TYPE,
+            log(&key, "2021-01-12T11:53:27.000Z", None),
+            chain,
+            log(
+                &old_key,
+                "2021-03-14T00:00:00.000Z",
+                Some("2022-10-31T23:59:59.999Z")
+            ),
+            log(&key, "2022-10-20T00:00:00Z", None),
+        );
+        let trust_root = TrustedRoot::from_json(json.as_bytes())
+            .unwrap()
+            .trust_root()
+            .unwrap();
+        assert_eq!(
+            trust_root.fulcio_chain.len(),
+            chain.matches("rawBytes").count()
+        );
+        assert_eq!(trust_root.ctlog_keys.len(), 2);
+        assert_eq!(
+            trust_root.ctlog_keys[0].log_id,
+            hash(
+                MessageDigest::sha256(),
+                &old_key.public_key_to_der().unwrap()
+            )
+            .unwrap()
+            .to_vec()
+        );
+        assert_eq!(trust_root.fulcio_authorities.len(), 1);
+        assert!(trust_root.fulcio_authorities[0].valid_for.is_some());
+        assert!(trust_root.rekor_key.unwrap().public_eq(&key));
+        assert!(trust_root.ctlog_key.unwrap().public_eq(&key));
+        assert!(trust_root.tsa_chain.is_empty());
+        assert!(TrustedRoot::from_json(br#"{"mediaType": "application/json"}"#).is_err());
+    }
+}
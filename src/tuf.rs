@@ -0,0 +1,943 @@
+//! A small TUF client for the sigstore trust root repositories.
+//!
+//! The metadata is updated the way the TUF specification describes: root
+//! rotations first, each signed by the previous and the new root keys, then
+//! timestamp, snapshot and targets, each checked against the threshold of
+//! its role, for rollbacks and for expiry. Targets are only handed out when
+//! their length and hashes match the targets metadata.
+//!
+//! Everything is cached in a directory per repository, from which
+//! [`Repository::cached_target`] serves targets without network access. The
+//! cached metadata is checked against the cached root, but not for expiry,
+//! since an offline copy is usually older than the timestamp role allows.
+//!
+//! Trust is anchored in an initial `root.json`. The one of the public
+//! sigstore repository is embedded, others have to be given with
+//! [`Repository::with_initial_root`].
+//!
+//! [`Repository::export`] and [`Repository::import`] carry the cache into a
+//! network without access to the repository, as a single [`Export`] file.
+//! Imports are chained from the trusted root like updates, through the root
+//! rotations cached along the way.
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::algorithm::SigningAlgorithm;
+use crate::cache;
+use crate::http::{self, Service};
+
+pub const SIGSTORE_TUF_URL: &str = "https://tuf-repo-cdn.sigstore.dev";
+pub const SIGSTORE_STAGING_TUF_URL: &str = "https://tuf-repo-cdn.sigstage.dev";
+
+// root version 2 of the public repository, later ones are rotated to
+const SIGSTORE_ROOT: &[u8] = include_bytes!("../roots/sigstore.json");
+
+const ROOT: &str = "root";
+const TIMESTAMP: &str = "timestamp";
+const SNAPSHOT: &str = "snapshot";
+const TARGETS: &str = "targets";
+// a repository rotating its root more often than this is broken
+const MAX_ROOT_ROTATIONS: u64 = 1024;
+// the cached metadata, by file name
+const METADATA_FILES: [&str; 4] = [
+    "root.json",
+    "timestamp.json",
+    "snapshot.json",
+    "targets.json",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Metadata {
+    signed: Value,
+    signatures: Vec<Signature>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Signature {
+    keyid: String,
+    sig: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Root {
+    version: u64,
+    expires: String,
+    keys: HashMap<String, Key>,
+    roles: HashMap<String, Role>,
+    #[serde(default)]
+    consistent_snapshot: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Key {
+    keytype: String,
+    keyval: KeyValue,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct KeyValue {
+    public: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Role {
+    keyids: Vec<String>,
+    threshold: usize,
+}
+
+/// Timestamp and snapshot metadata, which both describe other metadata.
+#[derive(Debug, Clone, Deserialize)]
+struct Meta {
+    version: u64,
+    expires: String,
+    meta: HashMap<String, MetaFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MetaFile {
+    version: u64,
+    #[serde(default)]
+    length: Option<u64>,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Targets {
+    version: u64,
+    expires: String,
+    targets: HashMap<String, TargetFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TargetFile {
+    length: u64,
+    hashes: HashMap<String, String>,
+}
+
+/// The cached metadata and some targets of a repository in one file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Export {
+    /// The repository the metadata is from.
+    pub url: String,
+    /// Base64 encoded metadata by file name, with the cached root
+    /// rotations as `<version>.root.json`.
+    pub metadata: BTreeMap<String, String>,
+    /// Base64 encoded targets by name.
+    pub targets: BTreeMap<String, String>,
+}
+
+impl Export {
+    pub fn from_json(json: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Parses an RFC 3339 UTC time, as TUF metadata and trusted roots write
+/// them. Fractions of a second are dropped.
+pub fn parse_time(time: &str) -> Result<Asn1Time, anyhow::Error> {
+    let invalid = || anyhow::anyhow!("invalid time {}", time);
+    let time = time.strip_suffix('Z').ok_or_else(invalid)?;
+    let time = time.split('.').next().unwrap_or(time);
+    let digits: String = time.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 14 {
+        return Err(invalid());
+    }
+    Asn1Time::from_str(&format!("{}Z", digits)).map_err(|_| invalid())
+}
+
+/// Canonical JSON as TUF signs it: sorted keys, no whitespace and only
+/// quotes and backslashes escaped.
+pub fn canonical_json(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.extend_from_slice(b"null"),
+        Value::Bool(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Value::Number(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Value::String(value) => write_canonical_string(value, out),
+        Value::Array(values) => {
+            out.push(b'[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(b']');
+        }
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push(b'{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical_string(key, out);
+                out.push(b':');
+                write_canonical(&map[key], out);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+fn write_canonical_string(value: &str, out: &mut Vec<u8>) {
+    out.push(b'"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            out.push(b'\\');
+        }
+        let mut buffer = [0; 4];
+        out.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    }
+    out.push(b'"');
+}
+
+fn public_key(key: &Key) -> Result<PKey<Public>, anyhow::Error> {
+    match key.keytype.as_str() {
+        "ed25519" => Ok(PKey::public_key_from_raw_bytes(
+            &data_encoding::HEXLOWER_PERMISSIVE.decode(key.keyval.public.as_bytes())?,
+            Id::ED25519,
+        )?),
+        "ecdsa" | "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "rsa" => {
+            if key.keyval.public.starts_with("-----BEGIN") {
+                Ok(PKey::public_key_from_pem(key.keyval.public.as_bytes())?)
+            } else {
+                hex_ec_key(&key.keytype, &key.keyval.public)
+            }
+        }
+        keytype => anyhow::bail!("unsupported TUF key type {}", keytype),
+    }
+}
+
+// early sigstore roots hex encode ecdsa keys as uncompressed points
+fn hex_ec_key(keytype: &str, public: &str) -> Result<PKey<Public>, anyhow::Error> {
+    let nid = match keytype {
+        "ecdsa-sha2-nistp384" => Nid::SECP384R1,
+        _ => Nid::X9_62_PRIME256V1,
+    };
+    let group = EcGroup::from_curve_name(nid)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(
+        &group,
+        &data_encoding::HEXLOWER_PERMISSIVE.decode(public.as_bytes())?,
+        &mut ctx,
+    )?;
+    Ok(PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?)
+}
+
+// the initial root embedded for `url`, if any. the staging root is not
+// embedded yet, --staging needs its root.json given with --tuf-root until
+// it is added to roots/ next to the public one
+fn embedded_root(url: &str) -> Option<&'static [u8]> {
+    match url {
+        SIGSTORE_TUF_URL => Some(SIGSTORE_ROOT),
+        _ => None,
+    }
+}
+
+fn parse<T: DeserializeOwned>(json: &[u8]) -> Result<(Metadata, T), anyhow::Error> {
+    let metadata: Metadata = serde_json::from_slice(json)?;
+    let signed = serde_json::from_value(metadata.signed.clone())?;
+    Ok((metadata, signed))
+}
+
+impl Root {
+    /// Checks that `metadata` carries valid signatures of at least the
+    /// threshold of distinct keys of `role`.
+    fn verify_role(&self, role: &str, metadata: &Metadata) -> Result<(), anyhow::Error> {
+        let role_keys = self
+            .roles
+            .get(role)
+            .ok_or_else(|| anyhow::anyhow!("root has no {} role", role))?;
+        let signed = canonical_json(&metadata.signed);
+        let mut signers = HashSet::new();
+        for signature in &metadata.signatures {
+            if !role_keys.keyids.contains(&signature.keyid) || signers.contains(&signature.keyid) {
+                continue;
+            }
+            let key = match self.keys.get(&signature.keyid) {
+                Some(key) => public_key(key)?,
+                None => continue,
+            };
+            let sig = match data_encoding::HEXLOWER_PERMISSIVE.decode(signature.sig.as_bytes()) {
+                Ok(sig) => sig,
+                Err(_) => continue,
+            };
+            let algorithm = SigningAlgorithm::for_key(&key)?;
+            if algorithm.verify(&key, &signed, &sig).unwrap_or(false) {
+                signers.insert(signature.keyid.clone());
+            }
+        }
+        if role_keys.threshold == 0 || signers.len() < role_keys.threshold {
+            anyhow::bail!(
+                "{} metadata has {} valid signatures, {} are required",
+                role,
+                signers.len(),
+                role_keys.threshold
+            );
+        }
+        Ok(())
+    }
+}
+
+fn check_expiry(role: &str, expires: &str) -> Result<(), anyhow::Error> {
+    let now = Asn1Time::days_from_now(0)?;
+    let expires_at = parse_time(expires)?;
+    if now.compare(&expires_at)? != Ordering::Less {
+        anyhow::bail!("{} metadata expired at {}", role, expires);
+    }
+    Ok(())
+}
+
+fn check_hashes(
+    name: &str,
+    data: &[u8],
+    length: Option<u64>,
+    hashes: &HashMap<String, String>,
+) -> Result<(), anyhow::Error> {
+    if let Some(length) = length {
+        if data.len() as u64 != length {
+            anyhow::bail!("{} is {} bytes, expected {}", name, data.len(), length);
+        }
+    }
+    for (algorithm, expected) in hashes {
+        let actual = match algorithm.as_str() {
+            "sha256" => Sha256::digest(data).to_vec(),
+            "sha512" => Sha512::digest(data).to_vec(),
+            _ => continue,
+        };
+        if data_encoding::HEXLOWER.encode(&actual) != expected.to_lowercase() {
+            anyhow::bail!("{} does not match its {} hash", name, algorithm);
+        }
+    }
+    Ok(())
+}
+
+fn read_cached(path: &Path) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => anyhow::bail!("could not read {}: {}", path.display(), e),
+    }
+}
+
+// the next version of `root`, which both have to have signed
+fn rotate(root: &Root, json: &[u8]) -> Result<Root, anyhow::Error> {
+    let next_version = root.version + 1;
+    let (metadata, next) = parse::<Root>(json)?;
+    root.verify_role(ROOT, &metadata)?;
+    next.verify_role(ROOT, &metadata)?;
+    if next.version != next_version {
+        anyhow::bail!("{}.root.json has version {}", next_version, next.version);
+    }
+    Ok(next)
+}
+
+/// A TUF repository and its local cache.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    url: String,
+    dir: PathBuf,
+    initial_root: Option<Vec<u8>>,
+}
+
+impl Repository {
+    pub fn new(url: &str, dir: impl Into<PathBuf>) -> Self {
+        let url = url.trim_end_matches('/').to_string();
+        Repository {
+            initial_root: embedded_root(&url).map(<[u8]>::to_vec),
+            url,
+            dir: dir.into(),
+        }
+    }
+
+    /// The repository at `url`, cached in `tuf/<host>` of the
+    /// [`cache::cache_dir`].
+    pub fn open_default(url: &str) -> Result<Self, anyhow::Error> {
+        let host = url::Url::parse(url)?
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("TUF repository url {} has no host", url))?
+            .to_string();
+        Ok(Repository::new(
+            url,
+            cache::cache_dir()?.join("tuf").join(host),
+        ))
+    }
+
+    /// Anchors trust in `root_json` rather than in the embedded root of the
+    /// repository. It is only used while nothing is cached.
+    pub fn with_initial_root(mut self, root_json: &[u8]) -> Self {
+        self.initial_root = Some(root_json.to_vec());
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let url = format!("{}/{}", self.url, path);
+        let response = http::send(http::client(Service::Other).get(&url)).await?;
+        // a missing root is how the end of the rotations is discovered
+        if response.status() == StatusCode::NOT_FOUND || response.status() == StatusCode::FORBIDDEN
+        {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn fetch_required(&self, path: &str) -> Result<Vec<u8>, anyhow::Error> {
+        self.fetch(path)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("{}/{} does not exist", self.url, path))
+    }
+
+    fn write(&self, name: &str, data: &[u8]) -> Result<(), anyhow::Error> {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data)
+            .map_err(|e| anyhow::anyhow!("could not write {}: {}", path.display(), e))
+    }
+
+    // the cached root, else the initial one. the repository cannot vouch
+    // for its own first root
+    fn trusted_root(&self) -> Result<Root, anyhow::Error> {
+        let (json, cached) = match read_cached(&self.dir.join("root.json"))? {
+            Some(json) => (json, true),
+            None => match &self.initial_root {
+                Some(json) => (json.clone(), false),
+                None => anyhow::bail!(
+                    "no trusted root for {}, give its initial root.json with --tuf-root",
+                    self.url
+                ),
+            },
+        };
+        let (metadata, root) = parse::<Root>(&json)?;
+        root.verify_role(ROOT, &metadata)?;
+        if !cached {
+            self.write("root.json", &json)?;
+        }
+        Ok(root)
+    }
+
+    async fn update_root(&self) -> Result<Root, anyhow::Error> {
+        let mut root = self.trusted_root()?;
+        for _ in 0..MAX_ROOT_ROTATIONS {
+            let next_version = root.version + 1;
+            let json = match self.fetch(&format!("{}.root.json", next_version)).await? {
+                Some(json) => json,
+                None => return Ok(root),
+            };
+            let next = rotate(&root, &json)?;
+            // kept so that exports can be chained from older roots
+            self.write(&format!("{}.root.json", next_version), &json)?;
+            self.write("root.json", &json)?;
+            root = next;
+        }
+        anyhow::bail!("{} rotated its root too often", self.url)
+    }
+
+    /// Brings the cached metadata up to date.
+    pub async fn update(&self) -> Result<(), anyhow::Error> {
+        let root = self.update_root().await?;
+        check_expiry(ROOT, &root.expires)?;
+
+        let json = self.fetch_required("timestamp.json").await?;
+        let (metadata, timestamp) = parse::<Meta>(&json)?;
+        root.verify_role(TIMESTAMP, &metadata)?;
+        self.check_rollback::<Meta>(&root, TIMESTAMP, timestamp.version, |t| t.version)?;
+        check_expiry(TIMESTAMP, &timestamp.expires)?;
+        self.write("timestamp.json", &json)?;
+
+        let snapshot_meta = timestamp
+            .meta
+            .get("snapshot.json")
+            .ok_or_else(|| anyhow::anyhow!("timestamp metadata does not list the snapshot"))?;
+        let json = self
+            .fetch_required(&self.metadata_path(&root, SNAPSHOT, snapshot_meta.version))
+            .await?;
+        check_hashes(
+            "snapshot.json",
+            &json,
+            snapshot_meta.length,
+            &snapshot_meta.hashes,
+        )?;
+        let (metadata, snapshot) = parse::<Meta>(&json)?;
+        root.verify_role(SNAPSHOT, &metadata)?;
+        if snapshot.version != snapshot_meta.version {
+            anyhow::bail!(
+                "snapshot metadata has version {}, expected {}",
+                snapshot.version,
+                snapshot_meta.version
+            );
+        }
+        self.check_rollback::<Meta>(&root, SNAPSHOT, snapshot.version, |s| s.version)?;
+        check_expiry(SNAPSHOT, &snapshot.expires)?;
+        self.write("snapshot.json", &json)?;
+
+        let targets_meta = snapshot
+            .meta
+            .get("targets.json")
+            .ok_or_else(|| anyhow::anyhow!("snapshot metadata does not list the targets"))?;
+        let json = self
+            .fetch_required(&self.metadata_path(&root, TARGETS, targets_meta.version))
+            .await?;
+        check_hashes(
+            "targets.json",
+            &json,
+            targets_meta.length,
+            &targets_meta.hashes,
+        )?;
+        let (metadata, targets) = parse::<Targets>(&json)?;
+        root.verify_role(TARGETS, &metadata)?;
+        if targets.version != targets_meta.version {
+            anyhow::bail!(
+                "targets metadata has version {}, expected {}",
+                targets.version,
+                targets_meta.version
+            );
+        }
+        self.check_rollback::<Targets>(&root, TARGETS, targets.version, |t| t.version)?;
+        check_expiry(TARGETS, &targets.expires)?;
+        self.write("targets.json", &json)?;
+        Ok(())
+    }
+
+    fn metadata_path(&self, root: &Root, role: &str, version: u64) -> String {
+        if root.consistent_snapshot {
+            format!("{}.{}.json", version, role)
+        } else {
+            format!("{}.json", role)
+        }
+    }
+
+    // cached metadata that no longer verifies, after a key rotation, is
+    // simply replaced
+    fn check_rollback<T: DeserializeOwned>(
+        &self,
+        root: &Root,
+        role: &str,
+        version: u64,
+        cached_version: impl Fn(&T) -> u64,
+    ) -> Result<(), anyhow::Error> {
+        let json = match read_cached(&self.dir.join(format!("{}.json", role)))? {
+            Some(json) => json,
+            None => return Ok(()),
+        };
+        let (metadata, cached) = parse::<T>(&json)?;
+        if root.verify_role(role, &metadata).is_ok() && version < cached_version(&cached) {
+            anyhow::bail!(
+                "{} metadata version {} is older than the cached version {}",
+                role,
+                version,
+                cached_version(&cached)
+            );
+        }
+        Ok(())
+    }
+
+    // the cached targets metadata, checked against the cached root
+    fn cached_targets(&self) -> Result<(Root, Targets), anyhow::Error> {
+        let json = read_cached(&self.dir.join("root.json"))?
+            .ok_or_else(|| anyhow::anyhow!("no trust root is cached in {}", self.dir.display()))?;
+        let (metadata, root) = parse::<Root>(&json)?;
+        root.verify_role(ROOT, &metadata)?;
+        let json = read_cached(&self.dir.join("targets.json"))?
+            .ok_or_else(|| anyhow::anyhow!("no targets are cached in {}", self.dir.display()))?;
+        let (metadata, targets) = parse::<Targets>(&json)?;
+        root.verify_role(TARGETS, &metadata)?;
+        Ok((root, targets))
+    }
+
+    fn target_file<'a>(
+        &self,
+        targets: &'a Targets,
+        name: &str,
+    ) -> Result<&'a TargetFile, anyhow::Error> {
+        targets
+            .targets
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("{} has no target {}", self.url, name))
+    }
+
+    /// The target `name` from the cache, without network access.
+    pub fn cached_target(&self, name: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let (_, targets) = self.cached_targets()?;
+        let target = self.target_file(&targets, name)?;
+        let path = self.dir.join("targets").join(name);
+        let data = read_cached(&path)?.ok_or_else(|| anyhow::anyhow!("{} is not cached", name))?;
+        check_hashes(name, &data, Some(target.length), &target.hashes)?;
+        Ok(data)
+    }
+
+    /// Updates the metadata and returns the target `name`, downloading it
+    /// unless the cached copy is current.
+    pub async fn fetch_target(&self, name: &str) -> Result<Vec<u8>, anyhow::Error> {
+        self.update().await?;
+        let (root, targets) = self.cached_targets()?;
+        let target = self.target_file(&targets, name)?;
+        if let Ok(data) = self.cached_target(name) {
+            return Ok(data);
+        }
+        let path = match target.hashes.get("sha256") {
+            Some(sha256) if root.consistent_snapshot => format!("targets/{}.{}", sha256, name),
+            _ => format!("targets/{}", name),
+        };
+        let data = self.fetch_required(&path).await?;
+        check_hashes(name, &data, Some(target.length), &target.hashes)?;
+        self.write(&format!("targets/{}", name), &data)?;
+        Ok(data)
+    }
+
+    /// The cached metadata and the cached targets `names`, which are checked
+    /// as [`Repository::cached_target`] checks them.
+    pub fn export(&self, names: &[&str]) -> Result<Export, anyhow::Error> {
+        let mut metadata = BTreeMap::new();
+        for file in METADATA_FILES {
+            let data = read_cached(&self.dir.join(file))?.ok_or_else(|| {
+                anyhow::anyhow!("{} is not cached in {}", file, self.dir.display())
+            })?;
+            metadata.insert(file.to_string(), base64::encode(data));
+        }
+        let (_, root) = parse::<Root>(&base64::decode(&metadata["root.json"])?)?;
+        for version in 1..root.version {
+            let file = format!("{}.root.json", version);
+            if let Some(data) = read_cached(&self.dir.join(&file))? {
+                metadata.insert(file, base64::encode(data));
+            }
+        }
+        let mut targets = BTreeMap::new();
+        for name in names {
+            targets.insert(name.to_string(), base64::encode(self.cached_target(name)?));
+        }
+        Ok(Export {
+            url: self.url.clone(),
+            metadata,
+            targets,
+        })
+    }
+
+    /// Replaces the cache with the contents of `export`, after checking its
+    /// signatures and target hashes. Its root has to chain from the cached
+    /// or initial one through the rotations in the export, an older root is
+    /// refused. The imported metadata is not checked for expiry.
+    pub fn import(&self, export: &Export) -> Result<(), anyhow::Error> {
+        if export.url.trim_end_matches('/') != self.url {
+            anyhow::bail!("the export is of {}, not of {}", export.url, self.url);
+        }
+        let mut files = HashMap::new();
+        for file in METADATA_FILES {
+            let data = export
+                .metadata
+                .get(file)
+                .ok_or_else(|| anyhow::anyhow!("the export has no {}", file))?;
+            files.insert(file, base64::decode(data)?);
+        }
+
+        let (metadata, exported) = parse::<Root>(&files["root.json"])?;
+        let mut root = self.trusted_root()?;
+        if exported.version < root.version {
+            anyhow::bail!(
+                "the exported root version {} is older than the trusted version {}",
+                exported.version,
+                root.version
+            );
+        }
+        let mut rotations = Vec::new();
+        while root.version + 1 < exported.version {
+            let file = format!("{}.root.json", root.version + 1);
+            let json = match export.metadata.get(&file) {
+                Some(data) => base64::decode(data)?,
+                None => anyhow::bail!(
+                    "the export has no {} to chain its root version {} from the trusted version {}",
+                    file,
+                    exported.version,
+                    root.version
+                ),
+            };
+            root = rotate(&root, &json)?;
+            rotations.push((file, json));
+        }
+        if root.version < exported.version {
+            rotations.push((
+                format!("{}.root.json", exported.version),
+                files["root.json"].clone(),
+            ));
+            root = rotate(&root, &files["root.json"])?;
+        } else {
+            // the same version, still signed by the keys trusted for it
+            root.verify_role(ROOT, &metadata)?;
+            exported.verify_role(ROOT, &metadata)?;
+            root = exported;
+        }
+        for (file, role) in [
+            ("timestamp.json", TIMESTAMP),
+            ("snapshot.json", SNAPSHOT),
+            ("targets.json", TARGETS),
+        ] {
+            let metadata: Metadata = serde_json::from_slice(&files[file])?;
+            root.verify_role(role, &metadata)?;
+        }
+        let (_, targets) = parse::<Targets>(&files["targets.json"])?;
+        let mut target_files = Vec::new();
+        for (name, data) in &export.targets {
+            let data = base64::decode(data)?;
+            let target = self.target_file(&targets, name)?;
+            check_hashes(name, &data, Some(target.length), &target.hashes)?;
+            target_files.push((format!("targets/{}", name), data));
+        }
+
+        for (file, json) in rotations {
+            self.write(&file, &json)?;
+        }
+        for file in METADATA_FILES {
+            self.write(file, &files[file])?;
+        }
+        for (path, data) in target_files {
+            self.write(&path, &data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn sign(signed: &Value, keys: &[(&str, &PKey<Private>)]) -> Metadata {
+        let data = canonical_json(signed);
+        Metadata {
+            signed: signed.clone(),
+            signatures: keys
+                .iter()
+                .map(|(keyid, key)| Signature {
+                    keyid: keyid.to_string(),
+                    sig: data_encoding::HEXLOWER
+                        .encode(&SigningAlgorithm::EcdsaP256Sha256.sign(key, &data).unwrap()),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let value: Value =
+            serde_json::from_str(r#"{"b": [1, true, null], "a": "say \"hi\"\n\\"}"#).unwrap();
+        assert_eq!(
+            canonical_json(&value),
+            b"{\"a\":\"say \\\"hi\\\"\n\\\\\",\"b\":[1,true,null]}"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let time = parse_time("2022-04-13T20:06:15.000Z").unwrap();
+        let expected = Asn1Time::from_str("20220413200615Z").unwrap();
+        assert_eq!(time.compare(&expected).unwrap(), Ordering::Equal);
+        assert!(parse_time("2022-04-13T20:06:15+02:00").is_err());
+        assert!(check_expiry(ROOT, "2000-01-01T00:00:00Z").is_err());
+        assert!(check_expiry(ROOT, "2999-01-01T00:00:00Z").is_ok());
+    }
+
+    #[test]
+    fn test_verify_role() {
+        let (one, two, other) = (key(), key(), key());
+        let pem =
+            |key: &PKey<Private>| String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+        let signed = serde_json::json!({
+            "_type": "root",
+            "version": 1,
+            "expires": "2999-01-01T00:00:00Z",
+            "consistent_snapshot": true,
+            "keys": {
+                "one": {"keytype": "ecdsa", "scheme": "ecdsa-sha2-nistp256", "keyval": {"public": pem(&one)}},
+                "two": {"keytype": "ecdsa", "scheme": "ecdsa-sha2-nistp256", "keyval": {"public": pem(&two)}},
+            },
+            "roles": {
+                "root": {"keyids": ["one", "two"], "threshold": 2},
+                "timestamp": {"keyids": ["one"], "threshold": 1},
+            },
+        });
+        let root: Root = serde_json::from_value(signed.clone()).unwrap();
+        assert!(root
+            .verify_role(ROOT, &sign(&signed, &[("one", &one), ("two", &two)]))
+            .is_ok());
+        // the same key twice does not make a threshold
+        assert!(root
+            .verify_role(ROOT, &sign(&signed, &[("one", &one), ("one", &one)]))
+            .is_err());
+        assert!(root
+            .verify_role(ROOT, &sign(&signed, &[("one", &one), ("two", &other)]))
+            .is_err());
+        assert!(root
+            .verify_role(TIMESTAMP, &sign(&signed, &[("one", &one)]))
+            .is_ok());
+        assert!(root
+            .verify_role(SNAPSHOT, &sign(&signed, &[("one", &one)]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_embedded_root() {
+        let (metadata, root) = parse::<Root>(SIGSTORE_ROOT).unwrap();
+        assert!(root.verify_role(ROOT, &metadata).is_ok());
+        assert!(embedded_root(SIGSTORE_TUF_URL).is_some());
+        assert!(embedded_root("https://tuf.example.com").is_none());
+        assert!(Repository::new("https://tuf.example.com", "unused")
+            .trusted_root()
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_hashes() {
+        let sha256 = data_encoding::HEXLOWER.encode(&Sha256::digest(b"ohhai"));
+        let hashes = HashMap::from([("sha256".to_string(), sha256)]);
+        assert!(check_hashes("t", b"ohhai", Some(5), &hashes).is_ok());
+        assert!(check_hashes("t", b"ohhai", Some(4), &hashes).is_err());
+        assert!(check_hashes("t", b"ohhaj", None, &hashes).is_err());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let (key, forged) = (key(), key());
+        let root_of = |key: &PKey<Private>, version: u64| {
+            let pem = String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+            let role = serde_json::json!({"keyids": ["k"], "threshold": 1});
+            serde_json::json!({
+                "_type": "root",
+                "version": version,
+                "expires": "2999-01-01T00:00:00Z",
+                "consistent_snapshot": true,
+                "keys": {"k": {"keytype": "ecdsa", "scheme": "ecdsa-sha2-nistp256", "keyval": {"public": pem}}},
+                "roles": {"root": role, "timestamp": role, "snapshot": role, "targets": role},
+            })
+        };
+        let target =
+            b"{\"mediaType\": \"application/vnd.dev.sigstore.trustedroot+json;version=0.1\"}";
+        let targets = serde_json::json!({
+            "_type": "targets",
+            "version": 1,
+            "expires": "2999-01-01T00:00:00Z",
+            "targets": {"trusted_root.json": {
+                "length": target.len(),
+                "hashes": {"sha256": data_encoding::HEXLOWER.encode(&Sha256::digest(target))},
+            }},
+        });
+        let meta = serde_json::json!({
+            "_type": "snapshot",
+            "version": 1,
+            "expires": "2999-01-01T00:00:00Z",
+            "meta": {"targets.json": {"version": 1}},
+        });
+        let signed = |signed: &Value, key: &PKey<Private>| {
+            serde_json::to_vec(&sign(signed, &[("k", key)])).unwrap()
+        };
+        let export_of = |root: &Value, key: &PKey<Private>| Export {
+            url: "https://tuf.example.com".to_string(),
+            metadata: BTreeMap::from([
+                ("root.json".to_string(), base64::encode(signed(root, key))),
+                (
+                    "timestamp.json".to_string(),
+                    base64::encode(signed(&meta, key)),
+                ),
+                (
+                    "snapshot.json".to_string(),
+                    base64::encode(signed(&meta, key)),
+                ),
+                (
+                    "targets.json".to_string(),
+                    base64::encode(signed(&targets, key)),
+                ),
+            ]),
+            targets: BTreeMap::from([("trusted_root.json".to_string(), base64::encode(target))]),
+        };
+        let export = export_of(&root_of(&key, 1), &key);
+
+        let dir = std::env::temp_dir().join(format!("ferris-sign-tuf-{}", std::process::id()));
+        let repository = Repository::new("https://tuf.example.com", &dir)
+            .with_initial_root(&signed(&root_of(&key, 1), &key));
+        repository.import(&export).unwrap();
+        assert_eq!(
+            repository.cached_target("trusted_root.json").unwrap(),
+            target
+        );
+        let json = repository
+            .export(&["trusted_root.json"])
+            .unwrap()
+            .to_json()
+            .unwrap();
+        assert_eq!(Export::from_json(json.as_bytes()).unwrap(), export);
+        let mut tampered = export.clone();
+        tampered
+            .targets
+            .insert("trusted_root.json".to_string(), base64::encode(b"{}"));
+        assert!(repository.import(&tampered).is_err());
+        assert!(Repository::new("https://other.example.com", &dir)
+            .import(&export)
+            .is_err());
+
+        // a root signed only by itself does not chain from the trusted one
+        assert!(repository
+            .import(&export_of(&root_of(&forged, 1), &forged))
+            .is_err());
+        assert!(repository
+            .import(&export_of(&root_of(&forged, 2), &forged))
+            .is_err());
+        assert!(
+            Repository::new("https://tuf.example.com", dir.join("empty"))
+                .import(&export)
+                .is_err()
+        );
+
+        // rotations are carried along to import from older roots
+        let older = Repository::new("https://tuf.example.com", dir.join("older"))
+            .with_initial_root(&signed(&root_of(&key, 1), &key));
+        assert!(older.import(&export_of(&root_of(&key, 3), &key)).is_err());
+        for version in [2, 3] {
+            repository
+                .import(&export_of(&root_of(&key, version), &key))
+                .unwrap();
+        }
+        let rotated = repository.export(&["trusted_root.json"]).unwrap();
+        assert!(rotated.metadata.contains_key("2.root.json"));
+        older.import(&rotated).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
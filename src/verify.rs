@@ -0,0 +1,766 @@
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::pkey::{PKey, PKeyRef, Public};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::{X509StoreContext, X509VerifyResult, X509};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::algorithm::{HashAlgorithm, SigningAlgorithm};
+use crate::bundle::{Bundle, TransparencyLogEntry};
+use crate::dsse::Envelope;
+use crate::expiry::Claim;
+use crate::intoto::{self, Statement};
+use crate::trusted_root::ValidFor;
+use crate::{sct, timestamp, tlog};
+
+/// Certificates and keys that verification is anchored in.
+#[derive(Debug, Clone, Default)]
+pub struct TrustRoot {
+    /// Fulcio root and intermediate certificates.
+    pub fulcio_chain: Vec<X509>,
+    /// Rekor public key, used for signed entry timestamps and checkpoints.
+    pub rekor_key: Option<PKey<Public>>,
+    /// Public key of the CT log Fulcio submits certificates to.
+    pub ctlog_key: Option<PKey<Public>>,
+    /// Timestamp authority root and intermediate certificates, bundled
+    /// timestamps are ignored without them.
+    pub tsa_chain: Vec<X509>,
+    /// Every Rekor key of a trusted root, log entries are checked against
+    /// the one with their log ID that was valid when they were integrated.
+    /// `rekor_key` is used without them.
+    pub tlog_keys: Vec<LogKey>,
+    /// Every CT log key of a trusted root, embedded SCTs are checked against
+    /// those valid when the certificate was issued. `ctlog_key` is used
+    /// without them.
+    pub ctlog_keys: Vec<LogKey>,
+    /// The authorities in `fulcio_chain` with the periods they issued
+    /// certificates in, any certificate chaining up to `fulcio_chain` is
+    /// accepted without them.
+    pub fulcio_authorities: Vec<Authority>,
+}
+
+/// A transparency log key, with the ID entries name the log by.
+#[derive(Debug, Clone)]
+pub struct LogKey {
+    /// SHA-256 of the DER public key, unless the trusted root says otherwise.
+    pub log_id: Vec<u8>,
+    pub key: PKey<Public>,
+    pub valid_for: Option<ValidFor>,
+}
+
+impl LogKey {
+    fn is_valid_at(&self, time: &Asn1TimeRef) -> Result<bool, anyhow::Error> {
+        match &self.valid_for {
+            Some(valid_for) => valid_for.contains(time),
+            None => Ok(true),
+        }
+    }
+}
+
+/// The certificates of a certificate authority.
+#[derive(Debug, Clone)]
+pub struct Authority {
+    pub certificates: Vec<X509>,
+    pub valid_for: Option<ValidFor>,
+}
+
+impl TrustRoot {
+    // the key logging `entry`, by its log ID and integrated time. rekor v2
+    // entries have no integrated time, the certificate was issued for the
+    // signature instead
+    fn tlog_key(
+        &self,
+        entry: &TransparencyLogEntry,
+        cert: &X509,
+    ) -> Result<&PKey<Public>, anyhow::Error> {
+        if self.tlog_keys.is_empty() {
+            return self.rekor_key.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("no Rekor public key to verify the log entry with")
+            });
+        }
+        let log_id = base64::decode(&entry.log_id.key_id)?;
+        let integrated_time;
+        let time = match entry.integrated_time.parse()? {
+            0 => cert.not_before(),
+            time => {
+                integrated_time = Asn1Time::from_unix(time)?;
+                &integrated_time
+            }
+        };
+        for log_key in &self.tlog_keys {
+            if log_key.log_id == log_id && log_key.is_valid_at(time)? {
+                return Ok(&log_key.key);
+            }
+        }
+        anyhow::bail!(
+            "no Rekor key with log ID {} was valid at {}",
+            data_encoding::HEXLOWER.encode(&log_id),
+            time
+        )
+    }
+
+    // the ct log keys that were valid when `cert` was issued, the sct names
+    // the one it is from
+    fn ctlog_keys_for(&self, cert: &X509) -> Result<Vec<&PKey<Public>>, anyhow::Error> {
+        if self.ctlog_keys.is_empty() {
+            return Ok(self.ctlog_key.iter().collect());
+        }
+        let mut keys = Vec::new();
+        for log_key in &self.ctlog_keys {
+            if log_key.is_valid_at(cert.not_before())? {
+                keys.push(&log_key.key);
+            }
+        }
+        if keys.is_empty() {
+            anyhow::bail!("no CT log key was valid at {}", cert.not_before());
+        }
+        Ok(keys)
+    }
+
+    // the authority issuing `cert` has to have been valid when it did
+    fn verify_authority(&self, cert: &X509) -> Result<(), anyhow::Error> {
+        if self.fulcio_authorities.is_empty() {
+            return Ok(());
+        }
+        for authority in &self.fulcio_authorities {
+            if issuer_of(cert, &authority.certificates).is_none() {
+                continue;
+            }
+            let valid = match &authority.valid_for {
+                Some(valid_for) => valid_for.contains(cert.not_before())?,
+                None => true,
+            };
+            if valid {
+                return Ok(());
+            }
+        }
+        anyhow::bail!(
+            "no certificate authority issuing the signing certificate was valid at {}",
+            cert.not_before()
+        )
+    }
+}
+
+/// Checks `signature` over the `digest_algorithm` digest of `artifact` with
+/// the public key in `cert`.
+pub fn verify_signature(
+    cert: &X509,
+    digest_algorithm: HashAlgorithm,
+    artifact: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let public_key = cert.public_key()?;
+    verify_signature_with_key(&public_key, digest_algorithm, artifact, signature)
+}
+
+/// Checks `signature` over the `digest_algorithm` digest of `artifact` with a
+/// self managed `public_key`. Ed25519 signs the artifact itself, so
+/// `digest_algorithm` does not matter for it.
+pub fn verify_signature_with_key(
+    public_key: &PKeyRef<Public>,
+    digest_algorithm: HashAlgorithm,
+    artifact: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let algorithm = SigningAlgorithm::for_key(public_key)?;
+    if !algorithm.is_prehashed() {
+        return algorithm.verify(public_key, artifact, signature);
+    }
+    let digest = digest_algorithm.digest(artifact)?;
+    algorithm.verify_digest(public_key, digest_algorithm, &digest, signature)
+}
+
+/// The digest signatures made with the key in `cert` are over by default.
+pub fn default_digest_algorithm(cert: &X509) -> Result<HashAlgorithm, anyhow::Error> {
+    let public_key = cert.public_key()?;
+    Ok(SigningAlgorithm::for_key(&public_key)?.hash())
+}
+
+/// Checks that `cert` chains up to a self signed certificate in
+/// `fulcio_chain`. Any other certificates in `fulcio_chain` are used as
+/// intermediates.
+pub fn verify_cert_chain(cert: &X509, fulcio_chain: &[X509]) -> Result<bool, anyhow::Error> {
+    let mut store_builder = X509StoreBuilder::new()?;
+    let mut intermediates = Stack::new()?;
+    for ca in fulcio_chain {
+        // self signed certs are trust anchors, anything else is an intermediate
+        if ca.issued(ca) == X509VerifyResult::OK {
+            store_builder.add_cert(ca.clone())?;
+        } else {
+            intermediates.push(ca.clone())?;
+        }
+    }
+    // fulcio certs are only valid for ten minutes after issuance, so the
+    // validity window is not checked here
+    store_builder.set_flags(X509VerifyFlags::NO_CHECK_TIME)?;
+    let store = store_builder.build();
+
+    let mut context = X509StoreContext::new()?;
+    Ok(context.init(&store, cert, &intermediates, |c| c.verify_cert())?)
+}
+
+/// Checks that `cert` was valid at `time`, in seconds since the epoch.
+///
+/// Fulcio certificates expire minutes after issuance, so they are checked
+/// against a trusted signing time rather than the current time.
+pub fn verify_cert_valid_at(cert: &X509, time: i64) -> Result<(), anyhow::Error> {
+    let at = Asn1Time::from_unix(time)?;
+    if cert.not_before() > at {
+        anyhow::bail!(
+            "certificate was not yet valid at {}, it is valid from {}",
+            &*at,
+            cert.not_before()
+        );
+    }
+    if cert.not_after() < at {
+        anyhow::bail!(
+            "certificate had expired at {}, it was valid until {}",
+            &*at,
+            cert.not_after()
+        );
+    }
+    Ok(())
+}
+
+/// Verifies both the certificate chain and the signature, failing with a
+/// descriptive error if either does not hold.
+pub fn verify_blob(
+    cert: &X509,
+    fulcio_chain: &[X509],
+    digest_algorithm: HashAlgorithm,
+    blob: &[u8],
+    signature: &[u8],
+) -> Result<(), anyhow::Error> {
+    if !verify_cert_chain(cert, fulcio_chain)? {
+        anyhow::bail!("Signing certificate does not chain up to the Fulcio root");
+    }
+    if !verify_signature(cert, digest_algorithm, blob, signature)? {
+        anyhow::bail!("Signature verification failed");
+    }
+    Ok(())
+}
+
+fn issuer_of<'a>(cert: &X509, chain: &'a [X509]) -> Option<&'a X509> {
+    chain
+        .iter()
+        .find(|ca| ca.issued(cert) == X509VerifyResult::OK)
+}
+
+/// Verifies the SCT embedded in `cert` against the CT log key, looking up the
+/// issuing certificate in `chain`.
+pub fn verify_embedded_sct(
+    cert: &X509,
+    chain: &[X509],
+    ctlog_key: &PKeyRef<Public>,
+) -> Result<(), anyhow::Error> {
+    let issuer = issuer_of(cert, chain)
+        .ok_or_else(|| anyhow::anyhow!("issuer of the signing certificate not found"))?;
+    sct::verify_sct(cert, issuer, ctlog_key)?;
+    Ok(())
+}
+
+// checks that the log entry is for this signature and artifact, returning
+// the logged PEM certificate or public key for the caller to compare
+fn verify_tlog_body(
+    entry: &TransparencyLogEntry,
+    signature: &[u8],
+    digest: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+    // both kinds are canonicalized to the same layout, rekord just hashes
+    // the artifact on the log side
+    if !matches!(entry.kind_version.kind.as_str(), "hashedrekord" | "rekord") {
+        anyhow::bail!("unsupported log entry kind {}", entry.kind_version.kind);
+    }
+    let body: serde_json::Value =
+        serde_json::from_slice(&base64::decode(&entry.canonicalized_body)?)?;
+    let (logged_signature, logged_cert, logged_digest) = if entry.kind_version.version == "0.0.2" {
+        logged_v002(&body["spec"]["hashedRekordV002"])?
+    } else {
+        let spec = &body["spec"];
+        (
+            base64::decode(spec["signature"]["content"].as_str().unwrap_or_default())?,
+            base64::decode(
+                spec["signature"]["publicKey"]["content"]
+                    .as_str()
+                    .unwrap_or_default(),
+            )?,
+            spec["data"]["hash"]["value"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        )
+    };
+
+    if logged_signature != signature {
+        anyhow::bail!("log entry is for a different signature");
+    }
+    if logged_digest != data_encoding::HEXLOWER.encode(digest) {
+        anyhow::bail!("log entry is for a different artifact");
+    }
+    Ok(logged_cert)
+}
+
+// hashedrekord 0.0.2, as Rekor v2 logs it, has the verifier in DER and the
+// digest in base64
+fn logged_v002(spec: &serde_json::Value) -> Result<(Vec<u8>, Vec<u8>, String), anyhow::Error> {
+    let signature = base64::decode(spec["signature"]["content"].as_str().unwrap_or_default())?;
+    let verifier = &spec["signature"]["verifier"];
+    let logged = if let Some(cert) = verifier["x509Certificate"]["rawBytes"].as_str() {
+        X509::from_der(&base64::decode(cert)?)?.to_pem()?
+    } else {
+        let key = verifier["publicKey"]["rawBytes"]
+            .as_str()
+            .unwrap_or_default();
+        PKey::public_key_from_der(&base64::decode(key)?)?.public_key_to_pem()?
+    };
+    let digest = base64::decode(spec["data"]["digest"].as_str().unwrap_or_default())?;
+    Ok((signature, logged, data_encoding::HEXLOWER.encode(&digest)))
+}
+
+/// Verifies `blob` against a Sigstore bundle using only the material in the
+/// bundle and `trust_root`, without any network access.
+///
+/// The embedded SCT is only checked when `trust_root` carries a CT log key.
+/// Returns the earliest trusted time the signature is known to have existed
+/// at.
+pub fn verify_bundle(
+    bundle: &Bundle,
+    blob: &[u8],
+    trust_root: &TrustRoot,
+) -> Result<i64, anyhow::Error> {
+    verify_bundle_tlog(bundle, blob, trust_root, true)
+}
+
+/// Like [`verify_bundle`], but accepts bundles without a transparency log
+/// entry, such as those written by `sign --no-upload`. Their signing time
+/// has to come from a timestamp instead. An entry that is there is still
+/// verified.
+pub fn verify_bundle_without_tlog(
+    bundle: &Bundle,
+    blob: &[u8],
+    trust_root: &TrustRoot,
+) -> Result<i64, anyhow::Error> {
+    verify_bundle_tlog(bundle, blob, trust_root, false)
+}
+
+fn verify_bundle_tlog(
+    bundle: &Bundle,
+    blob: &[u8],
+    trust_root: &TrustRoot,
+    require_tlog: bool,
+) -> Result<i64, anyhow::Error> {
+    let cert = bundle.signing_cert()?;
+    let signature = base64::decode(&bundle.message_signature.signature)?;
+    let digest = base64::decode(&bundle.message_signature.message_digest.digest)?;
+
+    let digest_algorithm =
+        HashAlgorithm::from_bundle_name(&bundle.message_signature.message_digest.algorithm)?;
+    if digest_algorithm.digest(blob)? != digest {
+        anyhow::bail!("artifact digest does not match the bundle");
+    }
+    verify_blob(
+        &cert,
+        &trust_root.fulcio_chain,
+        digest_algorithm,
+        blob,
+        &signature,
+    )?;
+    if let Some(expiry) = &bundle.expiry {
+        verify_expiry(bundle, expiry, &cert)?;
+    }
+
+    trust_root.verify_authority(&cert)?;
+
+    let ctlog_keys = trust_root.ctlog_keys_for(&cert)?;
+    if !ctlog_keys.is_empty() {
+        let mut errors = Vec::new();
+        let verified = ctlog_keys.iter().any(|ctlog_key| {
+            verify_embedded_sct(&cert, &trust_root.fulcio_chain, ctlog_key)
+                .map_err(|e| errors.push(e.to_string()))
+                .is_ok()
+        });
+        if !verified {
+            anyhow::bail!("no verified SCT: {}", errors.join(", "));
+        }
+    }
+
+    let mut signing_times = Vec::new();
+    let tlog_entries = &bundle.verification_material.tlog_entries;
+    if !tlog_entries.is_empty() {
+        // one entry verifying is enough, the others may be from other logs
+        let mut errors = Vec::new();
+        let signed_at = tlog_entries.iter().find_map(|entry| {
+            trust_root
+                .tlog_key(entry, &cert)
+                .and_then(|rekor_key| {
+                    verify_tlog_entry(entry, &cert, &signature, &digest, rekor_key)
+                })
+                .map_err(|e| errors.push(e.to_string()))
+                .ok()
+        });
+        match signed_at {
+            Some(time) => signing_times.extend(time),
+            None => anyhow::bail!("no verified log entry: {}", errors.join(", ")),
+        }
+    } else if require_tlog {
+        anyhow::bail!("bundle has no transparency log entry");
+    }
+
+    if !trust_root.tsa_chain.is_empty() {
+        for token in bundle.timestamps()? {
+            let info = timestamp::verify_token(&token, &signature, &trust_root.tsa_chain)?;
+            signing_times.push(info.gen_time);
+        }
+    }
+    for time in &signing_times {
+        verify_cert_valid_at(&cert, *time)?;
+    }
+    signing_times.into_iter().min().ok_or_else(|| {
+        anyhow::anyhow!("bundle has no trusted time to check the signing certificate against")
+    })
+}
+
+// the claim has to be signed with the key of the signature it limits
+fn verify_expiry(bundle: &Bundle, expiry: &Envelope, cert: &X509) -> Result<(), anyhow::Error> {
+    let public_key = cert.public_key()?;
+    let claim = Claim::verify(expiry, &public_key)?;
+    if claim.digest != bundle.message_signature.message_digest {
+        anyhow::bail!("expiration claim is for a different artifact");
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    if now > claim.expires {
+        let expires = Asn1Time::from_unix(claim.expires)?;
+        anyhow::bail!("signature expired at {}", &*expires);
+    }
+    Ok(())
+}
+
+/// Verifies one of the countersignatures of `bundle`, made over its
+/// signature rather than the artifact, and returns its signing time. The
+/// signature of `bundle` itself is not checked here.
+pub fn verify_countersignature(
+    bundle: &Bundle,
+    countersignature: &Bundle,
+    trust_root: &TrustRoot,
+) -> Result<i64, anyhow::Error> {
+    verify_bundle(countersignature, &bundle.signature()?, trust_root)
+}
+
+/// Verifies that `entry` records this signature over the artifact with the
+/// given `digest`, and that the log has committed to including it.
+///
+/// Returns the integrated time of the entry if the log signed it.
+pub fn verify_tlog_entry(
+    entry: &TransparencyLogEntry,
+    cert: &X509,
+    signature: &[u8],
+    digest: &[u8],
+    rekor_key: &PKeyRef<Public>,
+) -> Result<Option<i64>, anyhow::Error> {
+    let logged_cert = verify_tlog_body(entry, signature, digest)?;
+    if X509::from_pem(&logged_cert)?.to_der()? != cert.to_der()? {
+        anyhow::bail!("log entry is for a different certificate");
+    }
+    verify_tlog_commitment(entry, rekor_key)
+}
+
+/// Like [`verify_tlog_entry`], for signatures made with a self managed
+/// `public_key` rather than a certificate.
+pub fn verify_key_tlog_entry(
+    entry: &TransparencyLogEntry,
+    public_key: &PKeyRef<Public>,
+    signature: &[u8],
+    digest: &[u8],
+    rekor_key: &PKeyRef<Public>,
+) -> Result<Option<i64>, anyhow::Error> {
+    let logged_key = verify_tlog_body(entry, signature, digest)?;
+    let logged_key = PKey::public_key_from_pem(&logged_key)
+        .map_err(|_| anyhow::anyhow!("log entry is not for a bare public key"))?;
+    if logged_key.public_key_to_der()? != public_key.public_key_to_der()? {
+        anyhow::bail!("log entry is for a different public key");
+    }
+    verify_tlog_commitment(entry, rekor_key)
+}
+
+// the log has to have promised or proven inclusion of the entry, only the
+// promise covers the integrated time
+fn verify_tlog_commitment(
+    entry: &TransparencyLogEntry,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<Option<i64>, anyhow::Error> {
+    if entry.inclusion_proof.is_none() && entry.inclusion_promise.is_none() {
+        anyhow::bail!("log entry has neither an inclusion proof nor a promise");
+    }
+    if entry.inclusion_proof.is_some() {
+        tlog::verify_inclusion_proof(entry, Some(rekor_key))?;
+    }
+    if entry.inclusion_promise.is_none() {
+        return Ok(None);
+    }
+    tlog::verify_set(entry, rekor_key)?;
+    Ok(Some(entry.integrated_time.parse()?))
+}
+
+/// Verifies a DSSE `envelope` signed with the key of `cert`, checking that
+/// `cert` chains up to `fulcio_chain`, and returns the in-toto statement it
+/// carries.
+pub fn verify_attestation(
+    envelope: &Envelope,
+    cert: &X509,
+    fulcio_chain: &[X509],
+) -> Result<Statement, anyhow::Error> {
+    if !verify_cert_chain(cert, fulcio_chain)? {
+        anyhow::bail!("signing certificate does not chain up to the Fulcio root");
+    }
+    let public_key = cert.public_key()?;
+    envelope.verify(&public_key)?;
+    if envelope.payload_type != intoto::PAYLOAD_TYPE {
+        anyhow::bail!(
+            "envelope payload type {} is not an in-toto statement",
+            envelope.payload_type
+        );
+    }
+    Statement::from_json(&envelope.payload()?)
+}
+
+/// Verifies that `entry` is a dsse entry recording this `envelope` signed
+/// with `cert`, and that the log has committed to including it.
+pub fn verify_dsse_tlog_entry(
+    entry: &TransparencyLogEntry,
+    cert: &X509,
+    envelope: &Envelope,
+    rekor_key: &PKeyRef<Public>,
+) -> Result<Option<i64>, anyhow::Error> {
+    if entry.kind_version.kind != "dsse" {
+        anyhow::bail!("log entry kind {} is not dsse", entry.kind_version.kind);
+    }
+    let body: serde_json::Value =
+        serde_json::from_slice(&base64::decode(&entry.canonicalized_body)?)?;
+    let spec = &body["spec"];
+
+    let payload_hash = data_encoding::HEXLOWER.encode(&Sha256::digest(&envelope.payload()?));
+    if spec["payloadHash"]["value"] != payload_hash.as_str() {
+        anyhow::bail!("log entry is for a different attestation");
+    }
+    let cert_der = cert.to_der()?;
+    let logged = spec["signatures"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let signed_by_cert = logged.iter().any(|signature| {
+        base64::decode(signature["verifier"].as_str().unwrap_or_default())
+            .ok()
+            .and_then(|pem| X509::from_pem(&pem).ok())
+            .and_then(|logged_cert| logged_cert.to_der().ok())
+            .is_some_and(|logged_der| logged_der == cert_der)
+    });
+    if !signed_by_cert {
+        anyhow::bail!("log entry is for a different certificate");
+    }
+    verify_tlog_commitment(entry, rekor_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{create_keys, create_signer};
+    use crate::format::SignatureFormat;
+    use crate::trusted_root::TrustedRoot;
+    use openssl::hash::MessageDigest;
+    use std::fs;
+    use std::path::Path;
+    #[test]
+    fn test_verify_signature() {
+        let (private_key, _) = create_keys().unwrap();
+        let mut builder = X509::builder().unwrap();
+        builder.set_pubkey(&private_key).unwrap();
+        builder.sign(&private_key, MessageDigest::sha256()).unwrap();
+        let cert = builder.build();
+
+        let mut signer = create_signer(&private_key).unwrap();
+        signer.update(b"lolwut").unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        assert!(verify_signature(&cert, HashAlgorithm::Sha256, b"lolwut", &signature).unwrap());
+        assert!(!verify_signature(&cert, HashAlgorithm::Sha256, b"ohhai", &signature).unwrap());
+
+        let public_key =
+            PKey::public_key_from_der(&private_key.public_key_to_der().unwrap()).unwrap();
+        assert!(verify_signature_with_key(
+            &public_key,
+            HashAlgorithm::Sha256,
+            b"lolwut",
+            &signature
+        )
+        .unwrap());
+        assert!(!verify_signature_with_key(
+            &public_key,
+            HashAlgorithm::Sha256,
+            b"ohhai",
+            &signature
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_verify_cert_valid_at() {
+        let cert = X509::from_pem(&std::fs::read("test_data/signing_cert.pem").unwrap()).unwrap();
+        // issued at 2022-08-01 12:00 for ten minutes
+        verify_cert_valid_at(&cert, 1659355500).unwrap();
+        assert!(verify_cert_valid_at(&cert, 1659355000).is_err());
+        assert!(verify_cert_valid_at(&cert, 1659356000).is_err());
+    }
+
+    #[test]
+    fn test_trust_root_validity() {
+        let cert = X509::from_pem(&fs::read("test_data/signing_cert.pem").unwrap()).unwrap();
+        let chain = X509::stack_from_pem(&fs::read("test_data/fulcio_chain.pem").unwrap()).unwrap();
+        let valid_for = |start: &str, end: Option<&str>| {
+            Some(ValidFor {
+                start: start.to_string(),
+                end: end.map(str::to_string),
+            })
+        };
+        let log_key = |log_id: u8, valid_for: Option<ValidFor>| LogKey {
+            log_id: vec![log_id; 32],
+            key: cert.public_key().unwrap(),
+            valid_for,
+        };
+        let entry = |log_id: u8, integrated_time: i64| -> TransparencyLogEntry {
+            serde_json::from_value(serde_json::json!({
+                "logId": {"keyId": base64::encode([log_id; 32])},
+                "kindVersion": {"kind": "hashedrekord", "version": "0.0.1"},
+                "integratedTime": integrated_time.to_string(),
+                "canonicalizedBody": "",
+            }))
+            .unwrap()
+        };
+        let mut trust_root = TrustRoot {
+            tlog_keys: vec![
+                log_key(
+                    1,
+                    valid_for("2021-01-01T00:00:00Z", Some("2022-01-01T00:00:00Z")),
+                ),
+                log_key(2, valid_for("2022-01-01T00:00:00Z", None)),
+            ],
+            ctlog_keys: vec![log_key(
+                3,
+                valid_for("2021-01-01T00:00:00Z", Some("2022-01-01T00:00:00Z")),
+            )],
+            fulcio_authorities: vec![Authority {
+                certificates: chain.clone(),
+                valid_for: valid_for("2022-04-13T00:00:00Z", None),
+            }],
+            ..TrustRoot::default()
+        };
+
+        // issued at 2022-08-01, after the first key was retired
+        trust_root.tlog_key(&entry(1, 1640000000), &cert).unwrap();
+        assert!(trust_root.tlog_key(&entry(1, 1659355500), &cert).is_err());
+        trust_root.tlog_key(&entry(2, 1659355500), &cert).unwrap();
+        assert!(trust_root.tlog_key(&entry(4, 1659355500), &cert).is_err());
+        trust_root.tlog_key(&entry(2, 0), &cert).unwrap();
+        assert!(trust_root.tlog_key(&entry(1, 0), &cert).is_err());
+        assert!(trust_root.ctlog_keys_for(&cert).is_err());
+        trust_root.verify_authority(&cert).unwrap();
+
+        trust_root.fulcio_authorities[0].valid_for =
+            valid_for("2021-01-01T00:00:00Z", Some("2022-06-01T00:00:00Z"));
+        assert!(trust_root.verify_authority(&cert).is_err());
+        trust_root.fulcio_authorities[0] = Authority {
+            certificates: Vec::new(),
+            valid_for: None,
+        };
+        assert!(trust_root.verify_authority(&cert).is_err());
+    }
+
+    // what other clients produced, laid out as test_data/interop/README.md
+    // describes. cases named *_fail must not verify
+    #[test]
+    fn test_interop() {
+        let mut cases = 0;
+        for client in fs::read_dir("test_data/interop").unwrap() {
+            let client = client.unwrap().path();
+            if !client.is_dir() {
+                continue;
+            }
+            for case in fs::read_dir(&client).unwrap() {
+                let case = case.unwrap().path();
+                if !case.is_dir() {
+                    continue;
+                }
+                let result = verify_interop_case(&client, &case);
+                let name = case.file_name().unwrap().to_string_lossy();
+                if name.ends_with("_fail") {
+                    assert!(result.is_err(), "{} verified", case.display());
+                } else if let Err(e) = result {
+                    panic!("{} did not verify: {}", case.display(), e);
+                }
+                cases += 1;
+            }
+        }
+        assert!(cases > 0);
+    }
+
+    // a trusted_root.json next to the case or for the whole client, else
+    // the test fulcio chain
+    fn interop_trust_root(client: &Path, case: &Path) -> Result<TrustRoot, anyhow::Error> {
+        for dir in [case, client] {
+            match fs::read(dir.join("trusted_root.json")) {
+                Ok(json) => return TrustedRoot::from_json(&json)?.trust_root(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(TrustRoot {
+            fulcio_chain: X509::stack_from_pem(&fs::read("test_data/fulcio_chain.pem")?)?,
+            ..TrustRoot::default()
+        })
+    }
+
+    fn verify_interop_case(client: &Path, case: &Path) -> Result<(), anyhow::Error> {
+        let trust_root = interop_trust_root(client, case)?;
+        let artifact = fs::read(case.join("artifact"));
+        for name in ["bundle.sigstore.json", "bundle.sigstore.pb"] {
+            if let Ok(bundle) = fs::read(case.join(name)) {
+                let bundle = Bundle::parse(&bundle)?;
+                // signatures that were only timestamped have no log entry
+                if bundle.verification_material.tlog_entries.is_empty() {
+                    verify_bundle_without_tlog(&bundle, &artifact?, &trust_root)?;
+                } else {
+                    verify_bundle(&bundle, &artifact?, &trust_root)?;
+                }
+                return Ok(());
+            }
+        }
+        let cert = X509::from_pem(&fs::read(case.join("certificate.pem"))?)?;
+        if let Ok(envelope) = fs::read(case.join("envelope.json")) {
+            let statement = verify_attestation(
+                &Envelope::from_json(&envelope)?,
+                &cert,
+                &trust_root.fulcio_chain,
+            )?;
+            if let Ok(artifact) = artifact {
+                let digest = HashAlgorithm::Sha256.hex_digest(&artifact)?;
+                if !statement.has_sha256_subject(&digest) {
+                    anyhow::bail!("the statement is not about the artifact");
+                }
+            }
+            return Ok(());
+        }
+        let public_key = cert.public_key()?;
+        let signature = SignatureFormat::Base64.decode_signature(
+            &fs::read(case.join("signature"))?,
+            SigningAlgorithm::for_key(&public_key)?,
+        )?;
+        verify_blob(
+            &cert,
+            &trust_root.fulcio_chain,
+            default_digest_algorithm(&cert)?,
+            &artifact?,
+            &signature,
+        )
+    }
+}
@@ -0,0 +1,131 @@
+//! Waiting for files to change, to sign build outputs again each time they
+//! are rebuilt.
+//!
+//! Files are polled for their modification time and size rather than
+//! watched through the platform's notification API. A change is only
+//! reported once the files stopped changing for a while, so a file that is
+//! still being written is not signed halfway.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(1);
+
+// what is compared between polls, none while a file does not exist
+type FileState = Option<(SystemTime, u64)>;
+
+fn state(path: &Path) -> Result<FileState, anyhow::Error> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(Some((metadata.modified()?, metadata.len()))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reports which of a set of files changed.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    files: Vec<(PathBuf, FileState)>,
+    interval: Duration,
+    debounce: Duration,
+}
+
+impl Watcher {
+    /// Watches `paths`, changes are counted from their current state.
+    pub fn new(paths: &[PathBuf]) -> Result<Self, anyhow::Error> {
+        let files = paths
+            .iter()
+            .map(|path| Ok((path.clone(), state(path)?)))
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Watcher {
+            files,
+            interval: DEFAULT_INTERVAL,
+            debounce: DEFAULT_DEBOUNCE,
+        })
+    }
+
+    /// Polls every `interval` rather than every half second.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Waits for files to be unchanged for `debounce` rather than a second
+    /// before reporting them.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // the files whose state differs from the last one seen, updating it.
+    // files that were removed are not reported, there is nothing to sign
+    fn poll(&mut self) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut changed = Vec::new();
+        for (path, last) in &mut self.files {
+            let current = state(path)?;
+            if current != *last {
+                *last = current;
+                if current.is_some() {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Waits until files changed and then stayed unchanged for the debounce
+    /// time, and returns them.
+    pub async fn changed(&mut self) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut changed = Vec::new();
+        let mut quiet = Duration::ZERO;
+        loop {
+            tokio::time::sleep(self.interval).await;
+            let polled = self.poll()?;
+            if polled.is_empty() {
+                quiet += self.interval;
+            } else {
+                quiet = Duration::ZERO;
+                for path in polled {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+            if !changed.is_empty() && quiet >= self.debounce {
+                // the files keep the order they were given in
+                changed.sort_by_key(|path| self.files.iter().position(|(file, _)| file == path));
+                return Ok(changed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_changed() {
+        let dir = env::temp_dir().join(format!("ferris-sign-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first");
+        let second = dir.join("second");
+        fs::write(&first, b"lolwut").unwrap();
+
+        let mut watcher = Watcher::new(&[first.clone(), second.clone()])
+            .unwrap()
+            .with_interval(Duration::from_millis(10))
+            .with_debounce(Duration::from_millis(30));
+        assert!(watcher.poll().unwrap().is_empty());
+
+        fs::write(&second, b"ohhai").unwrap();
+        fs::write(&first, b"lolwut, again").unwrap();
+        let changed = watcher.changed().await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(changed, vec![first, second]);
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+}
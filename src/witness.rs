@@ -0,0 +1,133 @@
+//! Checking that Rekor only ever appends to its log.
+//!
+//! The latest checkpoint verified is kept in a local file. Every newer tree
+//! head has to come with a consistency proof against it, so a log that
+//! rewrites or drops entries is noticed even if each checkpoint is signed.
+
+use data_encoding::HEXLOWER_PERMISSIVE;
+use openssl::pkey::{PKey, Public};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cache;
+use crate::rekor_api;
+use crate::tlog::{self, Checkpoint};
+
+/// The outcome of checking the current tree head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consistency {
+    /// The checkpoint the current one was proven consistent with, none if
+    /// there was no earlier checkpoint for the same log.
+    pub previous: Option<Checkpoint>,
+    pub current: Checkpoint,
+}
+
+/// Verifies tree heads of one Rekor instance against the last one seen.
+#[derive(Debug, Clone)]
+pub struct Witness {
+    rekor_url: String,
+    rekor_key: PKey<Public>,
+    path: PathBuf,
+}
+
+impl Witness {
+    pub fn new(rekor_url: &str, rekor_key: PKey<Public>, path: impl Into<PathBuf>) -> Self {
+        Witness {
+            rekor_url: rekor_url.to_string(),
+            rekor_key,
+            path: path.into(),
+        }
+    }
+
+    /// Keeps the checkpoint in `checkpoints/<host>` of the
+    /// [`cache::cache_dir`].
+    pub fn open_default(rekor_url: &str, rekor_key: PKey<Public>) -> Result<Self, anyhow::Error> {
+        let host = url::Url::parse(rekor_url)?
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("Rekor url {} has no host", rekor_url))?
+            .to_string();
+        let path = cache::cache_dir()?.join("checkpoints").join(host);
+        Ok(Witness::new(rekor_url, rekor_key, path))
+    }
+
+    /// The checkpoint verified last, as stored.
+    pub fn latest(&self) -> Result<Option<Checkpoint>, anyhow::Error> {
+        match fs::read_to_string(&self.path) {
+            Ok(envelope) => Ok(Some(Checkpoint::parse(&envelope)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Fetches the current tree head, checks its signature and that it is
+    /// consistent with the stored checkpoint, and stores it in its place.
+    pub async fn update(&self) -> Result<Consistency, anyhow::Error> {
+        let info = rekor_api::get_log_info(&self.rekor_url).await?;
+        let current = Checkpoint::parse(&info.signed_tree_head)?;
+        current.verify(&self.rekor_key)?;
+        if current.tree_size != info.tree_size
+            || current.root_hash != HEXLOWER_PERMISSIVE.decode(info.root_hash.as_bytes())?
+        {
+            anyhow::bail!("signed tree head does not match the reported tree");
+        }
+
+        // a new shard starts a new tree, there is nothing to be consistent with
+        let previous = self
+            .latest()?
+            .filter(|previous| previous.origin == current.origin);
+        if let Some(previous) = &previous {
+            let hashes = if previous.tree_size < current.tree_size {
+                let proof = rekor_api::get_consistency_proof(
+                    &self.rekor_url,
+                    previous.tree_size,
+                    current.tree_size,
+                    &info.tree_id,
+                )
+                .await?;
+                proof
+                    .hashes
+                    .iter()
+                    .map(|hash| HEXLOWER_PERMISSIVE.decode(hash.as_bytes()))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                Vec::new()
+            };
+            tlog::verify_consistency(previous, &current, &hashes)?;
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, &info.signed_tree_head)?;
+        Ok(Consistency { previous, current })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use std::env;
+
+    #[test]
+    fn test_latest() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let key = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+        let path = env::temp_dir().join(format!("ferris-sign-witness-{}", std::process::id()));
+        let witness = Witness::new("https://rekor.example.com", key, &path);
+        assert_eq!(witness.latest().unwrap(), None);
+
+        let envelope = format!(
+            "rekor.example.com - 42\n7\n{}\n\n\u{2014} rekor.example.com {}\n",
+            base64::encode([0xabu8; 32]),
+            base64::encode([1u8, 2, 3, 4, 0x30, 0x45])
+        );
+        fs::write(&path, envelope).unwrap();
+        let latest = witness.latest().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(latest.origin, "rekor.example.com - 42");
+        assert_eq!(latest.tree_size, 7);
+    }
+}